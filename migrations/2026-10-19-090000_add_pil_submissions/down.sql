@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX IF EXISTS covid_tests__profile_test_idx;
+DROP TABLE IF EXISTS pil_submissions;
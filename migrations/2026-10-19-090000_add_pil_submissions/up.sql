@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+CREATE TABLE IF NOT EXISTS pil_submissions (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    idempotency_key VARCHAR NOT NULL,
+    cbsa_id UUID NOT NULL,
+    travel_response_ids UUID[] NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX pil_submissions__key_idx ON pil_submissions(cbsa_id, idempotency_key);
+
+CREATE INDEX covid_tests__profile_test_idx ON covid_tests(public_health_profile_id, test_name, date_taken);
@@ -0,0 +1,6 @@
+ALTER TABLE trips ALTER COLUMN trip_state TYPE VARCHAR USING trip_state::text;
+ALTER TABLE trips ALTER COLUMN travel_intent TYPE VARCHAR USING travel_intent::text;
+ALTER TABLE trips ALTER COLUMN travel_mode TYPE VARCHAR USING travel_mode::text;
+
+DROP TYPE IF EXISTS travel_intent_enum;
+DROP TYPE IF EXISTS travel_mode_enum;
@@ -0,0 +1,61 @@
+-- Store trip_state, travel_intent and travel_mode as enums.
+-- Existing free-text values are mapped onto the enum values; anything
+-- unrecognised falls back to what the rest of the row implies.
+
+CREATE TYPE travel_intent_enum AS ENUM ('entry', 'exit', 'transit');
+CREATE TYPE travel_mode_enum AS ENUM ('air', 'sea', 'land');
+
+ALTER TABLE trips ALTER COLUMN trip_state TYPE trip_state_enum USING (
+    CASE lower(regexp_replace(trim(trip_state), '[ -]', '_', 'g'))
+        WHEN 'planned' THEN 'planned'
+        WHEN 'scheduled' THEN 'planned'
+        WHEN 'booked' THEN 'planned'
+        WHEN 'in_progress' THEN 'in_progress'
+        WHEN 'in_process' THEN 'in_progress'
+        WHEN 'active' THEN 'in_progress'
+        WHEN 'completed' THEN 'completed'
+        WHEN 'complete' THEN 'completed'
+        WHEN 'arrived' THEN 'completed'
+        WHEN 'cancelled' THEN 'cancelled'
+        WHEN 'canceled' THEN 'cancelled'
+        ELSE
+            CASE
+                WHEN arrival_time IS NOT NULL THEN 'completed'
+                WHEN departure_time IS NOT NULL THEN 'in_progress'
+                ELSE 'planned'
+            END
+    END
+)::trip_state_enum;
+
+-- PIL queries are made for travellers arriving in Canada, so unknown
+-- intents are treated as entries
+ALTER TABLE trips ALTER COLUMN travel_intent TYPE travel_intent_enum USING (
+    CASE lower(regexp_replace(trim(travel_intent), '[ -]', '_', 'g'))
+        WHEN 'exit' THEN 'exit'
+        WHEN 'departure' THEN 'exit'
+        WHEN 'leaving' THEN 'exit'
+        WHEN 'transit' THEN 'transit'
+        WHEN 'in_transit' THEN 'transit'
+        WHEN 'connection' THEN 'transit'
+        ELSE 'entry'
+    END
+)::travel_intent_enum;
+
+-- Unknown modes are treated as air travel, which is all of the demo data
+ALTER TABLE trips ALTER COLUMN travel_mode TYPE travel_mode_enum USING (
+    CASE lower(regexp_replace(trim(travel_mode), '[ -]', '_', 'g'))
+        WHEN 'sea' THEN 'sea'
+        WHEN 'boat' THEN 'sea'
+        WHEN 'ship' THEN 'sea'
+        WHEN 'ferry' THEN 'sea'
+        WHEN 'cruise' THEN 'sea'
+        WHEN 'land' THEN 'land'
+        WHEN 'rail' THEN 'land'
+        WHEN 'train' THEN 'land'
+        WHEN 'bus' THEN 'land'
+        WHEN 'car' THEN 'land'
+        WHEN 'road' THEN 'land'
+        WHEN 'private_vehicle' THEN 'land'
+        ELSE 'air'
+    END
+)::travel_mode_enum;
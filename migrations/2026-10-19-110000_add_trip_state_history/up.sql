@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+CREATE TABLE IF NOT EXISTS trip_state_history (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    trip_id UUID NOT NULL REFERENCES trips (id) ON DELETE CASCADE,
+    from_state trip_state_enum NOT NULL,
+    to_state trip_state_enum NOT NULL,
+    changed_by UUID NOT NULL,
+    reason VARCHAR,
+    changed_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS trip_state_history__trip_idx ON trip_state_history (trip_id, changed_at);
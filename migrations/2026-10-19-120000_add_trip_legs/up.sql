@@ -0,0 +1,24 @@
+-- Your SQL goes here
+
+CREATE TABLE IF NOT EXISTS trip_legs (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    trip_id UUID NOT NULL REFERENCES trips (id) ON DELETE CASCADE,
+    leg_number INTEGER NOT NULL,
+    origin_place_id UUID NOT NULL,
+    destination_place_id UUID NOT NULL,
+    travel_identifier VARCHAR,
+    scheduled_departure_time TIMESTAMP,
+    scheduled_arrival_time TIMESTAMP,
+    departure_time TIMESTAMP,
+    arrival_time TIMESTAMP,
+    UNIQUE (trip_id, leg_number)
+);
+
+-- Existing trips become single leg itineraries
+INSERT INTO trip_legs (trip_id, leg_number, origin_place_id, destination_place_id,
+    travel_identifier, scheduled_departure_time, scheduled_arrival_time,
+    departure_time, arrival_time)
+SELECT id, 1, origin_place_id, destination_place_id,
+    travel_identifier, scheduled_departure_time, scheduled_arrival_time,
+    departure_time, arrival_time
+FROM trips;
@@ -0,0 +1,3 @@
+-- IANA timezone name, e.g. America/Toronto, used to show trip times in port local time
+
+ALTER TABLE places ADD COLUMN IF NOT EXISTS timezone VARCHAR;
@@ -0,0 +1,5 @@
+ALTER TABLE trips DROP COLUMN IF EXISTS travel_provider_id;
+
+DROP TABLE IF EXISTS travel_providers;
+
+DROP TYPE IF EXISTS travel_provider_type_enum;
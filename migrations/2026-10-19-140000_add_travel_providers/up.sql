@@ -0,0 +1,29 @@
+-- Your SQL goes here
+
+CREATE TYPE travel_provider_type_enum AS ENUM ('airline', 'rail', 'cruise', 'bus', 'other');
+
+CREATE TABLE IF NOT EXISTS travel_providers (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    provider_name VARCHAR UNIQUE NOT NULL,
+    provider_type travel_provider_type_enum NOT NULL DEFAULT 'other',
+    contact_name VARCHAR,
+    contact_email VARCHAR,
+    contact_phone VARCHAR,
+    api_key_prefix VARCHAR UNIQUE,
+    api_key_hash VARCHAR,
+    api_key_scopes VARCHAR[] NOT NULL DEFAULT '{}',
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+ALTER TABLE trips ADD COLUMN IF NOT EXISTS travel_provider_id UUID REFERENCES travel_providers (id);
+
+-- Register the providers already named on trips and link them
+INSERT INTO travel_providers (provider_name)
+SELECT DISTINCT trim(trip_provider) FROM trips
+WHERE trim(trip_provider) <> ''
+ON CONFLICT (provider_name) DO NOTHING;
+
+UPDATE trips SET travel_provider_id = travel_providers.id
+FROM travel_providers
+WHERE travel_providers.provider_name = trim(trips.trip_provider);
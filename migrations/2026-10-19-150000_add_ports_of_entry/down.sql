@@ -0,0 +1,14 @@
+ALTER TABLE trips DROP COLUMN IF EXISTS port_of_entry_id;
+
+DROP INDEX IF EXISTS places_un_locode_idx;
+
+ALTER TABLE places
+    DROP COLUMN IF EXISTS place_kind,
+    DROP COLUMN IF EXISTS iata_code,
+    DROP COLUMN IF EXISTS un_locode,
+    DROP COLUMN IF EXISTS latitude,
+    DROP COLUMN IF EXISTS longitude,
+    DROP COLUMN IF EXISTS is_port_of_entry,
+    DROP COLUMN IF EXISTS testing_rate;
+
+DROP TYPE IF EXISTS place_kind_enum;
@@ -0,0 +1,48 @@
+-- Your SQL goes here
+
+CREATE TYPE place_kind_enum AS ENUM ('city', 'airport', 'land_crossing', 'seaport');
+
+ALTER TABLE places
+    ADD COLUMN IF NOT EXISTS place_kind place_kind_enum NOT NULL DEFAULT 'city',
+    -- Three letter IATA airport code, e.g. YYZ
+    ADD COLUMN IF NOT EXISTS iata_code VARCHAR UNIQUE,
+    -- Five character UN/LOCODE, e.g. CAVAN
+    ADD COLUMN IF NOT EXISTS un_locode VARCHAR,
+    ADD COLUMN IF NOT EXISTS latitude DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS longitude DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS is_port_of_entry BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Fraction of arrivals referred for mandatory testing, the global rate if NULL
+    ADD COLUMN IF NOT EXISTS testing_rate DOUBLE PRECISION;
+
+CREATE INDEX IF NOT EXISTS places_un_locode_idx ON places (un_locode);
+
+ALTER TABLE trips ADD COLUMN IF NOT EXISTS port_of_entry_id UUID REFERENCES places (id);
+
+-- Canadian ports of entry
+INSERT INTO countries (country_name, risk_rate) VALUES ('Canada', 0.03)
+ON CONFLICT (country_name) DO NOTHING;
+
+INSERT INTO places (place_name, country_id, timezone, place_kind, iata_code, un_locode, latitude, longitude, is_port_of_entry)
+SELECT port.place_name, countries.id, port.timezone, port.place_kind::place_kind_enum,
+    port.iata_code, port.un_locode, port.latitude, port.longitude, TRUE
+FROM (VALUES
+    ('Toronto Pearson International Airport', 'America/Toronto', 'airport', 'YYZ', 'CATOR', 43.6777, -79.6248),
+    ('Montréal-Trudeau International Airport', 'America/Toronto', 'airport', 'YUL', 'CAMTR', 45.4706, -73.7408),
+    ('Vancouver International Airport', 'America/Vancouver', 'airport', 'YVR', 'CAVAN', 49.1947, -123.1792),
+    ('Calgary International Airport', 'America/Edmonton', 'airport', 'YYC', 'CACAL', 51.1315, -114.0106),
+    ('Edmonton International Airport', 'America/Edmonton', 'airport', 'YEG', 'CAEDM', 53.3097, -113.5797),
+    ('Ottawa Macdonald-Cartier International Airport', 'America/Toronto', 'airport', 'YOW', 'CAOTT', 45.3225, -75.6692),
+    ('Halifax Stanfield International Airport', 'America/Halifax', 'airport', 'YHZ', 'CAHAL', 44.8808, -63.5086),
+    ('Winnipeg James Armstrong Richardson International Airport', 'America/Winnipeg', 'airport', 'YWG', 'CAWNP', 49.9100, -97.2399),
+    ('Ambassador Bridge', 'America/Toronto', 'land_crossing', NULL, 'CAWND', 42.3118, -83.0739),
+    ('Peace Bridge', 'America/Toronto', 'land_crossing', NULL, 'CAFER', 42.9067, -78.9047),
+    ('Douglas (Peace Arch)', 'America/Vancouver', 'land_crossing', NULL, NULL, 49.0022, -122.7566),
+    ('St-Bernard-de-Lacolle', 'America/Toronto', 'land_crossing', NULL, NULL, 45.0083, -73.3722),
+    ('Port of Vancouver', 'America/Vancouver', 'seaport', NULL, 'CAVAN', 49.2888, -123.1111),
+    ('Port of Halifax', 'America/Halifax', 'seaport', NULL, 'CAHAL', 44.6400, -63.5600),
+    ('Port of Montréal', 'America/Toronto', 'seaport', NULL, 'CAMTR', 45.5500, -73.5300)
+) AS port (place_name, timezone, place_kind, iata_code, un_locode, latitude, longitude)
+JOIN countries ON countries.country_name = 'Canada'
+WHERE NOT EXISTS (
+    SELECT 1 FROM places WHERE places.place_name = port.place_name AND places.country_id = countries.id
+);
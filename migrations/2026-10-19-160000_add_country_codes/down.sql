@@ -0,0 +1,7 @@
+DROP TABLE IF EXISTS pending_countries;
+
+DROP TABLE IF EXISTS country_aliases;
+
+ALTER TABLE countries
+    DROP COLUMN IF EXISTS iso_alpha2,
+    DROP COLUMN IF EXISTS iso_alpha3;
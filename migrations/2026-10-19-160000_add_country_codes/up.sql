@@ -0,0 +1,182 @@
+-- Your SQL goes here
+
+ALTER TABLE countries
+    ADD COLUMN IF NOT EXISTS iso_alpha2 VARCHAR(2) UNIQUE,
+    ADD COLUMN IF NOT EXISTS iso_alpha3 VARCHAR(3) UNIQUE;
+
+-- Other names travellers and providers use for a country, stored normalized:
+-- lower case, no periods, single spaces
+CREATE TABLE IF NOT EXISTS country_aliases (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    country_id UUID NOT NULL REFERENCES countries (id) ON DELETE CASCADE,
+    alias VARCHAR UNIQUE NOT NULL
+);
+
+-- Names that matched no country, held for an Admin to approve or alias
+CREATE TABLE IF NOT EXISTS pending_countries (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    country_name VARCHAR UNIQUE NOT NULL,
+    seen_count INTEGER NOT NULL DEFAULT 1,
+    first_seen_at TIMESTAMP NOT NULL DEFAULT now(),
+    last_seen_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE TEMPORARY TABLE iso_countries (
+    country_name VARCHAR NOT NULL,
+    iso_alpha2 VARCHAR(2) NOT NULL,
+    iso_alpha3 VARCHAR(3) NOT NULL
+);
+
+INSERT INTO iso_countries VALUES
+    ('Canada', 'CA', 'CAN'),
+    ('United States', 'US', 'USA'),
+    ('United Kingdom', 'GB', 'GBR'),
+    ('France', 'FR', 'FRA'),
+    ('Germany', 'DE', 'DEU'),
+    ('Italy', 'IT', 'ITA'),
+    ('Spain', 'ES', 'ESP'),
+    ('Portugal', 'PT', 'PRT'),
+    ('Netherlands', 'NL', 'NLD'),
+    ('Belgium', 'BE', 'BEL'),
+    ('Switzerland', 'CH', 'CHE'),
+    ('Austria', 'AT', 'AUT'),
+    ('Ireland', 'IE', 'IRL'),
+    ('Sweden', 'SE', 'SWE'),
+    ('Norway', 'NO', 'NOR'),
+    ('Denmark', 'DK', 'DNK'),
+    ('Finland', 'FI', 'FIN'),
+    ('Poland', 'PL', 'POL'),
+    ('Czechia', 'CZ', 'CZE'),
+    ('Greece', 'GR', 'GRC'),
+    ('Turkey', 'TR', 'TUR'),
+    ('Russia', 'RU', 'RUS'),
+    ('Ukraine', 'UA', 'UKR'),
+    ('Israel', 'IL', 'ISR'),
+    ('United Arab Emirates', 'AE', 'ARE'),
+    ('Saudi Arabia', 'SA', 'SAU'),
+    ('Egypt', 'EG', 'EGY'),
+    ('Morocco', 'MA', 'MAR'),
+    ('Nigeria', 'NG', 'NGA'),
+    ('Kenya', 'KE', 'KEN'),
+    ('South Africa', 'ZA', 'ZAF'),
+    ('India', 'IN', 'IND'),
+    ('Pakistan', 'PK', 'PAK'),
+    ('China', 'CN', 'CHN'),
+    ('Hong Kong', 'HK', 'HKG'),
+    ('Japan', 'JP', 'JPN'),
+    ('South Korea', 'KR', 'KOR'),
+    ('Philippines', 'PH', 'PHL'),
+    ('Vietnam', 'VN', 'VNM'),
+    ('Thailand', 'TH', 'THA'),
+    ('Singapore', 'SG', 'SGP'),
+    ('Malaysia', 'MY', 'MYS'),
+    ('Indonesia', 'ID', 'IDN'),
+    ('Australia', 'AU', 'AUS'),
+    ('New Zealand', 'NZ', 'NZL'),
+    ('Mexico', 'MX', 'MEX'),
+    ('Cuba', 'CU', 'CUB'),
+    ('Jamaica', 'JM', 'JAM'),
+    ('Dominican Republic', 'DO', 'DOM'),
+    ('Haiti', 'HT', 'HTI'),
+    ('Brazil', 'BR', 'BRA'),
+    ('Argentina', 'AR', 'ARG'),
+    ('Chile', 'CL', 'CHL'),
+    ('Colombia', 'CO', 'COL'),
+    ('Peru', 'PE', 'PER');
+
+CREATE TEMPORARY TABLE iso_aliases (
+    alias VARCHAR NOT NULL,
+    iso_alpha3 VARCHAR(3) NOT NULL
+);
+
+INSERT INTO iso_aliases VALUES
+    ('united states of america', 'USA'),
+    ('america', 'USA'),
+    ('united kingdom of great britain and northern ireland', 'GBR'),
+    ('great britain', 'GBR'),
+    ('britain', 'GBR'),
+    ('uk', 'GBR'),
+    ('england', 'GBR'),
+    ('scotland', 'GBR'),
+    ('wales', 'GBR'),
+    ('northern ireland', 'GBR'),
+    ('deutschland', 'DEU'),
+    ('españa', 'ESP'),
+    ('the netherlands', 'NLD'),
+    ('holland', 'NLD'),
+    ('republic of ireland', 'IRL'),
+    ('czech republic', 'CZE'),
+    ('türkiye', 'TUR'),
+    ('turkiye', 'TUR'),
+    ('russian federation', 'RUS'),
+    ('uae', 'ARE'),
+    ('people''s republic of china', 'CHN'),
+    ('prc', 'CHN'),
+    ('hong kong sar', 'HKG'),
+    ('republic of korea', 'KOR'),
+    ('korea, republic of', 'KOR'),
+    ('viet nam', 'VNM'),
+    ('méxico', 'MEX'),
+    ('brasil', 'BRA');
+
+-- Existing countries and the ISO country each one names
+CREATE TEMPORARY TABLE country_matches AS
+SELECT countries.id, countries.country_name, iso_countries.iso_alpha3
+FROM countries
+JOIN iso_countries ON lower(regexp_replace(replace(trim(countries.country_name), '.', ''), '\s+', ' ', 'g'))
+    IN (lower(iso_countries.country_name), lower(iso_countries.iso_alpha2), lower(iso_countries.iso_alpha3))
+    OR lower(regexp_replace(replace(trim(countries.country_name), '.', ''), '\s+', ' ', 'g'))
+    IN (SELECT alias FROM iso_aliases WHERE iso_aliases.iso_alpha3 = iso_countries.iso_alpha3);
+
+-- Duplicates, e.g. "USA" and "United States", are merged into one row,
+-- preferring the row already using the ISO name
+CREATE TEMPORARY TABLE country_merges AS
+SELECT m.id AS from_id, m.country_name AS from_name, kept.id AS to_id
+FROM country_matches m
+JOIN LATERAL (
+    SELECT k.id FROM country_matches k
+    JOIN iso_countries i ON i.iso_alpha3 = k.iso_alpha3
+    WHERE k.iso_alpha3 = m.iso_alpha3
+    ORDER BY lower(k.country_name) = lower(i.country_name) DESC, k.id
+    LIMIT 1
+) kept ON TRUE;
+
+UPDATE places SET country_id = m.to_id
+FROM country_merges m WHERE places.country_id = m.from_id AND m.from_id <> m.to_id;
+
+UPDATE persons SET travel_document_issuer_id = m.to_id
+FROM country_merges m WHERE persons.travel_document_issuer_id = m.from_id AND m.from_id <> m.to_id;
+
+UPDATE postal_addresses SET address_country_id = m.to_id
+FROM country_merges m WHERE postal_addresses.address_country_id = m.from_id AND m.from_id <> m.to_id;
+
+DELETE FROM countries
+USING country_merges m WHERE countries.id = m.from_id AND m.from_id <> m.to_id;
+
+UPDATE countries SET
+    country_name = iso_countries.country_name,
+    iso_alpha2 = iso_countries.iso_alpha2,
+    iso_alpha3 = iso_countries.iso_alpha3
+FROM country_matches
+JOIN iso_countries ON iso_countries.iso_alpha3 = country_matches.iso_alpha3
+WHERE countries.id = country_matches.id;
+
+INSERT INTO countries (country_name, risk_rate, iso_alpha2, iso_alpha3)
+SELECT country_name, 0.03, iso_alpha2, iso_alpha3 FROM iso_countries
+WHERE iso_alpha3 NOT IN (SELECT iso_alpha3 FROM countries WHERE iso_alpha3 IS NOT NULL);
+
+INSERT INTO country_aliases (country_id, alias)
+SELECT countries.id, iso_aliases.alias
+FROM iso_aliases JOIN countries ON countries.iso_alpha3 = iso_aliases.iso_alpha3
+ON CONFLICT (alias) DO NOTHING;
+
+-- Keep the names merged or renamed rows were known by
+INSERT INTO country_aliases (country_id, alias)
+SELECT DISTINCT m.to_id, lower(regexp_replace(replace(trim(m.from_name), '.', ''), '\s+', ' ', 'g'))
+FROM country_merges m
+JOIN countries ON countries.id = m.to_id
+WHERE lower(regexp_replace(replace(trim(m.from_name), '.', ''), '\s+', ' ', 'g'))
+    NOT IN (lower(countries.country_name), lower(countries.iso_alpha2), lower(countries.iso_alpha3))
+ON CONFLICT (alias) DO NOTHING;
+
+DROP TABLE iso_countries, iso_aliases, country_matches, country_merges;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+-- Other spellings of a place, stored normalized: lower case, no accents or
+-- punctuation, single spaces. Matched within the place's country.
+CREATE TABLE IF NOT EXISTS place_aliases (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    place_id UUID NOT NULL REFERENCES places (id) ON DELETE CASCADE,
+    alias VARCHAR NOT NULL,
+    UNIQUE (place_id, alias)
+);
+
+CREATE INDEX IF NOT EXISTS place_aliases_alias_idx ON place_aliases (alias);
@@ -0,0 +1,7 @@
+DROP INDEX IF EXISTS persons_birth_date_idx;
+
+DROP TABLE IF EXISTS person_match_reviews;
+
+DROP TYPE IF EXISTS person_match_status_enum;
+
+DROP TABLE IF EXISTS prior_travel_documents;
@@ -0,0 +1,32 @@
+-- Your SQL goes here
+
+-- Documents a person travelled on before, e.g. an expired passport, so a
+-- renewed document still matches its holder
+CREATE TABLE IF NOT EXISTS prior_travel_documents (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    person_id UUID NOT NULL REFERENCES persons (id) ON DELETE CASCADE,
+    travel_document_id VARCHAR NOT NULL,
+    travel_document_issuer_id UUID NOT NULL REFERENCES countries (id),
+    recorded_at TIMESTAMP NOT NULL DEFAULT now(),
+    UNIQUE (person_id, travel_document_id, travel_document_issuer_id)
+);
+
+CREATE TYPE person_match_status_enum AS ENUM ('pending', 'merged', 'dismissed');
+
+-- Uncertain matches between a newly created person and an existing one.
+-- Not foreign keys so decisions are kept after a merge deletes the duplicate.
+CREATE TABLE IF NOT EXISTS person_match_reviews (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    person_id UUID NOT NULL,
+    candidate_id UUID NOT NULL,
+    score DOUBLE PRECISION NOT NULL,
+    reasons VARCHAR[] NOT NULL DEFAULT '{}',
+    status person_match_status_enum NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    reviewed_by UUID,
+    reviewed_at TIMESTAMP,
+    UNIQUE (person_id, candidate_id)
+);
+
+-- Candidates are found by birth date
+CREATE INDEX IF NOT EXISTS persons_birth_date_idx ON persons (birth_date);
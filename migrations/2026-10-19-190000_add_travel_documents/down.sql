@@ -0,0 +1,40 @@
+ALTER TABLE persons
+    ADD COLUMN travel_document_id VARCHAR,
+    ADD COLUMN travel_document_issuer_id UUID REFERENCES countries (id);
+
+-- The most recently presented active document becomes the person's document again
+UPDATE persons p
+SET travel_document_id = d.document_number,
+    travel_document_issuer_id = d.issuer_id
+FROM (
+    SELECT DISTINCT ON (person_id) person_id, document_number, issuer_id
+    FROM travel_documents
+    ORDER BY person_id, (status = 'active') DESC, last_presented_at DESC
+) d
+WHERE d.person_id = p.id;
+
+ALTER TABLE persons
+    ALTER COLUMN travel_document_id SET NOT NULL,
+    ALTER COLUMN travel_document_issuer_id SET NOT NULL;
+
+CREATE TABLE IF NOT EXISTS prior_travel_documents (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    person_id UUID NOT NULL REFERENCES persons (id) ON DELETE CASCADE,
+    travel_document_id VARCHAR NOT NULL,
+    travel_document_issuer_id UUID NOT NULL REFERENCES countries (id),
+    recorded_at TIMESTAMP NOT NULL DEFAULT now(),
+    UNIQUE (person_id, travel_document_id, travel_document_issuer_id)
+);
+
+INSERT INTO prior_travel_documents (person_id, travel_document_id, travel_document_issuer_id, recorded_at)
+SELECT d.person_id, d.document_number, d.issuer_id, d.last_presented_at
+FROM travel_documents d
+JOIN persons p ON p.id = d.person_id
+WHERE d.document_number <> p.travel_document_id OR d.issuer_id <> p.travel_document_issuer_id
+ON CONFLICT DO NOTHING;
+
+DROP TABLE IF EXISTS travel_documents;
+
+DROP TYPE IF EXISTS travel_document_status_enum;
+
+DROP TYPE IF EXISTS travel_document_type_enum;
@@ -0,0 +1,43 @@
+-- Your SQL goes here
+
+CREATE TYPE travel_document_type_enum AS ENUM (
+    'passport',
+    'nexus',
+    'permanent_resident_card',
+    'other'
+);
+
+CREATE TYPE travel_document_status_enum AS ENUM ('active', 'replaced', 'lost', 'revoked');
+
+-- Every document a person has travelled on. Expiry is checked against the
+-- expires_on date, status records what happened to the document.
+CREATE TABLE IF NOT EXISTS travel_documents (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    person_id UUID NOT NULL REFERENCES persons (id) ON DELETE CASCADE,
+    document_type travel_document_type_enum NOT NULL DEFAULT 'passport',
+    document_number VARCHAR NOT NULL,
+    issuer_id UUID NOT NULL REFERENCES countries (id),
+    expires_on DATE,
+    status travel_document_status_enum NOT NULL DEFAULT 'active',
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    last_presented_at TIMESTAMP NOT NULL DEFAULT now(),
+    UNIQUE (person_id, document_type, document_number, issuer_id)
+);
+
+CREATE INDEX IF NOT EXISTS travel_documents_document_number_idx ON travel_documents (document_number);
+
+-- Existing documents were all recorded as passports
+INSERT INTO travel_documents (person_id, document_type, document_number, issuer_id, status, created_at, last_presented_at)
+SELECT id, 'passport', travel_document_id, travel_document_issuer_id, 'active', created_at, created_at
+FROM persons;
+
+INSERT INTO travel_documents (person_id, document_type, document_number, issuer_id, status, created_at, last_presented_at)
+SELECT person_id, 'passport', travel_document_id, travel_document_issuer_id, 'replaced', recorded_at, recorded_at
+FROM prior_travel_documents
+ON CONFLICT DO NOTHING;
+
+DROP TABLE IF EXISTS prior_travel_documents;
+
+ALTER TABLE persons
+    DROP COLUMN travel_document_id,
+    DROP COLUMN travel_document_issuer_id;
@@ -0,0 +1,5 @@
+ALTER TABLE persons
+    DROP COLUMN IF EXISTS citizenship_ids,
+    DROP COLUMN IF EXISTS residency_status;
+
+DROP TYPE IF EXISTS residency_status_enum;
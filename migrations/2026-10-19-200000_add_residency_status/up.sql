@@ -0,0 +1,31 @@
+-- Your SQL goes here
+
+CREATE TYPE residency_status_enum AS ENUM ('citizen', 'permanent_resident', 'foreign_national');
+
+-- Status in Canada and countries of citizenship, as declared or shown by documents.
+-- NULL status is not yet known.
+ALTER TABLE persons
+    ADD COLUMN residency_status residency_status_enum,
+    ADD COLUMN citizenship_ids UUID[] NOT NULL DEFAULT '{}';
+
+-- Passport issuers are countries of citizenship
+UPDATE persons p
+SET citizenship_ids = ARRAY(
+    SELECT DISTINCT d.issuer_id
+    FROM travel_documents d
+    WHERE d.person_id = p.id AND d.document_type = 'passport'
+);
+
+UPDATE persons p
+SET residency_status = 'citizen'
+FROM countries c
+WHERE c.iso_alpha2 = 'CA' AND c.id = ANY (p.citizenship_ids);
+
+UPDATE persons p
+SET residency_status = 'permanent_resident'
+FROM travel_documents d
+JOIN countries c ON c.id = d.issuer_id AND c.iso_alpha2 = 'CA'
+WHERE d.person_id = p.id
+    AND d.document_type = 'permanent_resident_card'
+    AND d.status = 'active'
+    AND p.residency_status IS NULL;
@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS guardian_consents;
+
+DROP TABLE IF EXISTS travel_group_relationships;
+
+DROP TYPE IF EXISTS relationship_type_enum;
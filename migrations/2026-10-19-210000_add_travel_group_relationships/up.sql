@@ -0,0 +1,43 @@
+-- Your SQL goes here
+
+-- Groups submitted through PILQuery were only recorded on trips and persons
+INSERT INTO travel_groups (id)
+SELECT DISTINCT travel_group_id FROM trips
+ON CONFLICT DO NOTHING;
+
+INSERT INTO travel_groups (id)
+SELECT DISTINCT travel_group_id FROM persons
+ON CONFLICT DO NOTHING;
+
+CREATE TYPE relationship_type_enum AS ENUM ('guardian_of', 'spouse', 'household_member');
+
+-- person_id is the guardian of related_person_id for guardian_of,
+-- spouse and household_member go both ways
+CREATE TABLE IF NOT EXISTS travel_group_relationships (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    travel_group_id UUID NOT NULL REFERENCES travel_groups (id) ON DELETE CASCADE,
+    person_id UUID NOT NULL REFERENCES persons (id) ON DELETE CASCADE,
+    related_person_id UUID NOT NULL REFERENCES persons (id) ON DELETE CASCADE,
+    relationship_type relationship_type_enum NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    CHECK (person_id <> related_person_id),
+    UNIQUE (travel_group_id, person_id, related_person_id, relationship_type)
+);
+
+CREATE INDEX IF NOT EXISTS travel_group_relationships_related_person_id_idx
+    ON travel_group_relationships (related_person_id);
+
+-- Consent for a minor to travel, given by a guardian who may not be travelling
+CREATE TABLE IF NOT EXISTS guardian_consents (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    travel_group_id UUID NOT NULL REFERENCES travel_groups (id) ON DELETE CASCADE,
+    minor_id UUID NOT NULL REFERENCES persons (id) ON DELETE CASCADE,
+    guardian_name VARCHAR NOT NULL,
+    guardian_contact VARCHAR,
+    -- Set when the guardian is a known person
+    guardian_person_id UUID REFERENCES persons (id) ON DELETE SET NULL,
+    recorded_by UUID NOT NULL,
+    recorded_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS guardian_consents_minor_id_idx ON guardian_consents (minor_id);
@@ -0,0 +1,12 @@
+-- Operations registered by Admins. When PERSISTED_QUERIES_ONLY is set only
+-- these documents can be run by roles other than Admin.
+CREATE TABLE IF NOT EXISTS persisted_queries (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    -- Hex SHA-256 of the document, as sent in extensions.persistedQuery.sha256Hash
+    sha256_hash VARCHAR NOT NULL UNIQUE,
+    document TEXT NOT NULL,
+    operation_name VARCHAR,
+    description VARCHAR,
+    created_by UUID NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
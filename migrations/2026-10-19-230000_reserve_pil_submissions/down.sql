@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE pil_submissions DROP COLUMN IF EXISTS completed;
+ALTER TABLE pil_submissions DROP COLUMN IF EXISTS payload_hash;
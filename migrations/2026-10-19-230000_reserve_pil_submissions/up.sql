@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- Keys are reserved before a submission is processed and completed after
+ALTER TABLE pil_submissions ADD COLUMN payload_hash VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE pil_submissions ADD COLUMN completed BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE travel_responses DROP COLUMN IF EXISTS person_created;
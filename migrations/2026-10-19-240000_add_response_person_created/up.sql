@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+-- Whether PILQuery created the traveller's person rather than matching one
+ALTER TABLE travel_responses ADD COLUMN person_created BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,202 @@
+//! Replays recorded PIL requests and writes the engine's responses to JSONL
+//! so that the output of two engine versions can be diffed.
+//!
+//! Each line of the input file is one batch, either a JSON array of
+//! TravelDataInput objects or the GraphQL variables of a PILQuery request
+//! (`{"data": [...]}`, as in mutation_vec.graphql).
+//!
+//! Usage:
+//!     replay <input.jsonl> <output.jsonl> [--user-id UUID]
+//!     replay <input.jsonl> <output.jsonl> --server URL --token JWT
+//!
+//! Without --server the batches are processed in-process against DATABASE_URL.
+//! Each output line is `{"line": n, "responses": [...]}` or `{"line": n, "error": "..."}`.
+//! PILResponse ids, date_time and random_testing_referral will differ between
+//! runs and should be ignored when diffing.
+
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::process;
+
+use serde_json::json;
+use uuid::Uuid;
+
+use health_rules_engine::common_utils::Role;
+use health_rules_engine::database::POOL;
+use health_rules_engine::graphql::{create_schema_with_context, execute_pil_query_json,
+    read_pil_query_data, AppSchema, PIL_QUERY_DOCUMENT};
+use health_rules_engine::models::PILResponse;
+
+enum Target {
+    InProcess(AppSchema, Uuid),
+    Server(String, String),
+}
+
+struct Args {
+    input: String,
+    output: String,
+    server: Option<String>,
+    token: Option<String>,
+    user_id: Uuid,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut positional: Vec<String> = Vec::new();
+    let mut server = None;
+    let mut token = None;
+    let mut user_id = Uuid::nil();
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--server" => server = args.next(),
+            "--token" => token = args.next(),
+            "--user-id" => {
+                let value = args.next().unwrap_or_default();
+                user_id = Uuid::parse_str(&value)
+                    .map_err(|_| format!("Invalid --user-id: {}", value))?;
+            },
+            _ => positional.push(arg),
+        }
+    }
+
+    if positional.len() != 2 {
+        return Err("Expected an input and an output file".to_string());
+    }
+
+    if server.is_some() && token.is_none() {
+        return Err("--server requires --token".to_string());
+    }
+
+    Ok(Args {
+        output: positional.pop().unwrap(),
+        input: positional.pop().unwrap(),
+        server,
+        token,
+        user_id,
+    })
+}
+
+/// Accepts either a bare array of travellers or PILQuery variables
+fn travellers_from_line(line: &str) -> Result<serde_json::Value, String> {
+    let mut value: serde_json::Value = serde_json::from_str(line)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+
+    if value.is_array() {
+        return Ok(value);
+    }
+
+    match value.get_mut("data").map(|d| d.take()) {
+        Some(data) if data.is_array() => Ok(data),
+        _ => Err("Expected an array of TravelData or {\"data\": [...]}".to_string()),
+    }
+}
+
+async fn post_to_server(
+    client: &reqwest::Client,
+    url: &str,
+    token: &str,
+    travellers: serde_json::Value,
+) -> Result<Vec<PILResponse>, String> {
+
+    let body = json!({
+        "query": PIL_QUERY_DOCUMENT,
+        "variables": { "data": travellers },
+    });
+
+    let response: serde_json::Value = client
+        .post(url)
+        .bearer_auth(token)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Invalid response: {}", e))?;
+
+    if let Some(errors) = response.get("errors").and_then(|e| e.as_array()) {
+        if !errors.is_empty() {
+            let messages: Vec<String> = errors
+                .iter()
+                .map(|e| e["message"].as_str().unwrap_or_default().to_owned())
+                .collect();
+
+            return Err(messages.join("; "));
+        }
+    }
+
+    read_pil_query_data(response["data"].clone())
+}
+
+#[actix_rt::main]
+async fn main() {
+    dotenv::dotenv().ok();
+
+    let args = match parse_args() {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Usage: replay <input.jsonl> <output.jsonl> [--user-id UUID] [--server URL --token JWT]");
+            process::exit(2);
+        }
+    };
+
+    let target = match (args.server, args.token) {
+        (Some(url), Some(token)) => Target::Server(url, token),
+        _ => match create_schema_with_context(POOL.clone()) {
+            Ok(schema) => Target::InProcess(schema, args.user_id),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        },
+    };
+
+    let input = File::open(&args.input).expect("Unable to open input file");
+    let output = File::create(&args.output).expect("Unable to create output file");
+    let mut writer = BufWriter::new(output);
+
+    let client = reqwest::Client::new();
+
+    let mut replayed = 0;
+    let mut failed = 0;
+
+    for (i, line) in BufReader::new(input).lines().enumerate() {
+        let line = line.expect("Unable to read input file");
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let result = match travellers_from_line(&line) {
+            Ok(travellers) => match &target {
+                Target::InProcess(schema, user_id) => {
+                    // Replays run with Operator rights, as PILQuery requires
+                    execute_pil_query_json(schema, Role::Operator, *user_id, travellers).await
+                },
+                Target::Server(url, token) => post_to_server(&client, url, token, travellers).await,
+            },
+            Err(e) => Err(e),
+        };
+
+        let record = match result {
+            Ok(responses) => {
+                replayed += 1;
+                json!({ "line": i + 1, "responses": responses })
+            },
+            Err(e) => {
+                failed += 1;
+                json!({ "line": i + 1, "error": e })
+            },
+        };
+
+        writeln!(writer, "{}", record).expect("Unable to write output file");
+    }
+
+    writer.flush().expect("Unable to write output file");
+
+    println!("Replayed {} batches, {} failed", replayed, failed);
+}
@@ -1,6 +1,8 @@
 use async_graphql::Guard;
 use async_graphql::*;
 
+use crate::errors::error_handler::CustomError;
+
 #[derive(Eq, PartialEq, Display, EnumString, Copy, Clone)]
 #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 pub enum Role {
@@ -10,6 +12,23 @@ pub enum Role {
     User,
 }
 
+impl Role {
+    /// Same rule as is_analyst for callers outside a GraphQL context
+    pub fn is_analyst(&self) -> bool {
+        *self == Role::Admin || *self == Role::Analyst
+    }
+
+    /// Same rule as is_operator for callers outside a GraphQL context
+    pub fn is_operator(&self) -> bool {
+        *self == Role::Admin || *self == Role::Operator
+    }
+
+    /// Same rule as is_admin for callers outside a GraphQL context
+    pub fn is_admin(&self) -> bool {
+        *self == Role::Admin
+    }
+}
+
 pub struct RoleGuard {
     pub role: Role,
 }
@@ -27,10 +46,11 @@ impl Guard for RoleGuard {
         if context.data_opt::<Role>() == Some(&Role::Admin) || context.data_opt::<Role>() == Some(&self.role) {
             Ok(())
         } else {
-            let guard_error = context.data_opt::<jsonwebtoken::errors::Error>().clone();
+            let guard_error = context.data_opt::<CustomError>().clone();
             match guard_error {
-                Some(e) => return Err(format!("{:?}", e.kind()).into()),
-                None => return Err(format!("Access denied: {} role required", &self.role).into())
+                Some(e) => return Err(e.extend()),
+                None => return Err(CustomError::Unauthorized(
+                    format!("Access denied: {} role required", &self.role)).extend())
             }
         }
     }
@@ -39,18 +59,16 @@ impl Guard for RoleGuard {
 /// Field will be visible to users with Role::Admin and
 /// Role::Analyst
 pub fn is_analyst(ctx: &Context<'_>) -> bool {
-    ctx.data_opt::<Role>() == Some(&Role::Admin) ||
-    ctx.data_opt::<Role>() == Some(&Role::Analyst)
+    ctx.data_opt::<Role>().map(|r| r.is_analyst()).unwrap_or(false)
 }
 
 /// Field will be visible to users with Role::Admin and
 /// Role::Analyst
 pub fn is_operator(ctx: &Context<'_>) -> bool {
-    ctx.data_opt::<Role>() == Some(&Role::Admin) ||
-    ctx.data_opt::<Role>() == Some(&Role::Operator)
+    ctx.data_opt::<Role>().map(|r| r.is_operator()).unwrap_or(false)
 }
 
 /// Field will only be visible to users with Role::Admin
 pub fn is_admin(ctx: &Context<'_>) -> bool {
-    ctx.data_opt::<Role>() == Some(&Role::Admin)
+    ctx.data_opt::<Role>().map(|r| r.is_admin()).unwrap_or(false)
 }
\ No newline at end of file
@@ -1,4 +1,45 @@
+use crate::models::{EntryRules, PersonMatchRules};
+
 // Constants
 pub const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 pub const TOKEN_DURATION: i64 = 7200; // Duration for JWT sign-in in seconds
-pub const MANDATORY_TESTING_RATE: f64 = 0.01; // fraction of referrals to mandatory testing
\ No newline at end of file
+pub const MANDATORY_TESTING_RATE: f64 = 0.01; // fraction of referrals to mandatory testing
+pub const IMPORT_BATCH_SIZE: usize = 50; // default number of travellers sent to PILQuery per batch on bulk import
+pub const MAX_IMPORT_SIZE: usize = 10 * 1024 * 1024; // largest manifest accepted on bulk import, in bytes
+pub const EXPORT_PAGE_SIZE: i64 = 500; // rows loaded from the database per chunk of a streamed export
+pub const MAX_XLSX_ROWS: i64 = 100_000; // largest XLSX export, which is built in memory; larger exports must use CSV
+pub const IDEMPOTENCY_WINDOW: i64 = 86400; // Duration in seconds a PILQuery idempotency key returns the original responses
+pub const COUNTRIES_VISITED_DAYS: i64 = 14; // Days of travel history considered when listing countries a traveller visited
+pub const MAX_COUNTRIES_VISITED_DAYS: i64 = 365; // longest history accepted by Person::countries_visited
+pub const DEFAULT_PAGE_SIZE: usize = 50; // items per page of a list query when neither first nor last is given
+pub const MAX_PAGE_SIZE: usize = 500; // largest first or last accepted by a list query
+pub const HOME_COUNTRY_CODE: &str = "CA"; // ISO 3166 code of the country whose documents show citizenship or permanent residence
+pub const MAX_QUERY_DEPTH: usize = 12; // deepest selection accepted in a GraphQL query
+pub const MAX_QUERY_COMPLEXITY: usize = 10000; // highest cost accepted in a GraphQL query, see graphql::limits
+pub const NESTED_LIST_COST: usize = 5; // assumed length of a list nested in another object when costing a query
+pub const RATE_LIMIT_REQUESTS: u32 = 120; // GraphQL requests accepted from one user per RATE_LIMIT_WINDOW
+pub const RATE_LIMIT_WINDOW: u64 = 60; // Duration in seconds of a rate limit window
+
+// Matching submitted travellers to existing persons, see PersonMatchRules
+pub const PERSON_MATCH_RULES: PersonMatchRules = PersonMatchRules {
+    document_weight: 0.5,
+    family_name_weight: 0.3,
+    given_name_weight: 0.2,
+    transliterate_names: true,
+    fuzzy_names: true,
+    use_inactive_documents: true,
+    // Same document and family name, as matched before fuzzy rules
+    auto_match_score: 0.8,
+    // e.g. a renewed passport with the same names and birth date
+    review_score: 0.5,
+};
+
+// Entry requirements by status in Canada, see EntryRules
+pub const ENTRY_RULES: EntryRules = EntryRules {
+    fully_vaccinated_days: 14,
+    quarantine_unvaccinated_residents: true,
+    deny_unvaccinated_foreign_nationals: true,
+    deny_foreign_nationals_without_documents: true,
+    age_of_majority: 18,
+    household_quarantine: true,
+};
@@ -1,4 +1,7 @@
 use std::{io::stdin};
+use std::sync::Arc;
+use actix_web::web;
+use async_graphql::{Context, ErrorExtensions, FieldResult};
 use chrono::prelude::*;
 use chrono::Duration;
 use diesel::pg::PgConnection;
@@ -23,6 +26,8 @@ use crate::models::{Country, NewCountry, NewPerson, NewPlace,
     NewVaccine, Person, Place, PublicHealthProfile, TravelGroup, 
     Trip, Vaccine, Vaccination, CovidTest, SlimAddress, NewPostalAddress, PostalAddress};
 use crate::models::{User, UserData, InsertableUser};
+use crate::models::{TripLeg, NewTripLeg};
+use crate::models::{TravelDocument, NewTravelDocument};
 
 embed_migrations!();
 
@@ -68,14 +73,53 @@ pub fn init() {
 
             pre_populate_db_schema(&conn);
 
-            populate_db_with_demo_data(&conn);
+            populate_db_with_demo_data(&conn)
+                .expect("Unable to add demo data");
         }
     }
 }
 
 pub fn connection() -> Result<DbConnection, CustomError> {
     POOL.get()
-        .map_err(|e| CustomError::new(500, format!("Failed getting db connection: {}", e)))
+        .map_err(|e| CustomError::Internal(format!("Failed getting db connection: {}", e)))
+}
+
+#[derive(Clone)]
+/// Runs database work on the blocking thread pool, so synchronous Diesel
+/// calls don't stall the async workers serving other requests.
+pub struct Repository {
+    pool: Arc<PostgresPool>,
+}
+
+impl Repository {
+    pub fn new(pool: Arc<PostgresPool>) -> Self {
+        Repository { pool }
+    }
+
+    pub fn from_context(context: &Context<'_>) -> FieldResult<Repository> {
+        Ok(Repository::new(context.data::<Arc<PostgresPool>>()?.clone()))
+    }
+
+    /// Runs f with a pooled connection and waits for it without blocking
+    pub async fn run<T, F>(&self, f: F) -> FieldResult<T>
+    where
+        F: FnOnce(&PgConnection) -> FieldResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+
+        let res = web::block(move || {
+            let conn = pool.get()
+                .map_err(|e| CustomError::Internal(format!("Failed getting db connection: {}", e)).extend())?;
+
+            f(&*conn)
+        }).await;
+
+        match res {
+            Ok(r) => r,
+            Err(e) => Err(CustomError::Internal(format!("Database task failed: {}", e)).extend()),
+        }
+    }
 }
 
 /// Creates basic Country, Place and Vaccine entries in the database
@@ -86,14 +130,15 @@ pub fn pre_populate_db_schema(conn: &PgConnection) {
     new_countries.push(NewCountry::new("United Kingdom".to_string(), 0.05));
     new_countries.push(NewCountry::new("Canada".to_string(), 0.03));
     new_countries.push(NewCountry::new("Singapore".to_string(), 0.02));
-    new_countries.push(NewCountry::new("USA".to_string(), 0.04));
+    new_countries.push(NewCountry::new("United States".to_string(), 0.04));
     new_countries.push(NewCountry::new("France".to_string(), 0.03));
     new_countries.push(NewCountry::new("Brazil".to_string(), 0.06));
    
     let mut countries: Vec<Country> = Vec::new();
 
     for np in new_countries {
-        let c = Country::create(conn, &np).unwrap();
+        // Seeded with ISO codes by migration, so only the risk rate is set here
+        let c = Country::upsert(conn, &np).unwrap();
         countries.push(c);
     };
 
@@ -208,20 +253,20 @@ pub fn create_admin_user(conn: &PgConnection) {
 
 /// Testing function to generate dummy data when resetting the database
 /// Started adding unique names to countries, so only works once when DB is reset.
-pub fn populate_db_with_demo_data(conn: &PgConnection) {
+pub fn populate_db_with_demo_data(conn: &PgConnection) -> Result<(), CustomError> {
 
     // Set up RNG
     let mut rng = thread_rng();
 
     // Load country, place and vaccine data
     
-    let country_hash = Country::load_into_hash(&conn);
+    let country_hash = Country::load_into_hash(&conn)?;
 
     let countries = country_hash.values().cloned().collect::<Vec<Country>>();
 
     let canada_id = *&country_hash.iter().find(|h| h.1.country_name == "Canada".to_string()).unwrap().0;
 
-    let places_hash = Place::load_into_hash(&conn);
+    let places_hash = Place::load_into_hash(&conn)?;
 
     let mut origins: Vec<Place> = Vec::new();
     let mut destinations: Vec<Place> = Vec::new();
@@ -234,7 +279,7 @@ pub fn populate_db_with_demo_data(conn: &PgConnection) {
         }
     };
 
-    let vaccine_hash = Vaccine::load_into_hash(&conn);
+    let vaccine_hash = Vaccine::load_into_hash(&conn)?;
     let vaccines = vaccine_hash.values().cloned().collect::<Vec<Vaccine>>();
 
     // Populate with fake population data
@@ -253,11 +298,13 @@ pub fn populate_db_with_demo_data(conn: &PgConnection) {
 
             // Create person
             let person = NewPerson::fake(
-                country.id,
                 travel_group.id,
             );
 
             let created_p = Person::create(conn, &person).expect("Unable to create person");
+
+            let _document = TravelDocument::create(conn, &NewTravelDocument::fake(created_p.id, country.id))
+                .expect("Unable to create travel document");
                 
             // Create trip
             let origin  = origins.choose(&mut rng).unwrap();
@@ -270,7 +317,10 @@ pub fn populate_db_with_demo_data(conn: &PgConnection) {
                 &destination.id
             );
             
-            let _t = Trip::create(conn, &nt).expect("Unable to create trip");
+            let t = Trip::create(conn, &nt).expect("Unable to create trip");
+
+            let _legs = TripLeg::create_all(conn, &[NewTripLeg::from_trip(&t)])
+                .expect("Unable to create trip leg");
 
             // Create public health profile
             let profile = NewPublicHealthProfile::new(
@@ -338,4 +388,5 @@ pub fn populate_db_with_demo_data(conn: &PgConnection) {
         }
     }
 
+    Ok(())
 }
\ No newline at end of file
@@ -1,73 +1,135 @@
 pub mod error_handler {
-    
+
     use actix_web::http::StatusCode;
     use actix_web::{HttpResponse, ResponseError};
-    use diesel::result::Error as DieselError;
+    use diesel::result::{DatabaseErrorKind, Error as DieselError};
     use serde::Deserialize;
     use async_graphql::*;
     use std::fmt;
 
-    #[derive(Debug, Deserialize)]
-    pub struct CustomError {
-        pub error_status_code: u16,
-        pub error_message: String,
+    #[derive(Debug, Clone, Deserialize)]
+    /// Domain errors for the engine. In GraphQL responses the variant is
+    /// reported in extensions.code (see ErrorExtensions below) so clients
+    /// can act on the kind of error rather than parse the message.
+    pub enum CustomError {
+        /// Record does not exist
+        NotFound(String),
+        /// Input failed validation, one message per violation
+        Validation(Vec<String>),
+        /// Missing, invalid or insufficient credentials
+        Unauthorized(String),
+        /// Vaccine name or id not in the approved list
+        UnknownVaccine(String),
+        /// Place id that is not in the system
+        UnknownPlace(String),
+        /// Country name, code or alias that is not in the system
+        UnknownCountry(String),
+        /// Duplicate or conflicting record
+        Conflict(String),
+        /// Too many requests from one user, see RATE_LIMIT_REQUESTS
+        RateLimited(String),
+        /// Database or other server failure
+        Internal(String),
     }
 
     impl CustomError {
-        pub fn new(error_status_code: u16, error_message: String) -> Self {
-            CustomError {
-                error_status_code,
-                error_message,
+        /// Value of extensions.code in GraphQL errors
+        pub fn code(&self) -> &'static str {
+            match self {
+                CustomError::NotFound(_) => "NOT_FOUND",
+                CustomError::Validation(_) => "VALIDATION",
+                CustomError::Unauthorized(_) => "UNAUTHORIZED",
+                CustomError::UnknownVaccine(_) => "UNKNOWN_VACCINE",
+                CustomError::UnknownPlace(_) => "UNKNOWN_PLACE",
+                CustomError::UnknownCountry(_) => "UNKNOWN_COUNTRY",
+                CustomError::Conflict(_) => "CONFLICT",
+                CustomError::RateLimited(_) => "RATE_LIMITED",
+                CustomError::Internal(_) => "INTERNAL",
+            }
+        }
+
+        pub fn status_code(&self) -> StatusCode {
+            match self {
+                CustomError::NotFound(_) => StatusCode::NOT_FOUND,
+                CustomError::Validation(_) => StatusCode::BAD_REQUEST,
+                CustomError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+                CustomError::UnknownVaccine(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                CustomError::UnknownPlace(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                CustomError::UnknownCountry(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                CustomError::Conflict(_) => StatusCode::CONFLICT,
+                CustomError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+                CustomError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
             }
         }
     }
 
     impl fmt::Display for CustomError {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            f.write_str(&format!("{} = {}", self.error_message, self.error_status_code))
+            match self {
+                CustomError::NotFound(m) => write!(f, "Not found: {}", m),
+                CustomError::Validation(v) => write!(f, "Validation failed: {}", v.join("; ")),
+                CustomError::Unauthorized(m) => write!(f, "Unauthorized: {}", m),
+                CustomError::UnknownVaccine(m) => write!(f, "Unknown vaccine: {}", m),
+                CustomError::UnknownPlace(m) => write!(f, "Unknown place: {}", m),
+                CustomError::UnknownCountry(m) => write!(f, "Unknown country: {}", m),
+                CustomError::Conflict(m) => write!(f, "Conflict: {}", m),
+                CustomError::RateLimited(m) => write!(f, "Rate limited: {}", m),
+                CustomError::Internal(m) => write!(f, "Internal error: {}", m),
+            }
+        }
+    }
+
+    impl ErrorExtensions for CustomError {
+        fn extend(&self) -> Error {
+            Error::new(format!("{}", self)).extend_with(|_, e| {
+                e.set("code", self.code());
+
+                if let CustomError::Validation(violations) = self {
+                    e.set("violations", violations.clone());
+                }
+            })
         }
     }
 
     impl From<DieselError> for CustomError {
         fn from(error: DieselError) -> CustomError {
             match error {
+                DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, err) => {
+                    CustomError::Conflict(err.message().to_string())
+                },
+                DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, err) => {
+                    CustomError::Validation(vec![err.message().to_string()])
+                },
                 DieselError::DatabaseError(_, err) => {
-                    CustomError::new(501, err.message().to_string())
+                    CustomError::Internal(err.message().to_string())
                 },
                 DieselError::NotFound => {
-                    CustomError::new(502, "Record not found".to_string())
+                    CustomError::NotFound("Record not found".to_string())
                 },
-                err => CustomError::new(500, format!("Unknown Diesel Error: {}", err)),
+                err => CustomError::Internal(format!("Unknown Diesel Error: {}", err)),
             }
         }
     }
 
     impl From<FieldError> for CustomError {
         fn from(error: FieldError) -> CustomError {
-            CustomError {
-                error_status_code: 511,
-                error_message: format!("GraphQL FieldError: {:?}", error).to_string(),
-            }
+            CustomError::Internal(format!("GraphQL FieldError: {:?}", error))
         }
     }
 
     // Custom Error Codes
     impl ResponseError for CustomError {
-        fn error_response(&self) -> HttpResponse {
-            let status_code = match StatusCode::from_u16(self.error_status_code) {
-                Ok(status_code) => status_code,
-                Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            };
+        fn status_code(&self) -> StatusCode {
+            CustomError::status_code(self)
+        }
 
-            match status_code.as_u16() {
-                406 => {
+        fn error_response(&self) -> HttpResponse {
+            match self {
+                CustomError::Unauthorized(_) => {
                     return HttpResponse::Found().append_header(("Location", "/not_authorized")).finish()
                 },
-                i if i > 500 => {
-                    return HttpResponse::Found().append_header(("Location","/internal_server_error")).finish()
-                },
                 _ => return HttpResponse::Found().append_header(("Location","/internal_server_error")).finish()
             };
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config_variables::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE, NESTED_LIST_COST};
+
+// Query cost. Every field costs 1 unless it loads a list, in which case its
+// selection is counted once per item it may return. The schema rejects queries
+// over MAX_QUERY_COMPLEXITY or deeper than MAX_QUERY_DEPTH before they run.
+
+/// Cost of a page of a list query, counting the largest page first or last can return
+pub fn page_cost(first: Option<i32>, last: Option<i32>, child_complexity: usize) -> usize {
+    let size = match (first, last) {
+        (None, None) => DEFAULT_PAGE_SIZE,
+        _ => first.max(last).unwrap_or(0).max(0) as usize,
+    };
+
+    size.min(MAX_PAGE_SIZE).max(1) * child_complexity + 1
+}
+
+/// Cost of an unpaged list nested in another object, such as Person::trips
+pub fn list_cost(child_complexity: usize) -> usize {
+    NESTED_LIST_COST * child_complexity + 1
+}
+
+/// Fixed window request counts per user
+pub struct RateLimiter {
+    limit: u32,
+    window: Duration,
+    /// Proxies whose X-Forwarded-For is believed when keying by address
+    trusted_proxies: Vec<IpAddr>,
+    windows: Mutex<Windows>,
+}
+
+struct Windows {
+    hits: HashMap<String, (Instant, u32)>,
+    last_sweep: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        RateLimiter {
+            limit,
+            window,
+            trusted_proxies: Vec::new(),
+            windows: Mutex::new(Windows {
+                hits: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn with_trusted_proxies(mut self, trusted_proxies: Vec<IpAddr>) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    /// Addresses in TRUSTED_PROXIES, comma separated. Unparseable entries are skipped.
+    pub fn trusted_proxies_from_env() -> Vec<IpAddr> {
+        env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|a| a.trim().parse().ok())
+            .collect()
+    }
+
+    /// Key for a request before sign in. The forwarded client address is
+    /// only used when the peer is a trusted proxy, as anyone else can set it.
+    pub fn client_key(&self, peer: Option<SocketAddr>, forwarded_for: Option<&str>) -> String {
+        match (peer, forwarded_for) {
+            (Some(peer), Some(forwarded)) if self.trusted_proxies.contains(&peer.ip()) => forwarded.to_owned(),
+            (Some(peer), _) => peer.ip().to_string(),
+            (None, _) => "unknown".to_string(),
+        }
+    }
+
+    /// Counts a request from key. Returns the seconds until key may
+    /// make another request if it is over the limit.
+    pub fn check(&self, key: &str) -> Result<(), u64> {
+        self.check_at(key, Instant::now())
+    }
+
+    pub fn check_at(&self, key: &str, now: Instant) -> Result<(), u64> {
+        let mut windows = self.windows.lock().unwrap();
+        let window = self.window;
+
+        // Forget users whose window has passed, at most once per window
+        if now.saturating_duration_since(windows.last_sweep) >= window {
+            windows.hits.retain(|_, (start, _)| now.saturating_duration_since(*start) < window);
+            windows.last_sweep = now;
+        }
+
+        let (start, count) = windows.hits.entry(key.to_owned()).or_insert((now, 0));
+
+        if now.saturating_duration_since(*start) >= window {
+            *start = now;
+            *count = 0;
+        }
+
+        if *count >= self.limit {
+            let retry_after = window.saturating_sub(now.saturating_duration_since(*start));
+            return Err(retry_after.as_secs().max(1));
+        }
+
+        *count += 1;
+
+        Ok(())
+    }
+
+    /// Number of users currently counted
+    pub fn tracked(&self) -> usize {
+        self.windows.lock().unwrap().hits.len()
+    }
+}
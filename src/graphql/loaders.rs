@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use async_graphql::*;
+use async_graphql::dataloader::Loader;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use uuid::Uuid;
+
+use crate::database::Repository;
+use crate::graphql::graphql_translate;
+use crate::models::{Person, PostalAddress, PublicHealthProfile, TravelDocument, Trip, Vaccination};
+use crate::schema::*;
+
+// Loaders batch the keys requested by every resolver at the same level of a
+// query into one SQL statement. They don't cache between requests.
+
+/// Person by id, for Trip::person
+pub struct PersonLoader(pub Repository);
+
+#[async_trait::async_trait]
+impl Loader<Uuid> for PersonLoader {
+    type Value = Person;
+    type Error = Error;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Person>, Error> {
+        let keys = keys.to_vec();
+
+        self.0.run(move |conn| {
+            let people = graphql_translate(persons::table
+                .filter(persons::id.eq_any(keys))
+                .load::<Person>(conn))?;
+
+            Ok(people.into_iter().map(|p| (p.id, p)).collect())
+        }).await
+    }
+}
+
+/// PublicHealthProfile by person id, for Person::public_health_profile
+pub struct PublicHealthProfileLoader(pub Repository);
+
+#[async_trait::async_trait]
+impl Loader<Uuid> for PublicHealthProfileLoader {
+    type Value = PublicHealthProfile;
+    type Error = Error;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, PublicHealthProfile>, Error> {
+        let keys = keys.to_vec();
+
+        self.0.run(move |conn| {
+            let profiles = graphql_translate(public_health_profiles::table
+                .filter(public_health_profiles::person_id.eq_any(keys))
+                .load::<PublicHealthProfile>(conn))?;
+
+            let mut by_person: HashMap<Uuid, PublicHealthProfile> = HashMap::new();
+
+            // A person's first profile, as a single query would return
+            for profile in profiles {
+                by_person.entry(profile.person_id).or_insert(profile);
+            }
+
+            Ok(by_person)
+        }).await
+    }
+}
+
+/// Trips by person id ordered by arrival time, for Person::trips
+pub struct TripsByPersonLoader(pub Repository);
+
+#[async_trait::async_trait]
+impl Loader<Uuid> for TripsByPersonLoader {
+    type Value = Vec<Trip>;
+    type Error = Error;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Vec<Trip>>, Error> {
+        let keys = keys.to_vec();
+
+        self.0.run(move |conn| {
+            let trips = graphql_translate(trips::table
+                .filter(trips::person_id.eq_any(keys))
+                .order((trips::arrival_time, trips::id))
+                .load::<Trip>(conn))?;
+
+            let mut by_person: HashMap<Uuid, Vec<Trip>> = HashMap::new();
+
+            for trip in trips {
+                by_person.entry(trip.person_id).or_default().push(trip);
+            }
+
+            Ok(by_person)
+        }).await
+    }
+}
+
+/// Travel documents by person id, most recently presented first, for
+/// Person::travel_documents and Person::travel_document_id
+pub struct TravelDocumentsByPersonLoader(pub Repository);
+
+#[async_trait::async_trait]
+impl Loader<Uuid> for TravelDocumentsByPersonLoader {
+    type Value = Vec<TravelDocument>;
+    type Error = Error;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Vec<TravelDocument>>, Error> {
+        let keys = keys.to_vec();
+
+        self.0.run(move |conn| {
+            let documents = TravelDocument::get_by_person_ids(conn, keys)?;
+
+            let mut by_person: HashMap<Uuid, Vec<TravelDocument>> = HashMap::new();
+
+            for document in documents {
+                by_person.entry(document.person_id).or_default().push(document);
+            }
+
+            Ok(by_person)
+        }).await
+    }
+}
+
+/// Vaccinations by public health profile id ordered by when they were provided,
+/// for PublicHealthProfile::vaccination_history
+pub struct VaccinationsByProfileLoader(pub Repository);
+
+#[async_trait::async_trait]
+impl Loader<Uuid> for VaccinationsByProfileLoader {
+    type Value = Vec<Vaccination>;
+    type Error = Error;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Vec<Vaccination>>, Error> {
+        let keys = keys.to_vec();
+
+        self.0.run(move |conn| {
+            let vaccinations = graphql_translate(vaccinations::table
+                .filter(vaccinations::public_health_profile_id.eq_any(keys))
+                .order((vaccinations::provided_on, vaccinations::id))
+                .load::<Vaccination>(conn))?;
+
+            let mut by_profile: HashMap<Uuid, Vec<Vaccination>> = HashMap::new();
+
+            for vaccination in vaccinations {
+                by_profile.entry(vaccination.public_health_profile_id).or_default().push(vaccination);
+            }
+
+            Ok(by_profile)
+        }).await
+    }
+}
+
+/// PostalAddress by id, for QuarantinePlan::quarantine_address
+pub struct PostalAddressLoader(pub Repository);
+
+#[async_trait::async_trait]
+impl Loader<Uuid> for PostalAddressLoader {
+    type Value = PostalAddress;
+    type Error = Error;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, PostalAddress>, Error> {
+        let keys = keys.to_vec();
+
+        self.0.run(move |conn| {
+            let addresses = graphql_translate(postal_addresses::table
+                .filter(postal_addresses::id.eq_any(keys))
+                .load::<PostalAddress>(conn))?;
+
+            Ok(addresses.into_iter().map(|a| (a.id, a)).collect())
+        }).await
+    }
+}
@@ -1,9 +1,17 @@
 mod query;
 mod mutation;
 mod utilities;
+mod pagination;
+mod loaders;
+mod limits;
+mod persisted_queries;
 // mod subscription;
 
 pub use self::query::*;
 pub use self::mutation::*;
 pub use self::utilities::*;
+pub use self::pagination::*;
+pub use self::loaders::*;
+pub use self::limits::*;
+pub use self::persisted_queries::*;
 // pub use self::subscription::*;
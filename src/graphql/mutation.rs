@@ -1,17 +1,27 @@
 use std::str::FromStr;
 
 use async_graphql::*;
+use chrono::NaiveDateTime;
+use diesel::{Connection, PgConnection};
 use uuid::Uuid;
 
 use crate::models::{InsertableUser, LoginQuery, TravelData, PILResponse,
-    User, UserData, create_token, decode_token,
-    verify_password, UserUpdate, hash_password};
+    User, UserData, create_token,
+    verify_password, UserUpdate, hash_password,
+    IdempotencyKey, PILSubmission, Reservation, payload_hash, TransactionScope,
+    Trip, TripState, TravelProvider, NewTravelProvider, TravelProviderUpdate,
+    MANIFEST_SUBMIT_SCOPE, Place, PlaceAlias, Country, CountryAlias, PendingCountry,
+    Person, PersonMatchReview, TravelDocument, TravelDocumentStatus, validate_group,
+    TravelGroup, NewTravelGroup, TravelGroupRelationship, NewTravelGroupRelationship, RelationshipType,
+    GuardianConsent, NewGuardianConsent, PersistedQuery, NewPersistedQuery};
 use crate::common_utils::{Role,
     is_operator,
     is_admin, RoleGuard};
 // use rdkafka::producer::FutureProducer;
 // use crate::kafka::send_message;
-use crate::graphql::get_connection_from_context;
+use crate::database::Repository;
+use crate::errors::error_handler::CustomError;
+use crate::{reload_reference_caches, ReferenceData, ReferenceSource};
 
 pub struct Mutation;
 
@@ -28,44 +38,537 @@ impl Mutation {
     /// relating to entry to Canada for public health reasons and referrals to mandatory
     /// random testing. Also includes IDs for Person, Trip, QuarantinePlan
     /// for further mutations.
+    /// If an idempotency key is provided (argument or Idempotency-Key header),
+    /// a repeated submission with the same key returns the original responses.
+    /// Each traveller is processed in its own transaction unless transaction_scope
+    /// is GROUP. Submissions with an idempotency key are always processed as
+    /// GROUP so a failed one leaves nothing behind for its retry to duplicate.
+    /// Travellers that could not be processed are returned with
+    /// post_status "ERROR" and the reason in details.
+    /// Relationships and guardian consent between travellers are recorded after
+    /// every traveller is processed, then rules for unaccompanied minors and
+    /// household quarantine are applied to the group.
     pub async fn travel_data_response(
         &self,
         context: &Context<'_>,
         data: Vec<TravelData>,
+        idempotency_key: Option<String>,
+        transaction_scope: Option<TransactionScope>,
     ) -> FieldResult<Vec<PILResponse>> {
 
-        let cbsa_id = context.data_opt::<Uuid>().expect("Unable to parse CBSA ID");
+        let cbsa_id = *context.data_opt::<Uuid>()
+            .ok_or_else(|| CustomError::Unauthorized("Unable to identify CBSA user".to_string()).extend())?;
 
-        let mut responses_to_cbsa: Vec<PILResponse> = Vec::new();
+        let idempotency_key = idempotency_key
+            .or_else(|| context.data_opt::<IdempotencyKey>().map(|k| k.0.to_owned()))
+            .filter(|k| !k.is_empty());
 
-        let travel_group_id = Uuid::new_v4();
+        let transaction_scope = TransactionScope::for_submission(transaction_scope, idempotency_key.is_some())
+            .map_err(|e| e.extend())?;
 
-        for traveller in data {
-            let response = traveller.process(&context, travel_group_id, *cbsa_id)
-                .await?
-                .into();
-                
-            responses_to_cbsa.push(response);
+        let reference = ReferenceData::from_context(context)?;
 
-            /* 
-            // Create Kafka producer and send message for subscription service
-            let producer = context
-                .data::<FutureProducer>()
-                .expect("Can't get Kafka producer");
-            */
+        Repository::from_context(context)?.run(move |conn| {
+            // Reserving the key first keeps concurrent retries from both processing
+            if let Some(key) = &idempotency_key {
+                if let Reservation::Completed(submission) = PILSubmission::reserve(conn, cbsa_id, key, &payload_hash(&data))? {
+                    return submission.responses(conn);
+                }
+            }
+
+            let res = process_travellers(conn, &reference, &data, transaction_scope, cbsa_id);
+
+            if let Some(key) = &idempotency_key {
+                match &res {
+                    // Only complete submissions are kept. A failed one was rolled back
+                    // as a whole, so its retry processes every traveller again.
+                    Ok(responses) if !responses.iter().any(|r| r.is_error()) => {
+                        PILSubmission::complete(conn, cbsa_id, key, responses.iter().map(|r| r.id).collect())?;
+                    },
+                    _ => {
+                        PILSubmission::release(conn, cbsa_id, key)?;
+                    },
+                }
+            }
+
+            res
+        }).await
+    }
+
+    #[graphql(
+        name = "addTravelGroupRelationship",
+        guard = "RoleGuard::new(Role::Operator)",
+        visible = "is_operator",
+    )]
+    /// Records how two people in a travel group are related. For GUARDIAN_OF
+    /// the person is the guardian of the related person.
+    pub async fn add_travel_group_relationship(
+        &self,
+        context: &Context<'_>,
+        travel_group_id: Uuid,
+        person_id: Uuid,
+        related_person_id: Uuid,
+        relationship_type: RelationshipType,
+    ) -> FieldResult<TravelGroupRelationship> {
+
+        Repository::from_context(context)?.run(move |conn| {
+            let relationship = NewTravelGroupRelationship::new(
+                travel_group_id,
+                person_id,
+                related_person_id,
+                relationship_type,
+            );
+
+            TravelGroup::add_relationship(conn, &relationship)
+        }).await
+    }
+
+    #[graphql(
+        name = "recordGuardianConsent",
+        guard = "RoleGuard::new(Role::Operator)",
+        visible = "is_operator",
+    )]
+    /// Records consent from a parent or guardian for a minor in a travel group
+    pub async fn record_guardian_consent(
+        &self,
+        context: &Context<'_>,
+        travel_group_id: Uuid,
+        minor_id: Uuid,
+        guardian_name: String,
+        guardian_contact: Option<String>,
+        guardian_person_id: Option<Uuid>,
+    ) -> FieldResult<GuardianConsent> {
+
+        let user_id = *context.data_opt::<Uuid>()
+            .ok_or_else(|| CustomError::Unauthorized("Unable to identify user".to_string()).extend())?;
+
+        if guardian_name.trim().is_empty() {
+            return Err(CustomError::Validation(vec!["guardian_name must not be empty".to_string()]).extend());
+        }
+
+        Repository::from_context(context)?.run(move |conn| {
+            let consent = NewGuardianConsent::new(
+                travel_group_id,
+                minor_id,
+                guardian_name,
+                guardian_contact,
+                guardian_person_id,
+                user_id,
+            );
+
+            TravelGroup::record_guardian_consent(conn, &consent)
+        }).await
+    }
+
+    #[graphql(
+        name = "updateTripState",
+        guard = "RoleGuard::new(Role::Operator)",
+        visible = "is_operator",
+    )]
+    /// Moves a Trip along PLANNED -> IN_PROGRESS -> COMPLETED or to CANCELLED.
+    /// Illegal transitions return a CONFLICT error.
+    pub async fn update_trip_state(
+        &self,
+        context: &Context<'_>,
+        trip_id: Uuid,
+        trip_state: TripState,
+        reason: Option<String>,
+    ) -> FieldResult<Trip> {
+
+        let user_id = *context.data_opt::<Uuid>()
+            .ok_or_else(|| CustomError::Unauthorized("Unable to identify user".to_string()).extend())?;
+
+        Repository::from_context(context)?
+            .run(move |conn| Trip::transition(conn, trip_id, trip_state, user_id, reason))
+            .await
+    }
+
+    #[graphql(
+        name = "reportTripTimes",
+        guard = "RoleGuard::new(Role::Operator)",
+        visible = "is_operator",
+    )]
+    /// Records the actual departure and/or arrival of a Trip, moving it
+    /// to IN_PROGRESS or COMPLETED as appropriate.
+    pub async fn report_trip_times(
+        &self,
+        context: &Context<'_>,
+        trip_id: Uuid,
+        departure_time: Option<NaiveDateTime>,
+        arrival_time: Option<NaiveDateTime>,
+    ) -> FieldResult<Trip> {
+
+        let user_id = *context.data_opt::<Uuid>()
+            .ok_or_else(|| CustomError::Unauthorized("Unable to identify user".to_string()).extend())?;
+
+        Repository::from_context(context)?
+            .run(move |conn| Trip::report_times(conn, trip_id, departure_time, arrival_time, user_id))
+            .await
+    }
+
+    #[graphql(
+        name = "createTravelProvider",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    pub async fn create_travel_provider(
+        &self,
+        context: &Context<'_>,
+        provider_data: NewTravelProvider,
+    ) -> FieldResult<TravelProvider> {
+
+        Repository::from_context(context)?
+            .run(move |conn| TravelProvider::create(conn, &provider_data))
+            .await
+    }
+
+    #[graphql(
+        name = "updateTravelProvider",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    pub async fn update_travel_provider(
+        &self,
+        context: &Context<'_>,
+        provider_data: TravelProviderUpdate,
+    ) -> FieldResult<TravelProvider> {
+
+        Repository::from_context(context)?.run(move |conn| {
+            let mut provider = TravelProvider::get_by_id(conn, provider_data.id)?;
+
+            if let Some(s) = provider_data.provider_name {
+                provider.provider_name = s;
+            };
+
+            if let Some(t) = provider_data.provider_type {
+                provider.provider_type = t;
+            };
+
+            if let Some(s) = provider_data.contact_name {
+                provider.contact_name = Some(s);
+            };
+
+            if let Some(s) = provider_data.contact_email {
+                provider.contact_email = Some(s);
+            };
+
+            if let Some(s) = provider_data.contact_phone {
+                provider.contact_phone = Some(s);
+            };
+
+            if let Some(b) = provider_data.active {
+                provider.active = b;
+            };
+
+            provider.update(conn)
+        }).await
+    }
+
+    #[graphql(
+        name = "issueProviderApiKey",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Issues a new API key for the provider, replacing any previous key.
+    /// The key is only shown once. Scopes default to manifest:submit.
+    pub async fn issue_provider_api_key(
+        &self,
+        context: &Context<'_>,
+        provider_id: Uuid,
+        scopes: Option<Vec<String>>,
+    ) -> FieldResult<String> {
+
+        Repository::from_context(context)?.run(move |conn| {
+            let provider = TravelProvider::get_by_id(conn, provider_id)?;
+
+            let scopes = scopes.unwrap_or_else(|| vec![MANIFEST_SUBMIT_SCOPE.to_string()]);
+
+            provider.issue_api_key(conn, scopes)
+        }).await
+    }
+
+    #[graphql(
+        name = "revokeProviderApiKey",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    pub async fn revoke_provider_api_key(
+        &self,
+        context: &Context<'_>,
+        provider_id: Uuid,
+    ) -> FieldResult<TravelProvider> {
+
+        Repository::from_context(context)?.run(move |conn| {
+            let provider = TravelProvider::get_by_id(conn, provider_id)?;
+
+            provider.revoke_api_key(conn)
+        }).await
+    }
+
+    #[graphql(
+        name = "setPortTestingRate",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Sets the fraction of arrivals at a port of entry referred for mandatory
+    /// testing. Null returns the port to the global rate.
+    pub async fn set_port_testing_rate(
+        &self,
+        context: &Context<'_>,
+        place_id: Uuid,
+        testing_rate: Option<f64>,
+    ) -> FieldResult<Place> {
+
+        let reference = ReferenceData::from_context(context)?;
+
+        Repository::from_context(context)?.run(move |conn| {
+            let port = Place::set_testing_rate(conn, place_id, testing_rate)?;
+
+            // PILQuery reads the rate from the places cache
+            reload_reference_caches(&reference, conn)?;
+
+            Ok(port)
+        }).await
+    }
+
+    #[graphql(
+        name = "mergePlaces",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Repoints trips, itinerary legs, vaccinations and addresses from the duplicate
+    /// place to the canonical one and deletes the duplicate. Its name becomes an alias.
+    pub async fn merge_places(
+        &self,
+        context: &Context<'_>,
+        duplicate_place_id: Uuid,
+        canonical_place_id: Uuid,
+    ) -> FieldResult<Place> {
+
+        let reference = ReferenceData::from_context(context)?;
+
+        Repository::from_context(context)?.run(move |conn| {
+            let place = Place::merge(conn, duplicate_place_id, canonical_place_id)?;
+
+            reload_reference_caches(&reference, conn)?;
+
+            Ok(place)
+        }).await
+    }
 
-            // Sent ArriveCan messages to Kafka
-            let arrivecan_message = serde_json::to_string(&traveller)
-                .expect("Can't serialize ArriveCan PIL message");
+    #[graphql(
+        name = "addPlaceAlias",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Adds another spelling of a place so submissions using it match the place
+    pub async fn add_place_alias(
+        &self,
+        context: &Context<'_>,
+        place_id: Uuid,
+        alias: String,
+    ) -> FieldResult<PlaceAlias> {
+
+        let reference = ReferenceData::from_context(context)?;
+
+        Repository::from_context(context)?.run(move |conn| {
+            let alias = {
+                let places = reference.places()?.lock().unwrap();
+
+                PlaceAlias::add(conn, place_id, &alias, &places)?
+            };
+
+            reload_reference_caches(&reference, conn)?;
+
+            Ok(alias)
+        }).await
+    }
+
+    #[graphql(
+        name = "mergePersons",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Moves trips, travel responses, health profiles, travel documents and travel group
+    /// relationships from the duplicate person to the surviving one and deletes the duplicate. Pending reviews of the pair
+    /// are marked MERGED.
+    pub async fn merge_persons(
+        &self,
+        context: &Context<'_>,
+        duplicate_person_id: Uuid,
+        surviving_person_id: Uuid,
+    ) -> FieldResult<Person> {
+
+        let user_id = *context.data_opt::<Uuid>()
+            .ok_or_else(|| CustomError::Unauthorized("Unable to identify user".to_string()).extend())?;
+
+        Repository::from_context(context)?
+            .run(move |conn| Person::merge(conn, duplicate_person_id, surviving_person_id, user_id))
+            .await
+    }
+
+    #[graphql(
+        name = "dismissPersonMatch",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Records that the people in a review are not the same person
+    pub async fn dismiss_person_match(
+        &self,
+        context: &Context<'_>,
+        review_id: Uuid,
+    ) -> FieldResult<PersonMatchReview> {
 
-            /* 
-            // Remove subscription until we set up Kafka service
-            println!("Sending ArriveCan PIL Message to Subscription");
-            send_message(producer, "arrivecan_pil", arrivecan_message, "CBSA".to_string()).await;
-            */
-        };        
-        
-        Ok(responses_to_cbsa)
+        let user_id = *context.data_opt::<Uuid>()
+            .ok_or_else(|| CustomError::Unauthorized("Unable to identify user".to_string()).extend())?;
+
+        Repository::from_context(context)?
+            .run(move |conn| PersonMatchReview::dismiss(conn, review_id, user_id))
+            .await
+    }
+
+    #[graphql(
+        name = "setTravelDocumentStatus",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Reports a travel document LOST or REVOKED, or returns it to ACTIVE
+    pub async fn set_travel_document_status(
+        &self,
+        context: &Context<'_>,
+        document_id: Uuid,
+        status: TravelDocumentStatus,
+    ) -> FieldResult<TravelDocument> {
+
+        Repository::from_context(context)?
+            .run(move |conn| TravelDocument::set_status(conn, document_id, status))
+            .await
+    }
+
+    #[graphql(
+        name = "approveCountry",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Creates a Country for a pending name. If country_name is given the
+    /// country is created with that name and the pending name becomes an alias.
+    pub async fn approve_country(
+        &self,
+        context: &Context<'_>,
+        pending_country_id: Uuid,
+        country_name: Option<String>,
+        iso_alpha2: String,
+        iso_alpha3: String,
+        risk_rate: f64,
+    ) -> FieldResult<Country> {
+
+        let reference = ReferenceData::from_context(context)?;
+
+        Repository::from_context(context)?.run(move |conn| {
+            let country = PendingCountry::approve(
+                conn,
+                pending_country_id,
+                country_name,
+                iso_alpha2,
+                iso_alpha3,
+                risk_rate,
+            )?;
+
+            reload_reference_caches(&reference, conn)?;
+
+            Ok(country)
+        }).await
+    }
+
+    #[graphql(
+        name = "addCountryAlias",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Resolves alias to an existing country, e.g. a pending "Holland" to Netherlands
+    pub async fn add_country_alias(
+        &self,
+        context: &Context<'_>,
+        country_id: Uuid,
+        alias: String,
+    ) -> FieldResult<CountryAlias> {
+
+        let reference = ReferenceData::from_context(context)?;
+
+        Repository::from_context(context)?.run(move |conn| {
+            let alias = CountryAlias::add(conn, country_id, &alias)?;
+
+            reload_reference_caches(&reference, conn)?;
+
+            Ok(alias)
+        }).await
+    }
+
+    #[graphql(
+        name = "dismissPendingCountry",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Removes a name that is not a country from the queue. It is queued again if resubmitted.
+    pub async fn dismiss_pending_country(
+        &self,
+        context: &Context<'_>,
+        pending_country_id: Uuid,
+    ) -> FieldResult<PendingCountry> {
+
+        Repository::from_context(context)?.run(move |conn| {
+            let pending = PendingCountry::get_by_id(conn, pending_country_id)?;
+
+            PendingCountry::delete(conn, pending.id)?;
+
+            Ok(pending)
+        }).await
+    }
+
+    #[graphql(
+        name = "registerPersistedQuery",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Adds a query document clients may run by its hash. With PERSISTED_QUERIES_ONLY
+    /// set, only registered documents can be run by roles other than Admin.
+    pub async fn register_persisted_query(
+        &self,
+        context: &Context<'_>,
+        document: String,
+        operation_name: Option<String>,
+        description: Option<String>,
+    ) -> FieldResult<PersistedQuery> {
+
+        let user_id = *context.data_opt::<Uuid>()
+            .ok_or_else(|| CustomError::Unauthorized("Unable to identify user".to_string()).extend())?;
+
+        Repository::from_context(context)?.run(move |conn| {
+            let query = NewPersistedQuery::new(document, operation_name, description, user_id);
+
+            PersistedQuery::create(conn, &query)
+        }).await
+    }
+
+    #[graphql(
+        name = "removePersistedQuery",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Clients can no longer run the document by its hash
+    pub async fn remove_persisted_query(
+        &self,
+        context: &Context<'_>,
+        persisted_query_id: Uuid,
+    ) -> FieldResult<PersistedQuery> {
+
+        Repository::from_context(context)?.run(move |conn| {
+            let query = PersistedQuery::get_by_id(conn, persisted_query_id)?;
+
+            PersistedQuery::delete(conn, query.id)?;
+
+            Ok(query)
+        }).await
     }
 
     #[graphql(
@@ -80,9 +583,9 @@ impl Mutation {
     ) -> FieldResult<User> {
         let new_user = InsertableUser::from(user_data);
 
-        let created_user = User::create(new_user, &get_connection_from_context(context));
-
-        created_user
+        Repository::from_context(context)?
+            .run(move |conn| User::create(new_user, conn))
+            .await
     }
 
     #[graphql(
@@ -96,29 +599,29 @@ impl Mutation {
         user_data: UserUpdate,
     ) -> FieldResult<User> {
 
-        let conn = get_connection_from_context(context);
-
-        let mut target_user = User::get_by_id(&user_data.id, &conn)?;
+        Repository::from_context(context)?.run(move |conn| {
+            let mut target_user = User::get_by_id(&user_data.id, conn)?;
 
-        if let Some(s) = user_data.name {
-            target_user.name = s;
-        };
+            if let Some(s) = user_data.name {
+                target_user.name = s;
+            };
 
-        if let Some(s) = user_data.email {
-            target_user.email = s;
-        };
+            if let Some(s) = user_data.email {
+                target_user.email = s;
+            };
 
-        if let Some(s) = user_data.password {
-            target_user.hash = hash_password(&s)?;
-        };
+            if let Some(s) = user_data.password {
+                target_user.hash = hash_password(&s)?;
+            };
 
-        if let Some(s) = user_data.role {
-            target_user.role = s;
-        };
+            if let Some(s) = user_data.role {
+                target_user.role = s;
+            };
 
-        let updated_user = target_user.update(&conn);
+            let updated_user = target_user.update(conn);
 
-        updated_user
+            updated_user
+        }).await
     }
 
     pub async fn sign_in(
@@ -127,28 +630,153 @@ impl Mutation {
         input: LoginQuery,
     ) -> Result<String, Error> {
 
-        let conn = get_connection_from_context(&context);
+        Repository::from_context(context)?.run(move |conn| {
+            let maybe_user = User::get_by_email(&input.email, conn).ok();
+
+            if let Some(user) = maybe_user {
+
+                if let Ok(matching) = verify_password(&user.hash.to_string(), &input.password) {
+                    if matching {
+                        let role = Role::from_str(user.role.as_str())
+                            .map_err(|_| CustomError::Internal(format!("Invalid role for user: {}", user.role)).extend())?;
+
+                        // Return the token which would be accepted by the ArriveCan 
+                        // app and used to authenticate actions
+                        let token = create_token(user.id.to_string(), role);
+
+                        return Ok(token);
+                    }
+                }
+            }
+
+            Err(CustomError::Unauthorized("Can't authenticate a user".to_string()).extend())
+        }).await
+    }
+}
+
+/// Processes a PILQuery's travellers and applies the group rules
+fn process_travellers(
+    conn: &PgConnection,
+    reference: &ReferenceData,
+    data: &[TravelData],
+    transaction_scope: TransactionScope,
+    cbsa_id: Uuid,
+) -> FieldResult<Vec<PILResponse>> {
+    // References between travellers are checked before anything is stored
+    let violations = validate_group(data);
+
+    if !violations.is_empty() {
+        return Err(CustomError::Validation(violations).extend());
+    }
+
+    // ArriveCan messages for Kafka, serialized before anything is written so
+    // a failure can't follow a commit
+    let arrivecan_messages = data.iter()
+        .map(|traveller| serde_json::to_string(traveller)
+            .map_err(|e| CustomError::Internal(format!("Can't serialize ArriveCan PIL message: {}", e)).extend()))
+        .collect::<FieldResult<Vec<String>>>()?;
+
+    let responses_to_cbsa: Vec<PILResponse> = match transaction_scope {
+        TransactionScope::Traveller => {
+            let travel_group_id = TravelGroup::create_travel_group(conn, &NewTravelGroup::new())?.id;
+
+            let mut responses = Vec::new();
+
+            for traveller in data.iter() {
+                let res = conn.transaction::<PILResponse, Error, _>(|| {
+                    traveller.process(reference, conn, travel_group_id, cbsa_id)
+                });
+
+                reference.finish_transaction(conn, res.is_ok())?;
 
-        let maybe_user = User::get_by_email(&input.email, &conn).ok();
+                let response = match res {
+                    Ok(r) => r,
+                    Err(e) => PILResponse::error(cbsa_id, e.message),
+                };
 
-        if let Some(user) = maybe_user {
+                responses.push(response);
+            }
+
+            // Travellers already committed keep their responses if the group rules fail
+            let mut amended = responses.clone();
 
-            if let Ok(matching) = verify_password(&user.hash.to_string(), &input.password) {
-                if matching {
-                    let role = Role::from_str(user.role.as_str())
-                        .expect("Cannot convert &str to Role");
+            let res = conn.transaction::<(), Error, _>(|| {
+                TravelGroup::apply_rules(conn, travel_group_id, data, &mut amended, cbsa_id)
+            });
 
-                    // Return the token which would be accepted by the ArriveCan 
-                    // app and used to authenticate actions
-                    let token = create_token(user.id.to_string(), role);
+            match res {
+                Ok(()) => amended,
+                Err(e) => {
+                    for r in responses.iter_mut().filter(|r| !r.is_error()) {
+                        let note = format!("Group rules not applied: {}", e.message);
 
-                    println!("JWT: {}\nData{:?}", &token, decode_token(&token));
+                        r.details = Some(match &r.details {
+                            Some(d) if d != "None" => format!("{}; {}", d, note),
+                            _ => note,
+                        });
+                    }
 
-                    return Ok(token);
+                    responses
                 }
             }
-        }
+        },
+        TransactionScope::Group => {
+            // Index and reason of the traveller that caused the rollback
+            let mut failure: Option<(usize, String)> = None;
+
+            let res = conn.transaction::<Vec<PILResponse>, Error, _>(|| {
+                let travel_group_id = TravelGroup::create_travel_group(conn, &NewTravelGroup::new())?.id;
+                let mut responses = Vec::new();
+
+                for (i, traveller) in data.iter().enumerate() {
+                    match traveller.process(reference, conn, travel_group_id, cbsa_id) {
+                        Ok(r) => responses.push(r),
+                        Err(e) => {
+                            failure = Some((i, e.message.to_owned()));
+                            return Err(e);
+                        }
+                    }
+                }
 
-        Err(Error::new("Can't authenticate a user"))
-    }
-}
\ No newline at end of file
+                TravelGroup::apply_rules(conn, travel_group_id, data, &mut responses, cbsa_id)?;
+
+                Ok(responses)
+            });
+
+            reference.finish_transaction(conn, res.is_ok())?;
+
+            match res {
+                Ok(r) => r,
+                Err(e) => {
+                    (0..data.len()).map(|i| match &failure {
+                        Some((failed, message)) if *failed == i => {
+                            PILResponse::error(cbsa_id, message.to_owned())
+                        },
+                        Some((failed, _)) => {
+                            PILResponse::error(cbsa_id, format!("Rolled back: traveller {} in the group failed", failed + 1))
+                        },
+                        // The commit itself failed
+                        None => PILResponse::error(cbsa_id, e.message.to_owned()),
+                    }).collect()
+                }
+            }
+        },
+    };
+
+    /* 
+    // Create Kafka producer and send message for subscription service
+    let producer = context
+        .data::<FutureProducer>()
+        .map_err(|_| CustomError::Internal("Can't get Kafka producer".to_string()).extend())?;
+    */
+
+    for _arrivecan_message in arrivecan_messages {
+        /* 
+        // Remove subscription until we set up Kafka service
+        println!("Sending ArriveCan PIL Message to Subscription");
+        send_message(producer, "arrivecan_pil", _arrivecan_message, "CBSA".to_string()).await;
+        */
+    };
+
+    Ok(responses_to_cbsa)
+}
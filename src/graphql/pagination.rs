@@ -0,0 +1,239 @@
+use std::sync::Arc;
+
+use async_graphql::*;
+use async_graphql::connection::{Connection, CursorType, Edge, EmptyFields};
+use chrono::prelude::*;
+use diesel::{PgConnection, QueryResult};
+use uuid::Uuid;
+
+use crate::config_variables::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+use crate::database::Repository;
+use crate::errors::error_handler::CustomError;
+use crate::graphql::graphql_translate;
+
+/// Counts the whole list of a page
+type CountQuery = Arc<dyn Fn(&PgConnection) -> QueryResult<i64> + Send + Sync>;
+
+pub struct PageFields {
+    repository: Repository,
+    count: CountQuery,
+}
+
+impl PageFields {
+    /// count is only run if totalCount is selected
+    pub fn new<F>(repository: Repository, count: F) -> Self
+    where
+        F: Fn(&PgConnection) -> QueryResult<i64> + Send + Sync + 'static,
+    {
+        PageFields {
+            repository,
+            count: Arc::new(count),
+        }
+    }
+}
+
+#[Object]
+impl PageFields {
+    /// Number of items in the whole list, not just this page
+    pub async fn total_count(&self) -> FieldResult<i64> {
+        let count = self.count.clone();
+
+        self.repository
+            .run(move |conn| graphql_translate(count(conn)))
+            .await
+    }
+}
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+impl Default for SortDirection {
+    fn default() -> Self {
+        SortDirection::Asc
+    }
+}
+
+impl SortDirection {
+    pub fn reversed(&self) -> Self {
+        match self {
+            SortDirection::Asc => SortDirection::Desc,
+            SortDirection::Desc => SortDirection::Asc,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Position of an item in a list sorted by key then id. Dates are kept as
+/// midnight. key is None for items without the time sorted on.
+pub struct KeysetCursor {
+    pub key: Option<NaiveDateTime>,
+    pub id: Uuid,
+}
+
+const CURSOR_KEY_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+impl KeysetCursor {
+    pub fn new(key: Option<NaiveDateTime>, id: Uuid) -> Self {
+        KeysetCursor { key, id }
+    }
+
+    pub fn on_date(key: NaiveDate, id: Uuid) -> Self {
+        KeysetCursor { key: Some(key.and_hms(0, 0, 0)), id }
+    }
+
+    pub fn date(&self) -> Option<NaiveDate> {
+        self.key.map(|k| k.date())
+    }
+}
+
+impl CursorType for KeysetCursor {
+    type Error = String;
+
+    fn decode_cursor(s: &str) -> Result<Self, Self::Error> {
+        let invalid = || format!("Invalid cursor {}", s);
+
+        let (key, id) = s.split_once('|').ok_or_else(invalid)?;
+
+        let key = match key {
+            "" => None,
+            k => Some(NaiveDateTime::parse_from_str(k, CURSOR_KEY_FORMAT).map_err(|_| invalid())?),
+        };
+
+        Ok(KeysetCursor {
+            key,
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+
+    fn encode_cursor(&self) -> String {
+        let key = self.key
+            .map(|k| k.format(CURSOR_KEY_FORMAT).to_string())
+            .unwrap_or_default();
+
+        format!("{}|{}", key, self.id)
+    }
+}
+
+/// Relay connection. Cursors are the sort key and id of an item, so pages
+/// stay in place when items are added to the list while paging through it.
+pub type Page<T> = Connection<KeysetCursor, T, PageFields, EmptyFields>;
+
+#[macro_export]
+/// Filters the boxed query to the rows strictly after (later is true) or
+/// before the cursor in ascending (key, id) order, with rows without a key
+/// last as Postgres sorts them. Descending order is this order reversed.
+macro_rules! keyset_filter {
+    ($query:expr, $key:expr, $id:expr, $cursor_key:expr, $cursor_id:expr, $later:expr) => {
+        match ($cursor_key, $cursor_id, $later) {
+            (Some(k), id, true) => $query.filter($key.gt(k).or($key.eq(k).and($id.gt(id))).or($key.is_null())),
+            (None, id, true) => $query.filter($key.is_null().and($id.gt(id))),
+            (Some(k), id, false) => $query.filter($key.lt(k).or($key.eq(k).and($id.lt(id)))),
+            (None, id, false) => $query.filter($key.is_not_null().or($id.lt(id))),
+        }
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A page requested with first/after or last/before
+pub struct PageArgs {
+    pub after: Option<KeysetCursor>,
+    pub before: Option<KeysetCursor>,
+    /// Items on the page
+    pub size: usize,
+    /// Paging back from before with last, so items are loaded in reverse order
+    pub backward: bool,
+}
+
+impl PageArgs {
+    /// Pages are DEFAULT_PAGE_SIZE items if neither first nor last is given
+    /// and at most MAX_PAGE_SIZE.
+    pub fn new(
+        after: Option<KeysetCursor>,
+        before: Option<KeysetCursor>,
+        first: Option<usize>,
+        last: Option<usize>,
+    ) -> Result<Self, String> {
+        for (argument, size) in [("first", first), ("last", last)] {
+            if let Some(size) = size {
+                if size > MAX_PAGE_SIZE {
+                    return Err(format!("{} must be at most {}", argument, MAX_PAGE_SIZE));
+                }
+            }
+        }
+
+        let (size, backward) = match (first, last) {
+            (Some(_), Some(_)) => return Err("first and last can't be used together".to_string()),
+            (Some(first), None) => (first, false),
+            (None, Some(last)) => (last, true),
+            (None, None) => (DEFAULT_PAGE_SIZE, false),
+        };
+
+        Ok(PageArgs { after, before, size, backward })
+    }
+
+    /// As new, with violations returned as a GraphQL error
+    pub fn window(
+        after: Option<KeysetCursor>,
+        before: Option<KeysetCursor>,
+        first: Option<usize>,
+        last: Option<usize>,
+    ) -> FieldResult<Self> {
+        PageArgs::new(after, before, first, last)
+            .map_err(|e| CustomError::Validation(vec![e]).extend())
+    }
+
+    /// The cursors to filter on, each with whether the page's rows come
+    /// later than it in ascending order when the list is sorted in direction
+    pub fn bounds(&self, direction: SortDirection) -> Vec<(KeysetCursor, bool)> {
+        let ascending = direction == SortDirection::Asc;
+
+        self.after.iter().map(|c| (*c, ascending))
+            .chain(self.before.iter().map(|c| (*c, !ascending)))
+            .collect()
+    }
+
+    /// Order to load rows in for a list sorted in direction
+    pub fn load_direction(&self, direction: SortDirection) -> SortDirection {
+        if self.backward { direction.reversed() } else { direction }
+    }
+
+    /// Rows to load, one more than the page so the next page is known to exist
+    pub fn limit(&self) -> i64 {
+        self.size as i64 + 1
+    }
+
+    /// The page's items in list order from the rows loaded, with whether
+    /// there are items before and after it
+    pub fn trim<T>(&self, mut rows: Vec<T>) -> (Vec<T>, bool, bool) {
+        let more = rows.len() > self.size;
+        rows.truncate(self.size);
+
+        if self.backward {
+            rows.reverse();
+            (rows, more, self.before.is_some())
+        } else {
+            (rows, self.after.is_some(), more)
+        }
+    }
+}
+
+/// Builds the connection for the rows loaded with limit and the sort order
+/// (reversed if backward), each item's cursor given by cursor
+pub fn build_page<T, F>(args: &PageArgs, rows: Vec<T>, fields: PageFields, cursor: F) -> Page<T>
+where
+    T: OutputType,
+    F: Fn(&T) -> KeysetCursor,
+{
+    let (nodes, has_previous_page, has_next_page) = args.trim(rows);
+
+    let mut page = Connection::with_additional_fields(has_previous_page, has_next_page, fields);
+
+    page.append(nodes
+        .into_iter()
+        .map(|node| Edge::new(cursor(&node), node)));
+
+    page
+}
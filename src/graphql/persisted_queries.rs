@@ -0,0 +1,111 @@
+use std::env;
+
+use async_graphql::*;
+use async_graphql::parser::parse_query;
+use async_graphql::parser::types::{DocumentOperations, OperationType, Selection};
+use diesel::PgConnection;
+
+use crate::errors::error_handler::CustomError;
+use crate::models::{PersistedQuery, query_hash};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// Which documents /graphql runs for roles other than Admin
+pub enum QueryMode {
+    /// Any document
+    Open,
+    /// Only documents registered as a PersistedQuery
+    PersistedOnly,
+}
+
+impl QueryMode {
+    /// PersistedOnly if PERSISTED_QUERIES_ONLY is "true"
+    pub fn from_env() -> Self {
+        match env::var("PERSISTED_QUERIES_ONLY") {
+            Ok(v) if v.trim().eq_ignore_ascii_case("true") => QueryMode::PersistedOnly,
+            _ => QueryMode::Open,
+        }
+    }
+}
+
+/// Hash from extensions.persistedQuery.sha256Hash, as sent by Apollo clients
+pub fn requested_hash(request: &Request) -> Option<String> {
+    match request.extensions.get("persistedQuery") {
+        Some(Value::Object(persisted)) => match persisted.get("sha256Hash") {
+            Some(Value::String(hash)) => Some(hash.trim().to_lowercase()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether the document only calls the signIn mutation. Users need a token
+/// before anything else, so signIn runs in PersistedOnly mode without being
+/// registered.
+pub fn is_sign_in(request: &Request) -> bool {
+    let document = match parse_query(&request.query) {
+        Ok(d) => d,
+        Err(_) => return false,
+    };
+
+    let operations = match &document.operations {
+        DocumentOperations::Single(op) => vec![&op.node],
+        DocumentOperations::Multiple(ops) => ops.values().map(|op| &op.node).collect(),
+    };
+
+    operations.iter().all(|op| {
+        op.ty == OperationType::Mutation
+            && !op.selection_set.node.items.is_empty()
+            && op.selection_set.node.items.iter().all(|item| match &item.node {
+                Selection::Field(field) => field.node.name.node.as_str() == "signIn",
+                _ => false,
+            })
+    })
+}
+
+/// Whether the request needs the persisted query store at all
+pub fn needs_persisted_query(request: &Request, mode: QueryMode, is_admin: bool) -> bool {
+    requested_hash(request).is_some()
+        || (mode == QueryMode::PersistedOnly && !is_admin && !is_sign_in(request))
+}
+
+/// Fills in the document of a request sent by hash only, then rejects
+/// documents that aren't registered if mode is PersistedOnly and the
+/// user isn't an Admin. signIn is always allowed.
+pub fn apply_persisted_query(
+    conn: &PgConnection,
+    request: &mut Request,
+    mode: QueryMode,
+    is_admin: bool,
+) -> FieldResult<()> {
+
+    let checked = mode == QueryMode::PersistedOnly && !is_admin;
+
+    let registered = match requested_hash(request) {
+        Some(hash) if request.query.trim().is_empty() => {
+            let persisted = PersistedQuery::find_by_hash(conn, &hash)?
+                .ok_or_else(|| CustomError::NotFound(format!("Persisted query {}", hash)).extend())?;
+
+            request.query = persisted.document;
+            true
+        },
+        Some(hash) => {
+            if query_hash(&request.query) != hash {
+                return Err(CustomError::Validation(vec![
+                    "sha256Hash does not match the query".to_string()]).extend());
+            }
+
+            PersistedQuery::find_by_hash(conn, &hash)?.is_some()
+        },
+        None if checked && !is_sign_in(request) => {
+            PersistedQuery::find_by_hash(conn, &query_hash(&request.query))?.is_some()
+        },
+        // Any document runs
+        None => true,
+    };
+
+    if checked && !registered && !is_sign_in(request) {
+        return Err(CustomError::Unauthorized("Only persisted queries can be run".to_string()).extend());
+    }
+
+    Ok(())
+}
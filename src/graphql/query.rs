@@ -1,53 +1,59 @@
 use diesel::{RunQueryDsl};
-use diesel::{QueryDsl, ExpressionMethods};
+use diesel::{QueryDsl, BoolExpressionMethods, ExpressionMethods};
 use crate::schema::*;
 
 use async_graphql::*;
+use async_graphql::connection::query;
 
 use crate::models::{Person, QuarantinePlan, User,
-    TravelGroup, Trip, Vaccination, CovidTest};
+    TravelGroup, Trip, Vaccination, CovidTest, TravelProvider, Place, PlaceKind,
+    Country, CountryAlias, PendingCountry, PersonMatchReview, PersonMatchStatus,
+    TripFilter, TripSort, PersonFilter, PersonSort, CovidTestFilter, PILResponse, PILResponseFilter,
+    PersistedQuery};
 use uuid::Uuid;
 
-use crate::graphql::{graphql_translate, get_connection_from_context};
-use crate::common_utils::{RoleGuard, is_admin, Role};
+use crate::graphql::{graphql_translate, Page, PageArgs, PageFields, KeysetCursor, build_page, SortDirection,
+    page_cost, list_cost};
+use crate::common_utils::{RoleGuard, is_admin, is_analyst, Role};
+use crate::database::Repository;
+use crate::ReferenceSource;
 
 pub struct Query;
 
 #[Object]
 impl Query {
 
-    #[graphql(name = "allTrips")]
-    /// Returns a vector of all trips ordered by arrival time
-    pub async fn all_trips(
-        &self, 
-        context: &Context<'_>,) -> FieldResult<Vec<Trip>> {
-
-        let conn = get_connection_from_context(context);
-
-        let res = trips::table
-            .order(trips::arrival_time)
-            .load::<Trip>(&conn);
-
-        graphql_translate(res)
-    }
-
-    #[graphql(name = "getTrips")]
-    /// Accepts argument of "count" and returns a vector of {count} trips ordered by
-    /// arrival time.
-    pub async fn get_trips(
-        &self, 
+    #[graphql(name = "trips", complexity = "page_cost(first, last, child_complexity)")]
+    /// Pages through trips matching filter, ordered by arrival time unless sorted otherwise
+    pub async fn trips(
+        &self,
         context: &Context<'_>,
-        count: i64,
-    ) -> FieldResult<Vec<Trip>> {
-
-        let conn = get_connection_from_context(context);
-
-        let res = trips::table
-            .order(trips::arrival_time)
-            .limit(count)
-            .load::<Trip>(&conn);
-
-        graphql_translate(res)
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        filter: Option<TripFilter>,
+        sort: Option<TripSort>,
+    ) -> FieldResult<Page<Trip>> {
+        let repository = Repository::from_context(context)?;
+
+        let filter = filter.unwrap_or_default();
+        let sort = sort.unwrap_or_default();
+
+        query(after, before, first, last, |after, before, first, last| async move {
+            let args = PageArgs::window(after, before, first, last)?;
+
+            let count_filter = filter.clone();
+            let fields = PageFields::new(repository.clone(), move |conn| count_filter.query().count().get_result(conn));
+
+            repository.run(move |conn| {
+                let res = sort.page(filter.query(), &args)
+                    .limit(args.limit())
+                    .load::<Trip>(conn);
+
+                Ok(build_page(&args, graphql_translate(res)?, fields, |t| sort.cursor(t)))
+            }).await
+        }).await
     }
 
     #[graphql(name = "tripById")]
@@ -57,26 +63,26 @@ impl Query {
         id: Uuid
     ) -> FieldResult<Trip> {
 
-        let conn = get_connection_from_context(context);
-
-        let res = trips::table.filter(trips::id.eq(id))
-            .first(&conn);
+        Repository::from_context(context)?.run(move |conn| {
+            let res = trips::table.filter(trips::id.eq(id))
+                .first(conn);
 
-        graphql_translate(res)
+            graphql_translate(res)
+        }).await
     }
 
     /// Travel Groups
-    #[graphql(name = "allTravelGroups")]
+    #[graphql(name = "allTravelGroups", complexity = "list_cost(child_complexity)")]
     /// Returns a vector of all travel groups
     pub async fn all_travel_groups(
         &self, 
         context: &Context<'_>,
     ) -> FieldResult<Vec<TravelGroup>> {
-        let conn = get_connection_from_context(context);
-
-        let res = travel_groups::table.load::<TravelGroup>(&conn);
+        Repository::from_context(context)?.run(move |conn| {
+            let res = travel_groups::table.load::<TravelGroup>(conn);
 
-        graphql_translate(res)
+            graphql_translate(res)
+        }).await
     }
 
     
@@ -87,115 +93,236 @@ impl Query {
         context: &Context<'_>,
         id: Uuid
     ) -> FieldResult<TravelGroup> {
-        let conn = get_connection_from_context(context);
-
-        let res = travel_groups::table
-        .filter(travel_groups::id.eq(&id))
-        .first(&conn);
+        Repository::from_context(context)?.run(move |conn| {
+            let res = travel_groups::table
+            .filter(travel_groups::id.eq(id))
+            .first(conn);
         
-        graphql_translate(res)
+            graphql_translate(res)
+        }).await
     }
 
-    #[graphql(name = "allPeople")]
-    /// Returns a vector of all people
-    pub async fn all_people(&self, context: &Context<'_>) -> FieldResult<Vec<Person>> {
-        let conn = get_connection_from_context(context);
-
-        let res = persons::table.load::<Person>(&conn);
-
-        graphql_translate(res)
+    #[graphql(name = "people", complexity = "page_cost(first, last, child_complexity)")]
+    /// Pages through people matching filter, in the order they were added unless sorted otherwise
+    pub async fn people(
+        &self,
+        context: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        filter: Option<PersonFilter>,
+        sort: Option<PersonSort>,
+    ) -> FieldResult<Page<Person>> {
+        let repository = Repository::from_context(context)?;
+
+        let filter = filter.unwrap_or_default();
+        let sort = sort.unwrap_or_default();
+
+        query(after, before, first, last, |after, before, first, last| async move {
+            let args = PageArgs::window(after, before, first, last)?;
+
+            let count_filter = filter.clone();
+            let fields = PageFields::new(repository.clone(), move |conn| count_filter.query().count().get_result(conn));
+
+            repository.run(move |conn| {
+                let res = sort.page(filter.query(), &args)
+                    .limit(args.limit())
+                    .load::<Person>(conn);
+
+                Ok(build_page(&args, graphql_translate(res)?, fields, |p| sort.cursor(p)))
+            }).await
+        }).await
     }
 
-    #[graphql(name = "getPeople")]
-    /// Accepts an argument of "count" and returns a vector of {count} people
-    pub async fn get_people(&self, context: &Context<'_>, count: i64) -> FieldResult<Vec<Person>> {
-        let conn = get_connection_from_context(context);
-
-        let res = persons::table
-            .limit(count)
-            .load::<Person>(&conn);
+    #[graphql(name = "vaccinations", complexity = "page_cost(first, last, child_complexity)")]
+    /// Pages through vaccinations ordered by when they were provided
+    pub async fn vaccinations(
+        &self,
+        context: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> FieldResult<Page<Vaccination>> {
+        let repository = Repository::from_context(context)?;
 
-        graphql_translate(res)
-    }
+        query(after, before, first, last, |after, before, first, last| async move {
+            let args = PageArgs::window(after, before, first, last)?;
 
-    #[graphql(name = "allVaccinations")]
-    /// Returns a vector of all vaccination histories
-    pub async fn all_vaccinations(&self, context: &Context<'_>) -> FieldResult<Vec<Vaccination>> {
-        let conn = get_connection_from_context(context);
+            let fields = PageFields::new(repository.clone(), |conn| vaccinations::table.count().get_result(conn));
 
-        let res = vaccinations::table.load::<Vaccination>(&conn);
+            repository.run(move |conn| {
+                let mut page = vaccinations::table.into_boxed();
 
-        graphql_translate(res)
-    }
+                for (cursor, later) in args.bounds(SortDirection::Asc) {
+                    page = crate::keyset_filter!(page, vaccinations::provided_on, vaccinations::id, cursor.key, cursor.id, later);
+                }
 
-    #[graphql(name = "getVaccinations")]
-    /// Accepts argument "count" and returns a vector of {count} vaccination histories
-    pub async fn get_vaccinations(&self, context: &Context<'_>, count: i64) -> FieldResult<Vec<Vaccination>> {
-        let conn = get_connection_from_context(context);
+                let page = match args.load_direction(SortDirection::Asc) {
+                    SortDirection::Asc => page.order((vaccinations::provided_on.asc(), vaccinations::id.asc())),
+                    SortDirection::Desc => page.order((vaccinations::provided_on.desc(), vaccinations::id.desc())),
+                };
 
-        let res = vaccinations::table
-            .limit(count)
-            .load::<Vaccination>(&conn);
+                let res = page
+                    .limit(args.limit())
+                    .load::<Vaccination>(conn);
 
-        graphql_translate(res)
+                Ok(build_page(&args, graphql_translate(res)?, fields, |v| KeysetCursor::new(Some(v.provided_on), v.id)))
+            }).await
+        }).await
     }
 
-    #[graphql(name = "allQuarantinePlans")]
-    /// Returns a vector of all quarantine plans
-    pub async fn all_quarantine_plans(&self, context: &Context<'_>) -> FieldResult<Vec<QuarantinePlan>> {
-        let conn = get_connection_from_context(context);
+    #[graphql(name = "quarantinePlans", complexity = "page_cost(first, last, child_complexity)")]
+    /// Pages through quarantine plans ordered by date created
+    pub async fn quarantine_plans(
+        &self,
+        context: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> FieldResult<Page<QuarantinePlan>> {
+        let repository = Repository::from_context(context)?;
 
-        let res = quarantine_plans::table.load::<QuarantinePlan>(&conn);
+        query(after, before, first, last, |after, before, first, last| async move {
+            let args = PageArgs::window(after, before, first, last)?;
 
-        graphql_translate(res)
-    }
+            let fields = PageFields::new(repository.clone(), |conn| quarantine_plans::table.count().get_result(conn));
 
-    #[graphql(name = "getQuarantinePlans")]
-    /// Accepts argument "count" and returns a vector of {count} quarantine plans
-    pub async fn get_quarantine_plans(&self, context: &Context<'_>, count: i64) -> FieldResult<Vec<QuarantinePlan>> {
-        let conn = get_connection_from_context(context);
+            repository.run(move |conn| {
+                let mut page = quarantine_plans::table.into_boxed();
 
-        let res = quarantine_plans::table
-            .limit(count)
-            .load::<QuarantinePlan>(&conn);
+                for (cursor, later) in args.bounds(SortDirection::Asc) {
+                    page = crate::keyset_filter!(page, quarantine_plans::date_created, quarantine_plans::id, cursor.date(), cursor.id, later);
+                }
 
-        graphql_translate(res)
-    }
+                let page = match args.load_direction(SortDirection::Asc) {
+                    SortDirection::Asc => page.order((quarantine_plans::date_created.asc(), quarantine_plans::id.asc())),
+                    SortDirection::Desc => page.order((quarantine_plans::date_created.desc(), quarantine_plans::id.desc())),
+                };
 
-    #[graphql(name = "allCovidTestResults")]
-    /// Returns a vector of all covid test results
-    pub async fn all_covid_test_results(&self, context: &Context<'_>) -> FieldResult<Vec<CovidTest>> {
-        let conn = get_connection_from_context(context);
+                let res = page
+                    .limit(args.limit())
+                    .load::<QuarantinePlan>(conn);
 
-        let res = covid_tests::table.load::<CovidTest>(&conn);
-
-        graphql_translate(res)
+                Ok(build_page(&args, graphql_translate(res)?, fields, |q| KeysetCursor::on_date(q.date_created, q.id)))
+            }).await
+        }).await
     }
 
-    #[graphql(name = "getCovidTestResults")]
-    /// Accepts argument "count" and returns a vector of {count} covid test results
-    pub async fn get_covid_test_results(&self, context: &Context<'_>, count: i64) -> FieldResult<Vec<CovidTest>> {
-        let conn = get_connection_from_context(context);
-
-        let res = covid_tests::table
-            .limit(count)
-            .load::<CovidTest>(&conn);
+    #[graphql(name = "covidTestResults", complexity = "page_cost(first, last, child_complexity)")]
+    /// Pages through covid test results matching filter, ordered by date taken
+    pub async fn covid_test_results(
+        &self,
+        context: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        filter: Option<CovidTestFilter>,
+        direction: Option<SortDirection>,
+    ) -> FieldResult<Page<CovidTest>> {
+        let repository = Repository::from_context(context)?;
+
+        let filter = filter.unwrap_or_default();
+        let direction = direction.unwrap_or_default();
+
+        query(after, before, first, last, |after, before, first, last| async move {
+            let args = PageArgs::window(after, before, first, last)?;
+
+            let count_filter = filter.clone();
+            let fields = PageFields::new(repository.clone(), move |conn| count_filter.query().count().get_result(conn));
+
+            repository.run(move |conn| {
+                let res = filter.page(direction, &args)
+                    .limit(args.limit())
+                    .load::<CovidTest>(conn);
+
+                Ok(build_page(&args, graphql_translate(res)?, fields, CovidTestFilter::cursor))
+            }).await
+        }).await
+    }
 
-        graphql_translate(res)
+    #[graphql(
+        name = "travelResponses",
+        guard = "RoleGuard::new(Role::Analyst)",
+        visible = "is_analyst",
+        complexity = "page_cost(first, last, child_complexity)",
+    )]
+    /// Pages through the PILResponses given to CBSA matching filter, ordered by date
+    pub async fn travel_responses(
+        &self,
+        context: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        filter: Option<PILResponseFilter>,
+        direction: Option<SortDirection>,
+    ) -> FieldResult<Page<PILResponse>> {
+        let repository = Repository::from_context(context)?;
+
+        let filter = filter.unwrap_or_default();
+        let direction = direction.unwrap_or_default();
+
+        query(after, before, first, last, |after, before, first, last| async move {
+            let args = PageArgs::window(after, before, first, last)?;
+
+            let count_filter = filter.clone();
+            let fields = PageFields::new(repository.clone(), move |conn| count_filter.query().count().get_result(conn));
+
+            repository.run(move |conn| {
+                let res = filter.page(direction, &args)
+                    .limit(args.limit())
+                    .load::<PILResponse>(conn);
+
+                Ok(build_page(&args, graphql_translate(res)?, fields, PILResponseFilter::cursor))
+            }).await
+        }).await
     }
 
     #[graphql(
-        name = "allUsers",
+        name = "users",
         guard = "RoleGuard::new(Role::Admin)",
         visible = "is_admin",
+        complexity = "page_cost(first, last, child_complexity)",
     )]
-    /// Returns a vector of all users
-    pub async fn all_users(&self, context: &Context<'_>) -> FieldResult<Vec<User>> {
-        let conn = get_connection_from_context(context);
+    /// Pages through users in the order they were added
+    pub async fn users(
+        &self,
+        context: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> FieldResult<Page<User>> {
+        let repository = Repository::from_context(context)?;
+
+        query(after, before, first, last, |after, before, first, last| async move {
+            let args = PageArgs::window(after, before, first, last)?;
+
+            let fields = PageFields::new(repository.clone(), |conn| users::table.count().get_result(conn));
+
+            repository.run(move |conn| {
+                let mut page = users::table.into_boxed();
+
+                for (cursor, later) in args.bounds(SortDirection::Asc) {
+                    page = crate::keyset_filter!(page, users::created_at, users::id, cursor.key, cursor.id, later);
+                }
 
-        let res = users::table.load::<User>(&conn);
+                let page = match args.load_direction(SortDirection::Asc) {
+                    SortDirection::Asc => page.order((users::created_at.asc(), users::id.asc())),
+                    SortDirection::Desc => page.order((users::created_at.desc(), users::id.desc())),
+                };
 
-        graphql_translate(res)
+                let res = page
+                    .limit(args.limit())
+                    .load::<User>(conn);
+
+                Ok(build_page(&args, graphql_translate(res)?, fields, |u| KeysetCursor::new(Some(u.created_at), u.id)))
+            }).await
+        }).await
     }
 
     #[graphql(
@@ -205,11 +332,11 @@ impl Query {
     )]
     /// Returns a vector of all users
     pub async fn get_user_by_email(&self, context: &Context<'_>, email: String) -> FieldResult<User> {
-        let conn = get_connection_from_context(context);
-
-        let res = User::get_by_email(&email, &conn);
+        Repository::from_context(context)?.run(move |conn| {
+            let res = User::get_by_email(&email, conn);
 
-        res
+            res
+        }).await
     }
 
     #[graphql(
@@ -219,10 +346,99 @@ impl Query {
     )]
     /// Returns a vector of all users
     pub async fn get_user_by_id(&self, context: &Context<'_>, id: Uuid) -> FieldResult<User> {
-        let conn = get_connection_from_context(context);
+        Repository::from_context(context)?.run(move |conn| {
+            let res = User::get_by_id(&id, conn);
+
+            res
+        }).await
+    }
+
+    #[graphql(name = "allTravelProviders")]
+    /// Returns a vector of all registered travel providers ordered by name
+    pub async fn all_travel_providers(&self, context: &Context<'_>) -> FieldResult<Vec<TravelProvider>> {
+        Repository::from_context(context)?
+            .run(TravelProvider::get_all)
+            .await
+    }
+
+    #[graphql(name = "portsOfEntry")]
+    /// Returns a vector of Canadian ports of entry ordered by name, optionally of one kind
+    pub async fn ports_of_entry(
+        &self,
+        context: &Context<'_>,
+        kind: Option<PlaceKind>,
+    ) -> FieldResult<Vec<Place>> {
+        Repository::from_context(context)?
+            .run(move |conn| Place::get_ports_of_entry(conn, kind))
+            .await
+    }
+
+    #[graphql(name = "allCountries")]
+    /// Returns a vector of all countries ordered by name
+    pub async fn all_countries(&self, context: &Context<'_>) -> FieldResult<Vec<Country>> {
+        Repository::from_context(context)?
+            .run(Country::get_all)
+            .await
+    }
+
+    #[graphql(name = "countryAliases")]
+    /// Returns the other names a country is known by
+    pub async fn country_aliases(&self, context: &Context<'_>, country_id: Uuid) -> FieldResult<Vec<CountryAlias>> {
+        Repository::from_context(context)?
+            .run(move |conn| CountryAlias::get_by_country_id(conn, country_id))
+            .await
+    }
+
+    #[graphql(
+        name = "pendingCountries",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Returns submitted country names awaiting approval, most often seen first
+    pub async fn pending_countries(&self, context: &Context<'_>) -> FieldResult<Vec<PendingCountry>> {
+        Repository::from_context(context)?
+            .run(PendingCountry::get_all)
+            .await
+    }
 
-        let res = User::get_by_id(&id, &conn);
+    #[graphql(
+        name = "duplicatePlaces",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Returns groups of places in the same country with the same normalized name,
+    /// to be merged with mergePlaces
+    pub async fn duplicate_places(&self, context: &Context<'_>) -> FieldResult<Vec<Vec<Place>>> {
+        let places = context.places()?.lock().unwrap();
+
+        Ok(Place::find_duplicates(&places))
+    }
+
+    #[graphql(
+        name = "persistedQueries",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Returns the registered query documents, most recently registered first
+    pub async fn persisted_queries(&self, context: &Context<'_>) -> FieldResult<Vec<PersistedQuery>> {
+        Repository::from_context(context)?
+            .run(PersistedQuery::get_all)
+            .await
+    }
 
-        res
+    #[graphql(
+        name = "personMatchReviews",
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    /// Returns uncertain person matches, PENDING if no status is given, highest score first
+    pub async fn person_match_reviews(
+        &self,
+        context: &Context<'_>,
+        status: Option<PersonMatchStatus>,
+    ) -> FieldResult<Vec<PersonMatchReview>> {
+        Repository::from_context(context)?
+            .run(move |conn| PersonMatchReview::get_by_status(conn, status.unwrap_or(PersonMatchStatus::Pending)))
+            .await
     }
-}
\ No newline at end of file
+}
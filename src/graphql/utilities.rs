@@ -1,57 +1,157 @@
 use std::sync::{Arc, Mutex};
-use crate::{database::PostgresPool};
+use crate::database::{PostgresPool, Repository};
 
 use async_graphql::*;
-use diesel::{PgConnection};
-use diesel::r2d2::ConnectionManager;
-use r2d2::PooledConnection;
+use async_graphql::dataloader::DataLoader;
+use uuid::Uuid;
 
-use crate::models::{Country, Place, Vaccine,};
-use crate::graphql::{Query, Mutation}; // Removed Subscription
+use crate::common_utils::Role;
+use crate::config_variables::{MAX_QUERY_DEPTH, MAX_QUERY_COMPLEXITY};
+use crate::errors::error_handler::CustomError;
+use crate::models::{Country, CountryAlias, Place, PlaceAlias, Vaccine, TravelData, PILResponse, TransactionScope};
+use crate::graphql::{Query, Mutation, PersonLoader, PublicHealthProfileLoader, TripsByPersonLoader,
+    TravelDocumentsByPersonLoader, VaccinationsByProfileLoader, PostalAddressLoader}; // Removed Subscription
 
 // use crate::kafka::{create_producer};
 
 pub fn graphql_translate<T>(res: Result<T, diesel::result::Error>) -> FieldResult<T> {
     match res {
         Ok(t) => Ok(t),
-        Err(e) => Err(FieldError::from(e)),
+        Err(e) => Err(CustomError::from(e).extend()),
     }
 }
 
 pub type AppSchema = Schema<Query, Mutation, EmptySubscription>;
 
-pub fn create_schema_with_context(pg_pool: PostgresPool) -> async_graphql::Schema<Query, Mutation, EmptySubscription> {
+/// Builds the schema with the reference caches loaded from the database.
+/// Fails if the database can't be reached or the caches can't be loaded.
+pub fn create_schema_with_context(pg_pool: PostgresPool) -> Result<AppSchema, CustomError> {
     
-    let cloned_conn = pg_pool.clone().get().expect("Unable to connect to db");
+    let cloned_conn = pg_pool.clone().get()
+        .map_err(|e| CustomError::Internal(format!("Unable to connect to db: {}", e)))?;
     
     let arc_pool = Arc::new(pg_pool);
 
-    let countries = Arc::new(Mutex::new(Country::load_into_hash(&cloned_conn)));
-    let places = Arc::new(Mutex::new(Place::load_into_hash(&cloned_conn)));
-    let vaccines = Vaccine::load_into_hash(&cloned_conn);
+    let countries = Arc::new(Mutex::new(Country::load_into_hash(&cloned_conn)?));
+    let country_aliases = Arc::new(Mutex::new(CountryAlias::load_into_hash(&cloned_conn)?));
+    let places = Arc::new(Mutex::new(Place::load_into_hash(&cloned_conn)?));
+    let place_aliases = Arc::new(Mutex::new(PlaceAlias::load_into_hash(&cloned_conn)?));
+    let vaccines = Arc::new(Vaccine::load_into_hash(&cloned_conn)?);
     let identity: Option<String> = None;
 
     let kafka_consumer_counter = Mutex::new(0);
     
-    Schema::build(Query, Mutation, EmptySubscription)
+    let schema = Schema::build(Query, Mutation, EmptySubscription)
+        // Batched loads for nested resolvers
+        .data(DataLoader::new(PersonLoader(Repository::new(arc_pool.clone())), actix_rt::spawn))
+        .data(DataLoader::new(PublicHealthProfileLoader(Repository::new(arc_pool.clone())), actix_rt::spawn))
+        .data(DataLoader::new(TripsByPersonLoader(Repository::new(arc_pool.clone())), actix_rt::spawn))
+        .data(DataLoader::new(TravelDocumentsByPersonLoader(Repository::new(arc_pool.clone())), actix_rt::spawn))
+        .data(DataLoader::new(VaccinationsByProfileLoader(Repository::new(arc_pool.clone())), actix_rt::spawn))
+        .data(DataLoader::new(PostalAddressLoader(Repository::new(arc_pool.clone())), actix_rt::spawn))
         // Database connection
         .data(arc_pool)
-        // Live cached data -> may want to remove once dataloaders in place
+        // Live cached reference data
         .data(countries)
+        .data(country_aliases)
         .data(places)
+        .data(place_aliases)
         .data(vaccines)
         .data(identity)
         // Kafka
         // .data(create_producer())
         .data(kafka_consumer_counter)
-        .finish()
+        // Reject queries too deep or costly to run, see graphql::limits
+        .limit_depth(MAX_QUERY_DEPTH)
+        .limit_complexity(MAX_QUERY_COMPLEXITY)
+        .finish();
+
+    Ok(schema)
+}
+
+/// PILQuery document used when running TravelData through the engine outside of
+/// the /graphql endpoint. Fields are aliased to snake_case so the response
+/// deserializes straight into PILResponse.
+pub const PIL_QUERY_DOCUMENT: &str = r#"
+mutation PILQuery($data: [TravelDataInput!]!, $transactionScope: TransactionScope) {
+    PILQuery(data: $data, transactionScope: $transactionScope) {
+        id
+        post_status: postStatus
+        trip_id: tripId
+        person_id: personId
+        cbsa_id: cbsaId
+        response_code: responseCode
+        random_testing_referral: randomTestingReferral
+        quarantine_required: quarantineRequired
+        date_time: dateTime
+        details
+        person_created: personCreated
+    }
+}"#;
+
+/// Executes PILQuery in-process on behalf of the user identified by role and user_id.
+/// Guards are applied exactly as they would be for a request to /graphql.
+pub async fn execute_pil_query(
+    schema: &AppSchema,
+    role: Role,
+    user_id: Uuid,
+    data: &[TravelData],
+    transaction_scope: Option<TransactionScope>,
+) -> Result<Vec<PILResponse>, String> {
+
+    let travellers = Value::List(data.iter().map(|t| t.to_value()).collect());
+
+    let mut variables = Variables::default();
+    variables.insert(Name::new("data"), travellers);
+    variables.insert(Name::new("transactionScope"), transaction_scope.to_value());
+
+    run_pil_query(schema, role, user_id, variables).await
 }
 
-type Conn = PooledConnection<ConnectionManager<PgConnection>>;
+/// As execute_pil_query, but takes the travellers as they would appear in the
+/// $data variable of a request to /graphql (a JSON array of TravelDataInput).
+/// Each traveller is processed in its own transaction.
+pub async fn execute_pil_query_json(
+    schema: &AppSchema,
+    role: Role,
+    user_id: Uuid,
+    travellers: serde_json::Value,
+) -> Result<Vec<PILResponse>, String> {
+
+    run_pil_query(schema, role, user_id, Variables::from_json(serde_json::json!({ "data": travellers }))).await
+}
+
+async fn run_pil_query(
+    schema: &AppSchema,
+    role: Role,
+    user_id: Uuid,
+    variables: Variables,
+) -> Result<Vec<PILResponse>, String> {
+
+    let request = Request::new(PIL_QUERY_DOCUMENT)
+        .variables(variables)
+        .data(role)
+        .data(user_id);
+
+    let response = schema.execute(request).await;
+
+    if !response.errors.is_empty() {
+        let messages: Vec<String> = response.errors
+            .iter()
+            .map(|e| e.message.to_owned())
+            .collect();
+
+        return Err(messages.join("; "));
+    }
+
+    let data = response.data.into_json()
+        .map_err(|e| format!("Unable to read PILQuery response: {}", e))?;
+
+    read_pil_query_data(data)
+}
 
-pub fn get_connection_from_context(ctx: &Context<'_>) -> Conn {
-    ctx.data::<Arc<PostgresPool>>()
-        .expect("Can't get pool")
-        .get()
-        .expect("Can't get DB connection")
+/// Reads the PILResponses out of the data of a PIL_QUERY_DOCUMENT response
+pub fn read_pil_query_data(mut data: serde_json::Value) -> Result<Vec<PILResponse>, String> {
+    serde_json::from_value(data["PILQuery"].take())
+        .map_err(|e| format!("Unable to read PILQuery response: {}", e))
 }
\ No newline at end of file
@@ -1,12 +1,16 @@
 use actix_web::{web, HttpResponse, HttpRequest, Result};
 use async_graphql::http::{GraphQLPlaygroundConfig, playground_source};
-use async_graphql::Schema;
+use async_graphql::{ErrorExtensions, Pos, Response, Schema};
 
 use async_graphql_actix_web::{GraphQLSubscription,
     GraphQLRequest, GraphQLResponse};
+use uuid::Uuid;
 
 use crate::models;
-use crate::graphql::{AppSchema};
+use crate::graphql::{AppSchema, RateLimiter, QueryMode, apply_persisted_query, needs_persisted_query};
+use crate::common_utils::Role;
+use crate::database;
+use crate::errors::error_handler::CustomError;
 
 
 pub async fn playground_handler() -> HttpResponse {
@@ -19,13 +23,49 @@ pub async fn playground_handler() -> HttpResponse {
 
 pub async fn graphql(
     schema: web::Data<AppSchema>,
+    limiter: web::Data<RateLimiter>,
+    mode: web::Data<QueryMode>,
     http_request: HttpRequest,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
     
     let mut query = req.into_inner();
 
-    let maybe_role_id = models::get_claim(http_request);
+    let maybe_role_id = models::get_claim(http_request.clone());
+
+    if let Err(retry_after) = limiter.check(&rate_key(&limiter, &http_request, &maybe_role_id)) {
+        let error = CustomError::RateLimited(format!("try again in {} seconds", retry_after)).extend();
+
+        return Response::from_errors(vec![error.into_server_error(Pos::default())]).into();
+    }
+
+    // Documents sent by hash, and the allow list in PersistedOnly mode
+    let mode = *mode.get_ref();
+    let is_admin = matches!(&maybe_role_id, Ok((Role::Admin, _, _)));
+
+    if needs_persisted_query(&query, mode, is_admin) {
+        let res = web::block(move || {
+            let conn = database::connection().map_err(|e| e.extend())?;
+            apply_persisted_query(&conn, &mut query, mode, is_admin)?;
+            Ok::<_, async_graphql::Error>(query)
+        }).await;
+
+        query = match res {
+            Ok(Ok(q)) => q,
+            Ok(Err(e)) => return Response::from_errors(vec![e.into_server_error(Pos::default())]).into(),
+            Err(e) => {
+                let error = CustomError::Internal(format!("Persisted query lookup failed: {}", e)).extend();
+                return Response::from_errors(vec![error.into_server_error(Pos::default())]).into();
+            },
+        };
+    }
+
+    // Optional idempotency key for PILQuery retries
+    if let Some(key) = http_request.headers()
+        .get("Idempotency-Key")
+        .and_then(|v| v.to_str().ok()) {
+        query = query.data(models::IdempotencyKey(key.trim().to_owned()));
+    }
 
     // insert claim data into query or error for response
     match maybe_role_id {
@@ -42,10 +82,38 @@ pub async fn graphql(
     schema.execute(query).await.into()
 }
 
+/// Operations sent over a socket are never checked against the persisted
+/// query store, so upgrades are refused in PersistedOnly mode. Each upgrade
+/// counts against the rate limit like a request to /graphql.
 pub async fn graphql_ws(
     schema: web::Data<AppSchema>,
+    limiter: web::Data<RateLimiter>,
+    mode: web::Data<QueryMode>,
     req: HttpRequest,
     payload: web::Payload,
 ) -> Result<HttpResponse> {
+
+    if *mode.get_ref() == QueryMode::PersistedOnly {
+        return Ok(HttpResponse::Forbidden()
+            .body("Only persisted queries can be run, send them to /graphql over POST"));
+    }
+
+    if let Err(retry_after) = limiter.check(&rate_key(&limiter, &req, &models::get_claim(req.clone()))) {
+        return Ok(HttpResponse::TooManyRequests()
+            .append_header(("Retry-After", retry_after.to_string()))
+            .body(format!("Rate limited: try again in {} seconds", retry_after)));
+    }
+
     GraphQLSubscription::new(Schema::clone(&*schema)).start(&req, payload)
 }
+
+/// Requests are counted per JWT subject, or per address before sign in
+fn rate_key(limiter: &RateLimiter, http_request: &HttpRequest, claim: &Result<(Role, Uuid, i64), CustomError>) -> String {
+    match claim {
+        Ok((_, uuid, _)) => uuid.to_string(),
+        Err(_) => limiter.client_key(
+            http_request.peer_addr(),
+            http_request.connection_info().realip_remote_addr(),
+        ),
+    }
+}
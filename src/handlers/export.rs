@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use async_graphql::ErrorExtensions;
+use bytes::Bytes;
+
+use crate::common_utils::Role;
+use crate::config_variables::{EXPORT_PAGE_SIZE, MAX_XLSX_ROWS};
+use crate::database::{Repository, POOL};
+use crate::errors::error_handler::CustomError;
+use crate::models::{self, Export, ExportDataset, ExportFilter, ExportFormat, csv_bytes};
+
+/// Exports trips, travel_responses, vaccinations or covid_tests as CSV or XLSX.
+/// CSV is streamed in pages of EXPORT_PAGE_SIZE rows, each loaded on the
+/// blocking thread pool. XLSX is built in memory and limited to
+/// MAX_XLSX_ROWS rows.
+/// Access Level: Analyst, Admin
+pub async fn export_dataset(
+    http_request: HttpRequest,
+    dataset: web::Path<ExportDataset>,
+    filter: web::Query<ExportFilter>,
+) -> HttpResponse {
+
+    let (role, _user_id, _exp_time) = match models::get_claim(http_request) {
+        Ok(claim) => claim,
+        Err(e) => return HttpResponse::Unauthorized().body(e.to_string()),
+    };
+
+    if !role.is_analyst() {
+        return HttpResponse::Forbidden()
+            .body(format!("Access denied: {} role required", Role::Analyst));
+    }
+
+    let repository = Repository::new(Arc::new(POOL.clone()));
+
+    let dataset = dataset.into_inner();
+    let filter = filter.into_inner();
+    let format = filter.format.unwrap_or(ExportFormat::Csv);
+
+    let export = match repository.run(move |conn| Export::new(conn, dataset, &filter, &role).map_err(|e| e.extend())).await {
+        Ok(e) => Arc::new(e),
+        Err(e) => return HttpResponse::InternalServerError().body(e.message),
+    };
+
+    let disposition = format!("attachment; filename=\"{}.{}\"", dataset.name(), format.extension());
+
+    match format {
+        ExportFormat::Xlsx => {
+            // Ok(Err(..)) is an export refused as too large, reported with its own status
+            let workbook = repository.run(move |conn| {
+                Ok(export.to_xlsx(conn, EXPORT_PAGE_SIZE, MAX_XLSX_ROWS))
+            }).await;
+
+            match workbook {
+                Ok(Ok(workbook)) => HttpResponse::Ok()
+                    .content_type(format.content_type())
+                    .append_header(("Content-Disposition", disposition))
+                    .body(workbook),
+                Ok(Err(e)) => HttpResponse::build(e.status_code()).body(e.to_string()),
+                Err(e) => HttpResponse::InternalServerError().body(e.message),
+            }
+        },
+        ExportFormat::Csv => {
+            let body = async_stream::stream! {
+                match csv_bytes(&[export.headers()]) {
+                    Ok(b) => yield Ok(Bytes::from(b)),
+                    Err(e) => {
+                        yield Err(std::io::Error::new(std::io::ErrorKind::Other, e));
+                        return;
+                    }
+                };
+
+                let mut after = None;
+
+                loop {
+                    let page_export = export.clone();
+
+                    let page = repository.run(move |conn| {
+                        page_export.load_page(conn, after, EXPORT_PAGE_SIZE)
+                            .map_err(|e| CustomError::Internal(e.to_string()).extend())
+                    }).await
+                        .map_err(|e| e.message)
+                        .and_then(|page| csv_bytes(&page.rows).map(|b| (page.rows.len() as i64, page.last_id, b)));
+
+                    match page {
+                        Ok((count, last_id, b)) => {
+                            yield Ok(Bytes::from(b));
+
+                            if count < EXPORT_PAGE_SIZE {
+                                break;
+                            }
+                            after = last_id;
+                        },
+                        Err(e) => {
+                            yield Err(std::io::Error::new(std::io::ErrorKind::Other, e));
+                            break;
+                        }
+                    }
+                }
+            };
+
+            HttpResponse::Ok()
+                .content_type(format.content_type())
+                .append_header(("Content-Disposition", disposition))
+                .streaming(body)
+        },
+    }
+}
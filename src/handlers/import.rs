@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse};
+use async_graphql::ErrorExtensions;
+use futures::StreamExt;
+use serde::Deserialize;
+
+use crate::common_utils::Role;
+use crate::config_variables::{IMPORT_BATCH_SIZE, MAX_IMPORT_SIZE};
+use crate::database::{Repository, POOL};
+use crate::graphql::{AppSchema, execute_pil_query};
+use crate::models::{self, ImportReport, ImportRowResult,
+    ManifestFormat, PILResponse, TransactionScope, TravelData, TravelProvider, MANIFEST_SUBMIT_SCOPE, read_manifest};
+
+#[derive(Debug, Deserialize)]
+pub struct ImportOptions {
+    /// Number of travellers sent to PILQuery at a time
+    pub batch_size: Option<usize>,
+    /// Overrides format detection for the uploaded file
+    pub format: Option<ManifestFormat>,
+}
+
+/// Accepts a multipart upload of a CSV or XLSX traveller manifest and runs each
+/// valid row through PILQuery. Returns an ImportReport with the outcome of every row.
+/// Each batch is one transaction. If any row in it fails the batch is rolled
+/// back and its rows are retried one at a time, so no row is written twice.
+/// The upload must be a single file of at most MAX_IMPORT_SIZE bytes.
+/// Travel providers may submit their own manifests with an X-Api-Key header;
+/// rows for another provider's trips are rejected.
+/// Access Level: Operator, Admin, TravelProvider with manifest:submit scope
+pub async fn import_travellers(
+    schema: web::Data<AppSchema>,
+    http_request: HttpRequest,
+    options: web::Query<ImportOptions>,
+    mut payload: Multipart,
+) -> HttpResponse {
+
+    let api_key = http_request.headers()
+        .get("X-Api-Key")
+        .and_then(|v| v.to_str().ok())
+        .map(|k| k.to_owned());
+
+    let (role, user_id, provider) = match api_key {
+        Some(key) => {
+            let repository = Repository::new(Arc::new(POOL.clone()));
+
+            let res = repository.run(move |conn| {
+                TravelProvider::authenticate(conn, &key).map_err(|e| e.extend())
+            }).await;
+
+            let provider = match res {
+                Ok(p) => p,
+                Err(e) => return HttpResponse::Unauthorized().body(e.message),
+            };
+
+            if !provider.has_scope(MANIFEST_SUBMIT_SCOPE) {
+                return HttpResponse::Forbidden()
+                    .body(format!("Access denied: {} scope required", MANIFEST_SUBMIT_SCOPE));
+            }
+
+            // Manifests are processed as an operator would, recorded against the provider
+            (Role::Operator, provider.id, Some(provider))
+        },
+        None => {
+            let (role, user_id, _exp_time) = match models::get_claim(http_request) {
+                Ok(claim) => claim,
+                Err(e) => return HttpResponse::Unauthorized().body(e.to_string()),
+            };
+
+            if !role.is_operator() {
+                return HttpResponse::Forbidden()
+                    .body(format!("Access denied: {} role required", Role::Operator));
+            }
+
+            (role, user_id, None)
+        },
+    };
+
+    // The upload is a single manifest file
+    let mut upload: Vec<u8> = Vec::new();
+
+    if let Some(item) = payload.next().await {
+        let mut field = match item {
+            Ok(f) => f,
+            Err(e) => return HttpResponse::BadRequest().body(format!("Invalid upload: {}", e)),
+        };
+
+        while let Some(chunk) = field.next().await {
+            match chunk {
+                Ok(bytes) if upload.len() + bytes.len() > MAX_IMPORT_SIZE => {
+                    return HttpResponse::PayloadTooLarge()
+                        .body(format!("Manifests are limited to {} bytes", MAX_IMPORT_SIZE));
+                },
+                Ok(bytes) => upload.extend_from_slice(&bytes),
+                Err(e) => return HttpResponse::BadRequest().body(format!("Invalid upload: {}", e)),
+            }
+        }
+    }
+
+    if payload.next().await.is_some() {
+        return HttpResponse::BadRequest().body("Upload a single manifest file");
+    }
+
+    if upload.is_empty() {
+        return HttpResponse::BadRequest().body("No manifest file uploaded");
+    }
+
+    let format = options.format.unwrap_or_else(|| ManifestFormat::detect(&upload));
+
+    let rows = match read_manifest(&upload, format) {
+        Ok(r) => r,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    let batch_size = options.batch_size.unwrap_or(IMPORT_BATCH_SIZE).max(1);
+
+    let mut results: Vec<ImportRowResult> = Vec::new();
+    let mut valid: Vec<(usize, TravelData)> = Vec::new();
+
+    for (row, parsed) in rows {
+        let data = parsed
+            .map_err(|e| vec![e])
+            .and_then(|r| r.into_travel_data())
+            .and_then(|data| match &provider {
+                Some(p) => scope_to_provider(data, p),
+                None => Ok(data),
+            });
+
+        match data {
+            Ok(data) => valid.push((row, data)),
+            Err(reasons) => results.push(ImportRowResult::rejected(row, reasons)),
+        }
+    }
+
+    for batch in valid.chunks(batch_size) {
+        let travellers: Vec<TravelData> = batch.iter().map(|(_, t)| t.clone()).collect();
+
+        match execute_pil_query(&schema, role, user_id, &travellers, Some(TransactionScope::Group)).await {
+            Ok(responses) if !responses.iter().any(|r| r.is_error()) => {
+                for ((row, _), response) in batch.iter().zip(responses) {
+                    results.push(row_result(*row, response));
+                }
+            },
+            // The batch was rolled back, so each row is retried alone to report its own error
+            _ => {
+                for (row, traveller) in batch {
+                    let single = std::slice::from_ref(traveller);
+
+                    let result = match execute_pil_query(&schema, role, user_id, single, Some(TransactionScope::Group)).await {
+                        Ok(mut responses) if !responses.is_empty() => row_result(*row, responses.remove(0)),
+                        Ok(_) => ImportRowResult::rejected(*row, vec!["No response for traveller".to_string()]),
+                        Err(e) => ImportRowResult::rejected(*row, vec![e]),
+                    };
+
+                    results.push(result);
+                }
+            },
+        }
+    }
+
+    HttpResponse::Ok().json(ImportReport::from(results))
+}
+
+fn row_result(row: usize, response: PILResponse) -> ImportRowResult {
+    if response.is_error() {
+        return ImportRowResult::rejected(row, response.details.into_iter().collect());
+    }
+
+    ImportRowResult::processed(row, &response)
+}
+
+/// Providers may only submit their own trips. trip_provider may be left
+/// blank and is set to the registered name so the trip links to the provider.
+fn scope_to_provider(mut data: TravelData, provider: &TravelProvider) -> Result<TravelData, Vec<String>> {
+    let trip_provider = data.trip_provider.trim();
+
+    if !trip_provider.is_empty() && !trip_provider.eq_ignore_ascii_case(&provider.provider_name) {
+        return Err(vec![format!(
+            "trip_provider {} does not match API key provider {}",
+            data.trip_provider, provider.provider_name)]);
+    }
+
+    data.trip_provider = provider.provider_name.to_owned();
+
+    Ok(data)
+}
@@ -1,8 +1,12 @@
 mod base;
 mod routes;
 mod endpoints;
+mod import;
+mod export;
 
 pub use self::routes::configure_services;
 
 pub use self::base::{index, api_base};
-pub use self::endpoints::*;
\ No newline at end of file
+pub use self::endpoints::*;
+pub use self::import::*;
+pub use self::export::*;
\ No newline at end of file
@@ -6,6 +6,8 @@ use crate::handlers::{
     playground_handler,
     graphql,
     graphql_ws,
+    import_travellers,
+    export_dataset,
     
     // API
     // get_trips,
@@ -32,4 +34,8 @@ pub fn configure_services(config: &mut web::ServiceConfig) {
         )
         .route(web::post().to(graphql))
     );
+    // Bulk upload of traveller manifests (CSV, XLSX)
+    config.route("/import/travellers", web::post().to(import_travellers));
+    // Exports (CSV, XLSX), personal columns for analysts only
+    config.route("/export/{dataset}", web::get().to(export_dataset));
 }
@@ -16,13 +16,16 @@ extern crate strum_macros;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use async_graphql::*;
-use models::{Country, NewCountry, Place, Vaccine};
+use models::{Country, CountryAlias, PendingCountry, Place, PlaceAlias, PlaceKind, Vaccine,
+    normalize_country_name, normalize_place_name};
 use tera::{Tera};
 
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
 use uuid::Uuid;
 
+use crate::errors::error_handler::CustomError;
+
 pub mod models;
 pub mod handlers;
 pub mod errors;
@@ -33,113 +36,290 @@ pub mod common_utils;
 pub mod config_variables;
 //ub mod kafka;
 
-use crate::graphql::{get_connection_from_context};
-
 pub struct AppData {
     pub tmpl: Tera
 }
 
-pub fn get_place_by_id(context: &Context<'_>, id: Uuid) -> FieldResult<Place> {
+pub type Countries = Arc<Mutex<HashMap<Uuid, Country>>>;
+pub type CountryAliases = Arc<Mutex<HashMap<String, Uuid>>>;
+pub type Places = Arc<Mutex<HashMap<Uuid, Place>>>;
+pub type PlaceAliases = Arc<Mutex<HashMap<String, Vec<Uuid>>>>;
+pub type Vaccines = Arc<HashMap<Uuid, Vaccine>>;
+pub type Staged = Arc<Mutex<StagedReferences>>;
+
+#[derive(Debug, Default)]
+/// Reference rows created inside a transaction. They are only published to
+/// the shared caches once the transaction commits, see ReferenceData::finish_transaction.
+pub struct StagedReferences {
+    places: Vec<Place>,
+    /// Country names to queue for Admin approval, committed or not
+    unknown_countries: Vec<String>,
+}
+
+/// The reference caches, from a resolver's Context or a ReferenceData
+/// moved onto the blocking thread pool
+pub trait ReferenceSource {
+    fn countries(&self) -> FieldResult<&Countries>;
+    fn country_aliases(&self) -> FieldResult<&CountryAliases>;
+    fn places(&self) -> FieldResult<&Places>;
+    fn place_aliases(&self) -> FieldResult<&PlaceAliases>;
+    fn vaccines(&self) -> FieldResult<&Vaccines>;
+    fn staged(&self) -> FieldResult<&Staged>;
+}
+
+impl<'a> ReferenceSource for Context<'a> {
+    fn countries(&self) -> FieldResult<&Countries> {
+        self.data::<Countries>()
+    }
+
+    fn country_aliases(&self) -> FieldResult<&CountryAliases> {
+        self.data::<CountryAliases>()
+    }
+
+    fn places(&self) -> FieldResult<&Places> {
+        self.data::<Places>()
+    }
+
+    fn place_aliases(&self) -> FieldResult<&PlaceAliases> {
+        self.data::<PlaceAliases>()
+    }
+
+    fn vaccines(&self) -> FieldResult<&Vaccines> {
+        self.data::<Vaccines>()
+    }
+
+    /// Resolvers don't create reference rows, writes go through a ReferenceData
+    fn staged(&self) -> FieldResult<&Staged> {
+        Err(CustomError::Internal("Reference rows can only be created through ReferenceData".to_string()).extend())
+    }
+}
+
+#[derive(Clone)]
+/// Shares a request's reference caches with work run through a Repository
+pub struct ReferenceData {
+    countries: Countries,
+    country_aliases: CountryAliases,
+    places: Places,
+    place_aliases: PlaceAliases,
+    vaccines: Vaccines,
+    /// Not shared with other requests
+    staged: Staged,
+}
+
+impl ReferenceData {
+    pub fn from_context(context: &Context<'_>) -> FieldResult<ReferenceData> {
+        Ok(ReferenceData {
+            countries: context.countries()?.clone(),
+            country_aliases: context.country_aliases()?.clone(),
+            places: context.places()?.clone(),
+            place_aliases: context.place_aliases()?.clone(),
+            vaccines: context.vaccines()?.clone(),
+            staged: Staged::default(),
+        })
+    }
+
+    /// Called with the caller's connection after each transaction that may have
+    /// created reference rows. Places created in a committed transaction are
+    /// added to the shared caches, those from a rolled back one are dropped.
+    /// Unknown countries are queued for approval either way.
+    pub fn finish_transaction(&self, conn: &PgConnection, committed: bool) -> FieldResult<()> {
+        let staged = std::mem::take(&mut *self.staged.lock().unwrap());
+
+        if committed {
+            let mut places = self.places.lock().unwrap();
+
+            for place in staged.places {
+                places.insert(place.id, place);
+            }
+        }
+
+        for country_name in staged.unknown_countries {
+            PendingCountry::record(conn, &country_name)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ReferenceSource for ReferenceData {
+    fn countries(&self) -> FieldResult<&Countries> {
+        Ok(&self.countries)
+    }
+
+    fn country_aliases(&self) -> FieldResult<&CountryAliases> {
+        Ok(&self.country_aliases)
+    }
+
+    fn places(&self) -> FieldResult<&Places> {
+        Ok(&self.places)
+    }
+
+    fn place_aliases(&self) -> FieldResult<&PlaceAliases> {
+        Ok(&self.place_aliases)
+    }
+
+    fn vaccines(&self) -> FieldResult<&Vaccines> {
+        Ok(&self.vaccines)
+    }
+
+    fn staged(&self) -> FieldResult<&Staged> {
+        Ok(&self.staged)
+    }
+}
+
+pub fn get_place_by_id(context: &impl ReferenceSource, id: Uuid) -> FieldResult<Place> {
 
-let places = context.data::<Arc<Mutex<HashMap<Uuid, Place>>>>()?.lock().unwrap();
+let places = context.places()?.lock().unwrap();
 
 let place = places
     .get(&id)
-    .expect("Unable to retrieve Place");
+    .ok_or_else(|| CustomError::UnknownPlace(id.to_string()).extend())?;
 
 Ok(place.clone())
 }
 
-// Change back to get_or_create_place_by_name_and_country_id
-pub fn get_or_create_place_by_name_and_country_id(context: &Context<'_>, name: String, country_id: Uuid) -> FieldResult<Place> {
+/// Port of entry with this IATA code or UN/LOCODE. A UN/LOCODE can be shared
+/// by a city's airport and seaport, so ports of kind are preferred.
+pub fn get_port_of_entry_by_code(context: &impl ReferenceSource, code: &str, kind: PlaceKind) -> FieldResult<Place> {
+
+let places = context.places()?.lock().unwrap();
 
-    let mut places = context.data::<Arc<Mutex<HashMap<Uuid, Place>>>>()?.lock().unwrap();
+let mut ports: Vec<&Place> = places
+    .values()
+    .filter(|p| p.is_port_of_entry && p.has_code(code))
+    .collect();
 
-    let res = places.iter()
-        .find_map(
-            |(_key, val)| 
-            if val.name == name && val.country_id == country_id { 
-                Some(val.clone()) 
-            } else { None });
+ports.sort_by_key(|p| p.kind != kind);
 
-    let place = match res {
-        Some(p) => p,
-        None => {
-            let conn = get_connection_from_context(context);
+let port = ports
+    .first()
+    .ok_or_else(|| CustomError::UnknownPlace(format!("port of entry {}", code)).extend())?;
 
-            let p = models::NewPlace::new(name, country_id);
-            let place = models::Place::create(
-                &conn, 
-                &p)?;
-            
-            places.insert(place.id, place.clone());
-            drop(places);
-            place
+Ok((*port).clone())
+}
+
+/// Matches name ignoring case, accents and punctuation, then by alias.
+/// Uses conn for any insert so that new places are part of the caller's transaction.
+/// New places are staged and only cached once that transaction commits.
+pub fn get_or_create_place_by_name_and_country_id(context: &impl ReferenceSource, conn: &PgConnection, name: String, country_id: Uuid) -> FieldResult<Place> {
+
+    let res = {
+        let places = context.places()?.lock().unwrap();
+
+        let res = places.values()
+            .find(|p| p.country_id == country_id && p.is_named(&name))
+            .cloned();
+
+        match res {
+            Some(p) => Some(p),
+            None => {
+                let aliases = context.place_aliases()?.lock().unwrap();
+
+                aliases
+                    .get(&normalize_place_name(&name))
+                    .and_then(|ids| ids.iter()
+                        .filter_map(|id| places.get(id))
+                        .find(|p| p.country_id == country_id))
+                    .cloned()
+            }
         }
     };
 
-    Ok(place.clone())
+    if let Some(p) = res {
+        return Ok(p);
+    }
+
+    let mut staged = context.staged()?.lock().unwrap();
+
+    // Created earlier in the same transaction
+    if let Some(p) = staged.places.iter().find(|p| p.country_id == country_id && p.is_named(&name)) {
+        return Ok(p.clone());
+    }
+
+    let p = models::NewPlace::new(name.trim().to_owned(), country_id);
+    let place = models::Place::create(
+        conn, 
+        &p)?;
+
+    staged.places.push(place.clone());
+
+    Ok(place)
 }
 
-pub fn get_country_by_id(context: &Context<'_>, id: Uuid) -> FieldResult<Country> {
+pub fn get_country_by_id(context: &impl ReferenceSource, id: Uuid) -> FieldResult<Country> {
 
-let countries = context.data::<Arc<Mutex<HashMap<Uuid, Country>>>>()?.lock().unwrap();
+let countries = context.countries()?.lock().unwrap();
 
 let country = countries
     .get(&id)
-    .expect("Unable to retrieve Country");
+    .ok_or_else(|| CustomError::NotFound(format!("Country {}", id)).extend())?;
 
     Ok(country.clone())
 }
 
-pub fn get_or_create_country_by_name(context: &Context<'_>, country_name: String) -> FieldResult<Country> {
-
-let mut countries = context.data::<Arc<Mutex<HashMap<Uuid, Country>>>>()?.lock().unwrap();
+/// Resolves a country name, ISO 3166 code or alias to its Country.
+/// Countries aren't created from submissions: an unknown name is staged to be
+/// queued for Admin approval after the transaction and the submission rejected.
+pub fn get_country_by_name(context: &impl ReferenceSource, country_name: &str) -> FieldResult<Country> {
 
-let res = countries.iter()
-    .find_map(|(_key, val)| if val.country_name == country_name { Some(val) } else { None });
+let countries = context.countries()?.lock().unwrap();
 
-let country = match res {
-    Some(c) => c.clone(),
+let res = countries.values()
+    .find(|c| c.is_named(country_name))
+    .cloned();
 
-    // None should *rarely* happen
+let res = match res {
+    Some(c) => Some(c),
     None => {
-        let c = NewCountry::new(country_name, 0.03);
+        let aliases = context.country_aliases()?.lock().unwrap();
 
-        let conn = get_connection_from_context(context);
-
-        // Insert country into DB
-        let country = Country::create(
-            &conn, 
-            &c)?;
-        
-        // Insert into Hashmap cache
-        countries.insert(country.id, country.clone());
-        drop(countries);
-        
-        country
+        aliases
+            .get(&normalize_country_name(country_name))
+            .and_then(|id| countries.get(id))
+            .cloned()
     }
 };
 
-    Ok(country.clone())
+drop(countries);
+
+match res {
+    Some(c) => Ok(c),
+    None => {
+        // Recorded by finish_transaction so the name stays queued if the caller's transaction rolls back
+        context.staged()?.lock().unwrap().unknown_countries.push(country_name.to_owned());
+
+        Err(CustomError::UnknownCountry(format!("{} is awaiting Admin approval", country_name.trim())).extend())
+    }
+}
 }
 
-pub fn get_vaccine_by_id(context: &Context<'_>, id: Uuid) -> FieldResult<Vaccine> {
-let vaccine = context.data::<HashMap<Uuid, Vaccine>>()?
+pub fn get_vaccine_by_id(context: &impl ReferenceSource, id: Uuid) -> FieldResult<Vaccine> {
+let vaccine = context.vaccines()?
     .get(&id)
-    .expect("Unable to retrieve Vaccine");
+    .ok_or_else(|| CustomError::UnknownVaccine(id.to_string()).extend())?;
 
     Ok(vaccine.clone())
 }
 
-pub fn get_vaccine_by_name(context: &Context<'_>, name: String) -> FieldResult<Vaccine> {
-let res = context.data::<HashMap<Uuid, Vaccine>>()?
+pub fn get_vaccine_by_name(context: &impl ReferenceSource, name: String) -> FieldResult<Vaccine> {
+let res = context.vaccines()?
     .iter()
     .find_map(|(_key, val)| if val.vaccine_name == name { Some(val) } else { None })
-    .expect("Unable to find vaccine");
+    .ok_or_else(|| CustomError::UnknownVaccine(name.to_owned()).extend())?;
 
     Ok(res.clone())
 }
 
+/// Reloads the Country, Place and alias caches from the database. Called after
+/// an Admin changes reference data.
+pub fn reload_reference_caches(context: &impl ReferenceSource, conn: &PgConnection) -> FieldResult<()> {
+    *context.countries()?.lock().unwrap() = Country::load_into_hash(conn).map_err(|e| e.extend())?;
+    *context.country_aliases()?.lock().unwrap() = CountryAlias::load_into_hash(conn).map_err(|e| e.extend())?;
+    *context.places()?.lock().unwrap() = Place::load_into_hash(conn).map_err(|e| e.extend())?;
+    *context.place_aliases()?.lock().unwrap() = PlaceAlias::load_into_hash(conn).map_err(|e| e.extend())?;
+
+    Ok(())
+}
+
 
 pub type Pool = r2d2::Pool<ConnectionManager<PgConnection>>;
\ No newline at end of file
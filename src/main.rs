@@ -1,10 +1,12 @@
 use std::env;
+use std::time::Duration;
 use actix_web::{web, App, HttpServer, middleware};
 use tera::{Tera};
 use tera_text_filters::snake_case;
 
 use health_rules_engine::database::{self, POOL};
-use health_rules_engine::graphql::{create_schema_with_context};
+use health_rules_engine::graphql::{create_schema_with_context, RateLimiter, QueryMode};
+use health_rules_engine::config_variables::{RATE_LIMIT_REQUESTS, RATE_LIMIT_WINDOW};
 use health_rules_engine::AppData;
 use health_rules_engine::handlers;
 
@@ -43,7 +45,18 @@ async fn main() -> std::io::Result<()> {
     println!("Serving on: {}:{}", &host, &port);
 
     // Create Schema
-    let schema = web::Data::new(create_schema_with_context(POOL.clone()));
+    let schema = match create_schema_with_context(POOL.clone()) {
+        Ok(s) => web::Data::new(s),
+        Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::Other, e.to_string())),
+    };
+
+    // Shared by every worker so a user's requests are counted together.
+    // Set TRUSTED_PROXIES to the load balancer's addresses to count clients behind it separately.
+    let limiter = web::Data::new(RateLimiter::new(RATE_LIMIT_REQUESTS, Duration::from_secs(RATE_LIMIT_WINDOW))
+        .with_trusted_proxies(RateLimiter::trusted_proxies_from_env()));
+
+    // Set PERSISTED_QUERIES_ONLY=true in production to only run registered documents
+    let query_mode = web::Data::new(QueryMode::from_env());
 
     
     HttpServer::new(move || {
@@ -60,6 +73,8 @@ async fn main() -> std::io::Result<()> {
             //.data(POOL.clone())
             .configure(handlers::configure_services)
             .app_data(schema.clone())
+            .app_data(limiter.clone())
+            .app_data(query_mode.clone())
             .app_data(app_data)
             .wrap(middleware::Logger::default())
     })
@@ -11,6 +11,7 @@ use jsonwebtoken::errors::*;
 
 use crate::common_utils::Role;
 use crate::config_variables::TOKEN_DURATION;
+use crate::errors::error_handler::CustomError;
 
 lazy_static! {
     static ref JWT_SECRET_KEY: String = 
@@ -46,20 +47,17 @@ pub fn create_token(user_id: String, role: Role) -> String {
     .expect("Can't create token")
 }
 
-pub fn get_claim(http_request: HttpRequest) -> Result<(Role, uuid::Uuid, i64), jsonwebtoken::errors::Error> {
-
-    println!("{:?}", &http_request.headers().get("Authorization"));
+/// Reads the role, user id and expiry from the bearer token.
+/// Any missing, invalid or unparseable token is CustomError::Unauthorized.
+pub fn get_claim(http_request: HttpRequest) -> Result<(Role, uuid::Uuid, i64), CustomError> {
 
     let token_data = http_request
         .headers()
         .get("Authorization")
         .and_then(|header_value| {
             header_value.to_str().ok().map(|s| {
-                let jwt_start_index = "Bearer ".len();
-                let jwt = s[jwt_start_index..s.len()].to_string();
-                let token_data = decode_token(&jwt);
-                println!("TOKEN: {:?}", &token_data);
-                token_data
+                let jwt = s.strip_prefix("Bearer ").unwrap_or(s).trim().to_string();
+                decode_token(&jwt)
             })
         });
 
@@ -67,15 +65,17 @@ pub fn get_claim(http_request: HttpRequest) -> Result<(Role, uuid::Uuid, i64), j
             Some(td) => {
                 let token = match td {
                     Ok(t) => t,
-                    Err(e) => return Err(e),
+                    Err(e) => return Err(CustomError::Unauthorized(format!("{:?}", e.kind()))),
                 };
                 token
             },
-            None => return Err(jsonwebtoken::errors::Error::from(ErrorKind::InvalidToken)),
+            None => return Err(CustomError::Unauthorized(format!("{:?}", ErrorKind::InvalidToken))),
         };
 
-        let role = Role::from_str(&token.claims.role).expect("Can't parse role");
-        let uuid = uuid::Uuid::from_str(&token.claims.sub).expect("Can't parse CBSA_ID");
+        let role = Role::from_str(&token.claims.role)
+            .map_err(|_| CustomError::Unauthorized(format!("Invalid role in token: {}", token.claims.role)))?;
+        let uuid = uuid::Uuid::from_str(&token.claims.sub)
+            .map_err(|_| CustomError::Unauthorized(format!("Invalid user id in token: {}", token.claims.sub)))?;
         let exp_time = &token.claims.exp;
 
         Ok((role, uuid.to_owned(), *exp_time))
@@ -9,16 +9,30 @@ use diesel::{RunQueryDsl, QueryDsl};
 use uuid::Uuid;
 
 use async_graphql::*;
+use diesel::pg::upsert::excluded;
 
 use crate::graphql::graphql_translate;
+use crate::errors::error_handler::CustomError;
 use crate::schema::*;
 
+/// Lower case without periods or repeated spaces, so "U.S.A." and "usa" match.
+/// Country aliases are stored in this form.
+pub fn normalize_country_name(name: &str) -> String {
+    name.replace('.', "")
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
 #[table_name = "countries"]
 /// Represents an insertable Country
 pub struct NewCountry {
     country_name: String,
     risk_rate: f64,
+    iso_alpha2: Option<String>,
+    iso_alpha3: Option<String>,
 }
 
 impl NewCountry {
@@ -26,6 +40,18 @@ impl NewCountry {
         NewCountry {
             country_name,
             risk_rate,
+            iso_alpha2: None,
+            iso_alpha3: None,
+        }
+    }
+
+    /// ISO 3166-1 codes are stored upper case
+    pub fn with_iso_codes(country_name: String, risk_rate: f64, iso_alpha2: String, iso_alpha3: String) -> Self {
+        NewCountry {
+            country_name,
+            risk_rate,
+            iso_alpha2: Some(iso_alpha2.trim().to_uppercase()),
+            iso_alpha3: Some(iso_alpha3.trim().to_uppercase()),
         }
     }
 }
@@ -41,6 +67,10 @@ pub struct Country {
     pub id: Uuid,
     pub country_name: String,
     pub risk_rate: f64,
+    /// ISO 3166-1 alpha-2 code, e.g. CA
+    pub iso_alpha2: Option<String>,
+    /// ISO 3166-1 alpha-3 code, e.g. CAN
+    pub iso_alpha3: Option<String>,
 }
 
 impl Country {
@@ -52,6 +82,26 @@ impl Country {
         graphql_translate(res)
     }
 
+    /// Creates the country, or updates the risk rate of the country with its name
+    pub fn upsert(conn: &PgConnection, country: &NewCountry) -> FieldResult<Country> {
+        let res = diesel::insert_into(countries::table)
+            .values(country)
+            .on_conflict(countries::country_name)
+            .do_update()
+            .set(countries::risk_rate.eq(excluded(countries::risk_rate)))
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+
+    pub fn get_all(conn: &PgConnection) -> FieldResult<Vec<Country>> {
+        let res = countries::table
+            .order(countries::country_name)
+            .load::<Country>(conn);
+
+        graphql_translate(res)
+    }
+
     pub fn get_by_id(conn: &PgConnection, id: &Uuid) -> FieldResult<Country> {
         let res = countries::table.filter(countries::id.eq(id))
             .first(conn);
@@ -59,16 +109,26 @@ impl Country {
         graphql_translate(res)
     }
 
-    pub fn load_into_hash(conn: &PgConnection) -> HashMap<Uuid, Country> {
+    pub fn load_into_hash(conn: &PgConnection) -> Result<HashMap<Uuid, Country>, CustomError> {
         let res = countries::table
-            .load::<Country>(conn)
-            .expect("Unable to load countries");
+            .load::<Country>(conn)?;
 
         let mut countries: HashMap<Uuid, Country> = HashMap::new();
         for c in res {
             countries.insert(c.id, c);
         };
 
-        countries 
+        Ok(countries)
+    }
+
+    /// True if name is this country's name or ISO code, ignoring case and periods.
+    /// Aliases are checked separately, see CountryAlias.
+    pub fn is_named(&self, name: &str) -> bool {
+        let name = normalize_country_name(name);
+
+        name == normalize_country_name(&self.country_name)
+            || [&self.iso_alpha2, &self.iso_alpha3]
+                .iter()
+                .any(|c| c.as_ref().map(|c| c.to_lowercase() == name).unwrap_or(false))
     }
 }
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use diesel::{self, Insertable, PgConnection, Queryable, ExpressionMethods};
+use diesel::{Connection, RunQueryDsl, QueryDsl};
+use uuid::Uuid;
+use async_graphql::*;
+
+use crate::graphql::graphql_translate;
+use crate::errors::error_handler::CustomError;
+use crate::models::{Country, PendingCountry, normalize_country_name};
+use crate::schema::*;
+
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable, SimpleObject)]
+/// Another name for a Country, e.g. "Holland" for Netherlands.
+/// ISO codes don't need aliases, they are matched on Country directly.
+pub struct CountryAlias {
+    pub id: Uuid,
+    pub country_id: Uuid,
+    /// Normalized with normalize_country_name
+    pub alias: String,
+}
+
+impl CountryAlias {
+    pub fn create(conn: &PgConnection, alias: &NewCountryAlias) -> FieldResult<CountryAlias> {
+        let res = diesel::insert_into(country_aliases::table)
+            .values(alias)
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Adds alias to the country and clears it from the pending queue
+    pub fn add(conn: &PgConnection, country_id: Uuid, alias: &str) -> FieldResult<CountryAlias> {
+        conn.transaction::<CountryAlias, Error, _>(|| {
+            let country = Country::get_by_id(conn, &country_id)?;
+
+            let alias = CountryAlias::create(conn, &NewCountryAlias::new(country.id, alias))?;
+
+            PendingCountry::resolve(conn, &alias.alias)?;
+
+            Ok(alias)
+        })
+    }
+
+    pub fn get_by_country_id(conn: &PgConnection, country_id: Uuid) -> FieldResult<Vec<CountryAlias>> {
+        let res = country_aliases::table
+            .filter(country_aliases::country_id.eq(country_id))
+            .order(country_aliases::alias)
+            .load::<CountryAlias>(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Country ids keyed by alias
+    pub fn load_into_hash(conn: &PgConnection) -> Result<HashMap<String, Uuid>, CustomError> {
+        let res = country_aliases::table
+            .load::<CountryAlias>(conn)?;
+
+        Ok(res.into_iter()
+            .map(|a| (a.alias, a.country_id))
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "country_aliases"]
+pub struct NewCountryAlias {
+    pub country_id: Uuid,
+    pub alias: String,
+}
+
+impl NewCountryAlias {
+    pub fn new(country_id: Uuid, alias: &str) -> Self {
+        NewCountryAlias {
+            country_id,
+            alias: normalize_country_name(alias),
+        }
+    }
+}
@@ -1,12 +1,15 @@
+use std::collections::HashSet;
+
 use chrono::prelude::*;
 use async_graphql::*;
 use serde::{Deserialize, Serialize};
 use diesel::{self, Insertable, PgConnection, Queryable,
-    RunQueryDsl};
+    RunQueryDsl, QueryDsl, BoolExpressionMethods, ExpressionMethods};
+use diesel::pg::Pg;
 use uuid::Uuid;
 
 use crate::config_variables::{DATE_FORMAT};
-use crate::graphql::graphql_translate;
+use crate::graphql::{graphql_translate, KeysetCursor, PageArgs, SortDirection};
 use crate::schema::*;
 
 #[derive(Debug, Clone, Deserialize, Serialize, Queryable, Insertable)]
@@ -43,6 +46,58 @@ impl CovidTest {
     }
 }
 
+#[derive(Debug, Clone, Default, InputObject)]
+/// Conditions a test must meet to be listed. Every condition given applies.
+/// Ranges include from and exclude to.
+pub struct CovidTestFilter {
+    pub taken_from: Option<NaiveDateTime>,
+    pub taken_to: Option<NaiveDateTime>,
+    pub test_type: Option<String>,
+    pub test_result: Option<bool>,
+}
+
+impl CovidTestFilter {
+    pub fn query(&self) -> covid_tests::BoxedQuery<'static, Pg> {
+        let mut query = covid_tests::table.into_boxed();
+
+        if let Some(from) = self.taken_from {
+            query = query.filter(covid_tests::date_taken.ge(from));
+        }
+
+        if let Some(to) = self.taken_to {
+            query = query.filter(covid_tests::date_taken.lt(to));
+        }
+
+        if let Some(test_type) = &self.test_type {
+            query = query.filter(covid_tests::test_type.eq(test_type.to_owned()));
+        }
+
+        if let Some(test_result) = self.test_result {
+            query = query.filter(covid_tests::test_result.eq(test_result));
+        }
+
+        query
+    }
+
+    /// The rows of a page by date taken, ties ordered by id so pages don't overlap
+    pub fn page(&self, direction: SortDirection, args: &PageArgs) -> covid_tests::BoxedQuery<'static, Pg> {
+        let mut query = self.query();
+
+        for (cursor, later) in args.bounds(direction) {
+            query = crate::keyset_filter!(query, covid_tests::date_taken, covid_tests::id, cursor.key, cursor.id, later);
+        }
+
+        match args.load_direction(direction) {
+            SortDirection::Asc => query.order((covid_tests::date_taken.asc(), covid_tests::id.asc())),
+            SortDirection::Desc => query.order((covid_tests::date_taken.desc(), covid_tests::id.desc())),
+        }
+    }
+
+    pub fn cursor(test: &CovidTest) -> KeysetCursor {
+        KeysetCursor::new(Some(test.date_taken), test.id)
+    }
+}
+
 impl CovidTest {
     pub fn create(conn: &PgConnection, test: &NewCovidTest) -> FieldResult<CovidTest> {
         let res = diesel::insert_into(covid_tests::table)
@@ -51,6 +106,35 @@ impl CovidTest {
         
         graphql_translate(res)
     }
+
+    /// Retried submissions report the same test again, so a test is matched
+    /// on profile, test name and the time it was taken.
+    pub fn get_or_create(conn: &PgConnection, test: &NewCovidTest) -> FieldResult<CovidTest> {
+        let res = covid_tests::table
+            .filter(covid_tests::public_health_profile_id.eq(&test.public_health_profile_id))
+            .filter(covid_tests::test_name.eq(&test.test_name))
+            .filter(covid_tests::date_taken.eq(&test.date_taken))
+            .first(conn);
+
+        match res {
+            Ok(t) => Ok(t),
+            Err(diesel::result::Error::NotFound) => CovidTest::create(conn, test),
+            Err(e) => graphql_translate(Err(e)),
+        }
+    }
+
+    /// Ids of the tests in moved that repeat a test in kept or earlier in
+    /// moved, matched as in get_or_create
+    pub fn duplicates(kept: &[CovidTest], moved: &[CovidTest]) -> Vec<Uuid> {
+        let mut tests: HashSet<(&str, NaiveDateTime)> = kept.iter()
+            .map(|t| (t.test_name.as_str(), t.date_taken))
+            .collect();
+
+        moved.iter()
+            .filter(|t| !tests.insert((t.test_name.as_str(), t.date_taken)))
+            .map(|t| t.id)
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, InputObject, Insertable)]
@@ -0,0 +1,215 @@
+use chrono::{Duration, prelude::*};
+use serde::{Deserialize, Serialize};
+use diesel_derive_enum::DbEnum;
+use async_graphql::*;
+
+use crate::models::Vaccine;
+
+#[derive(Debug, Clone, Copy)]
+/// Entry requirements that depend on the traveller's status in Canada.
+/// Citizens and permanent residents have a right of entry, so these rules
+/// can order them to quarantine but never deny them.
+/// Set in config_variables::ENTRY_RULES.
+pub struct EntryRules {
+    /// Days after the last required dose before a traveller is fully vaccinated
+    pub fully_vaccinated_days: i64,
+    /// Citizens and permanent residents who aren't fully vaccinated quarantine
+    pub quarantine_unvaccinated_residents: bool,
+    /// Foreign nationals who aren't fully vaccinated are denied entry
+    pub deny_unvaccinated_foreign_nationals: bool,
+    /// Foreign nationals without a valid travel document are denied entry
+    pub deny_foreign_nationals_without_documents: bool,
+    /// Travellers younger than this on arrival are minors
+    pub age_of_majority: i32,
+    /// Travellers related to a member of their group who must quarantine
+    /// quarantine with them
+    pub household_quarantine: bool,
+}
+
+#[derive(Debug, Clone)]
+/// What the rules know about a traveller arriving
+pub struct EntryAssessment {
+    pub residency_status: ResidencyStatus,
+    pub has_valid_document: bool,
+    /// Set by CBSA with TravelData
+    pub vaccination_required: bool,
+    pub fully_vaccinated: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct EntryOutcome {
+    pub decision: EntryDecision,
+    /// Why, for the PILResponse
+    pub details: Vec<String>,
+}
+
+impl EntryRules {
+    /// When the doses, in any order, first complete an approved vaccine's
+    /// series. Mixed series count, e.g. one dose each of two 2 dose vaccines.
+    /// A dose recorded twice, the same vaccine at the same time, counts once.
+    pub fn series_completed_on(&self, doses: &[(Vaccine, NaiveDateTime)]) -> Option<NaiveDateTime> {
+        let mut approved: Vec<&(Vaccine, NaiveDateTime)> = doses
+            .iter()
+            .filter(|(v, _)| v.approved)
+            .collect();
+
+        approved.sort_by_key(|(v, provided_on)| (*provided_on, v.id));
+        approved.dedup_by_key(|(v, provided_on)| (*provided_on, v.id));
+
+        approved
+            .iter()
+            .enumerate()
+            .find(|(i, (v, _))| *i as i32 + 1 >= v.required_doses)
+            .map(|(_, (_, provided_on))| *provided_on)
+    }
+
+    /// Fully vaccinated fully_vaccinated_days after completing a series
+    pub fn is_fully_vaccinated(&self, doses: &[(Vaccine, NaiveDateTime)], arrival: NaiveDateTime) -> bool {
+        self.series_completed_on(doses)
+            .map(|completed| completed + Duration::days(self.fully_vaccinated_days) <= arrival)
+            .unwrap_or(false)
+    }
+
+    /// Age in whole years on the date
+    pub fn age_on(&self, birth_date: NaiveDate, date: NaiveDate) -> i32 {
+        let mut age = date.year() - birth_date.year();
+
+        if (date.month(), date.day()) < (birth_date.month(), birth_date.day()) {
+            age -= 1;
+        }
+
+        age.max(0)
+    }
+
+    pub fn is_minor_on(&self, birth_date: NaiveDate, date: NaiveDate) -> bool {
+        self.age_on(birth_date, date) < self.age_of_majority
+    }
+
+    /// Decisions for a group after household quarantine. Travellers linked by a
+    /// relationship, directly or through others, share a household. Admitted
+    /// members of a household with a member in quarantine quarantine too.
+    /// None for travellers who aren't entering.
+    pub fn household_decisions(
+        &self,
+        decisions: &[Option<EntryDecision>],
+        links: &[(usize, usize)],
+    ) -> Vec<Option<EntryDecision>> {
+        if !self.household_quarantine {
+            return decisions.to_vec();
+        }
+
+        // Each traveller starts in their own household, links merge them
+        let mut household: Vec<usize> = (0..decisions.len()).collect();
+
+        let mut merged = true;
+
+        while merged {
+            merged = false;
+
+            for (a, b) in links.iter().filter(|(a, b)| *a < decisions.len() && *b < decisions.len()) {
+                let lowest = household[*a].min(household[*b]);
+
+                if household[*a] != lowest || household[*b] != lowest {
+                    household[*a] = lowest;
+                    household[*b] = lowest;
+                    merged = true;
+                }
+            }
+        }
+
+        decisions
+            .iter()
+            .enumerate()
+            .map(|(i, decision)| match decision {
+                Some(EntryDecision::Admit) if decisions
+                    .iter()
+                    .enumerate()
+                    .any(|(j, d)| household[j] == household[i] && *d == Some(EntryDecision::Quarantine)) => {
+                    Some(EntryDecision::Quarantine)
+                },
+                _ => *decision,
+            })
+            .collect()
+    }
+
+    pub fn decide(&self, traveller: &EntryAssessment) -> EntryOutcome {
+        let status = traveller.residency_status;
+        let has_right_of_entry = status.has_right_of_entry();
+
+        let mut decision = EntryDecision::Admit;
+        let mut details: Vec<String> = Vec::new();
+
+        if !traveller.has_valid_document {
+            if has_right_of_entry {
+                details.push(format!("{} admitted without a valid travel document", status));
+            } else if self.deny_foreign_nationals_without_documents {
+                decision = decision.max(EntryDecision::Deny);
+                details.push(format!("{} without a valid travel document", status));
+            }
+        }
+
+        if traveller.vaccination_required && !traveller.fully_vaccinated {
+            if has_right_of_entry {
+                if self.quarantine_unvaccinated_residents {
+                    decision = decision.max(EntryDecision::Quarantine);
+                    details.push(format!("{} not fully vaccinated: quarantine required", status));
+                }
+            } else if self.deny_unvaccinated_foreign_nationals {
+                decision = decision.max(EntryDecision::Deny);
+                details.push(format!("{} not fully vaccinated", status));
+            }
+        }
+
+        EntryOutcome {
+            decision,
+            details,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize, DbEnum, Enum, Display, EnumString, EnumVariantNames)]
+#[PgType = "residency_status_enum"]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+/// Stored as residency_status_enum. Ordered from fewest to most rights in Canada.
+pub enum ResidencyStatus {
+    ForeignNational,
+    PermanentResident,
+    Citizen,
+}
+
+impl ResidencyStatus {
+    pub fn has_right_of_entry(&self) -> bool {
+        matches!(self, ResidencyStatus::Citizen | ResidencyStatus::PermanentResident)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize, Enum, Display)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+/// Ordered from least to most restrictive
+pub enum EntryDecision {
+    Admit,
+    Quarantine,
+    Deny,
+}
+
+impl EntryDecision {
+    /// PILResponse response_code
+    pub fn response_code(&self) -> &'static str {
+        match self {
+            EntryDecision::Admit => "I",
+            EntryDecision::Quarantine => "Q",
+            EntryDecision::Deny => "D",
+        }
+    }
+
+    pub fn from_response_code(response_code: &str) -> Option<EntryDecision> {
+        match response_code {
+            "I" => Some(EntryDecision::Admit),
+            "Q" => Some(EntryDecision::Quarantine),
+            "D" => Some(EntryDecision::Deny),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,617 @@
+use std::collections::HashMap;
+
+use chrono::prelude::*;
+use chrono::Duration;
+use diesel::prelude::*;
+use diesel::pg::Pg;
+use diesel::PgConnection;
+use serde::Deserialize;
+use simple_excel_writer::{Row, Workbook};
+use uuid::Uuid;
+
+use crate::common_utils::Role;
+use crate::config_variables::DATE_FORMAT;
+use crate::errors::error_handler::CustomError;
+use crate::models::{Country, CountryAlias, CovidTest, Person, PILResponse, Place, TravelDocument, Trip,
+    Vaccination, Vaccine, normalize_country_name};
+use crate::schema::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// Tables available to analysts for export
+pub enum ExportDataset {
+    Trips,
+    TravelResponses,
+    Vaccinations,
+    CovidTests,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Xlsx => "xlsx",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+/// Query parameters for an export.
+/// from and to are inclusive dates applied to the main date of the dataset
+/// (trip arrival, response time, vaccination date, test date).
+/// country is the origin country (name, ISO code or alias) of the traveller's
+/// trip and port the name, IATA code or UN/LOCODE of its port of entry.
+pub struct ExportFilter {
+    pub format: Option<ExportFormat>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub country: Option<String>,
+    pub port: Option<String>,
+}
+
+/// A column in an export. Columns marked pii follow the guards on the
+/// matching GraphQL fields and are only included for roles passing them.
+pub struct ExportColumn {
+    pub name: &'static str,
+    pub pii: bool,
+}
+
+impl ExportColumn {
+    const fn open(name: &'static str) -> Self {
+        ExportColumn { name, pii: false }
+    }
+
+    const fn pii(name: &'static str) -> Self {
+        ExportColumn { name, pii: true }
+    }
+
+    /// Mirrors RoleGuard::new(Role::Analyst) on Person, PostalAddress,
+    /// Trip::booking_id and PublicHealthProfile::person_id
+    pub fn visible_to(&self, role: &Role) -> bool {
+        !self.pii || role.is_analyst()
+    }
+}
+
+const TRIP_COLUMNS: &[ExportColumn] = &[
+    ExportColumn::open("id"),
+    ExportColumn::open("person_id"),
+    ExportColumn::pii("family_name"),
+    ExportColumn::pii("given_name"),
+    ExportColumn::pii("travel_document_id"),
+    ExportColumn::open("birth_date"),
+    ExportColumn::open("trip_provider"),
+    ExportColumn::open("travel_identifier"),
+    ExportColumn::pii("booking_id"),
+    ExportColumn::open("travel_mode"),
+    ExportColumn::open("origin"),
+    ExportColumn::open("origin_country"),
+    ExportColumn::open("destination"),
+    ExportColumn::open("destination_country"),
+    ExportColumn::open("port_of_entry"),
+    ExportColumn::open("travel_intent"),
+    ExportColumn::open("scheduled_departure_time"),
+    ExportColumn::open("scheduled_arrival_time"),
+    ExportColumn::open("departure_time"),
+    ExportColumn::open("arrival_time"),
+    ExportColumn::open("trip_state"),
+    ExportColumn::open("travel_group_id"),
+    ExportColumn::open("created_at"),
+];
+
+const TRAVEL_RESPONSE_COLUMNS: &[ExportColumn] = &[
+    ExportColumn::open("id"),
+    ExportColumn::open("trip_id"),
+    ExportColumn::open("person_id"),
+    ExportColumn::pii("family_name"),
+    ExportColumn::pii("given_name"),
+    ExportColumn::pii("travel_document_id"),
+    ExportColumn::open("cbsa_id"),
+    ExportColumn::open("post_status"),
+    ExportColumn::open("response_code"),
+    ExportColumn::open("random_testing_referral"),
+    ExportColumn::open("quarantine_required"),
+    ExportColumn::open("date_time"),
+    ExportColumn::open("details"),
+];
+
+const VACCINATION_COLUMNS: &[ExportColumn] = &[
+    ExportColumn::open("id"),
+    ExportColumn::pii("public_health_profile_id"),
+    ExportColumn::open("vaccine"),
+    ExportColumn::open("manufacturer"),
+    ExportColumn::open("dose_provider"),
+    ExportColumn::open("location_provided"),
+    ExportColumn::open("country_provided"),
+    ExportColumn::open("provided_on"),
+];
+
+const COVID_TEST_COLUMNS: &[ExportColumn] = &[
+    ExportColumn::open("id"),
+    ExportColumn::pii("public_health_profile_id"),
+    ExportColumn::open("test_name"),
+    ExportColumn::open("test_type"),
+    ExportColumn::open("date_taken"),
+    ExportColumn::open("test_result"),
+];
+
+impl ExportDataset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportDataset::Trips => "trips",
+            ExportDataset::TravelResponses => "travel_responses",
+            ExportDataset::Vaccinations => "vaccinations",
+            ExportDataset::CovidTests => "covid_tests",
+        }
+    }
+
+    pub fn columns(&self) -> &'static [ExportColumn] {
+        match self {
+            ExportDataset::Trips => TRIP_COLUMNS,
+            ExportDataset::TravelResponses => TRAVEL_RESPONSE_COLUMNS,
+            ExportDataset::Vaccinations => VACCINATION_COLUMNS,
+            ExportDataset::CovidTests => COVID_TEST_COLUMNS,
+        }
+    }
+}
+
+/// One page of an export. last_id is the id of the last row loaded and
+/// is passed back to load the next page.
+pub struct ExportPage {
+    pub rows: Vec<Vec<String>>,
+    pub last_id: Option<Uuid>,
+}
+
+/// Reference data and resolved filters for a single export
+pub struct Export {
+    pub dataset: ExportDataset,
+    /// One entry per column, false where the role may not see it
+    visible: Vec<bool>,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+    origin_place_ids: Option<Vec<Uuid>>,
+    port_place_ids: Option<Vec<Uuid>>,
+    places: HashMap<Uuid, Place>,
+    countries: HashMap<Uuid, Country>,
+    vaccines: HashMap<Uuid, Vaccine>,
+}
+
+impl Export {
+    pub fn new(conn: &PgConnection, dataset: ExportDataset, filter: &ExportFilter, role: &Role) -> Result<Self, CustomError> {
+        let places = Place::load_into_hash(conn)?;
+        let countries = Country::load_into_hash(conn)?;
+        let vaccines = Vaccine::load_into_hash(conn)?;
+
+        let country_aliases = CountryAlias::load_into_hash(conn)?;
+
+        let origin_place_ids = filter.country.as_ref().map(|name| {
+            let alias_id = country_aliases.get(&normalize_country_name(name));
+
+            let country_ids: Vec<Uuid> = countries.values()
+                .filter(|c| c.is_named(name) || Some(&c.id) == alias_id)
+                .map(|c| c.id)
+                .collect();
+
+            places.values()
+                .filter(|p| country_ids.contains(&p.country_id))
+                .map(|p| p.id)
+                .collect::<Vec<Uuid>>()
+        });
+
+        let port_place_ids = filter.port.as_ref().map(|name| {
+            let name = name.trim().to_lowercase();
+
+            places.values()
+                .filter(|p| p.is_port_of_entry)
+                .filter(|p| p.name.to_lowercase() == name || p.has_code(&name))
+                .map(|p| p.id)
+                .collect::<Vec<Uuid>>()
+        });
+
+        let visible = dataset.columns()
+            .iter()
+            .map(|c| c.visible_to(role))
+            .collect();
+
+        Ok(Export {
+            dataset,
+            visible,
+            from: filter.from.map(|d| d.and_hms(0, 0, 0)),
+            // inclusive of the whole last day
+            to: filter.to.map(|d| d.and_hms(0, 0, 0) + Duration::days(1)),
+            origin_place_ids,
+            port_place_ids,
+            places,
+            countries,
+            vaccines,
+        })
+    }
+
+    pub fn headers(&self) -> Vec<String> {
+        let headers = self.dataset.columns()
+            .iter()
+            .map(|c| c.name.to_string())
+            .collect();
+
+        mask(headers, &self.visible)
+    }
+
+    /// Loads the page of rows after the row with id after, ordered by id,
+    /// already converted to strings and with hidden columns removed.
+    pub fn load_page(&self, conn: &PgConnection, after: Option<Uuid>, limit: i64) -> QueryResult<ExportPage> {
+        let rows = match self.dataset {
+            ExportDataset::Trips => self.trip_rows(conn, after, limit)?,
+            ExportDataset::TravelResponses => self.travel_response_rows(conn, after, limit)?,
+            ExportDataset::Vaccinations => self.vaccination_rows(conn, after, limit)?,
+            ExportDataset::CovidTests => self.covid_test_rows(conn, after, limit)?,
+        };
+
+        Ok(ExportPage {
+            last_id: rows.last().map(|(id, _)| *id),
+            rows: rows.into_iter().map(|(_, r)| mask(r, &self.visible)).collect(),
+        })
+    }
+
+    /// Builds a complete XLSX workbook. XLSX files are zip archives, so
+    /// unlike CSV they cannot be streamed as pages are loaded and the whole
+    /// workbook is held in memory. Exports of more than max_rows rows are
+    /// refused with a Validation error naming CSV as the alternative.
+    pub fn to_xlsx(&self, conn: &PgConnection, page_size: i64, max_rows: i64) -> Result<Vec<u8>, CustomError> {
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        let mut after = None;
+
+        loop {
+            let page = self.load_page(conn, after, page_size)?;
+            let count = page.rows.len() as i64;
+            rows.extend(page.rows);
+
+            if rows.len() as i64 > max_rows {
+                return Err(CustomError::Validation(vec![format!(
+                    "format: XLSX exports are limited to {} rows, narrow the filter or use format=csv",
+                    max_rows,
+                )]));
+            }
+
+            if count < page_size {
+                break;
+            }
+            after = page.last_id;
+        }
+
+        let headers = self.headers();
+
+        let mut workbook = Workbook::create_in_memory();
+        let mut sheet = workbook.create_sheet(self.dataset.name());
+
+        workbook.write_sheet(&mut sheet, |sheet_writer| {
+            sheet_writer.append_row(string_row(&headers))?;
+
+            for r in rows.iter() {
+                sheet_writer.append_row(string_row(r))?;
+            }
+            Ok(())
+        }).map_err(|e| CustomError::Internal(e.to_string()))?;
+
+        workbook.close()
+            .map_err(|e| CustomError::Internal(e.to_string()))?
+            .ok_or_else(|| CustomError::Internal("Unable to build workbook".to_string()))
+    }
+
+    /// Trips matching the country and port filters, as a subquery so the
+    /// ids never leave the database
+    fn filtered_trip_ids(&self) -> Option<trips::BoxedQuery<'static, Pg, diesel::sql_types::Uuid>> {
+        if self.origin_place_ids.is_none() && self.port_place_ids.is_none() {
+            return None;
+        }
+
+        let mut query = trips::table.select(trips::id).into_boxed();
+
+        if let Some(ids) = &self.origin_place_ids {
+            query = query.filter(trips::origin_place_id.eq_any(ids.clone()));
+        }
+
+        if let Some(ids) = &self.port_place_ids {
+            query = query.filter(trips::port_of_entry_id.eq_any(ids.clone()));
+        }
+
+        Some(query)
+    }
+
+    /// Health profiles of travellers with trips matching the country and
+    /// port filters, as a subquery
+    fn filtered_profile_ids(&self) -> Option<public_health_profiles::BoxedQuery<'static, Pg, diesel::sql_types::Uuid>> {
+        let trip_ids = self.filtered_trip_ids()?;
+
+        let person_ids = trips::table
+            .filter(trips::id.eq_any(trip_ids))
+            .select(trips::person_id);
+
+        Some(public_health_profiles::table
+            .filter(public_health_profiles::person_id.eq_any(person_ids))
+            .select(public_health_profiles::id)
+            .into_boxed())
+    }
+
+    fn load_persons(&self, conn: &PgConnection, ids: Vec<Uuid>) -> QueryResult<HashMap<Uuid, Person>> {
+        let persons = persons::table
+            .filter(persons::id.eq_any(ids))
+            .load::<Person>(conn)?;
+
+        Ok(persons.into_iter().map(|p| (p.id, p)).collect())
+    }
+
+    /// Number of each person's primary travel document
+    fn load_document_numbers(&self, conn: &PgConnection, person_ids: Vec<Uuid>) -> QueryResult<HashMap<Uuid, String>> {
+        let documents = travel_documents::table
+            .filter(travel_documents::person_id.eq_any(person_ids))
+            .load::<TravelDocument>(conn)?;
+
+        let mut by_person: HashMap<Uuid, Vec<TravelDocument>> = HashMap::new();
+
+        for d in documents {
+            by_person.entry(d.person_id).or_insert_with(Vec::new).push(d);
+        }
+
+        Ok(by_person
+            .iter()
+            .filter_map(|(id, documents)| {
+                TravelDocument::primary(documents).map(|d| (*id, d.document_number.to_owned()))
+            })
+            .collect())
+    }
+
+    fn place_name(&self, id: &Uuid) -> String {
+        self.places.get(id).map(|p| p.name.to_owned()).unwrap_or_default()
+    }
+
+    fn place_country_name(&self, id: &Uuid) -> String {
+        self.places.get(id)
+            .and_then(|p| self.countries.get(&p.country_id))
+            .map(|c| c.country_name.to_owned())
+            .unwrap_or_default()
+    }
+
+    fn trip_rows(&self, conn: &PgConnection, after: Option<Uuid>, limit: i64) -> QueryResult<Vec<(Uuid, Vec<String>)>> {
+        let mut query = trips::table.into_boxed();
+
+        if let Some(ids) = &self.origin_place_ids {
+            query = query.filter(trips::origin_place_id.eq_any(ids.clone()));
+        }
+
+        if let Some(ids) = &self.port_place_ids {
+            query = query.filter(trips::port_of_entry_id.eq_any(ids.clone()));
+        }
+
+        if let Some(from) = self.from {
+            query = query.filter(trips::arrival_time.ge(from));
+        }
+
+        if let Some(to) = self.to {
+            query = query.filter(trips::arrival_time.lt(to));
+        }
+
+        if let Some(after) = after {
+            query = query.filter(trips::id.gt(after));
+        }
+
+        let trips = query
+            .order(trips::id)
+            .limit(limit)
+            .load::<Trip>(conn)?;
+
+        let persons = self.load_persons(conn, trips.iter().map(|t| t.person_id).collect())?;
+        let document_numbers = self.load_document_numbers(conn, trips.iter().map(|t| t.person_id).collect())?;
+
+        let rows = trips.into_iter().map(|t| {
+            let person = persons.get(&t.person_id);
+
+            (t.id, vec![
+                t.id.to_string(),
+                t.person_id.to_string(),
+                person.map(|p| p.family_name.to_owned()).unwrap_or_default(),
+                person.map(|p| p.given_name.to_owned()).unwrap_or_default(),
+                document_numbers.get(&t.person_id).cloned().unwrap_or_default(),
+                person.map(|p| p.birth_date.format("%Y-%m-%d").to_string()).unwrap_or_default(),
+                t.trip_provider.to_owned(),
+                t.travel_identifier.to_owned().unwrap_or_default(),
+                t.booking_id.to_owned().unwrap_or_default(),
+                t.travel_mode.to_string(),
+                self.place_name(&t.origin_place_id),
+                self.place_country_name(&t.origin_place_id),
+                self.place_name(&t.destination_place_id),
+                self.place_country_name(&t.destination_place_id),
+                t.port_of_entry_id.map(|id| self.place_name(&id)).unwrap_or_default(),
+                t.travel_intent.to_string(),
+                format_optional_time(t.scheduled_departure_time),
+                format_optional_time(t.scheduled_arrival_time),
+                format_optional_time(t.departure_time),
+                format_optional_time(t.arrival_time),
+                t.trip_state.to_string(),
+                t.travel_group_id.to_string(),
+                t.created_at.format(DATE_FORMAT).to_string(),
+            ])
+        }).collect();
+
+        Ok(rows)
+    }
+
+    fn travel_response_rows(&self, conn: &PgConnection, after: Option<Uuid>, limit: i64) -> QueryResult<Vec<(Uuid, Vec<String>)>> {
+        let mut query = travel_responses::table.into_boxed();
+
+        if let Some(ids) = self.filtered_trip_ids() {
+            query = query.filter(travel_responses::trip_id.eq_any(ids));
+        }
+
+        if let Some(from) = self.from {
+            query = query.filter(travel_responses::date_time.ge(from));
+        }
+
+        if let Some(to) = self.to {
+            query = query.filter(travel_responses::date_time.lt(to));
+        }
+
+        if let Some(after) = after {
+            query = query.filter(travel_responses::id.gt(after));
+        }
+
+        let responses = query
+            .order(travel_responses::id)
+            .limit(limit)
+            .load::<PILResponse>(conn)?;
+
+        let persons = self.load_persons(conn, responses.iter().map(|r| r.person_id).collect())?;
+        let document_numbers = self.load_document_numbers(conn, responses.iter().map(|r| r.person_id).collect())?;
+
+        let rows = responses.into_iter().map(|r| {
+            let person = persons.get(&r.person_id);
+
+            (r.id, vec![
+                r.id.to_string(),
+                r.trip_id.to_string(),
+                r.person_id.to_string(),
+                person.map(|p| p.family_name.to_owned()).unwrap_or_default(),
+                person.map(|p| p.given_name.to_owned()).unwrap_or_default(),
+                document_numbers.get(&r.person_id).cloned().unwrap_or_default(),
+                r.cbsa_id.to_owned(),
+                r.post_status.to_owned(),
+                r.response_code.to_owned(),
+                r.random_testing_referral.to_string(),
+                r.quarantine_required.to_string(),
+                r.date_time.format(DATE_FORMAT).to_string(),
+                r.details.to_owned().unwrap_or_default(),
+            ])
+        }).collect();
+
+        Ok(rows)
+    }
+
+    fn vaccination_rows(&self, conn: &PgConnection, after: Option<Uuid>, limit: i64) -> QueryResult<Vec<(Uuid, Vec<String>)>> {
+        let mut query = vaccinations::table.into_boxed();
+
+        if let Some(ids) = self.filtered_profile_ids() {
+            query = query.filter(vaccinations::public_health_profile_id.eq_any(ids));
+        }
+
+        if let Some(from) = self.from {
+            query = query.filter(vaccinations::provided_on.ge(from));
+        }
+
+        if let Some(to) = self.to {
+            query = query.filter(vaccinations::provided_on.lt(to));
+        }
+
+        if let Some(after) = after {
+            query = query.filter(vaccinations::id.gt(after));
+        }
+
+        let vaccinations = query
+            .order(vaccinations::id)
+            .limit(limit)
+            .load::<Vaccination>(conn)?;
+
+        let rows = vaccinations.into_iter().map(|v| {
+            let vaccine = self.vaccines.get(&v.vaccine_id);
+
+            (v.id, vec![
+                v.id.to_string(),
+                v.public_health_profile_id.to_string(),
+                vaccine.map(|x| x.vaccine_name.to_owned()).unwrap_or_default(),
+                vaccine.map(|x| x.manufacturer.to_owned()).unwrap_or_default(),
+                v.dose_provider.to_owned(),
+                self.place_name(&v.location_provided_id),
+                self.place_country_name(&v.location_provided_id),
+                v.provided_on.format(DATE_FORMAT).to_string(),
+            ])
+        }).collect();
+
+        Ok(rows)
+    }
+
+    fn covid_test_rows(&self, conn: &PgConnection, after: Option<Uuid>, limit: i64) -> QueryResult<Vec<(Uuid, Vec<String>)>> {
+        let mut query = covid_tests::table.into_boxed();
+
+        if let Some(ids) = self.filtered_profile_ids() {
+            query = query.filter(covid_tests::public_health_profile_id.eq_any(ids));
+        }
+
+        if let Some(from) = self.from {
+            query = query.filter(covid_tests::date_taken.ge(from));
+        }
+
+        if let Some(to) = self.to {
+            query = query.filter(covid_tests::date_taken.lt(to));
+        }
+
+        if let Some(after) = after {
+            query = query.filter(covid_tests::id.gt(after));
+        }
+
+        let tests = query
+            .order(covid_tests::id)
+            .limit(limit)
+            .load::<CovidTest>(conn)?;
+
+        let rows = tests.into_iter().map(|t| {
+            (t.id, vec![
+                t.id.to_string(),
+                t.public_health_profile_id.to_string(),
+                t.test_name.to_owned(),
+                t.test_type.to_owned(),
+                t.date_taken.format(DATE_FORMAT).to_string(),
+                t.test_result.to_string(),
+            ])
+        }).collect();
+
+        Ok(rows)
+    }
+}
+
+/// Writes rows as CSV without a header line
+pub fn csv_bytes(rows: &[Vec<String>]) -> Result<Vec<u8>, String> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+
+    for r in rows {
+        writer.write_record(r).map_err(|e| e.to_string())?;
+    }
+
+    writer.into_inner().map_err(|e| e.to_string())
+}
+
+fn format_optional_time(time: Option<NaiveDateTime>) -> String {
+    match time {
+        Some(t) => t.format(DATE_FORMAT).to_string(),
+        None => String::new(),
+    }
+}
+
+fn string_row(values: &[String]) -> Row {
+    let mut row = Row::new();
+    for v in values {
+        row.add_cell(v.to_owned());
+    }
+    row
+}
+
+/// Drops the values for columns the role may not see
+fn mask(values: Vec<String>, visible: &[bool]) -> Vec<String> {
+    values.into_iter()
+        .zip(visible.iter())
+        .filter(|(_, v)| **v)
+        .map(|(value, _)| value)
+        .collect()
+}
@@ -0,0 +1,146 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use diesel::{self, Insertable, PgConnection, Queryable, ExpressionMethods};
+use diesel::{RunQueryDsl, QueryDsl};
+use uuid::Uuid;
+use async_graphql::*;
+
+use crate::graphql::graphql_translate;
+use crate::database::Repository;
+use crate::models::Person;
+use crate::schema::*;
+
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable)]
+/// Consent from a parent or guardian for a minor to travel in a TravelGroup.
+/// The guardian may not be travelling or known to the system.
+pub struct GuardianConsent {
+    pub id: Uuid,
+    pub travel_group_id: Uuid,
+    pub minor_id: Uuid,
+    pub guardian_name: String,
+    pub guardian_contact: Option<String>,
+    pub guardian_person_id: Option<Uuid>,
+    /// User who recorded the consent
+    pub recorded_by: Uuid,
+    pub recorded_at: NaiveDateTime,
+}
+
+#[Object]
+impl GuardianConsent {
+    pub async fn id(&self) -> FieldResult<Uuid> {
+        Ok(self.id)
+    }
+
+    pub async fn travel_group_id(&self) -> FieldResult<Uuid> {
+        Ok(self.travel_group_id)
+    }
+
+    pub async fn minor(&self, context: &Context<'_>) -> FieldResult<Person> {
+        let minor_id = self.minor_id;
+
+        Repository::from_context(context)?
+            .run(move |conn| Person::get_by_id(conn, minor_id))
+            .await
+    }
+
+    pub async fn guardian_name(&self) -> FieldResult<String> {
+        Ok(self.guardian_name.to_owned())
+    }
+
+    pub async fn guardian_contact(&self) -> FieldResult<Option<String>> {
+        Ok(self.guardian_contact.to_owned())
+    }
+
+    /// Null if the guardian isn't a known person
+    pub async fn guardian(&self, context: &Context<'_>) -> FieldResult<Option<Person>> {
+        match self.guardian_person_id {
+            Some(id) => Repository::from_context(context)?
+                .run(move |conn| Person::find_by_id(conn, id))
+                .await,
+            None => Ok(None),
+        }
+    }
+
+    pub async fn recorded_by(&self) -> FieldResult<Uuid> {
+        Ok(self.recorded_by)
+    }
+
+    pub async fn recorded_at(&self) -> NaiveDateTime {
+        self.recorded_at
+    }
+}
+
+// Non Graphql
+impl GuardianConsent {
+    pub fn create(conn: &PgConnection, consent: &NewGuardianConsent) -> FieldResult<GuardianConsent> {
+        let res = diesel::insert_into(guardian_consents::table)
+            .values(consent)
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+
+    pub fn get_by_travel_group(conn: &PgConnection, travel_group_id: Uuid) -> FieldResult<Vec<GuardianConsent>> {
+        let res = guardian_consents::table
+            .filter(guardian_consents::travel_group_id.eq(travel_group_id))
+            .order(guardian_consents::recorded_at)
+            .load::<GuardianConsent>(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Moves consents given for or by one person to another when merging duplicates
+    pub fn move_to_person(conn: &PgConnection, from_person_id: Uuid, to_person_id: Uuid) -> FieldResult<()> {
+        graphql_translate(diesel::update(guardian_consents::table.filter(guardian_consents::minor_id.eq(from_person_id)))
+            .set(guardian_consents::minor_id.eq(to_person_id))
+            .execute(conn))?;
+
+        graphql_translate(diesel::update(guardian_consents::table.filter(guardian_consents::guardian_person_id.eq(from_person_id)))
+            .set(guardian_consents::guardian_person_id.eq(to_person_id))
+            .execute(conn))?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "guardian_consents"]
+pub struct NewGuardianConsent {
+    pub travel_group_id: Uuid,
+    pub minor_id: Uuid,
+    pub guardian_name: String,
+    pub guardian_contact: Option<String>,
+    pub guardian_person_id: Option<Uuid>,
+    pub recorded_by: Uuid,
+}
+
+impl NewGuardianConsent {
+    pub fn new(
+        travel_group_id: Uuid,
+        minor_id: Uuid,
+        guardian_name: String,
+        guardian_contact: Option<String>,
+        guardian_person_id: Option<Uuid>,
+        recorded_by: Uuid,
+    ) -> Self {
+        NewGuardianConsent {
+            travel_group_id,
+            minor_id,
+            guardian_name: guardian_name.trim().to_owned(),
+            guardian_contact,
+            guardian_person_id,
+            recorded_by,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, InputObject, SimpleObject)]
+#[graphql(input_name = "GuardianConsentInput")]
+/// Consent for a minor submitted with their TravelData
+pub struct SlimGuardianConsent {
+    pub guardian_name: String,
+    /// Optional phone number or email
+    pub guardian_contact: Option<String>,
+    /// Optional traveller_ref of the guardian if they are in the same PILQuery
+    pub guardian_traveller_ref: Option<String>,
+}
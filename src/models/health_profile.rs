@@ -5,11 +5,13 @@ use diesel::{self, Insertable, PgConnection, Queryable,
 use uuid::Uuid;
 
 use async_graphql::*;
+use async_graphql::dataloader::DataLoader;
 use crate::common_utils::{is_analyst, RoleGuard, Role};
 
 use crate::models::{Vaccination,
     QuarantinePlan, CovidTest};
-use crate::graphql::{graphql_translate, get_connection_from_context};
+use crate::graphql::{graphql_translate, VaccinationsByProfileLoader, list_cost};
+use crate::database::Repository;
 use crate::schema::*;
 
 
@@ -50,34 +52,36 @@ impl PublicHealthProfile {
         }
     }
 
+    #[graphql(complexity = "list_cost(child_complexity)")]
+    /// Ordered by when the doses were provided
     pub async fn vaccination_history(&self, context: &Context<'_>) -> FieldResult<Vec<Vaccination>> {
-        let conn = get_connection_from_context(context);
+        let vaccinations = context.data::<DataLoader<VaccinationsByProfileLoader>>()?
+            .load_one(self.id)
+            .await?;
 
-        let res = vaccinations::table
-            .filter(vaccinations::public_health_profile_id.eq(self.id))
-            .load::<Vaccination>(&conn);
-
-        graphql_translate(res)
+        Ok(vaccinations.unwrap_or_default())
     }
 
+    #[graphql(complexity = "list_cost(child_complexity)")]
     pub async fn testing_history(&self, context: &Context<'_>) -> FieldResult<Vec<CovidTest>> {
-        let conn = get_connection_from_context(context);
+        let profile_id = self.id;
 
-        let res = covid_tests::table
-            .filter(covid_tests::public_health_profile_id.eq(self.id))
-            .load::<CovidTest>(&conn);
-
-        graphql_translate(res)
+        Repository::from_context(context)?
+            .run(move |conn| graphql_translate(covid_tests::table
+                .filter(covid_tests::public_health_profile_id.eq(profile_id))
+                .load::<CovidTest>(conn)))
+            .await
     }
 
+    #[graphql(complexity = "list_cost(child_complexity)")]
     pub async fn quarantine_plans(&self, context: &Context<'_>) -> FieldResult<Vec<QuarantinePlan>> {
-        let conn = get_connection_from_context(context);
+        let profile_id = self.id;
 
-        let res = quarantine_plans::table
-            .filter(quarantine_plans::public_health_profile_id.eq(self.id))
-            .load::<QuarantinePlan>(&conn);
-
-        graphql_translate(res)
+        Repository::from_context(context)?
+            .run(move |conn| graphql_translate(quarantine_plans::table
+                .filter(quarantine_plans::public_health_profile_id.eq(profile_id))
+                .load::<QuarantinePlan>(conn)))
+            .await
     }
 }
 
@@ -97,16 +101,120 @@ impl PublicHealthProfile {
             .distinct()
             .first(conn);
 
-        let profile = match res {
-            Ok(p) => p,
-            Err(e) => {
-                // Profile not found
-                println!("{:?}", e);
-                let p = PublicHealthProfile::create(conn, profile).expect("Unable to create person");
-                p
-            }
+        match res {
+            Ok(p) => Ok(p),
+            // Profile not found
+            Err(diesel::result::Error::NotFound) => PublicHealthProfile::create(conn, profile),
+            Err(e) => graphql_translate(Err(e)),
+        }
+    }
+
+    pub fn get_by_person_ids(conn: &PgConnection, person_ids: Vec<Uuid>) -> FieldResult<Vec<PublicHealthProfile>> {
+        let res = public_health_profiles::table
+            .filter(public_health_profiles::person_id.eq_any(person_ids))
+            .order(public_health_profiles::id)
+            .load::<PublicHealthProfile>(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Folds the health profiles of from_person_id into a single profile of
+    /// to_person_id, moving their vaccinations, covid tests and quarantine
+    /// plans and deleting the emptied profiles. Doses and tests both profiles
+    /// hold are kept once. Run inside the merge transaction.
+    pub fn move_to_person(conn: &PgConnection, from_person_id: Uuid, to_person_id: Uuid) -> FieldResult<Option<PublicHealthProfile>> {
+        let profiles = PublicHealthProfile::get_by_person_ids(conn, vec![from_person_id, to_person_id])?;
+
+        let plan = match ProfileMerge::plan(to_person_id, &profiles) {
+            Some(plan) => plan,
+            None => return Ok(None),
         };
-        Ok(profile)
+
+        let profile_ids: Vec<Uuid> = profiles.iter().map(|p| p.id).collect();
+
+        // Doses and tests recorded for both people are kept once
+        let (kept, moved): (Vec<Vaccination>, Vec<Vaccination>) = graphql_translate(vaccinations::table
+            .filter(vaccinations::public_health_profile_id.eq_any(profile_ids.clone()))
+            .order((vaccinations::provided_on, vaccinations::id))
+            .load::<Vaccination>(conn))?
+            .into_iter()
+            .partition(|v| v.public_health_profile_id == plan.target_id);
+
+        graphql_translate(diesel::delete(vaccinations::table
+            .filter(vaccinations::id.eq_any(Vaccination::duplicates(&kept, &moved))))
+            .execute(conn))?;
+
+        let (kept, moved): (Vec<CovidTest>, Vec<CovidTest>) = graphql_translate(covid_tests::table
+            .filter(covid_tests::public_health_profile_id.eq_any(profile_ids))
+            .order((covid_tests::date_taken, covid_tests::id))
+            .load::<CovidTest>(conn))?
+            .into_iter()
+            .partition(|t| t.public_health_profile_id == plan.target_id);
+
+        graphql_translate(diesel::delete(covid_tests::table
+            .filter(covid_tests::id.eq_any(CovidTest::duplicates(&kept, &moved))))
+            .execute(conn))?;
+
+        graphql_translate(diesel::update(vaccinations::table
+            .filter(vaccinations::public_health_profile_id.eq_any(plan.absorbed_ids.clone())))
+            .set(vaccinations::public_health_profile_id.eq(plan.target_id))
+            .execute(conn))?;
+
+        graphql_translate(diesel::update(covid_tests::table
+            .filter(covid_tests::public_health_profile_id.eq_any(plan.absorbed_ids.clone())))
+            .set(covid_tests::public_health_profile_id.eq(plan.target_id))
+            .execute(conn))?;
+
+        graphql_translate(diesel::update(quarantine_plans::table
+            .filter(quarantine_plans::public_health_profile_id.eq_any(plan.absorbed_ids.clone())))
+            .set(quarantine_plans::public_health_profile_id.eq(plan.target_id))
+            .execute(conn))?;
+
+        graphql_translate(diesel::delete(public_health_profiles::table
+            .filter(public_health_profiles::id.eq_any(plan.absorbed_ids.clone())))
+            .execute(conn))?;
+
+        let res = diesel::update(public_health_profiles::table.filter(public_health_profiles::id.eq(plan.target_id)))
+            .set((
+                public_health_profiles::person_id.eq(to_person_id),
+                public_health_profiles::smart_healthcard_pk.eq(plan.smart_healthcard_pk),
+            ))
+            .get_result(conn);
+
+        graphql_translate(res).map(Some)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Which profile survives when two persons are merged. The surviving
+/// person's first profile is kept if they have one, otherwise the
+/// duplicate's. Every other profile is absorbed into it.
+pub struct ProfileMerge {
+    pub target_id: Uuid,
+    pub absorbed_ids: Vec<Uuid>,
+    /// The kept profile's key, or the first absorbed profile's if it has none
+    pub smart_healthcard_pk: Option<String>,
+}
+
+impl ProfileMerge {
+    /// None if neither person has a profile
+    pub fn plan(surviving_person_id: Uuid, profiles: &[PublicHealthProfile]) -> Option<ProfileMerge> {
+        let target = profiles.iter()
+            .find(|p| p.person_id == surviving_person_id)
+            .or_else(|| profiles.first())?;
+
+        let absorbed: Vec<&PublicHealthProfile> = profiles.iter()
+            .filter(|p| p.id != target.id)
+            .collect();
+
+        let smart_healthcard_pk = target.smart_healthcard_pk.to_owned()
+            .or_else(|| absorbed.iter().find_map(|p| p.smart_healthcard_pk.to_owned()));
+
+        Some(ProfileMerge {
+            target_id: target.id,
+            absorbed_ids: absorbed.iter().map(|p| p.id).collect(),
+            smart_healthcard_pk,
+        })
     }
 }
 
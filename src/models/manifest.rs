@@ -0,0 +1,534 @@
+use std::io::Cursor;
+
+use calamine::{DataType, Reader, Xlsx};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::{PILResponse, TravelData, SlimVaccination, SlimCovidTest, SlimQuarantinePlan};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+/// File formats accepted for traveller manifests
+pub enum ManifestFormat {
+    Csv,
+    Xlsx,
+}
+
+impl ManifestFormat {
+    /// XLSX files are zip archives, so anything starting with the zip
+    /// signature is read as a workbook and everything else as CSV.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(b"PK\x03\x04") {
+            ManifestFormat::Xlsx
+        } else {
+            ManifestFormat::Csv
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+/// A single traveller row from an airline or operator manifest.
+/// Column names follow the snake_case field names of TravelData, with
+/// nested objects flattened into prefixed columns.
+pub struct ManifestRow {
+    pub family_name: String,
+    pub given_name: String,
+    /// Semicolon separated
+    pub additional_names: Option<String>,
+    pub birth_date: String,
+    pub gender: String,
+    pub travel_document_id: String,
+    pub travel_document_issuer: String,
+    /// Optional columns, PASSPORT and no expiry if missing
+    #[serde(default)]
+    pub travel_document_type: Option<String>,
+    #[serde(default)]
+    pub travel_document_expires_on: Option<String>,
+    /// Optional columns, taken from the person's record and documents if missing
+    #[serde(default)]
+    pub residency_status: Option<String>,
+    /// Semicolon separated
+    #[serde(default)]
+    pub citizenships: Option<String>,
+    pub approved_access_level: String,
+    pub approved_access_granularity: String,
+
+    pub trip_provider: String,
+    pub travel_identifier: Option<String>,
+    pub booking_id: Option<String>,
+    pub travel_mode: String,
+    pub origin_name: String,
+    pub origin_country_name: String,
+    pub destination_name: String,
+    pub destination_country_name: String,
+    /// Optional column, older manifests don't have it
+    #[serde(default)]
+    pub port_of_entry_code: Option<String>,
+    pub travel_intent: String,
+    pub scheduled_departure_time: Option<String>,
+    pub scheduled_arrival_time: Option<String>,
+    pub departure_time: Option<String>,
+    pub arrival_time: Option<String>,
+    pub trip_state: String,
+
+    pub smart_healthcard_pk: Option<String>,
+
+    /// Up to three doses as vaccination_1_* .. vaccination_3_*
+    pub vaccination_required: Option<String>,
+    pub vaccination_1_vaccine_name: Option<String>,
+    pub vaccination_1_dose_provider: Option<String>,
+    pub vaccination_1_location_provided: Option<String>,
+    pub vaccination_1_country_provided: Option<String>,
+    pub vaccination_1_provided_on: Option<String>,
+    pub vaccination_2_vaccine_name: Option<String>,
+    pub vaccination_2_dose_provider: Option<String>,
+    pub vaccination_2_location_provided: Option<String>,
+    pub vaccination_2_country_provided: Option<String>,
+    pub vaccination_2_provided_on: Option<String>,
+    pub vaccination_3_vaccine_name: Option<String>,
+    pub vaccination_3_dose_provider: Option<String>,
+    pub vaccination_3_location_provided: Option<String>,
+    pub vaccination_3_country_provided: Option<String>,
+    pub vaccination_3_provided_on: Option<String>,
+
+    pub covid_test_required: Option<String>,
+    pub covid_test_name: Option<String>,
+    pub covid_test_type: Option<String>,
+    pub covid_test_date_taken: Option<String>,
+    pub covid_test_result: Option<String>,
+
+    pub quarantine_plan_required: Option<String>,
+    pub quarantine_plan_date_created: Option<String>,
+    pub quarantine_plan_confirmation_no_vulnerable: Option<String>,
+    pub quarantine_plan_postal_address_id: Option<String>,
+
+    pub date_time: Option<String>,
+    pub cbsa_officer_id: String,
+}
+
+impl ManifestRow {
+    /// Converts the row into TravelData, returning every problem found
+    /// with the row rather than stopping at the first one.
+    pub fn into_travel_data(self) -> Result<TravelData, Vec<String>> {
+        let mut errors: Vec<String> = Vec::new();
+
+        let required = [
+            ("family_name", &self.family_name),
+            ("given_name", &self.given_name),
+            ("gender", &self.gender),
+            ("travel_document_id", &self.travel_document_id),
+            ("travel_document_issuer", &self.travel_document_issuer),
+            ("trip_provider", &self.trip_provider),
+            ("travel_mode", &self.travel_mode),
+            ("origin_name", &self.origin_name),
+            ("origin_country_name", &self.origin_country_name),
+            ("destination_name", &self.destination_name),
+            ("destination_country_name", &self.destination_country_name),
+            ("travel_intent", &self.travel_intent),
+            ("trip_state", &self.trip_state),
+            ("cbsa_officer_id", &self.cbsa_officer_id),
+        ];
+
+        for (column, value) in required.iter() {
+            if value.trim().is_empty() {
+                errors.push(format!("{}: value is required", column));
+            }
+        }
+
+        let birth_date = collect(&mut errors, parse_date("birth_date", &self.birth_date));
+
+        let travel_document_expires_on = match &self.travel_document_expires_on {
+            Some(v) if !v.trim().is_empty() => collect(&mut errors,
+                parse_date("travel_document_expires_on", v)),
+            _ => None,
+        };
+
+        let scheduled_departure_time = collect(&mut errors,
+            parse_optional_date_time("scheduled_departure_time", &self.scheduled_departure_time));
+        let scheduled_arrival_time = collect(&mut errors,
+            parse_optional_date_time("scheduled_arrival_time", &self.scheduled_arrival_time));
+        let departure_time = collect(&mut errors,
+            parse_optional_date_time("departure_time", &self.departure_time));
+        let arrival_time = collect(&mut errors,
+            parse_optional_date_time("arrival_time", &self.arrival_time));
+        let date_time = collect(&mut errors,
+            parse_optional_date_time("date_time", &self.date_time));
+
+        let vaccination_required = collect(&mut errors,
+            parse_bool("vaccination_required", &self.vaccination_required));
+        let covid_test_required = collect(&mut errors,
+            parse_bool("covid_test_required", &self.covid_test_required));
+        let quarantine_plan_required = collect(&mut errors,
+            parse_bool("quarantine_plan_required", &self.quarantine_plan_required));
+
+        // Vaccinations
+        let vaccination_columns = [
+            (1, &self.vaccination_1_vaccine_name, &self.vaccination_1_dose_provider,
+                &self.vaccination_1_location_provided, &self.vaccination_1_country_provided,
+                &self.vaccination_1_provided_on),
+            (2, &self.vaccination_2_vaccine_name, &self.vaccination_2_dose_provider,
+                &self.vaccination_2_location_provided, &self.vaccination_2_country_provided,
+                &self.vaccination_2_provided_on),
+            (3, &self.vaccination_3_vaccine_name, &self.vaccination_3_dose_provider,
+                &self.vaccination_3_location_provided, &self.vaccination_3_country_provided,
+                &self.vaccination_3_provided_on),
+        ];
+
+        let mut vaccinations: Vec<SlimVaccination> = Vec::new();
+
+        for (n, name, provider, location, country, provided_on) in vaccination_columns.iter() {
+            let prefix = format!("vaccination_{}", n);
+
+            if name.is_none() && provider.is_none() && location.is_none()
+                && country.is_none() && provided_on.is_none() {
+                continue;
+            }
+
+            let name = collect(&mut errors, require(&format!("{}_vaccine_name", prefix), name));
+            let provider = collect(&mut errors, require(&format!("{}_dose_provider", prefix), provider));
+            let location = collect(&mut errors, require(&format!("{}_location_provided", prefix), location));
+            let country = collect(&mut errors, require(&format!("{}_country_provided", prefix), country));
+            let provided_on = collect(&mut errors, require(&format!("{}_provided_on", prefix), provided_on)
+                .and_then(|v| parse_date_time(&format!("{}_provided_on", prefix), &v)));
+
+            if let (Some(vaccine_name), Some(dose_provider), Some(location_provided),
+                Some(country_provided), Some(provided_on)) = (name, provider, location, country, provided_on) {
+                vaccinations.push(SlimVaccination {
+                    vaccine_name,
+                    dose_provider,
+                    location_provided,
+                    country_provided,
+                    provided_on,
+                });
+            }
+        }
+
+        // Covid test
+        let covid_test = if self.covid_test_name.is_none() && self.covid_test_type.is_none()
+            && self.covid_test_date_taken.is_none() && self.covid_test_result.is_none() {
+            None
+        } else {
+            let test_name = collect(&mut errors, require("covid_test_name", &self.covid_test_name));
+            let test_type = collect(&mut errors, require("covid_test_type", &self.covid_test_type));
+            let date_taken = collect(&mut errors, require("covid_test_date_taken", &self.covid_test_date_taken)
+                .and_then(|v| parse_date_time("covid_test_date_taken", &v)));
+            let test_result = collect(&mut errors, require("covid_test_result", &self.covid_test_result)
+                .and_then(|v| parse_bool("covid_test_result", &Some(v))));
+
+            match (test_name, test_type, date_taken, test_result) {
+                (Some(test_name), Some(test_type), Some(date_taken), Some(test_result)) => {
+                    Some(SlimCovidTest {
+                        test_name,
+                        test_type,
+                        date_taken,
+                        test_result,
+                    })
+                },
+                _ => None,
+            }
+        };
+
+        // Quarantine plan
+        let quarantine_plan = if self.quarantine_plan_date_created.is_none()
+            && self.quarantine_plan_confirmation_no_vulnerable.is_none()
+            && self.quarantine_plan_postal_address_id.is_none() {
+            None
+        } else {
+            let date_created = collect(&mut errors, require("quarantine_plan_date_created", &self.quarantine_plan_date_created)
+                .and_then(|v| parse_date("quarantine_plan_date_created", &v)));
+            let confirmation_no_vulnerable = collect(&mut errors,
+                parse_bool("quarantine_plan_confirmation_no_vulnerable", &self.quarantine_plan_confirmation_no_vulnerable));
+            let postal_address_id = collect(&mut errors, require("quarantine_plan_postal_address_id", &self.quarantine_plan_postal_address_id)
+                .and_then(|v| Uuid::parse_str(&v)
+                    .map_err(|_| format!("quarantine_plan_postal_address_id: '{}' is not a valid UUID", v))));
+
+            match (date_created, confirmation_no_vulnerable, postal_address_id) {
+                (Some(date_created), Some(confirmation_no_vulnerable), Some(postal_address_id)) => {
+                    Some(SlimQuarantinePlan {
+                        date_created,
+                        confirmation_no_vulnerable,
+                        postal_address_id,
+                    })
+                },
+                _ => None,
+            }
+        };
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let birth_date = birth_date.ok_or_else(|| vec!["birth_date: value is required".to_string()])?;
+
+        let citizenships = self.citizenships.map(|names| {
+            names.split(';')
+                .map(|n| n.trim().to_owned())
+                .filter(|n| !n.is_empty())
+                .collect::<Vec<String>>()
+        });
+
+        let additional_names = self.additional_names.map(|names| {
+            names.split(';')
+                .map(|n| n.trim().to_owned())
+                .filter(|n| !n.is_empty())
+                .collect::<Vec<String>>()
+        });
+
+        Ok(TravelData {
+            family_name: self.family_name,
+            given_name: self.given_name,
+            additional_names,
+            birth_date,
+            gender: self.gender,
+            travel_document_id: self.travel_document_id,
+            travel_document_issuer: self.travel_document_issuer,
+            travel_document_type: self.travel_document_type.filter(|t| !t.trim().is_empty()),
+            travel_document_expires_on,
+            additional_travel_documents: None,
+            residency_status: self.residency_status.filter(|s| !s.trim().is_empty()),
+            citizenships,
+            traveller_ref: None,
+            relationships: None,
+            guardian_consent: None,
+            approved_access_level: self.approved_access_level,
+            approved_access_granularity: self.approved_access_granularity,
+            trip_provider: self.trip_provider,
+            travel_identifier: self.travel_identifier,
+            booking_id: self.booking_id,
+            travel_mode: self.travel_mode,
+            origin_name: self.origin_name,
+            origin_country_name: self.origin_country_name,
+            destination_name: self.destination_name,
+            destination_country_name: self.destination_country_name,
+            port_of_entry_code: self.port_of_entry_code.filter(|c| !c.trim().is_empty()),
+            travel_intent: self.travel_intent,
+            scheduled_departure_time: scheduled_departure_time.flatten(),
+            scheduled_arrival_time: scheduled_arrival_time.flatten(),
+            departure_time: departure_time.flatten(),
+            arrival_time: arrival_time.flatten(),
+            trip_state: self.trip_state,
+            itinerary: None,
+            smart_healthcard_pk: self.smart_healthcard_pk,
+            vaccination_required: vaccination_required.unwrap_or(false),
+            vaccinations: if vaccinations.is_empty() { None } else { Some(vaccinations) },
+            covid_test_required: covid_test_required.unwrap_or(false),
+            covid_test,
+            quarantine_plan_required: quarantine_plan_required.unwrap_or(false),
+            quarantine_plan,
+            date_time: date_time.flatten().unwrap_or_else(|| Utc::now().naive_utc()),
+            cbsa_officer_id: self.cbsa_officer_id,
+        })
+    }
+}
+
+/// Reads a CSV or XLSX manifest into rows. Each entry is paired with its
+/// spreadsheet row number (the header is row 1) so problems can be reported
+/// back to the uploader.
+pub fn read_manifest(bytes: &[u8], format: ManifestFormat) -> Result<Vec<(usize, Result<ManifestRow, String>)>, String> {
+    let records = match format {
+        ManifestFormat::Csv => read_csv_records(bytes)?,
+        ManifestFormat::Xlsx => read_xlsx_records(bytes)?,
+    };
+
+    let mut records = records.into_iter();
+
+    let headers = match records.next() {
+        Some(h) => h,
+        None => return Err("Manifest is empty".to_string()),
+    };
+
+    let rows = records
+        .enumerate()
+        // skip blank lines at the end of spreadsheets
+        .filter(|(_, record)| record.iter().any(|f| !f.trim().is_empty()))
+        .map(|(i, record)| {
+            let row = record.deserialize::<ManifestRow>(Some(&headers))
+                .map_err(|e| format!("{}", e));
+            (i + 2, row)
+        })
+        .collect();
+
+    Ok(rows)
+}
+
+fn read_csv_records(bytes: &[u8]) -> Result<Vec<csv::StringRecord>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(bytes);
+
+    reader.records()
+        .collect::<Result<Vec<csv::StringRecord>, csv::Error>>()
+        .map_err(|e| format!("Unable to read CSV: {}", e))
+}
+
+fn read_xlsx_records(bytes: &[u8]) -> Result<Vec<csv::StringRecord>, String> {
+    let mut workbook: Xlsx<_> = Xlsx::new(Cursor::new(bytes))
+        .map_err(|e| format!("Unable to read XLSX: {}", e))?;
+
+    let range = match workbook.worksheet_range_at(0) {
+        Some(r) => r.map_err(|e| format!("Unable to read XLSX: {}", e))?,
+        None => return Err("Workbook has no worksheets".to_string()),
+    };
+
+    let records = range.rows()
+        .map(|row| {
+            row.iter()
+                .map(cell_to_string)
+                .collect::<csv::StringRecord>()
+        })
+        .collect();
+
+    Ok(records)
+}
+
+fn cell_to_string(cell: &DataType) -> String {
+    match cell {
+        DataType::Empty => String::new(),
+        DataType::String(s) => s.trim().to_owned(),
+        DataType::DateTime(_) => cell.as_datetime()
+            .map(|d| d.format("%Y-%m-%dT%H:%M:%S").to_string())
+            .unwrap_or_default(),
+        DataType::Float(f) if f.fract() == 0.0 => format!("{}", *f as i64),
+        other => other.to_string(),
+    }
+}
+
+/// Pushes the error (if any) and returns the value as an Option
+fn collect<T>(errors: &mut Vec<String>, res: Result<T, String>) -> Option<T> {
+    match res {
+        Ok(v) => Some(v),
+        Err(e) => {
+            errors.push(e);
+            None
+        }
+    }
+}
+
+fn require(column: &str, value: &Option<String>) -> Result<String, String> {
+    match value {
+        Some(v) if !v.trim().is_empty() => Ok(v.trim().to_owned()),
+        _ => Err(format!("{}: value is required", column)),
+    }
+}
+
+pub fn parse_bool(column: &str, value: &Option<String>) -> Result<bool, String> {
+    match value.as_ref().map(|v| v.trim().to_lowercase()) {
+        None => Ok(false),
+        Some(v) => match v.as_str() {
+            "" | "false" | "no" | "n" | "0" => Ok(false),
+            "true" | "yes" | "y" | "1" => Ok(true),
+            _ => Err(format!("{}: '{}' is not true or false", column, v)),
+        }
+    }
+}
+
+pub fn parse_date(column: &str, value: &str) -> Result<NaiveDate, String> {
+    let value = value.trim();
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .or_else(|_| parse_date_time(column, value).map(|d| d.date()))
+        .map_err(|_| format!("{}: '{}' is not a date (YYYY-MM-DD)", column, value))
+}
+
+pub fn parse_date_time(column: &str, value: &str) -> Result<NaiveDateTime, String> {
+    let value = value.trim();
+
+    let formats = [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%SZ",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+    ];
+
+    for f in formats.iter() {
+        if let Ok(d) = NaiveDateTime::parse_from_str(value, f) {
+            return Ok(d);
+        }
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|d| d.and_hms(0, 0, 0))
+        .map_err(|_| format!("{}: '{}' is not a date time (YYYY-MM-DD HH:MM:SS)", column, value))
+}
+
+pub fn parse_optional_date_time(column: &str, value: &Option<String>) -> Result<Option<NaiveDateTime>, String> {
+    match value {
+        Some(v) if !v.trim().is_empty() => parse_date_time(column, v).map(Some),
+        _ => Ok(None),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+    /// A new Person was created for the row
+    Created,
+    /// The row matched a Person already in the system
+    Matched,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// Outcome for a single manifest row
+pub struct ImportRowResult {
+    pub row: usize,
+    pub status: ImportStatus,
+    pub person_id: Option<Uuid>,
+    pub trip_id: Option<Uuid>,
+    pub response_code: Option<String>,
+    pub reasons: Vec<String>,
+}
+
+impl ImportRowResult {
+    /// A row PILQuery processed, created or matched as the response records
+    pub fn processed(row: usize, response: &PILResponse) -> Self {
+        ImportRowResult {
+            row,
+            status: if response.person_created { ImportStatus::Created } else { ImportStatus::Matched },
+            person_id: Some(response.person_id),
+            trip_id: Some(response.trip_id),
+            response_code: Some(response.response_code.to_owned()),
+            reasons: Vec::new(),
+        }
+    }
+
+    pub fn rejected(row: usize, reasons: Vec<String>) -> Self {
+        ImportRowResult {
+            row,
+            status: ImportStatus::Rejected,
+            person_id: None,
+            trip_id: None,
+            response_code: None,
+            reasons,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// Per-row report returned from a bulk traveller import
+pub struct ImportReport {
+    pub total: usize,
+    pub created: usize,
+    pub matched: usize,
+    pub rejected: usize,
+    pub rows: Vec<ImportRowResult>,
+}
+
+impl ImportReport {
+    pub fn from(mut rows: Vec<ImportRowResult>) -> Self {
+        rows.sort_by_key(|r| r.row);
+
+        let count = |status: ImportStatus| rows.iter().filter(|r| r.status == status).count();
+
+        ImportReport {
+            total: rows.len(),
+            created: count(ImportStatus::Created),
+            matched: count(ImportStatus::Matched),
+            rejected: count(ImportStatus::Rejected),
+            rows,
+        }
+    }
+}
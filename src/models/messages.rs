@@ -1,24 +1,30 @@
 use diesel::PgConnection;
 use diesel::{self, Insertable, Queryable};
-use diesel::{RunQueryDsl};
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, RunQueryDsl};
+use diesel::pg::Pg;
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 use chrono::prelude::*;
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use rand::Rng;
 use async_graphql::*;
 // use rdkafka::{producer::FutureProducer};
 
-use crate::graphql::{graphql_translate, get_connection_from_context};
+use crate::graphql::{graphql_translate, KeysetCursor, PageArgs, SortDirection};
+use crate::errors::error_handler::CustomError;
 use crate::schema::*;
 // use crate::kafka::send_message;
-use crate::get_or_create_country_by_name;
-use crate::config_variables::MANDATORY_TESTING_RATE;
+use crate::{get_country_by_name, get_place_by_id, get_vaccine_by_id, ReferenceSource};
+use crate::config_variables::{MANDATORY_TESTING_RATE, COUNTRIES_VISITED_DAYS, ENTRY_RULES, HOME_COUNTRY_CODE};
 
 use crate::models::{NewPerson, 
     NewPublicHealthProfile, NewTrip, NewVaccination, Trip,
     Person, PublicHealthProfile, SlimQuarantinePlan,
-    Vaccination, CovidTest, SlimCovidTest, SlimVaccination};
+    Vaccination, CovidTest, SlimCovidTest, SlimVaccination,
+    TravelMode, TravelIntent, TripState, parse_enum_value,
+    SlimTripLeg, NewTripLeg, TripLeg, SlimTravelDocument, PresentedTravelDocument,
+    TravelDocumentType, check_entry_documents, ResidencyStatus, EntryAssessment, EntryDecision,
+    SlimRelationship, SlimGuardianConsent};
 
 use super::{NewCovidTest, NewQuarantinePlan, QuarantinePlan};
 
@@ -36,6 +42,9 @@ pub struct PILResponse {
     pub quarantine_required: bool,
     pub date_time: NaiveDateTime,
     pub details: Option<String>,
+    /// True if the traveller was recorded as a new person rather than
+    /// matched to an existing one
+    pub person_created: bool,
 }
 
 impl PILResponse {
@@ -46,6 +55,146 @@ impl PILResponse {
 
         graphql_translate(res)
     }
+
+    /// Response for a traveller that could not be processed.
+    /// Nothing is stored for the traveller, so the ids are nil.
+    pub fn error(cbsa_id: Uuid, details: String) -> Self {
+        PILResponse {
+            id: Uuid::nil(),
+            post_status: "ERROR".to_string(),
+            trip_id: Uuid::nil(),
+            person_id: Uuid::nil(),
+            cbsa_id: cbsa_id.to_string(),
+            response_code: "E".to_string(),
+            random_testing_referral: false,
+            quarantine_required: false,
+            date_time: Utc::now().naive_utc(),
+            details: Some(details),
+            person_created: false,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.post_status == "ERROR"
+    }
+
+    /// Adds the outcome of rules evaluated for the whole group to a stored response
+    pub fn amend(&self, conn: &PgConnection, decision: Option<EntryDecision>, details: &[String]) -> FieldResult<PILResponse> {
+        let (response_code, quarantine_required) = match decision {
+            Some(d) => (d.response_code().to_string(), d == EntryDecision::Quarantine),
+            None => (self.response_code.to_owned(), self.quarantine_required),
+        };
+
+        let mut all_details: Vec<String> = self.details
+            .iter()
+            .filter(|d| d.as_str() != "None")
+            .cloned()
+            .collect();
+
+        all_details.extend(details.iter().cloned());
+
+        let res = diesel::update(travel_responses::table.filter(travel_responses::id.eq(self.id)))
+            .set((
+                travel_responses::response_code.eq(response_code),
+                travel_responses::quarantine_required.eq(quarantine_required),
+                travel_responses::details.eq(Some(all_details.join("; "))),
+            ))
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+}
+
+#[derive(Debug, Clone, Default, InputObject)]
+/// Conditions a stored PILResponse must meet to be listed. Every condition
+/// given applies. Ranges include from and exclude to.
+pub struct PILResponseFilter {
+    pub date_from: Option<NaiveDateTime>,
+    pub date_to: Option<NaiveDateTime>,
+    /// I, Q or D
+    pub response_code: Option<String>,
+    pub random_testing_referral: Option<bool>,
+    pub quarantine_required: Option<bool>,
+    pub person_id: Option<Uuid>,
+    pub trip_id: Option<Uuid>,
+}
+
+impl PILResponseFilter {
+    pub fn query(&self) -> travel_responses::BoxedQuery<'static, Pg> {
+        let mut query = travel_responses::table.into_boxed();
+
+        if let Some(from) = self.date_from {
+            query = query.filter(travel_responses::date_time.ge(from));
+        }
+
+        if let Some(to) = self.date_to {
+            query = query.filter(travel_responses::date_time.lt(to));
+        }
+
+        if let Some(code) = &self.response_code {
+            query = query.filter(travel_responses::response_code.eq(code.trim().to_uppercase()));
+        }
+
+        if let Some(referral) = self.random_testing_referral {
+            query = query.filter(travel_responses::random_testing_referral.eq(referral));
+        }
+
+        if let Some(quarantine_required) = self.quarantine_required {
+            query = query.filter(travel_responses::quarantine_required.eq(quarantine_required));
+        }
+
+        if let Some(person_id) = self.person_id {
+            query = query.filter(travel_responses::person_id.eq(person_id));
+        }
+
+        if let Some(trip_id) = self.trip_id {
+            query = query.filter(travel_responses::trip_id.eq(trip_id));
+        }
+
+        query
+    }
+
+    /// The rows of a page by date, ties ordered by id so pages don't overlap
+    pub fn page(&self, direction: SortDirection, args: &PageArgs) -> travel_responses::BoxedQuery<'static, Pg> {
+        let mut query = self.query();
+
+        for (cursor, later) in args.bounds(direction) {
+            query = crate::keyset_filter!(query, travel_responses::date_time, travel_responses::id, cursor.key, cursor.id, later);
+        }
+
+        match args.load_direction(direction) {
+            SortDirection::Asc => query.order((travel_responses::date_time.asc(), travel_responses::id.asc())),
+            SortDirection::Desc => query.order((travel_responses::date_time.desc(), travel_responses::id.desc())),
+        }
+    }
+
+    pub fn cursor(response: &PILResponse) -> KeysetCursor {
+        KeysetCursor::new(Some(response.date_time), response.id)
+    }
+}
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+/// Unit of work for the database transactions of a PILQuery
+pub enum TransactionScope {
+    /// Each traveller is committed or rolled back on its own
+    Traveller,
+    /// The whole group is rolled back if any traveller fails
+    Group,
+}
+
+impl TransactionScope {
+    /// Scope a PILQuery runs in. A submission with an idempotency key is
+    /// released for a retry when any traveller fails, so it runs as one
+    /// transaction and a retry never finds some travellers already written.
+    pub fn for_submission(requested: Option<TransactionScope>, keyed: bool) -> Result<TransactionScope, CustomError> {
+        match (requested, keyed) {
+            (Some(TransactionScope::Traveller), true) => Err(CustomError::Validation(vec![
+                "transactionScope: TRAVELLER can't be used with an idempotency key".to_string(),
+            ])),
+            (_, true) => Ok(TransactionScope::Group),
+            (requested, false) => Ok(requested.unwrap_or(TransactionScope::Traveller)),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Insertable, SimpleObject)]
@@ -63,6 +212,7 @@ pub struct NewPILResponse {
     pub quarantine_required: bool,
     pub date_time: NaiveDateTime,
     pub details: Option<String>,
+    pub person_created: bool,
 }
 
 impl NewPILResponse {
@@ -70,6 +220,7 @@ impl NewPILResponse {
             post_status: String,
             trip_id: Uuid,
             person_id: Uuid,
+            person_created: bool,
             cbsa_id: String,
             response_code: String,
             random_testing_referral: bool,
@@ -93,6 +244,7 @@ impl NewPILResponse {
             quarantine_required,
             date_time: Utc::now().naive_utc(),
             details,
+            person_created,
         }
     }
 }
@@ -114,6 +266,25 @@ pub struct TravelData {
     pub gender: String,
     pub travel_document_id: String,
     pub travel_document_issuer: String, // Country
+    /// Optional type of travel_document_id, PASSPORT if None
+    pub travel_document_type: Option<String>,
+    /// Optional expiry date of travel_document_id
+    pub travel_document_expires_on: Option<NaiveDate>,
+    /// Optional other documents the traveller holds, e.g. a NEXUS or PR card.
+    /// The traveller is matched to a known person on any of them.
+    pub additional_travel_documents: Option<Vec<SlimTravelDocument>>,
+    /// Optional CITIZEN, PERMANENT_RESIDENT or FOREIGN_NATIONAL as verified by CBSA.
+    /// If None the status is taken from the person's record and documents.
+    pub residency_status: Option<String>,
+    /// Optional countries of citizenship. Passport issuers are added.
+    pub citizenships: Option<Vec<String>>,
+    /// Optional reference for the traveller, unique within the PILQuery,
+    /// used by other travellers' relationships and guardian consent
+    pub traveller_ref: Option<String>,
+    /// Optional relationships to other travellers in the PILQuery
+    pub relationships: Option<Vec<SlimRelationship>>,
+    /// Optional consent from a parent or guardian if the traveller is a minor
+    pub guardian_consent: Option<SlimGuardianConsent>,
     pub approved_access_level: String, // AccessLevel
     pub approved_access_granularity: String,
 
@@ -129,6 +300,9 @@ pub struct TravelData {
     pub origin_country_name: String,
     pub destination_name: String,
     pub destination_country_name: String,
+    /// Optional IATA code or UN/LOCODE of the Canadian port of entry.
+    /// If None the destination is used when it is a port of entry.
+    pub port_of_entry_code: Option<String>,
 
     pub travel_intent: String,
     /// Optional NaiveDateTime
@@ -140,6 +314,10 @@ pub struct TravelData {
     /// Optional NaiveDateTime
     pub arrival_time: Option<NaiveDateTime>,
     pub trip_state: String,
+    /// Optional Vec of legs from origin to final destination in order of travel.
+    /// If None the trip is a single leg from origin to destination.
+    /// For TRANSIT the destination is a connection and later legs continue on.
+    pub itinerary: Option<Vec<SlimTripLeg>>,
 
     /// PublicHealthProfile data
     /// May or may not have this detail. Will create if not.
@@ -173,25 +351,83 @@ pub struct TravelData {
 }
 
 impl TravelData {
-    pub async fn process(
+    /// The document the traveller presented followed by any additional documents
+    pub fn travel_documents(&self) -> Vec<SlimTravelDocument> {
+        let presented = SlimTravelDocument {
+            document_type: self.travel_document_type.to_owned().unwrap_or_else(|| "PASSPORT".to_string()),
+            document_number: self.travel_document_id.to_owned(),
+            issuer: self.travel_document_issuer.to_owned(),
+            expires_on: self.travel_document_expires_on,
+        };
+
+        let mut documents = vec![presented];
+
+        if let Some(additional) = &self.additional_travel_documents {
+            documents.extend(additional.iter().cloned());
+        }
+
+        documents
+    }
+
+    /// Documents and vaccinations are checked on the day of arrival
+    pub fn arrival(&self) -> NaiveDateTime {
+        self.arrival_time
+            .or(self.scheduled_arrival_time)
+            .unwrap_or_else(|| Utc::now().naive_utc())
+    }
+
+    /// Processes a single traveller using conn for every read and write, so the
+    /// caller can run it inside a transaction.
+    pub fn process(
             &self, 
-            context: &Context<'_>,
+            context: &impl ReferenceSource,
+            conn: &PgConnection,
             travel_group_id: Uuid,
             cbsa_id: Uuid,
         ) -> FieldResult<PILResponse> {
 
-        // Connect to PostgresPool
-        let conn = get_connection_from_context(context);
+        // Reject the traveller with every violation before touching the database
+        let violations = self.validate();
+
+        if !violations.is_empty() {
+            return Err(CustomError::Validation(violations).extend());
+        }
 
         /* Remove Kafka service
         // Create Kafka producer and send message for subscription service
         let producer = context
             .data::<FutureProducer>()
-            .expect("Can't get Kafka producer");
+            .map_err(|_| CustomError::Internal("Can't get Kafka producer".to_string()).extend())?;
         */
 
-        // Identify country        
-        let country = get_or_create_country_by_name(context, self.travel_document_issuer.to_owned())?;
+        // Identify document issuers
+        let documents = self.travel_documents()
+            .iter()
+            .map(|d| d.to_presented(context))
+            .collect::<FieldResult<Vec<PresentedTravelDocument>>>()?;
+
+        let arrival = self.arrival();
+
+        let document_check = check_entry_documents(context, &documents, arrival.date())?;
+
+        // Countries of citizenship, passports are issued to citizens
+        let mut citizenship_ids: Vec<Uuid> = Vec::new();
+
+        for name in self.citizenships.iter().flatten() {
+            citizenship_ids.push(get_country_by_name(context, name)?.id);
+        }
+
+        for d in documents.iter().filter(|d| d.document_type == TravelDocumentType::Passport) {
+            if !citizenship_ids.contains(&d.issuer_id) {
+                citizenship_ids.push(d.issuer_id);
+            }
+        }
+
+        let declared_status = match &self.residency_status {
+            Some(s) => Some(parse_enum_value::<ResidencyStatus>("residency_status", s)
+                .map_err(|e| CustomError::Validation(vec![e]).extend())?),
+            None => None,
+        };
 
         // Identify or create person
         let new_person = NewPerson::new(
@@ -200,37 +436,76 @@ impl TravelData {
             self.additional_names.to_owned(),
             self.birth_date,
             self.gender.to_owned(),
-            self.travel_document_id.to_owned(),
-            country.id, // Country
             travel_group_id,
             self.approved_access_level.to_owned(), // AccessLevel
             self.approved_access_granularity.to_owned(),
+            declared_status,
+            citizenship_ids.to_owned(),
         );
 
-        let person = Person::get_or_create(&conn, &new_person)?;
+        let (person, person_created) = Person::get_or_create(conn, &new_person, &documents, self.date_time)?;
+
+        // Status in Canada as declared, otherwise the most rights known from the
+        // person's record, citizenships and documents
+        let home_country = get_country_by_name(context, HOME_COUNTRY_CODE)?;
+
+        let mut known_statuses = vec![document_check.documented_status];
+        known_statuses.extend(person.residency_status);
+
+        if person.citizenship_ids.contains(&home_country.id) || citizenship_ids.contains(&home_country.id) {
+            known_statuses.push(ResidencyStatus::Citizen);
+        }
+
+        let residency_status = declared_status
+            .or_else(|| known_statuses.into_iter().max())
+            .unwrap_or(ResidencyStatus::ForeignNational);
+
+        let person = person.record_residency(conn, residency_status, &citizenship_ids)?;
 
         // Add Trip Information
+        let travel_mode = parse_enum_value::<TravelMode>("travel_mode", &self.travel_mode)
+            .map_err(|e| CustomError::Validation(vec![e]).extend())?;
+
+        let travel_intent = parse_enum_value::<TravelIntent>("travel_intent", &self.travel_intent)
+            .map_err(|e| CustomError::Validation(vec![e]).extend())?;
+
+        let trip_state = parse_enum_value::<TripState>("trip_state", &self.trip_state)
+            .map_err(|e| CustomError::Validation(vec![e]).extend())?;
+
         let new_trip = NewTrip::new(
             context,
+            conn,
             self.trip_provider.to_owned(),
             self.travel_identifier.to_owned(),
             self.booking_id.to_owned(),
-            self.travel_mode.to_owned(),
+            travel_mode,
             self.origin_name.to_owned(),
             self.origin_country_name.to_owned(),
             self.destination_name.to_owned(),
             self.destination_country_name.to_owned(),
-            self.travel_intent.to_owned(),
+            self.port_of_entry_code.to_owned(),
+            travel_intent,
             self.scheduled_departure_time,
             self.scheduled_arrival_time,
             self.departure_time,
             self.arrival_time,
-            self.trip_state.to_owned(),
+            trip_state,
             travel_group_id,
             person.id,
-        );
+        )?;
+
+        let trip = Trip::create(conn, &new_trip)?;
+
+        let legs = match &self.itinerary {
+            Some(itinerary) => itinerary
+                .iter()
+                .enumerate()
+                .map(|(i, leg)| NewTripLeg::from(context, conn, trip.id, i as i32 + 1, leg))
+                .collect::<FieldResult<Vec<NewTripLeg>>>()?,
+            None => vec![NewTripLeg::from_trip(&trip)],
+        };
 
-        let trip = Trip::create(&conn, &new_trip).expect("Unable to create trip");
+        TripLeg::create_all(conn, &legs)?;
 
         // Add or get PublicHealthProfile
         let profile = NewPublicHealthProfile::new(
@@ -238,36 +513,34 @@ impl TravelData {
             self.smart_healthcard_pk.clone(),
         );
 
-        let public_health_profile = PublicHealthProfile::get_or_create(&conn, &profile)
-            .expect("Unable to find or create profile");
+        let public_health_profile = PublicHealthProfile::get_or_create(conn, &profile)?;
 
         // Add vaccinations
+        let mut vaccination_history: Vec<Vaccination> = Vec::new();
+
         if let Some(vaccinations) = &self.vaccinations {
-            let mut vaccination_history: Vec<Vaccination> = Vec::new();
-            
             for slim_v in vaccinations {
     
                 let nv = NewVaccination::from(
                     context, 
+                    conn,
                     &slim_v, 
-                    public_health_profile.id).expect("Unable to create NewVaccination");
+                    public_health_profile.id)?;
     
-                let v = Vaccination::get_or_create(&conn, &nv)
-                    .expect("Unable to find or create vaccination");
+                let v = Vaccination::get_or_create(conn, &nv)?;
                 vaccination_history.push(v);
             };
         }
         
         // Add Covid-Test if exists
         if let Some(t) = &self.covid_test {
-            // Add CovidTest -> update to get or create
-            
+            // Add CovidTest or match a previously submitted one
+
             let new_test = NewCovidTest::from(
             public_health_profile.id, 
             &t);
     
-            let _covid_test = CovidTest::create(&conn, &new_test)
-                .expect("Unable to create new covid test");
+            let _covid_test = CovidTest::get_or_create(conn, &new_test)?;
         }
 
         // Add QuarantinePlan if exists
@@ -279,15 +552,14 @@ impl TravelData {
             &p
             );
     
-            let _quarantine_plan = QuarantinePlan::create(&conn, &new_plan)
-                .expect("Unable to create new quarantine plan");
+            let _quarantine_plan = QuarantinePlan::create(conn, &new_plan)?;
         }
 
         // KAFKA
 
         // Sent Person messages to Kafka
         let person_message = serde_json::to_string(&person)
-            .expect("Can't serialize Person");
+            .map_err(|e| CustomError::Internal(format!("Can't serialize Person: {}", e)).extend())?;
 
         /* Remove Subscription Service until we set up Kafka service
         println!("Sending Person Message to Subscription");
@@ -295,7 +567,7 @@ impl TravelData {
 
         // Sent Trip messages to Kafka
         let trip_message = serde_json::to_string(&trip)
-            .expect("Can't serialize Trip");
+            .map_err(|e| CustomError::Internal(format!("Can't serialize Trip: {}", e)).extend())?;
 
         println!("Sending Message to Subscription");
         send_message(producer, "trips", trip_message, "CBSA".to_string()).await;
@@ -307,23 +579,85 @@ impl TravelData {
 
         let mut random_testing_referral = false;
 
-        if rng.gen::<f64>() < MANDATORY_TESTING_RATE {
+        // Ports can sample at their own rate
+        let testing_rate = match trip.port_of_entry_id {
+            Some(id) => get_place_by_id(context, id)?.testing_rate.unwrap_or(MANDATORY_TESTING_RATE),
+            None => MANDATORY_TESTING_RATE,
+        };
+
+        if rng.gen::<f64>() < testing_rate {
             random_testing_referral = true;
         };
 
+        let mut details: Vec<String> = Vec::new();
+
+        // Travel history includes the legs of earlier trips
+        let countries_visited = TripLeg::countries_visited(
+            context,
+            conn,
+            person.id,
+            arrival - Duration::days(COUNTRIES_VISITED_DAYS),
+        )?;
+
+        let destination_country_id = get_place_by_id(context, trip.destination_place_id)?.country_id;
+
+        let visited: Vec<String> = countries_visited
+            .into_iter()
+            .filter(|c| c.id != destination_country_id)
+            .map(|c| c.country_name)
+            .collect();
+
+        if !visited.is_empty() {
+            details.push(format!(
+                "Countries visited in last {} days: {}",
+                COUNTRIES_VISITED_DAYS, visited.join(", ")));
+        }
+
+        if travel_intent == TravelIntent::Transit {
+            details.push(format!("In transit through {}", self.destination_name));
+        }
+
+        // Entry requirements depend on the traveller's status in Canada
+        let mut decision = EntryDecision::Admit;
+
+        if travel_intent == TravelIntent::Entry {
+            details.extend(document_check.details);
+            details.push(format!("Residency status: {}", residency_status));
+
+            if document_check.has_valid_document && residency_status != document_check.documented_status {
+                details.push(format!("Travel documents show {}", document_check.documented_status));
+            }
+
+            let doses = vaccination_history
+                .iter()
+                .map(|v| get_vaccine_by_id(context, v.vaccine_id).map(|vaccine| (vaccine, v.provided_on)))
+                .collect::<FieldResult<Vec<_>>>()?;
+
+            let outcome = ENTRY_RULES.decide(&EntryAssessment {
+                residency_status,
+                has_valid_document: document_check.has_valid_document,
+                vaccination_required: self.vaccination_required,
+                fully_vaccinated: ENTRY_RULES.is_fully_vaccinated(&doses, arrival),
+            });
+
+            details.extend(outcome.details);
+            decision = outcome.decision;
+        }
+
         // Build TravelResponse
         let new_tr = NewPILResponse::new(
             "OK".to_string(),
             trip.id,
             person.id,
+            person_created,
             cbsa_id.to_string(),
-            "I".to_string(),
+            decision.response_code().to_string(),
             random_testing_referral,
-            false,
-            "None".to_string()
+            decision == EntryDecision::Quarantine,
+            if details.is_empty() { "None".to_string() } else { details.join("; ") },
         );
 
-        let travel_response = PILResponse::create(&conn, &new_tr)?;
+        let travel_response = PILResponse::create(conn, &new_tr)?;
         Ok(travel_response)
     }
 }
\ No newline at end of file
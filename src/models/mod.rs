@@ -1,21 +1,45 @@
 mod person;
+mod person_match;
+mod person_match_review;
+mod travel_document;
+mod entry_rules;
 mod health_profile;
 mod covid_test;
 mod vaccination;
 mod vaccine;
 mod quarantine_plan;
 mod trip;
+mod trip_state_history;
+mod trip_leg;
+mod travel_provider;
 mod place;
+mod place_alias;
 mod country;
+mod country_alias;
+mod pending_country;
 mod postal_address;
 mod travel_group;
+mod travel_group_relationship;
+mod guardian_consent;
 mod access_log;
 mod user;
 mod messages;
 mod auth;
+mod manifest;
+mod export;
+mod pil_submission;
+mod persisted_query;
+mod validation;
 
 pub use self::person::*;
+pub use self::person_match::*;
+pub use self::person_match_review::*;
+pub use self::travel_document::*;
+pub use self::entry_rules::*;
 pub use self::trip::*;
+pub use self::trip_state_history::*;
+pub use self::trip_leg::*;
+pub use self::travel_provider::*;
 pub use self::health_profile::*;
 pub use self::covid_test::*;
 pub use self::vaccination::*;
@@ -24,8 +48,18 @@ pub use self::quarantine_plan::*;
 pub use self::access_log::*;
 pub use self::user::*;
 pub use self::travel_group::{TravelGroup, NewTravelGroup};
-pub use self::place::{Place, NewPlace};
-pub use self::country::{Country, NewCountry};
+pub use self::travel_group_relationship::*;
+pub use self::guardian_consent::*;
+pub use self::place::{Place, NewPlace, PlaceKind, PlaceKindMapping, normalize_place_name};
+pub use self::place_alias::*;
+pub use self::country::{Country, NewCountry, normalize_country_name};
+pub use self::country_alias::*;
+pub use self::pending_country::*;
 pub use self::postal_address::*;
 pub use messages::*;
-pub use auth::*;
\ No newline at end of file
+pub use auth::*;
+pub use manifest::*;
+pub use export::*;
+pub use pil_submission::*;
+pub use persisted_query::*;
+pub use validation::*;
\ No newline at end of file
@@ -0,0 +1,147 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use diesel::{self, Insertable, PgConnection, Queryable, ExpressionMethods};
+use diesel::{Connection, RunQueryDsl, QueryDsl};
+use uuid::Uuid;
+use async_graphql::*;
+
+use crate::graphql::graphql_translate;
+use crate::errors::error_handler::CustomError;
+use crate::models::{Country, CountryAlias, NewCountry, NewCountryAlias, normalize_country_name};
+use crate::schema::*;
+
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable, SimpleObject)]
+/// A submitted country name that matched no Country or alias.
+/// Travellers naming it are rejected until an Admin approves it as a new
+/// Country or adds it as an alias of an existing one.
+pub struct PendingCountry {
+    pub id: Uuid,
+    pub country_name: String,
+    /// Number of submissions rejected for this name
+    pub seen_count: i32,
+    pub first_seen_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+}
+
+impl PendingCountry {
+    /// Adds the name to the queue or counts another sighting of it
+    pub fn record(conn: &PgConnection, country_name: &str) -> FieldResult<PendingCountry> {
+        let new_pending = NewPendingCountry {
+            country_name: country_name.trim().to_owned(),
+        };
+
+        let res = diesel::insert_into(pending_countries::table)
+            .values(&new_pending)
+            .on_conflict(pending_countries::country_name)
+            .do_update()
+            .set((
+                pending_countries::seen_count.eq(pending_countries::seen_count + 1),
+                pending_countries::last_seen_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+
+    pub fn get_by_id(conn: &PgConnection, id: Uuid) -> FieldResult<PendingCountry> {
+        let res = pending_countries::table
+            .filter(pending_countries::id.eq(id))
+            .first(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Most often seen first
+    pub fn get_all(conn: &PgConnection) -> FieldResult<Vec<PendingCountry>> {
+        let res = pending_countries::table
+            .order((pending_countries::seen_count.desc(), pending_countries::first_seen_at))
+            .load::<PendingCountry>(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Removes every pending name that normalizes to name
+    pub fn resolve(conn: &PgConnection, name: &str) -> FieldResult<usize> {
+        let name = normalize_country_name(name);
+
+        let ids: Vec<Uuid> = PendingCountry::get_all(conn)?
+            .into_iter()
+            .filter(|p| normalize_country_name(&p.country_name) == name)
+            .map(|p| p.id)
+            .collect();
+
+        let res = diesel::delete(pending_countries::table)
+            .filter(pending_countries::id.eq_any(ids))
+            .execute(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Creates a Country for the pending name, or for country_name with the
+    /// pending name kept as an alias, and clears it from the queue
+    pub fn approve(
+        conn: &PgConnection,
+        id: Uuid,
+        country_name: Option<String>,
+        iso_alpha2: String,
+        iso_alpha3: String,
+        risk_rate: f64,
+    ) -> FieldResult<Country> {
+
+        let mut violations: Vec<String> = Vec::new();
+
+        if iso_alpha2.trim().len() != 2 || !iso_alpha2.trim().chars().all(|c| c.is_ascii_alphabetic()) {
+            violations.push(format!("iso_alpha2 \"{}\" must be 2 letters", iso_alpha2));
+        }
+
+        if iso_alpha3.trim().len() != 3 || !iso_alpha3.trim().chars().all(|c| c.is_ascii_alphabetic()) {
+            violations.push(format!("iso_alpha3 \"{}\" must be 3 letters", iso_alpha3));
+        }
+
+        if !(0.0..=1.0).contains(&risk_rate) {
+            violations.push(format!("risk_rate {} must be between 0 and 1", risk_rate));
+        }
+
+        if !violations.is_empty() {
+            return Err(CustomError::Validation(violations).extend());
+        }
+
+        conn.transaction::<Country, Error, _>(|| {
+            let pending = PendingCountry::get_by_id(conn, id)?;
+
+            let country_name = country_name
+                .map(|n| n.trim().to_owned())
+                .unwrap_or_else(|| pending.country_name.to_owned());
+
+            let country = Country::create(conn, &NewCountry::with_iso_codes(
+                country_name,
+                risk_rate,
+                iso_alpha2,
+                iso_alpha3,
+            ))?;
+
+            if !country.is_named(&pending.country_name) {
+                CountryAlias::create(conn, &NewCountryAlias::new(country.id, &pending.country_name))?;
+            }
+
+            PendingCountry::resolve(conn, &pending.country_name)?;
+            PendingCountry::resolve(conn, &country.country_name)?;
+
+            Ok(country)
+        })
+    }
+
+    pub fn delete(conn: &PgConnection, id: Uuid) -> FieldResult<usize> {
+        let res = diesel::delete(pending_countries::table)
+            .filter(pending_countries::id.eq(id))
+            .execute(conn);
+
+        graphql_translate(res)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "pending_countries"]
+pub struct NewPendingCountry {
+    pub country_name: String,
+}
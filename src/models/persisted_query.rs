@@ -0,0 +1,114 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use diesel::{self, Insertable, PgConnection, Queryable, ExpressionMethods};
+use diesel::{RunQueryDsl, QueryDsl};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use async_graphql::*;
+use async_graphql::parser::parse_query;
+
+use crate::graphql::graphql_translate;
+use crate::errors::error_handler::CustomError;
+use crate::schema::*;
+
+/// Hex SHA-256 of a query document, as clients send it in
+/// extensions.persistedQuery.sha256Hash
+pub fn query_hash(document: &str) -> String {
+    format!("{:x}", Sha256::digest(document.as_bytes()))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable, SimpleObject)]
+/// A query document registered by an Admin. With PERSISTED_QUERIES_ONLY set,
+/// roles other than Admin can only run registered documents.
+pub struct PersistedQuery {
+    pub id: Uuid,
+    pub sha256_hash: String,
+    pub document: String,
+    pub operation_name: Option<String>,
+    pub description: Option<String>,
+    /// Admin who registered the query
+    pub created_by: Uuid,
+    pub created_at: NaiveDateTime,
+}
+
+impl PersistedQuery {
+    /// Registers the document. Documents that don't parse are rejected and
+    /// registering the same document again returns a CONFLICT error.
+    pub fn create(conn: &PgConnection, query: &NewPersistedQuery) -> FieldResult<PersistedQuery> {
+        if let Err(e) = parse_query(&query.document) {
+            return Err(CustomError::Validation(vec![format!("document is not a valid query: {}", e)]).extend());
+        }
+
+        let res = diesel::insert_into(persisted_queries::table)
+            .values(query)
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+
+    pub fn get_by_id(conn: &PgConnection, id: Uuid) -> FieldResult<PersistedQuery> {
+        let res = persisted_queries::table
+            .filter(persisted_queries::id.eq(id))
+            .first(conn);
+
+        graphql_translate(res)
+    }
+
+    /// None if no document with this hash is registered
+    pub fn find_by_hash(conn: &PgConnection, sha256_hash: &str) -> FieldResult<Option<PersistedQuery>> {
+        let res = persisted_queries::table
+            .filter(persisted_queries::sha256_hash.eq(sha256_hash.to_lowercase()))
+            .first(conn);
+
+        match res {
+            Ok(q) => Ok(Some(q)),
+            Err(diesel::result::Error::NotFound) => Ok(None),
+            Err(e) => graphql_translate(Err(e)),
+        }
+    }
+
+    /// Most recently registered first
+    pub fn get_all(conn: &PgConnection) -> FieldResult<Vec<PersistedQuery>> {
+        let res = persisted_queries::table
+            .order(persisted_queries::created_at.desc())
+            .load::<PersistedQuery>(conn);
+
+        graphql_translate(res)
+    }
+
+    pub fn delete(conn: &PgConnection, id: Uuid) -> FieldResult<usize> {
+        let res = diesel::delete(persisted_queries::table)
+            .filter(persisted_queries::id.eq(id))
+            .execute(conn);
+
+        graphql_translate(res)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "persisted_queries"]
+pub struct NewPersistedQuery {
+    pub sha256_hash: String,
+    pub document: String,
+    pub operation_name: Option<String>,
+    pub description: Option<String>,
+    pub created_by: Uuid,
+}
+
+impl NewPersistedQuery {
+    /// The hash is computed from the document as given
+    pub fn new(
+        document: String,
+        operation_name: Option<String>,
+        description: Option<String>,
+        created_by: Uuid,
+    ) -> Self {
+        NewPersistedQuery {
+            sha256_hash: query_hash(&document),
+            document,
+            operation_name,
+            description,
+            created_by,
+        }
+    }
+}
@@ -1,9 +1,13 @@
+use std::cmp::Ordering;
 use std::fmt::Debug;
 
-use chrono::{prelude::*};
+use chrono::{Duration, prelude::*};
 use serde::{Deserialize, Serialize};
-use diesel::{self, Insertable, PgConnection, Queryable, ExpressionMethods};
-use diesel::{RunQueryDsl, QueryDsl};
+use diesel::{self, Insertable, PgConnection, Queryable, BoolExpressionMethods, ExpressionMethods};
+use diesel::sql_types::{Integer, Text};
+use diesel::{Connection, RunQueryDsl, QueryDsl};
+use diesel::pg::Pg;
+use diesel::expression_methods::ArrayExpressionMethods;
 use uuid::Uuid;
 use async_graphql::*;
 use rand::{Rng, thread_rng};
@@ -12,12 +16,27 @@ use crate::common_utils::{
     is_analyst, RoleGuard, Role};
 
 use crate::schema::*;
-use crate::graphql::{graphql_translate, get_connection_from_context};
-use crate::models::{Country, Trip};
-use crate::get_country_by_id;
+use async_graphql::dataloader::DataLoader;
+
+use crate::graphql::{graphql_translate, KeysetCursor, PageArgs, SortDirection,
+    PublicHealthProfileLoader, TravelDocumentsByPersonLoader, TripsByPersonLoader, list_cost};
+use crate::errors::error_handler::CustomError;
+use crate::models::{Country, Trip, TripLeg, TravelDocument, PresentedTravelDocument,
+    PersonMatchReview, NewPersonMatchReview, ResidencyStatus, TravelGroupRelationship, GuardianConsent,
+    name_initial, name_initial_translation, normalize_document_id};
+use crate::config_variables::{COUNTRIES_VISITED_DAYS, MAX_COUNTRIES_VISITED_DAYS, PERSON_MATCH_RULES};
+use crate::{get_country_by_id, ReferenceData};
+use crate::database::Repository;
 
 use super::PublicHealthProfile;
 
+sql_function!(fn lower(x: Text) -> Text);
+sql_function!(fn upper(x: Text) -> Text);
+sql_function!(fn btrim(x: Text) -> Text);
+sql_function!(fn left(x: Text, n: Integer) -> Text);
+sql_function!(fn translate(x: Text, from: Text, to: Text) -> Text);
+sql_function!(fn regexp_replace(x: Text, pattern: Text, replacement: Text, flags: Text) -> Text);
+
 #[derive(Debug, Clone, Deserialize, Serialize, Queryable, Insertable, AsChangeset)]
 #[table_name = "persons"]
 /// Referenced by PublicHealthProfile
@@ -32,14 +51,15 @@ pub struct Person {
     pub birth_date: NaiveDate,
     pub gender: String,
 
-    pub travel_document_id: String,
-    pub travel_document_issuer_id: Uuid, // Country
-
     pub travel_group_id: Uuid,
     
     pub approved_access_level: String, // AccessLevel
     pub approved_access_granularity: String, // Granularity
     pub created_at: NaiveDateTime,
+
+    /// None until declared or shown by documents
+    pub residency_status: Option<ResidencyStatus>,
+    pub citizenship_ids: Vec<Uuid>, // Country
 }
 
 
@@ -53,27 +73,160 @@ impl Person {
         graphql_translate(res)
     }
     
-    pub fn get_or_create(conn: &PgConnection, person: &NewPerson) -> FieldResult<Person> {
+    pub fn get_by_id(conn: &PgConnection, id: Uuid) -> FieldResult<Person> {
         let res = persons::table
-        .filter(persons::travel_document_id.eq(&person.travel_document_id))
-        .filter(persons::family_name.eq(&person.family_name))
-        .filter(persons::travel_document_issuer_id.eq(&person.travel_document_issuer_id))
-        .filter(persons::birth_date.eq(&person.birth_date))
-        .distinct()
+        .filter(persons::id.eq(id))
         .first(conn);
-        
-        let person = match res {
-            Ok(p) => p,
-            Err(e) => {
-                // Person not found
-                println!("{:?}", e);
-                let p = Person::create(conn, person).expect("Unable to create person");
-                p
-            }
+
+        graphql_translate(res)
+    }
+
+    pub fn find_by_id(conn: &PgConnection, id: Uuid) -> FieldResult<Option<Person>> {
+        match persons::table.filter(persons::id.eq(id)).first(conn) {
+            Ok(p) => Ok(Some(p)),
+            Err(diesel::result::Error::NotFound) => Ok(None),
+            Err(e) => graphql_translate(Err(e)),
+        }
+    }
+
+    /// Batched with the other persons resolved at the same level of the query
+    async fn loaded_travel_documents(&self, context: &Context<'_>) -> FieldResult<Vec<TravelDocument>> {
+        let documents = context.data::<DataLoader<TravelDocumentsByPersonLoader>>()?
+            .load_one(self.id)
+            .await?;
+
+        Ok(documents.unwrap_or_default())
+    }
+
+    async fn primary_travel_document(&self, context: &Context<'_>) -> FieldResult<TravelDocument> {
+        let documents = self.loaded_travel_documents(context).await?;
+
+        TravelDocument::primary(&documents)
+            .cloned()
+            .ok_or_else(|| CustomError::NotFound(format!("Travel document for person {}", self.id)).extend())
+    }
+
+    /// Matches the submitted traveller to an existing person with PERSON_MATCH_RULES
+    /// on any of the documents they presented. A confident match returns the existing
+    /// person. An uncertain one creates a new person and queues the pair for Admin
+    /// review. The documents are then recorded for the person.
+    /// Returns the person and whether they were created.
+    pub fn get_or_create(
+        conn: &PgConnection,
+        person: &NewPerson,
+        documents: &[PresentedTravelDocument],
+        presented_at: NaiveDateTime,
+    ) -> FieldResult<(Person, bool)> {
+        let rules = PERSON_MATCH_RULES;
+
+        // Below the review score without the same family name or a document,
+        // so only people with the same initial or one of the documents are loaded
+        let (initial_from, initial_to) = name_initial_translation();
+
+        let holder_ids = travel_documents::table
+        .filter(travel_documents::issuer_id.eq_any(documents.iter().map(|d| d.issuer_id).collect::<Vec<Uuid>>()))
+        .filter(upper(regexp_replace(travel_documents::document_number, "[^[:alnum:]]", "", "g"))
+            .eq_any(documents.iter().map(|d| normalize_document_id(&d.document_number)).collect::<Vec<String>>()))
+        .select(travel_documents::person_id);
+
+        let candidates = graphql_translate(persons::table
+        .filter(persons::birth_date.eq(&person.birth_date))
+        .filter(lower(translate(left(btrim(persons::family_name), 1), initial_from, initial_to))
+            .eq(name_initial(&person.family_name).map(String::from).unwrap_or_default())
+            .or(persons::id.eq_any(holder_ids)))
+        .load::<Person>(conn))?;
+
+        let candidate_documents = TravelDocument::get_by_person_ids(conn, candidates.iter().map(|c| c.id).collect())?;
+
+        let best = candidates
+        .iter()
+        .filter_map(|c| rules.score(person, documents, c, &candidate_documents))
+        .max_by(|a, b| a.score.partial_cmp(&b.score).unwrap_or(Ordering::Equal));
+
+        let (person, created) = match best {
+            Some(m) if m.score >= rules.auto_match_score => (m.candidate, false),
+            Some(m) if m.score >= rules.review_score => {
+                let new_person = Person::create(conn, person)?;
+
+                PersonMatchReview::create(conn, &NewPersonMatchReview::from(new_person.id, &m))?;
+
+                (new_person, true)
+            },
+            _ => (Person::create(conn, person)?, true),
         };
-        Ok(person)
+
+        for d in documents {
+            TravelDocument::record(conn, person.id, d, presented_at)?;
+        }
+
+        Ok((person, created))
+    }
+
+    /// Moves trips, travel responses, health profiles, travel documents,
+    /// relationships, guardian consents and citizenships from the duplicate to the surviving person and deletes the
+    /// duplicate.
+    pub fn merge(conn: &PgConnection, duplicate_id: Uuid, surviving_id: Uuid, merged_by: Uuid) -> FieldResult<Person> {
+        if duplicate_id == surviving_id {
+            return Err(CustomError::Validation(vec![
+                "A person can't be merged into themselves".to_string()]).extend());
+        }
+
+        conn.transaction::<Person, Error, _>(|| {
+            let duplicate = Person::get_by_id(conn, duplicate_id)?;
+            let survivor = Person::get_by_id(conn, surviving_id)?;
+
+            graphql_translate(diesel::update(trips::table.filter(trips::person_id.eq(duplicate.id)))
+            .set(trips::person_id.eq(survivor.id))
+            .execute(conn))?;
+
+            graphql_translate(diesel::update(travel_responses::table.filter(travel_responses::person_id.eq(duplicate.id)))
+            .set(travel_responses::person_id.eq(survivor.id))
+            .execute(conn))?;
+
+            // The survivor is left with a single profile holding both histories
+            PublicHealthProfile::move_to_person(conn, duplicate.id, survivor.id)?;
+
+            // Documents the survivor already holds are removed with the duplicate
+            TravelDocument::move_to_person(conn, duplicate.id, survivor.id)?;
+
+            TravelGroupRelationship::move_to_person(conn, duplicate.id, survivor.id)?;
+            GuardianConsent::move_to_person(conn, duplicate.id, survivor.id)?;
+
+            // The survivor keeps the most rights in Canada either was known to have
+            let survivor = match survivor.residency_status.max(duplicate.residency_status) {
+                Some(status) => survivor.record_residency(conn, status, &duplicate.citizenship_ids)?,
+                None => survivor,
+            };
+
+            PersonMatchReview::move_to_person(conn, duplicate.id, survivor.id, merged_by)?;
+
+            graphql_translate(diesel::delete(persons::table.filter(persons::id.eq(duplicate.id)))
+            .execute(conn))?;
+
+            Ok(survivor)
+        })
     }
     
+    /// Sets the person's status and adds to their countries of citizenship
+    pub fn record_residency(&self, conn: &PgConnection, residency_status: ResidencyStatus, citizenship_ids: &[Uuid]) -> FieldResult<Person> {
+        let mut all_citizenship_ids = self.citizenship_ids.to_owned();
+
+        for id in citizenship_ids {
+            if !all_citizenship_ids.contains(id) {
+                all_citizenship_ids.push(*id);
+            }
+        }
+
+        let res = diesel::update(persons::table.filter(persons::id.eq(self.id)))
+            .set((
+                persons::residency_status.eq(Some(residency_status)),
+                persons::citizenship_ids.eq(all_citizenship_ids),
+            ))
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+
     pub fn update(&self, conn: &PgConnection) -> FieldResult<Self> {
         let res = diesel::update(persons::table)
         .filter(persons::id.eq(&self.id))
@@ -86,6 +239,10 @@ impl Person {
 
 #[Object]
 impl Person {
+
+    pub async fn id(&self) -> FieldResult<Uuid> {
+        Ok(self.id)
+    }
     
     #[graphql(
         guard = "RoleGuard::new(Role::Analyst)",
@@ -123,47 +280,217 @@ impl Person {
         Ok(self.approved_access_granularity.to_owned())
     }
     
+    #[graphql(deprecation = "Use travelDocuments")]
+    /// Issuer of the most recently presented active document
     pub async fn travel_document_issuer(&self, context: &Context<'_>) -> FieldResult<Country> {
-        get_country_by_id(context, self.travel_document_issuer_id)
+        let document = self.primary_travel_document(context).await?;
+
+        get_country_by_id(context, document.issuer_id)
     }
     
     #[graphql(
         guard = "RoleGuard::new(Role::Analyst)",
         visible = "is_analyst",
+        deprecation = "Use travelDocuments",
     )]
     /// This is personally identifiable information and can only be accessed
     /// by Analyst or Admin roles.
-    pub async fn travel_document_id(&self) -> FieldResult<String> {
-        Ok(self.travel_document_id.to_owned())
+    /// Number of the most recently presented active document
+    pub async fn travel_document_id(&self, context: &Context<'_>) -> FieldResult<String> {
+        Ok(self.primary_travel_document(context).await?.document_number)
+    }
+    
+    #[graphql(
+        guard = "RoleGuard::new(Role::Analyst)",
+        visible = "is_analyst",
+        complexity = "list_cost(child_complexity)",
+    )]
+    /// Every document the person has travelled on, most recently presented first
+    pub async fn travel_documents(&self, context: &Context<'_>) -> FieldResult<Vec<TravelDocument>> {
+        self.loaded_travel_documents(context).await
+    }
+    
+    #[graphql(
+        guard = "RoleGuard::new(Role::Analyst)",
+        visible = "is_analyst",
+    )]
+    /// Citizen, permanent resident or foreign national, null if not yet known
+    pub async fn residency_status(&self) -> FieldResult<Option<ResidencyStatus>> {
+        Ok(self.residency_status)
+    }
+
+    #[graphql(
+        guard = "RoleGuard::new(Role::Analyst)",
+        visible = "is_analyst",
+    )]
+    pub async fn citizenships(&self, context: &Context<'_>) -> FieldResult<Vec<Country>> {
+        self.citizenship_ids
+            .iter()
+            .map(|id| get_country_by_id(context, *id))
+            .collect()
+    }
+
+    #[graphql(
+        guard = "RoleGuard::new(Role::Analyst)",
+        visible = "is_analyst",
+        complexity = "list_cost(child_complexity)",
+    )]
+    /// Relationships to people the person travelled with, in every travel group
+    pub async fn relationships(&self, context: &Context<'_>) -> FieldResult<Vec<TravelGroupRelationship>> {
+        let person_id = self.id;
+
+        Repository::from_context(context)?
+            .run(move |conn| TravelGroupRelationship::get_by_person(conn, person_id))
+            .await
     }
     
     pub async fn public_health_profile(&self, context: &Context<'_>) -> FieldResult<PublicHealthProfile> {
-        let conn = get_connection_from_context(context);
-        
-        let res = public_health_profiles::table
-        .filter(public_health_profiles::person_id.eq(self.id))
-        .first(&conn);
-        
-        graphql_translate(res)
+        context.data::<DataLoader<PublicHealthProfileLoader>>()?
+            .load_one(self.id)
+            .await?
+            .ok_or_else(|| CustomError::NotFound(format!("Public health profile for person {}", self.id)).extend())
     }
     
+    #[graphql(complexity = "list_cost(child_complexity)")]
+    /// Ordered by arrival time
     pub async fn trips(&self, context: &Context<'_>) -> FieldResult<Vec<Trip>> {
-        let conn = get_connection_from_context(context);
-        
-        let res = trips::table.
-        filter(trips::person_id.eq(self.id))
-        .order_by(trips::arrival_time)
-        .order_by(trips::person_id)
-        .load::<Trip>(&conn);
-        
-        graphql_translate(res)
+        let trips = context.data::<DataLoader<TripsByPersonLoader>>()?
+            .load_one(self.id)
+            .await?;
+
+        Ok(trips.unwrap_or_default())
     }
     
+    #[graphql(complexity = "list_cost(child_complexity)")]
+    /// Countries on any leg of the person's trips in the last days
+    /// (COUNTRIES_VISITED_DAYS if not given, at most MAX_COUNTRIES_VISITED_DAYS)
+    pub async fn countries_visited(&self, context: &Context<'_>, days: Option<i64>) -> FieldResult<Vec<Country>> {
+        let person_id = self.id;
+        let reference = ReferenceData::from_context(context)?;
+
+        let since = Utc::now().naive_utc() - Duration::days(visited_days(days).map_err(|e| e.extend())?);
+
+        Repository::from_context(context)?
+            .run(move |conn| TripLeg::countries_visited(&reference, conn, person_id, since))
+            .await
+    }
+
     pub async fn created_at(&self) -> NaiveDateTime {
         self.created_at
     }
 }
 
+/// Days of history for Person::countries_visited, rejecting values
+/// outside 1..=MAX_COUNTRIES_VISITED_DAYS
+pub fn visited_days(days: Option<i64>) -> Result<i64, CustomError> {
+    let days = days.unwrap_or(COUNTRIES_VISITED_DAYS);
+
+    if !(1..=MAX_COUNTRIES_VISITED_DAYS).contains(&days) {
+        return Err(CustomError::Validation(vec![
+            format!("days must be between 1 and {}", MAX_COUNTRIES_VISITED_DAYS)]));
+    }
+
+    Ok(days)
+}
+
+#[derive(Debug, Clone, Default, InputObject)]
+/// Conditions a person must meet to be listed. Every condition given applies.
+/// Ranges include from and exclude to.
+pub struct PersonFilter {
+    pub created_from: Option<NaiveDateTime>,
+    pub created_to: Option<NaiveDateTime>,
+    pub born_from: Option<NaiveDate>,
+    pub born_to: Option<NaiveDate>,
+    pub residency_status: Option<ResidencyStatus>,
+    /// Country of citizenship
+    pub citizenship_id: Option<Uuid>,
+}
+
+impl PersonFilter {
+    pub fn query(&self) -> persons::BoxedQuery<'static, Pg> {
+        let mut query = persons::table.into_boxed();
+
+        if let Some(from) = self.created_from {
+            query = query.filter(persons::created_at.ge(from));
+        }
+
+        if let Some(to) = self.created_to {
+            query = query.filter(persons::created_at.lt(to));
+        }
+
+        if let Some(from) = self.born_from {
+            query = query.filter(persons::birth_date.ge(from));
+        }
+
+        if let Some(to) = self.born_to {
+            query = query.filter(persons::birth_date.lt(to));
+        }
+
+        if let Some(status) = self.residency_status {
+            query = query.filter(persons::residency_status.eq(status));
+        }
+
+        if let Some(country_id) = self.citizenship_id {
+            query = query.filter(persons::citizenship_ids.contains(vec![country_id]));
+        }
+
+        query
+    }
+}
+
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PersonSortField {
+    CreatedAt,
+    BirthDate,
+}
+
+#[derive(Debug, Clone, Copy, InputObject)]
+pub struct PersonSort {
+    pub field: PersonSortField,
+    #[graphql(default)]
+    pub direction: SortDirection,
+}
+
+impl Default for PersonSort {
+    fn default() -> Self {
+        PersonSort {
+            field: PersonSortField::CreatedAt,
+            direction: SortDirection::Asc,
+        }
+    }
+}
+
+impl PersonSort {
+    /// Ties are ordered by id so pages don't overlap
+    pub fn apply(&self, query: persons::BoxedQuery<'static, Pg>) -> persons::BoxedQuery<'static, Pg> {
+        match (self.field, self.direction) {
+            (PersonSortField::CreatedAt, SortDirection::Asc) => query.order((persons::created_at.asc(), persons::id.asc())),
+            (PersonSortField::CreatedAt, SortDirection::Desc) => query.order((persons::created_at.desc(), persons::id.desc())),
+            (PersonSortField::BirthDate, SortDirection::Asc) => query.order((persons::birth_date.asc(), persons::id.asc())),
+            (PersonSortField::BirthDate, SortDirection::Desc) => query.order((persons::birth_date.desc(), persons::id.desc())),
+        }
+    }
+
+    /// The rows of a page, loaded in reverse order if paging backward
+    pub fn page(&self, mut query: persons::BoxedQuery<'static, Pg>, args: &PageArgs) -> persons::BoxedQuery<'static, Pg> {
+        for (cursor, later) in args.bounds(self.direction) {
+            query = match self.field {
+                PersonSortField::CreatedAt => crate::keyset_filter!(query, persons::created_at, persons::id, cursor.key, cursor.id, later),
+                PersonSortField::BirthDate => crate::keyset_filter!(query, persons::birth_date, persons::id, cursor.date(), cursor.id, later),
+            };
+        }
+
+        PersonSort { direction: args.load_direction(self.direction), ..*self }.apply(query)
+    }
+
+    pub fn cursor(&self, person: &Person) -> KeysetCursor {
+        match self.field {
+            PersonSortField::CreatedAt => KeysetCursor::new(Some(person.created_at), person.id),
+            PersonSortField::BirthDate => KeysetCursor::on_date(person.birth_date, person.id),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, Insertable, SimpleObject)]
 /// Linked from HealthProfile
 /// Linked to Trip
@@ -175,13 +502,13 @@ pub struct NewPerson {
     pub birth_date: NaiveDate,
     pub gender: String,
 
-    pub travel_document_id: String,
-    pub travel_document_issuer_id: Uuid, // Country
-
     pub travel_group_id: Uuid,
 
     pub approved_access_level: String, // AccessLevel
     pub approved_access_granularity: String, // Granularity
+
+    pub residency_status: Option<ResidencyStatus>,
+    pub citizenship_ids: Vec<Uuid>, // Country
 }
 
 impl NewPerson {
@@ -192,11 +519,11 @@ impl NewPerson {
         additional_names: Option<Vec<String>>,
         birth_date: NaiveDate,
         gender: String,
-        travel_document_id: String,
-        travel_document_issuer_id: Uuid, // Country
         travel_group_id: Uuid,
         approved_access_level: String, // AccessLevel
         approved_access_granularity: String,
+        residency_status: Option<ResidencyStatus>,
+        citizenship_ids: Vec<Uuid>, // Country
     ) -> Self {
         NewPerson {
             family_name,
@@ -204,15 +531,15 @@ impl NewPerson {
             additional_names,
             birth_date,
             gender,
-            travel_document_id,
-            travel_document_issuer_id,
             travel_group_id,
             approved_access_level,
             approved_access_granularity,
+            residency_status,
+            citizenship_ids,
         }
     }
 
-    pub fn fake(travel_document_issuer_id: Uuid, travel_group_id: Uuid) -> NewPerson {
+    pub fn fake(travel_group_id: Uuid) -> NewPerson {
 
         let mut rng = thread_rng();
         let random_year = rng.gen_range(1945..2002);
@@ -227,11 +554,11 @@ impl NewPerson {
             additional_names: None,
             birth_date: dob,
             gender: "female".to_string(),
-            travel_document_id: "HDFSHFKJHD372840".to_string(),
-            travel_document_issuer_id,
             travel_group_id,
             approved_access_level: "medical_records".to_string(),
             approved_access_granularity: "aggregated".to_string(),
+            residency_status: None,
+            citizenship_ids: Vec::new(),
         }
     }
 }
@@ -0,0 +1,265 @@
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
+
+use crate::models::{NewPerson, Person, PresentedTravelDocument, TravelDocument, TravelDocumentStatus};
+
+#[derive(Debug, Clone, Copy)]
+/// Weights and thresholds for matching a submitted traveller to a Person.
+/// Candidates must share a birth date; document and names add to the score.
+/// Set in config_variables::PERSON_MATCH_RULES.
+pub struct PersonMatchRules {
+    /// Same document type, number and issuer as any the candidate holds
+    pub document_weight: f64,
+    pub family_name_weight: f64,
+    pub given_name_weight: f64,
+    /// Compare names after transliteration, e.g. Müller, Mueller and Muller
+    pub transliterate_names: bool,
+    /// Count names one edit apart, or a given name that is an initial
+    /// or prefix of the other, at half weight
+    pub fuzzy_names: bool,
+    /// Match on documents that were replaced, reported lost or revoked
+    pub use_inactive_documents: bool,
+    /// At or above this score the traveller is the candidate
+    pub auto_match_score: f64,
+    /// At or above this score, but below auto_match_score, a new person is
+    /// created and the pair queued for Admin review
+    pub review_score: f64,
+}
+
+#[derive(Debug, Clone)]
+/// How well a submitted traveller matches an existing Person
+pub struct PersonMatch {
+    pub candidate: Person,
+    pub score: f64,
+    /// Why points were given, shown in the review queue
+    pub reasons: Vec<String>,
+    /// The candidate matched only on a document that is no longer active
+    pub matched_inactive_document: bool,
+}
+
+impl PersonMatchRules {
+    /// Scores candidate against the submitted person and the documents they
+    /// presented. Returns None if they can't be the same person.
+    pub fn score(
+        &self,
+        person: &NewPerson,
+        documents: &[PresentedTravelDocument],
+        candidate: &Person,
+        candidate_documents: &[TravelDocument],
+    ) -> Option<PersonMatch> {
+
+        if person.birth_date != candidate.birth_date {
+            return None;
+        }
+
+        let mut score = 0.0;
+        let mut reasons: Vec<String> = Vec::new();
+        let mut matched_inactive_document = false;
+
+        let matched: Vec<&TravelDocument> = candidate_documents
+            .iter()
+            .filter(|d| d.person_id == candidate.id && documents.iter().any(|p| d.matches(p)))
+            .collect();
+
+        if matched.iter().any(|d| d.status == TravelDocumentStatus::Active) {
+            score += self.document_weight;
+            reasons.push("Same travel document".to_string());
+        } else if self.use_inactive_documents && !matched.is_empty() {
+            score += self.document_weight;
+            matched_inactive_document = true;
+            reasons.push(format!("Same as a {} travel document", format!("{:?}", matched[0].status).to_lowercase()));
+        }
+
+        match self.compare_names(&person.family_name, &candidate.family_name, false) {
+            NameMatch::Same => {
+                score += self.family_name_weight;
+                reasons.push("Same family name".to_string());
+            },
+            NameMatch::Similar => {
+                score += self.family_name_weight / 2.0;
+                reasons.push(format!("Similar family name: {} / {}", person.family_name, candidate.family_name));
+            },
+            NameMatch::Different => {},
+        }
+
+        match self.compare_names(&person.given_name, &candidate.given_name, true) {
+            NameMatch::Same => {
+                score += self.given_name_weight;
+                reasons.push("Same given name".to_string());
+            },
+            NameMatch::Similar => {
+                score += self.given_name_weight / 2.0;
+                reasons.push(format!("Similar given name: {} / {}", person.given_name, candidate.given_name));
+            },
+            NameMatch::Different => {},
+        }
+
+        Some(PersonMatch {
+            candidate: candidate.clone(),
+            score,
+            reasons,
+            matched_inactive_document,
+        })
+    }
+
+    fn compare_names(&self, name: &str, other: &str, allow_initials: bool) -> NameMatch {
+        let (a, b) = if self.transliterate_names {
+            (name_variants(name), name_variants(other))
+        } else {
+            (vec![name.trim().to_lowercase()], vec![other.trim().to_lowercase()])
+        };
+
+        if a.iter().any(|n| b.contains(n)) {
+            return NameMatch::Same;
+        }
+
+        if !self.fuzzy_names {
+            return NameMatch::Different;
+        }
+
+        let similar = a.iter().any(|n| b.iter().any(|o| {
+            let one_edit = n.chars().count().min(o.chars().count()) >= 5 && edit_distance(n, o) <= 1;
+            let prefix = allow_initials && !n.is_empty() && !o.is_empty()
+                && (n.starts_with(o.as_str()) || o.starts_with(n.as_str()));
+
+            one_edit || prefix
+        }));
+
+        if similar { NameMatch::Similar } else { NameMatch::Different }
+    }
+}
+
+enum NameMatch {
+    Same,
+    Similar,
+    Different,
+}
+
+/// Upper case without spaces or dashes
+pub fn normalize_document_id(document_id: &str) -> String {
+    document_id
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+/// Spellings a name may have on different documents: accents removed
+/// (Müller -> muller) and the ICAO 9303 passport transliteration
+/// (Müller -> mueller). Letters only, lower case.
+pub fn name_variants(name: &str) -> Vec<String> {
+    let lower = name.trim().to_lowercase();
+
+    let icao: String = lower
+        .chars()
+        .map(|c| match c {
+            'ä' | 'æ' => "ae".to_string(),
+            'ö' | 'ø' | 'œ' => "oe".to_string(),
+            'ü' => "ue".to_string(),
+            'å' => "aa".to_string(),
+            'ß' => "ss".to_string(),
+            'þ' => "th".to_string(),
+            c => c.to_string(),
+        })
+        .collect();
+
+    let mut variants: Vec<String> = [lower, icao]
+        .iter()
+        .map(|n| transliterate(n))
+        .collect();
+
+    variants.dedup();
+    variants
+}
+
+/// Accented and special letters with the letter name_variants begins every
+/// spelling of a name starting with them with, e.g. Ø and Ö are O in both
+/// Ostergaard and Oestergaard. Used in SQL translate() to narrow candidates
+/// by initial.
+pub const NAME_INITIAL_FOLDS: &[(char, char)] = &[
+    ('à', 'a'), ('á', 'a'), ('â', 'a'), ('ã', 'a'), ('ä', 'a'), ('å', 'a'), ('æ', 'a'), ('ā', 'a'), ('ă', 'a'), ('ą', 'a'),
+    ('ç', 'c'), ('ć', 'c'), ('ĉ', 'c'), ('ċ', 'c'), ('č', 'c'),
+    ('ď', 'd'), ('đ', 'd'),
+    ('è', 'e'), ('é', 'e'), ('ê', 'e'), ('ë', 'e'), ('ē', 'e'), ('ĕ', 'e'), ('ė', 'e'), ('ę', 'e'), ('ě', 'e'),
+    ('ĝ', 'g'), ('ğ', 'g'), ('ġ', 'g'), ('ģ', 'g'),
+    ('ĥ', 'h'),
+    ('ì', 'i'), ('í', 'i'), ('î', 'i'), ('ï', 'i'), ('ĩ', 'i'), ('ī', 'i'), ('į', 'i'),
+    ('ĵ', 'j'),
+    ('ķ', 'k'),
+    ('ĺ', 'l'), ('ļ', 'l'), ('ľ', 'l'), ('ł', 'l'),
+    ('ñ', 'n'), ('ń', 'n'), ('ņ', 'n'), ('ň', 'n'),
+    ('ò', 'o'), ('ó', 'o'), ('ô', 'o'), ('õ', 'o'), ('ö', 'o'), ('ø', 'o'), ('œ', 'o'), ('ō', 'o'), ('ŏ', 'o'), ('ő', 'o'),
+    ('ŕ', 'r'), ('ŗ', 'r'), ('ř', 'r'),
+    ('ś', 's'), ('ŝ', 's'), ('ş', 's'), ('š', 's'), ('ș', 's'), ('ß', 's'),
+    ('ţ', 't'), ('ť', 't'), ('ț', 't'), ('þ', 't'),
+    ('ù', 'u'), ('ú', 'u'), ('û', 'u'), ('ü', 'u'), ('ũ', 'u'), ('ū', 'u'), ('ŭ', 'u'), ('ů', 'u'), ('ű', 'u'), ('ų', 'u'),
+    ('ŵ', 'w'),
+    ('ý', 'y'), ('ÿ', 'y'), ('ŷ', 'y'),
+    ('ź', 'z'), ('ż', 'z'), ('ž', 'z'),
+];
+
+/// First letter of every spelling name_variants gives the name. Two names
+/// can only be the same under transliteration if their initials are equal.
+pub fn name_initial(name: &str) -> Option<char> {
+    name_variants(name).first()?.chars().next()
+}
+
+/// The from and to arguments of an SQL translate() that folds the first
+/// letter of a name, in either case, as name_initial does before lower()
+pub fn name_initial_translation() -> (String, String) {
+    let mut from = String::new();
+    let mut to = String::new();
+
+    for (letter, folded) in NAME_INITIAL_FOLDS.iter() {
+        from.push(*letter);
+        to.push(*folded);
+
+        let mut upper = letter.to_uppercase();
+
+        if let (Some(u), None) = (upper.next(), upper.next()) {
+            if u != *letter {
+                from.push(u);
+                to.push(*folded);
+            }
+        }
+    }
+
+    (from, to)
+}
+
+fn transliterate(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'ß' => "ss".to_string(),
+            'æ' => "ae".to_string(),
+            'œ' => "oe".to_string(),
+            'ø' => "o".to_string(),
+            'ł' => "l".to_string(),
+            'đ' => "d".to_string(),
+            'þ' => "th".to_string(),
+            c => c.to_string(),
+        })
+        .collect::<String>()
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .filter(|c| c.is_alphabetic())
+        .collect()
+}
+
+/// Levenshtein distance between a and b
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
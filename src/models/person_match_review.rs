@@ -0,0 +1,238 @@
+use std::collections::HashSet;
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use diesel::{self, Insertable, PgConnection, Queryable, ExpressionMethods};
+use diesel::{BoolExpressionMethods, RunQueryDsl, QueryDsl};
+use diesel_derive_enum::DbEnum;
+use uuid::Uuid;
+use async_graphql::*;
+
+use crate::graphql::graphql_translate;
+use crate::database::Repository;
+use crate::errors::error_handler::CustomError;
+use crate::models::{Person, PersonMatch};
+use crate::schema::*;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Queryable)]
+/// An uncertain match between a newly created Person and an existing one,
+/// waiting for an Admin to merge them or dismiss the match
+pub struct PersonMatchReview {
+    pub id: Uuid,
+    /// Person created from the submission
+    pub person_id: Uuid,
+    /// Existing person they may be
+    pub candidate_id: Uuid,
+    pub score: f64,
+    pub reasons: Vec<String>,
+    pub status: PersonMatchStatus,
+    pub created_at: NaiveDateTime,
+    pub reviewed_by: Option<Uuid>,
+    pub reviewed_at: Option<NaiveDateTime>,
+}
+
+#[Object]
+impl PersonMatchReview {
+    pub async fn id(&self) -> FieldResult<Uuid> {
+        Ok(self.id)
+    }
+
+    /// Null once merged into the candidate
+    pub async fn person(&self, context: &Context<'_>) -> FieldResult<Option<Person>> {
+        let person_id = self.person_id;
+
+        Repository::from_context(context)?
+            .run(move |conn| Person::find_by_id(conn, person_id))
+            .await
+    }
+
+    /// Null once merged into the person
+    pub async fn candidate(&self, context: &Context<'_>) -> FieldResult<Option<Person>> {
+        let candidate_id = self.candidate_id;
+
+        Repository::from_context(context)?
+            .run(move |conn| Person::find_by_id(conn, candidate_id))
+            .await
+    }
+
+    pub async fn score(&self) -> FieldResult<f64> {
+        Ok(self.score)
+    }
+
+    pub async fn reasons(&self) -> FieldResult<Vec<String>> {
+        Ok(self.reasons.to_owned())
+    }
+
+    pub async fn status(&self) -> FieldResult<PersonMatchStatus> {
+        Ok(self.status)
+    }
+
+    pub async fn created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+
+    pub async fn reviewed_by(&self) -> FieldResult<Option<Uuid>> {
+        Ok(self.reviewed_by)
+    }
+
+    pub async fn reviewed_at(&self) -> Option<NaiveDateTime> {
+        self.reviewed_at
+    }
+}
+
+// Non Graphql
+impl PersonMatchReview {
+    /// Ignores a pair already queued
+    pub fn create(conn: &PgConnection, review: &NewPersonMatchReview) -> FieldResult<usize> {
+        let res = diesel::insert_into(person_match_reviews::table)
+            .values(review)
+            .on_conflict_do_nothing()
+            .execute(conn);
+
+        graphql_translate(res)
+    }
+
+    pub fn get_by_id(conn: &PgConnection, id: Uuid) -> FieldResult<PersonMatchReview> {
+        let res = person_match_reviews::table
+            .filter(person_match_reviews::id.eq(id))
+            .first(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Highest scores first
+    pub fn get_by_status(conn: &PgConnection, status: PersonMatchStatus) -> FieldResult<Vec<PersonMatchReview>> {
+        let res = person_match_reviews::table
+            .filter(person_match_reviews::status.eq(status))
+            .order((person_match_reviews::score.desc(), person_match_reviews::created_at))
+            .load::<PersonMatchReview>(conn);
+
+        graphql_translate(res)
+    }
+
+    pub fn dismiss(conn: &PgConnection, id: Uuid, reviewed_by: Uuid) -> FieldResult<PersonMatchReview> {
+        let review = PersonMatchReview::get_by_id(conn, id)?;
+
+        if review.status != PersonMatchStatus::Pending {
+            return Err(CustomError::Conflict(format!(
+                "Review {} is already {:?}", review.id, review.status)).extend());
+        }
+
+        let res = diesel::update(person_match_reviews::table.filter(person_match_reviews::id.eq(id)))
+            .set((
+                person_match_reviews::status.eq(PersonMatchStatus::Dismissed),
+                person_match_reviews::reviewed_by.eq(Some(reviewed_by)),
+                person_match_reviews::reviewed_at.eq(Some(Utc::now().naive_utc())),
+            ))
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Resolves the pending reviews of a merged duplicate as planned by
+    /// ReviewMove::plan. Run inside the merge transaction.
+    pub fn move_to_person(conn: &PgConnection, duplicate_id: Uuid, surviving_id: Uuid, reviewed_by: Uuid) -> FieldResult<usize> {
+        let people = vec![duplicate_id, surviving_id];
+
+        let reviews = graphql_translate(person_match_reviews::table
+            .filter(person_match_reviews::person_id.eq_any(people.clone())
+                .or(person_match_reviews::candidate_id.eq_any(people)))
+            .order(person_match_reviews::created_at)
+            .load::<PersonMatchReview>(conn))?;
+
+        let plan = ReviewMove::plan(duplicate_id, surviving_id, &reviews);
+
+        let closed = graphql_translate(diesel::update(person_match_reviews::table)
+            .filter(person_match_reviews::id.eq_any(plan.closed_ids.clone()))
+            .set((
+                person_match_reviews::status.eq(PersonMatchStatus::Merged),
+                person_match_reviews::reviewed_by.eq(Some(reviewed_by)),
+                person_match_reviews::reviewed_at.eq(Some(Utc::now().naive_utc())),
+            ))
+            .execute(conn))?;
+
+        for r in plan.repointed.iter() {
+            graphql_translate(diesel::update(person_match_reviews::table.filter(person_match_reviews::id.eq(r.id)))
+                .set((
+                    person_match_reviews::person_id.eq(r.person_id),
+                    person_match_reviews::candidate_id.eq(r.candidate_id),
+                ))
+                .execute(conn))?;
+        }
+
+        Ok(closed + plan.repointed.len())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// What happens to pending reviews when a duplicate is merged into a
+/// surviving person. Reviews between the two are closed as merged. Other
+/// pending reviews of the duplicate are re-pointed at the survivor, or
+/// closed if the survivor already has a review with the same person.
+/// Decided reviews keep the duplicate's id as a record of the decision.
+pub struct ReviewMove {
+    pub closed_ids: Vec<Uuid>,
+    /// Reviews with the duplicate replaced by the survivor
+    pub repointed: Vec<PersonMatchReview>,
+}
+
+impl ReviewMove {
+    /// reviews are every review of either person, oldest first
+    pub fn plan(duplicate_id: Uuid, surviving_id: Uuid, reviews: &[PersonMatchReview]) -> ReviewMove {
+        let involves = |r: &PersonMatchReview, id: Uuid| r.person_id == id || r.candidate_id == id;
+
+        // Pairs the survivor is already reviewed with, in either order
+        let mut pairs: HashSet<(Uuid, Uuid)> = reviews.iter()
+            .filter(|r| !involves(*r, duplicate_id))
+            .flat_map(|r| vec![(r.person_id, r.candidate_id), (r.candidate_id, r.person_id)])
+            .collect();
+
+        let mut plan = ReviewMove { closed_ids: Vec::new(), repointed: Vec::new() };
+
+        for r in reviews.iter().filter(|r| r.status == PersonMatchStatus::Pending && involves(*r, duplicate_id)) {
+            let replace = |id: Uuid| if id == duplicate_id { surviving_id } else { id };
+            let (person_id, candidate_id) = (replace(r.person_id), replace(r.candidate_id));
+
+            if person_id == candidate_id || pairs.contains(&(person_id, candidate_id)) {
+                plan.closed_ids.push(r.id);
+            } else {
+                pairs.insert((person_id, candidate_id));
+                pairs.insert((candidate_id, person_id));
+
+                plan.repointed.push(PersonMatchReview { person_id, candidate_id, ..r.clone() });
+            }
+        }
+
+        plan
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "person_match_reviews"]
+pub struct NewPersonMatchReview {
+    pub person_id: Uuid,
+    pub candidate_id: Uuid,
+    pub score: f64,
+    pub reasons: Vec<String>,
+}
+
+impl NewPersonMatchReview {
+    pub fn from(person_id: Uuid, person_match: &PersonMatch) -> Self {
+        NewPersonMatchReview {
+            person_id,
+            candidate_id: person_match.candidate.id,
+            score: person_match.score,
+            reasons: person_match.reasons.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize, DbEnum, Enum)]
+#[PgType = "person_match_status_enum"]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+/// Stored as person_match_status_enum
+pub enum PersonMatchStatus {
+    Pending,
+    Merged,
+    Dismissed,
+}
@@ -0,0 +1,179 @@
+use chrono::{Duration, prelude::*};
+use serde::{Deserialize, Serialize};
+use diesel::{self, Insertable, PgConnection, Queryable, ExpressionMethods};
+use diesel::{RunQueryDsl, QueryDsl};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use async_graphql::*;
+
+use crate::config_variables::IDEMPOTENCY_WINDOW;
+use crate::errors::error_handler::CustomError;
+use crate::graphql::graphql_translate;
+use crate::models::PILResponse;
+use crate::schema::*;
+
+/// Idempotency key sent in the Idempotency-Key header of a /graphql request.
+/// An idempotency_key argument on PILQuery takes precedence.
+#[derive(Debug, Clone)]
+pub struct IdempotencyKey(pub String);
+
+/// Hex SHA-256 of a submission's travellers. A key reused with a
+/// different payload is rejected rather than returning the original responses.
+pub fn payload_hash<T: Serialize>(payload: &T) -> String {
+    let json = serde_json::to_string(payload).unwrap_or_default();
+
+    format!("{:x}", Sha256::digest(json.as_bytes()))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable)]
+/// Record of a PILQuery submitted with an idempotency key.
+/// The key is reserved before the travellers are processed and completed
+/// with the responses once they all succeed. A retry with the same key by
+/// the same CBSA user within IDEMPOTENCY_WINDOW returns the original
+/// responses instead of processing the travellers again.
+pub struct PILSubmission {
+    pub id: Uuid,
+    pub idempotency_key: String,
+    pub cbsa_id: Uuid,
+    pub travel_response_ids: Vec<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub payload_hash: String,
+    /// False while the submission is being processed
+    pub completed: bool,
+}
+
+/// Outcome of reserving an idempotency key
+pub enum Reservation {
+    /// The key is new. Process the travellers, then complete or release it.
+    Reserved,
+    /// The key was already completed, return its responses
+    Completed(PILSubmission),
+}
+
+impl PILSubmission {
+    /// Reserves the key for this payload. The unique index on (cbsa_id,
+    /// idempotency_key) lets only one of several concurrent retries reserve
+    /// it, the others are rejected with CONFLICT until it completes.
+    /// Must run outside a transaction so the reservation is seen by them.
+    pub fn reserve(conn: &PgConnection, cbsa_id: Uuid, key: &str, payload_hash: &str) -> FieldResult<Reservation> {
+        PILSubmission::remove_expired(conn, cbsa_id, key)?;
+
+        let reserved = graphql_translate(diesel::insert_into(pil_submissions::table)
+            .values(&NewPILSubmission::pending(key.to_owned(), cbsa_id, payload_hash.to_owned()))
+            .on_conflict_do_nothing()
+            .execute(conn))?;
+
+        if reserved == 1 {
+            return Ok(Reservation::Reserved);
+        }
+
+        let res = pil_submissions::table
+            .filter(pil_submissions::cbsa_id.eq(cbsa_id))
+            .filter(pil_submissions::idempotency_key.eq(key))
+            .first::<PILSubmission>(conn);
+
+        let submission = match res {
+            Ok(s) => s,
+            // Released by a failed attempt since the insert
+            Err(diesel::result::Error::NotFound) => return Err(CustomError::Conflict(
+                format!("Idempotency key {} was released, retry the submission", key)).extend()),
+            Err(e) => return graphql_translate(Err(e)),
+        };
+
+        // Submissions recorded before payloads were hashed have no hash
+        if !submission.payload_hash.is_empty() && submission.payload_hash != payload_hash {
+            return Err(CustomError::Conflict(
+                format!("Idempotency key {} was used for a different submission", key)).extend());
+        }
+
+        if !submission.completed {
+            return Err(CustomError::Conflict(
+                format!("A submission with idempotency key {} is still being processed", key)).extend());
+        }
+
+        Ok(Reservation::Completed(submission))
+    }
+
+    /// Records the responses for a reserved key
+    pub fn complete(conn: &PgConnection, cbsa_id: Uuid, key: &str, travel_response_ids: Vec<Uuid>) -> FieldResult<usize> {
+        let res = diesel::update(pil_submissions::table
+            .filter(pil_submissions::cbsa_id.eq(cbsa_id))
+            .filter(pil_submissions::idempotency_key.eq(key))
+            .filter(pil_submissions::completed.eq(false)))
+            .set((
+                pil_submissions::travel_response_ids.eq(travel_response_ids),
+                pil_submissions::completed.eq(true),
+            ))
+            .execute(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Frees a reserved key so a retry processes the travellers again
+    pub fn release(conn: &PgConnection, cbsa_id: Uuid, key: &str) -> FieldResult<usize> {
+        let res = diesel::delete(pil_submissions::table
+            .filter(pil_submissions::cbsa_id.eq(cbsa_id))
+            .filter(pil_submissions::idempotency_key.eq(key))
+            .filter(pil_submissions::completed.eq(false)))
+            .execute(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Removes the key's submission once IDEMPOTENCY_WINDOW has passed so
+    /// it can be used again. Reservations left by a crashed server expire
+    /// the same way.
+    fn remove_expired(conn: &PgConnection, cbsa_id: Uuid, key: &str) -> FieldResult<usize> {
+        let expired_before = Utc::now().naive_utc() - Duration::seconds(IDEMPOTENCY_WINDOW);
+
+        let res = diesel::delete(pil_submissions::table
+            .filter(pil_submissions::cbsa_id.eq(cbsa_id))
+            .filter(pil_submissions::idempotency_key.eq(key))
+            .filter(pil_submissions::created_at.lt(expired_before)))
+            .execute(conn);
+
+        graphql_translate(res)
+    }
+
+    /// The PILResponses originally returned, in their original order
+    pub fn responses(&self, conn: &PgConnection) -> FieldResult<Vec<PILResponse>> {
+        let mut responses = travel_responses::table
+            .filter(travel_responses::id.eq_any(self.travel_response_ids.clone()))
+            .load::<PILResponse>(conn)?;
+
+        responses.sort_by_key(|r| {
+            self.travel_response_ids.iter().position(|id| *id == r.id)
+        });
+
+        Ok(responses)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "pil_submissions"]
+pub struct NewPILSubmission {
+    pub idempotency_key: String,
+    pub cbsa_id: Uuid,
+    pub travel_response_ids: Vec<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub payload_hash: String,
+    pub completed: bool,
+}
+
+impl NewPILSubmission {
+    /// A reservation, completed once the travellers are processed
+    pub fn pending(
+        idempotency_key: String,
+        cbsa_id: Uuid,
+        payload_hash: String,
+    ) -> Self {
+        NewPILSubmission {
+            idempotency_key,
+            cbsa_id,
+            travel_response_ids: Vec::new(),
+            created_at: Utc::now().naive_utc(),
+            payload_hash,
+            completed: false,
+        }
+    }
+}
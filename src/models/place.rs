@@ -1,16 +1,36 @@
 use crate::PgConnection;
 use serde::{Serialize, Deserialize};
-use diesel::{self, Insertable, Queryable};
-use diesel::{RunQueryDsl};
+use diesel::{self, Insertable, Queryable, ExpressionMethods};
+use diesel::{Connection, RunQueryDsl, QueryDsl};
+use diesel_derive_enum::DbEnum;
 use async_graphql::*;
+use chrono_tz::Tz;
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
 use uuid::Uuid;
 use std::collections::HashMap;
 
 use crate::graphql::graphql_translate;
-use crate::models::Country;
+use crate::database::Repository;
+use crate::errors::error_handler::CustomError;
+use crate::models::{Country, PlaceAlias, NewPlaceAlias};
 use crate::schema::*;
 use crate::get_country_by_id;
 
+/// Lower case without accents, punctuation or repeated spaces, so
+/// "Montréal" and "montreal" or "Saint-Jean" and "Saint Jean" match.
+/// Place aliases are stored in this form.
+pub fn normalize_place_name(name: &str) -> String {
+    name.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .filter(|c| !matches!(c, '.' | '\'' | '’'))
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
 
 #[derive(Debug, Clone, Deserialize, Serialize, Queryable)]
 /// Will be cities, airports, ports of entry, destinations
@@ -19,6 +39,19 @@ pub struct Place {
     pub id: Uuid,
     pub name: String,
     pub country_id: Uuid,
+    /// IANA timezone name, e.g. America/Toronto
+    pub timezone: Option<String>,
+    pub kind: PlaceKind,
+    /// Three letter airport code, e.g. YYZ
+    pub iata_code: Option<String>,
+    /// Five character UN/LOCODE, e.g. CAVAN. Shared by a city's airport and seaport.
+    pub un_locode: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub is_port_of_entry: bool,
+    /// Fraction of arrivals referred for mandatory testing at this port.
+    /// MANDATORY_TESTING_RATE applies if None.
+    pub testing_rate: Option<f64>,
 }
 
 impl Place {
@@ -30,17 +63,187 @@ impl Place {
         graphql_translate(res)
     }
 
-    pub fn load_into_hash(conn: &PgConnection) -> HashMap<Uuid, Place> {
+    pub fn get_by_id(conn: &PgConnection, id: Uuid) -> FieldResult<Self> {
+        let res = places::table
+            .filter(places::id.eq(id))
+            .first(conn);
+
+        graphql_translate(res)
+    }
+
+    pub fn load_into_hash(conn: &PgConnection) -> Result<HashMap<Uuid, Place>, CustomError> {
         let res = places::table
-            .load::<Place>(conn)
-            .expect("Unable to load countries");
+            .load::<Place>(conn)?;
 
         let mut new_map: HashMap<Uuid, Place> = HashMap::new();
         for v in res {
             new_map.insert(v.id, v);
         };
 
-        new_map 
+        Ok(new_map)
+    }
+
+    /// Ports of entry by name, only those of kind if given
+    pub fn get_ports_of_entry(conn: &PgConnection, kind: Option<PlaceKind>) -> FieldResult<Vec<Self>> {
+        let mut query = places::table
+            .filter(places::is_port_of_entry.eq(true))
+            .into_boxed();
+
+        if let Some(kind) = kind {
+            query = query.filter(places::place_kind.eq(kind));
+        }
+
+        let res = query
+            .order(places::place_name)
+            .load::<Place>(conn);
+
+        graphql_translate(res)
+    }
+
+    /// None returns the port to MANDATORY_TESTING_RATE
+    pub fn set_testing_rate(conn: &PgConnection, id: Uuid, testing_rate: Option<f64>) -> FieldResult<Self> {
+        if let Some(rate) = testing_rate {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(CustomError::Validation(vec![format!(
+                    "testing_rate {} must be between 0 and 1", rate)]).extend());
+            }
+        }
+
+        let res = diesel::update(places::table)
+            .filter(places::id.eq(id))
+            .filter(places::is_port_of_entry.eq(true))
+            .set(places::testing_rate.eq(testing_rate))
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Timezone of the place, UTC if unknown
+    pub fn tz(&self) -> Tz {
+        self.timezone
+            .as_ref()
+            .and_then(|tz| tz.parse::<Tz>().ok())
+            .unwrap_or(Tz::UTC)
+    }
+
+    /// True if name is this place's name ignoring case, accents and punctuation.
+    /// Aliases are checked separately, see PlaceAlias.
+    pub fn is_named(&self, name: &str) -> bool {
+        normalize_place_name(&self.name) == normalize_place_name(name)
+    }
+
+    /// Groups of places in the same country whose names normalize the same,
+    /// created before names were normalized. Candidates for merge.
+    pub fn find_duplicates(places: &HashMap<Uuid, Place>) -> Vec<Vec<Place>> {
+        let mut groups: HashMap<(Uuid, String), Vec<Place>> = HashMap::new();
+
+        for p in places.values() {
+            groups.entry((p.country_id, normalize_place_name(&p.name)))
+                .or_default()
+                .push(p.clone());
+        }
+
+        let mut duplicates: Vec<Vec<Place>> = groups
+            .into_values()
+            .filter(|g| g.len() > 1)
+            .collect();
+
+        duplicates.sort_by_key(|g| normalize_place_name(&g[0].name));
+
+        duplicates
+    }
+
+    /// Repoints everything referencing duplicate to canonical, keeps the
+    /// duplicate's name as an alias and deletes it
+    pub fn merge(conn: &PgConnection, duplicate_id: Uuid, canonical_id: Uuid) -> FieldResult<Place> {
+        if duplicate_id == canonical_id {
+            return Err(CustomError::Validation(vec![
+                "A place can't be merged into itself".to_string()]).extend());
+        }
+
+        conn.transaction::<Place, Error, _>(|| {
+            let duplicate = Place::get_by_id(conn, duplicate_id)?;
+            let canonical = Place::get_by_id(conn, canonical_id)?;
+
+            if duplicate.country_id != canonical.country_id {
+                return Err(CustomError::Conflict(format!(
+                    "{} and {} are in different countries", duplicate.name, canonical.name)).extend());
+            }
+
+            // Ports keep their codes and rates, so a port is only merged into another port
+            if duplicate.is_port_of_entry && !canonical.is_port_of_entry {
+                return Err(CustomError::Conflict(format!(
+                    "{} is a port of entry and {} is not; merge {} into {} instead",
+                    duplicate.name, canonical.name, canonical.name, duplicate.name)).extend());
+            }
+
+            graphql_translate(diesel::update(trips::table.filter(trips::origin_place_id.eq(duplicate.id)))
+                .set(trips::origin_place_id.eq(canonical.id))
+                .execute(conn))?;
+
+            graphql_translate(diesel::update(trips::table.filter(trips::destination_place_id.eq(duplicate.id)))
+                .set(trips::destination_place_id.eq(canonical.id))
+                .execute(conn))?;
+
+            graphql_translate(diesel::update(trips::table.filter(trips::port_of_entry_id.eq(duplicate.id)))
+                .set(trips::port_of_entry_id.eq(canonical.id))
+                .execute(conn))?;
+
+            graphql_translate(diesel::update(trip_legs::table.filter(trip_legs::origin_place_id.eq(duplicate.id)))
+                .set(trip_legs::origin_place_id.eq(canonical.id))
+                .execute(conn))?;
+
+            graphql_translate(diesel::update(trip_legs::table.filter(trip_legs::destination_place_id.eq(duplicate.id)))
+                .set(trip_legs::destination_place_id.eq(canonical.id))
+                .execute(conn))?;
+
+            graphql_translate(diesel::update(vaccinations::table.filter(vaccinations::location_provided_id.eq(duplicate.id)))
+                .set(vaccinations::location_provided_id.eq(canonical.id))
+                .execute(conn))?;
+
+            graphql_translate(diesel::update(postal_addresses::table.filter(postal_addresses::address_locality_id.eq(duplicate.id)))
+                .set(postal_addresses::address_locality_id.eq(canonical.id))
+                .execute(conn))?;
+
+            // The duplicate's aliases are removed with it, so they move to canonical first
+            let mut aliases: Vec<String> = PlaceAlias::get_by_place_id(conn, duplicate.id)?
+                .into_iter()
+                .map(|a| a.alias)
+                .collect();
+
+            aliases.push(normalize_place_name(&duplicate.name));
+
+            let existing = PlaceAlias::get_by_place_id(conn, canonical.id)?;
+
+            for alias in aliases {
+                if !canonical.is_named(&alias) && !existing.iter().any(|a| a.alias == alias) {
+                    PlaceAlias::create(conn, &NewPlaceAlias::new(canonical.id, &alias))?;
+                }
+            }
+
+            graphql_translate(diesel::delete(places::table.filter(places::id.eq(duplicate.id)))
+                .execute(conn))?;
+
+            // Fill in what only the duplicate knew
+            let res = diesel::update(places::table.filter(places::id.eq(canonical.id)))
+                .set((
+                    places::timezone.eq(canonical.timezone.to_owned().or(duplicate.timezone)),
+                    places::latitude.eq(canonical.latitude.or(duplicate.latitude)),
+                    places::longitude.eq(canonical.longitude.or(duplicate.longitude)),
+                ))
+                .get_result(conn);
+
+            graphql_translate(res)
+        })
+    }
+
+    /// True if code is this place's IATA code or UN/LOCODE
+    pub fn has_code(&self, code: &str) -> bool {
+        let code = code.trim();
+
+        [&self.iata_code, &self.un_locode]
+            .iter()
+            .any(|c| c.as_ref().map(|c| c.eq_ignore_ascii_case(code)).unwrap_or(false))
     }
 }
 
@@ -58,6 +261,51 @@ impl Place {
 
         get_country_by_id(context, self.country_id)
     }
+
+    pub async fn timezone(&self) -> FieldResult<Option<String>> {
+        Ok(self.timezone.to_owned())
+    }
+
+    pub async fn kind(&self) -> FieldResult<PlaceKind> {
+        Ok(self.kind)
+    }
+
+    pub async fn iata_code(&self) -> FieldResult<Option<String>> {
+        Ok(self.iata_code.to_owned())
+    }
+
+    pub async fn un_locode(&self) -> FieldResult<Option<String>> {
+        Ok(self.un_locode.to_owned())
+    }
+
+    pub async fn latitude(&self) -> FieldResult<Option<f64>> {
+        Ok(self.latitude)
+    }
+
+    pub async fn longitude(&self) -> FieldResult<Option<f64>> {
+        Ok(self.longitude)
+    }
+
+    pub async fn is_port_of_entry(&self) -> FieldResult<bool> {
+        Ok(self.is_port_of_entry)
+    }
+
+    pub async fn testing_rate(&self) -> FieldResult<Option<f64>> {
+        Ok(self.testing_rate)
+    }
+
+    pub async fn aliases(&self, context: &Context<'_>) -> FieldResult<Vec<String>> {
+        let place_id = self.id;
+
+        let aliases = Repository::from_context(context)?
+            .run(move |conn| PlaceAlias::get_by_place_id(conn, place_id))
+            .await?
+            .into_iter()
+            .map(|a| a.alias)
+            .collect();
+
+        Ok(aliases)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
@@ -73,4 +321,16 @@ impl NewPlace {
     pub fn new(place_name: String, country_id: Uuid) -> Self {
         NewPlace { place_name, country_id }
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize, DbEnum, Enum, Display, EnumString, EnumVariantNames)]
+#[PgType = "place_kind_enum"]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+/// Stored as place_kind_enum
+pub enum PlaceKind {
+    City,
+    Airport,
+    LandCrossing,
+    Seaport,
+}
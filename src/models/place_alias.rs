@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use diesel::{self, Insertable, PgConnection, Queryable, ExpressionMethods};
+use diesel::{RunQueryDsl, QueryDsl};
+use uuid::Uuid;
+use async_graphql::*;
+
+use crate::graphql::graphql_translate;
+use crate::errors::error_handler::CustomError;
+use crate::models::{Place, normalize_place_name};
+use crate::schema::*;
+
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable, SimpleObject)]
+/// Another spelling of a Place, e.g. "Montreal" for "Montréal".
+/// Created when places are merged or by an Admin.
+pub struct PlaceAlias {
+    pub id: Uuid,
+    pub place_id: Uuid,
+    /// Normalized with normalize_place_name
+    pub alias: String,
+}
+
+impl PlaceAlias {
+    pub fn create(conn: &PgConnection, alias: &NewPlaceAlias) -> FieldResult<PlaceAlias> {
+        let res = diesel::insert_into(place_aliases::table)
+            .values(alias)
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Fails if another place in the same country already has the name or alias
+    pub fn add(conn: &PgConnection, place_id: Uuid, alias: &str, places: &HashMap<Uuid, Place>) -> FieldResult<PlaceAlias> {
+        let place = places
+            .get(&place_id)
+            .ok_or_else(|| CustomError::UnknownPlace(place_id.to_string()).extend())?;
+
+        let taken = places.values()
+            .find(|p| p.id != place.id && p.country_id == place.country_id && p.is_named(alias));
+
+        if let Some(other) = taken {
+            return Err(CustomError::Conflict(format!(
+                "{} is already the name of place {}; merge the places instead", alias, other.id)).extend());
+        }
+
+        let others = place_aliases::table
+            .filter(place_aliases::alias.eq(normalize_place_name(alias)))
+            .filter(place_aliases::place_id.ne(place.id))
+            .select(place_aliases::place_id)
+            .load::<Uuid>(conn);
+
+        let aliased = graphql_translate(others)?
+            .into_iter()
+            .find(|id| places.get(id).map(|p| p.country_id == place.country_id).unwrap_or(false));
+
+        if let Some(other) = aliased {
+            return Err(CustomError::Conflict(format!(
+                "{} is already an alias of place {}", alias, other)).extend());
+        }
+
+        PlaceAlias::create(conn, &NewPlaceAlias::new(place.id, alias))
+    }
+
+    pub fn get_by_place_id(conn: &PgConnection, place_id: Uuid) -> FieldResult<Vec<PlaceAlias>> {
+        let res = place_aliases::table
+            .filter(place_aliases::place_id.eq(place_id))
+            .order(place_aliases::alias)
+            .load::<PlaceAlias>(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Place ids keyed by alias. The same alias can name places in different countries.
+    pub fn load_into_hash(conn: &PgConnection) -> Result<HashMap<String, Vec<Uuid>>, CustomError> {
+        let res = place_aliases::table
+            .load::<PlaceAlias>(conn)?;
+
+        let mut aliases: HashMap<String, Vec<Uuid>> = HashMap::new();
+        for a in res {
+            aliases.entry(a.alias).or_default().push(a.place_id);
+        };
+
+        Ok(aliases)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "place_aliases"]
+pub struct NewPlaceAlias {
+    pub place_id: Uuid,
+    pub alias: String,
+}
+
+impl NewPlaceAlias {
+    pub fn new(place_id: Uuid, alias: &str) -> Self {
+        NewPlaceAlias {
+            place_id,
+            alias: normalize_place_name(alias),
+        }
+    }
+}
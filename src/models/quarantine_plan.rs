@@ -5,9 +5,12 @@ use diesel::{self, Insertable, PgConnection, Queryable,
 use uuid::Uuid;
 
 use async_graphql::*;
+use async_graphql::dataloader::DataLoader;
 
 use crate::common_utils::{is_analyst, RoleGuard, Role};
-use crate::graphql::{graphql_translate, get_connection_from_context};
+use crate::errors::error_handler::CustomError;
+use crate::graphql::{graphql_translate, PostalAddressLoader, list_cost};
+use crate::database::Repository;
 use crate::schema::*;
 use crate::models::PostalAddress;
 
@@ -61,13 +64,10 @@ impl QuarantinePlan {
         visible = "is_analyst",
     )]
     pub async fn quarantine_address(&self, context: &Context<'_>) -> FieldResult<PostalAddress> {
-        let conn = get_connection_from_context(context);
-
-        let res = postal_addresses::table
-            .filter(postal_addresses::id.eq(self.postal_address_id))
-            .first(&conn);
-
-        graphql_translate(res)
+        context.data::<DataLoader<PostalAddressLoader>>()?
+            .load_one(self.postal_address_id)
+            .await?
+            .ok_or_else(|| CustomError::NotFound(format!("Postal address {}", self.postal_address_id)).extend())
     }
 
     pub async fn active(&self) -> FieldResult<bool> {
@@ -77,15 +77,16 @@ impl QuarantinePlan {
     #[graphql(
         guard = "RoleGuard::new(Role::Analyst)",
         visible = "is_analyst",
+        complexity = "list_cost(child_complexity)",
     )]
     pub async fn check_in_history(&self, context: &Context<'_>) -> FieldResult<Vec<CheckInResult>> {
-        let conn = get_connection_from_context(context);
+        let plan_id = self.id;
 
-        let res = check_in_results::table
-            .filter(check_in_results::quarantine_plan_id.eq(self.id))
-            .load::<CheckInResult>(&conn);
-
-        graphql_translate(res)
+        Repository::from_context(context)?
+            .run(move |conn| graphql_translate(check_in_results::table
+                .filter(check_in_results::quarantine_plan_id.eq(plan_id))
+                .load::<CheckInResult>(conn)))
+            .await
     }
 }
 
@@ -0,0 +1,437 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use diesel::{self, Insertable, PgConnection, Queryable, ExpressionMethods};
+use diesel::{RunQueryDsl, QueryDsl};
+use diesel_derive_enum::DbEnum;
+use uuid::Uuid;
+use async_graphql::*;
+
+use crate::graphql::graphql_translate;
+use crate::errors::error_handler::CustomError;
+use crate::config_variables::HOME_COUNTRY_CODE;
+use crate::models::{Country, ResidencyStatus, normalize_document_id, parse_enum_value};
+use crate::schema::*;
+use crate::{get_country_by_id, get_country_by_name, ReferenceSource};
+
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable)]
+/// A passport, NEXUS card or other document a Person has travelled on.
+/// A person has one active document of each type and issuer, older ones
+/// are kept as replaced so they still match the person.
+pub struct TravelDocument {
+    pub id: Uuid,
+    pub person_id: Uuid,
+    pub document_type: TravelDocumentType,
+    pub document_number: String,
+    pub issuer_id: Uuid, // Country
+    pub expires_on: Option<NaiveDate>,
+    pub status: TravelDocumentStatus,
+    pub created_at: NaiveDateTime,
+    pub last_presented_at: NaiveDateTime,
+}
+
+#[Object]
+impl TravelDocument {
+    pub async fn id(&self) -> FieldResult<Uuid> {
+        Ok(self.id)
+    }
+
+    pub async fn document_type(&self) -> FieldResult<TravelDocumentType> {
+        Ok(self.document_type)
+    }
+
+    pub async fn document_number(&self) -> FieldResult<String> {
+        Ok(self.document_number.to_owned())
+    }
+
+    pub async fn issuer(&self, context: &Context<'_>) -> FieldResult<Country> {
+        get_country_by_id(context, self.issuer_id)
+    }
+
+    pub async fn expires_on(&self) -> Option<NaiveDate> {
+        self.expires_on
+    }
+
+    pub async fn status(&self) -> FieldResult<TravelDocumentStatus> {
+        Ok(self.status)
+    }
+
+    /// Active and not expired today
+    pub async fn is_valid(&self) -> bool {
+        self.is_valid_on(Utc::now().naive_utc().date())
+    }
+
+    pub async fn created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+
+    pub async fn last_presented_at(&self) -> NaiveDateTime {
+        self.last_presented_at
+    }
+}
+
+// Non Graphql
+impl TravelDocument {
+    pub fn create(conn: &PgConnection, document: &NewTravelDocument) -> FieldResult<TravelDocument> {
+        let res = diesel::insert_into(travel_documents::table)
+            .values(document)
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+
+    pub fn get_by_id(conn: &PgConnection, id: Uuid) -> FieldResult<TravelDocument> {
+        let res = travel_documents::table
+            .filter(travel_documents::id.eq(id))
+            .first(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Most recently presented first
+    pub fn get_by_person_ids(conn: &PgConnection, person_ids: Vec<Uuid>) -> FieldResult<Vec<TravelDocument>> {
+        let res = travel_documents::table
+            .filter(travel_documents::person_id.eq_any(person_ids))
+            .order(travel_documents::last_presented_at.desc())
+            .load::<TravelDocument>(conn);
+
+        graphql_translate(res)
+    }
+
+    /// The most recently presented active document, or the most recently
+    /// presented of any status if none are active
+    pub fn primary(documents: &[TravelDocument]) -> Option<&TravelDocument> {
+        documents
+            .iter()
+            .filter(|d| d.status == TravelDocumentStatus::Active)
+            .max_by_key(|d| d.last_presented_at)
+            .or_else(|| documents.iter().max_by_key(|d| d.last_presented_at))
+    }
+
+    pub fn is_expired_on(&self, date: NaiveDate) -> bool {
+        self.expires_on.map(|e| e < date).unwrap_or(false)
+    }
+
+    pub fn is_valid_on(&self, date: NaiveDate) -> bool {
+        self.status == TravelDocumentStatus::Active && !self.is_expired_on(date)
+    }
+
+    /// Same type and issuer, and the same number ignoring case, spaces and dashes
+    pub fn matches(&self, document: &PresentedTravelDocument) -> bool {
+        self.document_type == document.document_type
+            && self.issuer_id == document.issuer_id
+            && normalize_document_id(&self.document_number) == normalize_document_id(&document.document_number)
+    }
+
+    /// Records a document the person presented at presented_at. A known document
+    /// has its expiry and last presented time updated. A new one replaces the
+    /// person's active document of the same type and issuer.
+    pub fn record(
+        conn: &PgConnection,
+        person_id: Uuid,
+        document: &PresentedTravelDocument,
+        presented_at: NaiveDateTime,
+    ) -> FieldResult<TravelDocument> {
+
+        let documents = TravelDocument::get_by_person_ids(conn, vec![person_id])?;
+
+        let id = match documents.iter().find(|d| d.matches(document)) {
+            Some(d) => {
+                let res = diesel::update(travel_documents::table.filter(travel_documents::id.eq(d.id)))
+                    .set((
+                        travel_documents::expires_on.eq(document.expires_on.or(d.expires_on)),
+                        travel_documents::last_presented_at.eq(d.last_presented_at.max(presented_at)),
+                    ))
+                    .execute(conn);
+
+                graphql_translate(res)?;
+                d.id
+            },
+            None => TravelDocument::create(conn, &NewTravelDocument::from(person_id, document, presented_at))?.id,
+        };
+
+        TravelDocument::settle_replaced(conn, person_id)?;
+
+        TravelDocument::get_by_id(conn, id)
+    }
+
+    /// Moves the documents of from_person_id to to_person_id, except those
+    /// to_person_id already has. Call within the transaction that removes
+    /// from_person_id.
+    pub fn move_to_person(conn: &PgConnection, from_person_id: Uuid, to_person_id: Uuid) -> FieldResult<usize> {
+        let documents = TravelDocument::get_by_person_ids(conn, vec![from_person_id, to_person_id])?;
+
+        let (kept, moving): (Vec<TravelDocument>, Vec<TravelDocument>) = documents
+            .into_iter()
+            .partition(|d| d.person_id == to_person_id);
+
+        let ids: Vec<Uuid> = moving
+            .iter()
+            .filter(|d| !kept.iter().any(|k| k.matches(&PresentedTravelDocument::from(d))))
+            .map(|d| d.id)
+            .collect();
+
+        let res = diesel::update(travel_documents::table.filter(travel_documents::id.eq_any(ids)))
+            .set(travel_documents::person_id.eq(to_person_id))
+            .execute(conn);
+
+        let moved = graphql_translate(res)?;
+
+        TravelDocument::settle_replaced(conn, to_person_id)?;
+
+        Ok(moved)
+    }
+
+    /// Reports a document lost or revoked, or returns it to active. A document
+    /// returned to active replaces the person's other active document of its
+    /// type and issuer if it was presented more recently.
+    pub fn set_status(conn: &PgConnection, id: Uuid, status: TravelDocumentStatus) -> FieldResult<TravelDocument> {
+        if status == TravelDocumentStatus::Replaced {
+            return Err(CustomError::Validation(vec![
+                "Documents are replaced by recording a newer document".to_string()]).extend());
+        }
+
+        let res = diesel::update(travel_documents::table.filter(travel_documents::id.eq(id)))
+            .set(travel_documents::status.eq(status))
+            .get_result::<TravelDocument>(conn);
+
+        let document = graphql_translate(res)?;
+
+        TravelDocument::settle_replaced(conn, document.person_id)?;
+
+        TravelDocument::get_by_id(conn, id)
+    }
+
+    /// Leaves one active document of each type and issuer, the most recently presented
+    fn settle_replaced(conn: &PgConnection, person_id: Uuid) -> FieldResult<usize> {
+        let documents = TravelDocument::get_by_person_ids(conn, vec![person_id])?;
+
+        let mut seen: Vec<(TravelDocumentType, Uuid)> = Vec::new();
+        let mut replaced: Vec<Uuid> = Vec::new();
+
+        for d in documents.iter().filter(|d| d.status == TravelDocumentStatus::Active) {
+            let key = (d.document_type, d.issuer_id);
+
+            if seen.contains(&key) {
+                replaced.push(d.id);
+            } else {
+                seen.push(key);
+            }
+        }
+
+        if replaced.is_empty() {
+            return Ok(0);
+        }
+
+        let res = diesel::update(travel_documents::table.filter(travel_documents::id.eq_any(replaced)))
+            .set(travel_documents::status.eq(TravelDocumentStatus::Replaced))
+            .execute(conn);
+
+        graphql_translate(res)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "travel_documents"]
+pub struct NewTravelDocument {
+    pub person_id: Uuid,
+    pub document_type: TravelDocumentType,
+    pub document_number: String,
+    pub issuer_id: Uuid,
+    pub expires_on: Option<NaiveDate>,
+    pub last_presented_at: NaiveDateTime,
+}
+
+impl NewTravelDocument {
+    pub fn from(person_id: Uuid, document: &PresentedTravelDocument, presented_at: NaiveDateTime) -> Self {
+        NewTravelDocument {
+            person_id,
+            document_type: document.document_type,
+            document_number: document.document_number.trim().to_owned(),
+            issuer_id: document.issuer_id,
+            expires_on: document.expires_on,
+            last_presented_at: presented_at,
+        }
+    }
+
+    pub fn fake(person_id: Uuid, issuer_id: Uuid) -> Self {
+        NewTravelDocument {
+            person_id,
+            document_type: TravelDocumentType::Passport,
+            document_number: "HDFSHFKJHD372840".to_string(),
+            issuer_id,
+            expires_on: Some(Utc::now().naive_utc().date() + chrono::Duration::days(3650)),
+            last_presented_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A document as submitted with TravelData, before it is recorded for a Person
+pub struct PresentedTravelDocument {
+    pub document_type: TravelDocumentType,
+    pub document_number: String,
+    pub issuer_id: Uuid, // Country
+    pub expires_on: Option<NaiveDate>,
+}
+
+impl PresentedTravelDocument {
+    pub fn new(
+        document_type: TravelDocumentType,
+        document_number: String,
+        issuer_id: Uuid,
+        expires_on: Option<NaiveDate>,
+    ) -> Self {
+        PresentedTravelDocument {
+            document_type,
+            document_number,
+            issuer_id,
+            expires_on,
+        }
+    }
+
+    pub fn from(document: &TravelDocument) -> Self {
+        PresentedTravelDocument {
+            document_type: document.document_type,
+            document_number: document.document_number.to_owned(),
+            issuer_id: document.issuer_id,
+            expires_on: document.expires_on,
+        }
+    }
+
+    pub fn is_expired_on(&self, date: NaiveDate) -> bool {
+        self.expires_on.map(|e| e < date).unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, InputObject, SimpleObject)]
+#[graphql(input_name = "TravelDocumentInput")]
+/// A travel document submitted with TravelData
+pub struct SlimTravelDocument {
+    /// PASSPORT, NEXUS, PERMANENT_RESIDENT_CARD or OTHER
+    pub document_type: String,
+    pub document_number: String,
+    pub issuer: String, // Country
+    /// Optional NaiveDate
+    pub expires_on: Option<NaiveDate>,
+}
+
+impl SlimTravelDocument {
+    /// Resolves the issuer to a Country
+    pub fn to_presented(&self, context: &impl ReferenceSource) -> FieldResult<PresentedTravelDocument> {
+        let document_type = parse_enum_value::<TravelDocumentType>("document_type", &self.document_type)
+            .map_err(|e| CustomError::Validation(vec![e]).extend())?;
+
+        let issuer = get_country_by_name(context, &self.issuer)?;
+
+        Ok(PresentedTravelDocument::new(
+            document_type,
+            self.document_number.trim().to_owned(),
+            issuer.id,
+            self.expires_on,
+        ))
+    }
+}
+
+#[derive(Debug, Clone)]
+/// What a traveller's documents show on the day they arrive
+pub struct DocumentCheck {
+    /// Expired documents and the document the traveller enters on
+    pub details: Vec<String>,
+    pub has_valid_document: bool,
+    /// Status in Canada shown by valid Canadian documents
+    pub documented_status: ResidencyStatus,
+}
+
+/// Checks the documents a traveller presented for expiry on date and for
+/// what their type and issuer show about the traveller's status in Canada
+pub fn check_entry_documents(
+    context: &impl ReferenceSource,
+    documents: &[PresentedTravelDocument],
+    date: NaiveDate,
+) -> FieldResult<DocumentCheck> {
+
+    let mut details: Vec<String> = Vec::new();
+    let mut valid: Vec<(&PresentedTravelDocument, Country)> = Vec::new();
+
+    for d in documents {
+        let issuer = get_country_by_id(context, d.issuer_id)?;
+
+        match d.expires_on {
+            Some(expires_on) if d.is_expired_on(date) => details.push(format!(
+                "{} issued by {} expired on {}", d.document_type, issuer.country_name, expires_on)),
+            _ => valid.push((d, issuer)),
+        }
+    }
+
+    let is_home = |issuer: &Country| issuer.iso_alpha2.as_deref() == Some(HOME_COUNTRY_CODE);
+
+    // Canadian documents first, then passports over other documents
+    valid.sort_by_key(|(d, issuer)| (!is_home(issuer), d.document_type.entry_rank()));
+
+    match valid.first() {
+        Some((d, issuer)) => details.push(format!(
+            "Entering on {} issued by {}", d.document_type, issuer.country_name)),
+        None => details.push(format!("No travel document valid on {}", date)),
+    }
+
+    let documented_status = valid
+        .iter()
+        .filter(|(_, issuer)| is_home(issuer))
+        .filter_map(|(d, _)| d.document_type.home_status())
+        .max()
+        .unwrap_or(ResidencyStatus::ForeignNational);
+
+    Ok(DocumentCheck {
+        details,
+        has_valid_document: !valid.is_empty(),
+        documented_status,
+    })
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize, DbEnum, Enum, Display, EnumString, EnumVariantNames)]
+#[PgType = "travel_document_type_enum"]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+/// Stored as travel_document_type_enum
+pub enum TravelDocumentType {
+    Passport,
+    /// Trusted traveller card issued by Canada or the United States
+    Nexus,
+    PermanentResidentCard,
+    Other,
+}
+
+impl TravelDocumentType {
+    /// Order documents are preferred for entry
+    pub fn entry_rank(&self) -> u8 {
+        match self {
+            TravelDocumentType::Passport => 0,
+            TravelDocumentType::PermanentResidentCard => 1,
+            TravelDocumentType::Nexus => 2,
+            TravelDocumentType::Other => 3,
+        }
+    }
+
+    /// Status of the holder of this type of document issued by the home country.
+    /// NEXUS is also issued to foreign nationals so doesn't show status.
+    pub fn home_status(&self) -> Option<ResidencyStatus> {
+        match self {
+            TravelDocumentType::Passport => Some(ResidencyStatus::Citizen),
+            TravelDocumentType::PermanentResidentCard => Some(ResidencyStatus::PermanentResident),
+            TravelDocumentType::Nexus | TravelDocumentType::Other => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize, DbEnum, Enum)]
+#[PgType = "travel_document_status_enum"]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+/// Stored as travel_document_status_enum. Expiry is checked by date.
+pub enum TravelDocumentStatus {
+    Active,
+    /// A newer document of the same type and issuer was presented
+    Replaced,
+    /// Reported lost or stolen
+    Lost,
+    Revoked,
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use diesel::prelude::*;
@@ -7,7 +9,14 @@ use uuid::Uuid;
 use async_graphql::*;
 
 use crate::schema::*;
-use crate::graphql::{graphql_translate, get_connection_from_context};
+use crate::graphql::{graphql_translate, list_cost};
+use crate::database::Repository;
+use crate::errors::error_handler::CustomError;
+use crate::common_utils::{is_analyst, RoleGuard, Role};
+use crate::config_variables::ENTRY_RULES;
+use crate::models::{TravelData, PILResponse, TravelIntent, EntryDecision, RelationshipType,
+    TravelGroupRelationship, NewTravelGroupRelationship, GuardianConsent, NewGuardianConsent,
+    parse_enum_value};
 use super::{Trip, Person};
 
 #[derive(Debug, Clone, Deserialize, Serialize, Queryable, PartialEq, PartialOrd, Identifiable)]
@@ -32,26 +41,61 @@ impl TravelGroup {
         self.created_at
     }
 
+    #[graphql(complexity = "list_cost(child_complexity)")]
     pub async fn trips(&self, context: &Context<'_>) -> FieldResult<Vec<Trip>> {
-        let conn = get_connection_from_context(context);
-
-        let res = trips::table.
-            filter(trips::travel_group_id.eq(self.id))
-            .order_by(trips::arrival_time)
-            .order_by(trips::person_id)
-            .load::<Trip>(&conn);
+        let group_id = self.id;
 
-        graphql_translate(res)
+        Repository::from_context(context)?
+            .run(move |conn| graphql_translate(trips::table
+                .filter(trips::travel_group_id.eq(group_id))
+                .order_by(trips::arrival_time)
+                .order_by(trips::person_id)
+                .load::<Trip>(conn)))
+            .await
     }
 
+    #[graphql(complexity = "list_cost(child_complexity)")]
+    /// People with a trip in the group. Returning travellers keep the
+    /// travel_group_id of the group they were first seen in.
     pub async fn people(&self, context: &Context<'_>) -> FieldResult<Vec<Person>> {
-        let conn = get_connection_from_context(context);
+        let group_id = self.id;
 
-        let res = persons::table.
-            filter(persons::travel_group_id.eq(self.id))
-            .load::<Person>(&conn);
+        Repository::from_context(context)?
+            .run(move |conn| {
+                let member_ids = TravelGroup::member_ids(conn, group_id)?;
 
-        graphql_translate(res)
+                graphql_translate(persons::table
+                    .filter(persons::id.eq_any(member_ids).or(persons::travel_group_id.eq(group_id)))
+                    .load::<Person>(conn))
+            })
+            .await
+    }
+
+    #[graphql(
+        guard = "RoleGuard::new(Role::Analyst)",
+        visible = "is_analyst",
+        complexity = "list_cost(child_complexity)",
+    )]
+    pub async fn relationships(&self, context: &Context<'_>) -> FieldResult<Vec<TravelGroupRelationship>> {
+        let group_id = self.id;
+
+        Repository::from_context(context)?
+            .run(move |conn| TravelGroupRelationship::get_by_travel_group(conn, group_id))
+            .await
+    }
+
+    #[graphql(
+        guard = "RoleGuard::new(Role::Analyst)",
+        visible = "is_analyst",
+        complexity = "list_cost(child_complexity)",
+    )]
+    /// Consents recorded for minors in the group
+    pub async fn guardian_consents(&self, context: &Context<'_>) -> FieldResult<Vec<GuardianConsent>> {
+        let group_id = self.id;
+
+        Repository::from_context(context)?
+            .run(move |conn| GuardianConsent::get_by_travel_group(conn, group_id))
+            .await
     }
 }
 
@@ -65,6 +109,157 @@ impl TravelGroup {
 
         graphql_translate(res)
     }
+
+    /// Ids of the people with a trip in the group
+    pub fn member_ids(conn: &PgConnection, travel_group_id: Uuid) -> FieldResult<Vec<Uuid>> {
+        let res = trips::table
+            .filter(trips::travel_group_id.eq(travel_group_id))
+            .select(trips::person_id)
+            .distinct()
+            .load::<Uuid>(conn);
+
+        graphql_translate(res)
+    }
+
+    fn check_member(conn: &PgConnection, travel_group_id: Uuid, person_id: Uuid) -> FieldResult<()> {
+        if !TravelGroup::member_ids(conn, travel_group_id)?.contains(&person_id) {
+            return Err(CustomError::Validation(vec![format!(
+                "Person {} has no trip in travel group {}", person_id, travel_group_id)]).extend());
+        }
+
+        Ok(())
+    }
+
+    /// Both people must have a trip in the group
+    pub fn add_relationship(conn: &PgConnection, relationship: &NewTravelGroupRelationship) -> FieldResult<TravelGroupRelationship> {
+        TravelGroup::check_member(conn, relationship.travel_group_id, relationship.person_id)?;
+        TravelGroup::check_member(conn, relationship.travel_group_id, relationship.related_person_id)?;
+
+        TravelGroupRelationship::get_or_create(conn, relationship)
+    }
+
+    /// The minor must have a trip in the group
+    pub fn record_guardian_consent(conn: &PgConnection, consent: &NewGuardianConsent) -> FieldResult<GuardianConsent> {
+        TravelGroup::check_member(conn, consent.travel_group_id, consent.minor_id)?;
+
+        GuardianConsent::create(conn, consent)
+    }
+
+    /// Records the relationships and guardian consents submitted with a PILQuery
+    /// once every traveller is processed, then applies the rules for unaccompanied
+    /// minors and household quarantine to their responses. Travellers that could
+    /// not be processed are skipped.
+    pub fn apply_rules(
+        conn: &PgConnection,
+        travel_group_id: Uuid,
+        data: &[TravelData],
+        responses: &mut [PILResponse],
+        recorded_by: Uuid,
+    ) -> FieldResult<()> {
+        let rules = ENTRY_RULES;
+
+        let processed: Vec<bool> = responses.iter().map(|r| !r.is_error()).collect();
+
+        // Position of each processed traveller
+        let index_by_ref: HashMap<String, usize> = data
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| processed[*i])
+            .filter_map(|(i, t)| t.traveller_ref.as_ref().map(|r| (r.trim().to_owned(), i)))
+            .collect();
+
+        let mut links: Vec<(usize, usize)> = Vec::new();
+        // Travellers with a guardian in the group, and the guardian
+        let mut guardians: Vec<(usize, usize)> = Vec::new();
+
+        for (i, traveller) in data.iter().enumerate().filter(|(i, _)| processed[*i]) {
+            for slim in traveller.relationships.iter().flatten() {
+                let j = match index_by_ref.get(slim.related_traveller_ref.trim()) {
+                    Some(j) if responses[*j].person_id != responses[i].person_id => *j,
+                    _ => continue,
+                };
+
+                let relationship_type = parse_enum_value::<RelationshipType>("relationship_type", &slim.relationship_type)
+                    .map_err(|e| CustomError::Validation(vec![e]).extend())?;
+
+                TravelGroupRelationship::get_or_create(conn, &NewTravelGroupRelationship::new(
+                    travel_group_id,
+                    responses[i].person_id,
+                    responses[j].person_id,
+                    relationship_type,
+                ))?;
+
+                links.push((i, j));
+
+                if relationship_type == RelationshipType::GuardianOf {
+                    guardians.push((j, i));
+                }
+            }
+
+            if let Some(consent) = &traveller.guardian_consent {
+                let guardian_person_id = consent.guardian_traveller_ref
+                    .as_ref()
+                    .and_then(|r| index_by_ref.get(r.trim()))
+                    .map(|j| responses[*j].person_id);
+
+                GuardianConsent::create(conn, &NewGuardianConsent::new(
+                    travel_group_id,
+                    responses[i].person_id,
+                    consent.guardian_name.to_owned(),
+                    consent.guardian_contact.to_owned(),
+                    guardian_person_id,
+                    recorded_by,
+                ))?;
+            }
+        }
+
+        let consents = GuardianConsent::get_by_travel_group(conn, travel_group_id)?;
+
+        // Decisions of the travellers entering Canada
+        let decisions: Vec<Option<EntryDecision>> = data
+            .iter()
+            .zip(responses.iter())
+            .map(|(t, r)| {
+                let is_entry = parse_enum_value::<TravelIntent>("travel_intent", &t.travel_intent) == Ok(TravelIntent::Entry);
+
+                if r.is_error() || !is_entry {
+                    None
+                } else {
+                    EntryDecision::from_response_code(&r.response_code)
+                }
+            })
+            .collect();
+
+        let household_decisions = rules.household_decisions(&decisions, &links);
+
+        for (i, traveller) in data.iter().enumerate().filter(|(i, _)| processed[*i]) {
+            let mut details: Vec<String> = Vec::new();
+
+            // A guardian who is denied entry doesn't accompany the minor
+            let is_accompanied = guardians
+                .iter()
+                .any(|(minor, guardian)| *minor == i && decisions[*guardian] != Some(EntryDecision::Deny));
+
+            if rules.is_minor_on(traveller.birth_date, traveller.arrival().date()) && !is_accompanied {
+                match consents.iter().find(|c| c.minor_id == responses[i].person_id) {
+                    Some(c) => details.push(format!("Unaccompanied minor: consent recorded from {}", c.guardian_name)),
+                    None => details.push("Unaccompanied minor: no guardian consent recorded".to_string()),
+                }
+            }
+
+            let decision = household_decisions[i].filter(|d| Some(*d) != decisions[i]);
+
+            if decision.is_some() {
+                details.push("Household member requires quarantine".to_string());
+            }
+
+            if !details.is_empty() {
+                responses[i] = responses[i].amend(conn, decision, &details)?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Insertable, Debug, InputObject)]
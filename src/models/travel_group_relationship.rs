@@ -0,0 +1,178 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use diesel::{self, Insertable, PgConnection, Queryable, ExpressionMethods};
+use diesel::{BoolExpressionMethods, RunQueryDsl, QueryDsl};
+use diesel_derive_enum::DbEnum;
+use uuid::Uuid;
+use async_graphql::*;
+
+use crate::graphql::graphql_translate;
+use crate::database::Repository;
+use crate::errors::error_handler::CustomError;
+use crate::models::Person;
+use crate::schema::*;
+
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable)]
+/// How two people travelling in the same TravelGroup are related.
+/// For GUARDIAN_OF the person is the guardian of the related person,
+/// SPOUSE and HOUSEHOLD_MEMBER apply both ways.
+pub struct TravelGroupRelationship {
+    pub id: Uuid,
+    pub travel_group_id: Uuid,
+    pub person_id: Uuid,
+    pub related_person_id: Uuid,
+    pub relationship_type: RelationshipType,
+    pub created_at: NaiveDateTime,
+}
+
+#[Object]
+impl TravelGroupRelationship {
+    pub async fn id(&self) -> FieldResult<Uuid> {
+        Ok(self.id)
+    }
+
+    pub async fn travel_group_id(&self) -> FieldResult<Uuid> {
+        Ok(self.travel_group_id)
+    }
+
+    pub async fn person(&self, context: &Context<'_>) -> FieldResult<Person> {
+        let person_id = self.person_id;
+
+        Repository::from_context(context)?
+            .run(move |conn| Person::get_by_id(conn, person_id))
+            .await
+    }
+
+    pub async fn related_person(&self, context: &Context<'_>) -> FieldResult<Person> {
+        let related_person_id = self.related_person_id;
+
+        Repository::from_context(context)?
+            .run(move |conn| Person::get_by_id(conn, related_person_id))
+            .await
+    }
+
+    pub async fn relationship_type(&self) -> FieldResult<RelationshipType> {
+        Ok(self.relationship_type)
+    }
+
+    pub async fn created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+}
+
+// Non Graphql
+impl TravelGroupRelationship {
+    /// Returns the existing relationship if it was already recorded
+    pub fn get_or_create(conn: &PgConnection, relationship: &NewTravelGroupRelationship) -> FieldResult<TravelGroupRelationship> {
+        if relationship.person_id == relationship.related_person_id {
+            return Err(CustomError::Validation(vec![
+                "A person can't be related to themselves".to_string()]).extend());
+        }
+
+        graphql_translate(diesel::insert_into(travel_group_relationships::table)
+            .values(relationship)
+            .on_conflict_do_nothing()
+            .execute(conn))?;
+
+        let res = travel_group_relationships::table
+            .filter(travel_group_relationships::travel_group_id.eq(relationship.travel_group_id))
+            .filter(travel_group_relationships::person_id.eq(relationship.person_id))
+            .filter(travel_group_relationships::related_person_id.eq(relationship.related_person_id))
+            .filter(travel_group_relationships::relationship_type.eq(relationship.relationship_type))
+            .first(conn);
+
+        graphql_translate(res)
+    }
+
+    pub fn get_by_travel_group(conn: &PgConnection, travel_group_id: Uuid) -> FieldResult<Vec<TravelGroupRelationship>> {
+        let res = travel_group_relationships::table
+            .filter(travel_group_relationships::travel_group_id.eq(travel_group_id))
+            .order(travel_group_relationships::created_at)
+            .load::<TravelGroupRelationship>(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Relationships on either side, in every group the person travelled in
+    pub fn get_by_person(conn: &PgConnection, person_id: Uuid) -> FieldResult<Vec<TravelGroupRelationship>> {
+        let res = travel_group_relationships::table
+            .filter(travel_group_relationships::person_id.eq(person_id)
+                .or(travel_group_relationships::related_person_id.eq(person_id)))
+            .order(travel_group_relationships::created_at)
+            .load::<TravelGroupRelationship>(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Copies the relationships of one person to another when merging duplicates.
+    /// The originals are removed with the person. Relationships between the two
+    /// are dropped.
+    pub fn move_to_person(conn: &PgConnection, from_person_id: Uuid, to_person_id: Uuid) -> FieldResult<()> {
+        let moved_id = |id: Uuid| if id == from_person_id { to_person_id } else { id };
+
+        for r in TravelGroupRelationship::get_by_person(conn, from_person_id)? {
+            let person_id = moved_id(r.person_id);
+            let related_person_id = moved_id(r.related_person_id);
+
+            if person_id == related_person_id {
+                continue;
+            }
+
+            graphql_translate(diesel::insert_into(travel_group_relationships::table)
+                .values(&NewTravelGroupRelationship::new(r.travel_group_id, person_id, related_person_id, r.relationship_type))
+                .on_conflict_do_nothing()
+                .execute(conn))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "travel_group_relationships"]
+pub struct NewTravelGroupRelationship {
+    pub travel_group_id: Uuid,
+    pub person_id: Uuid,
+    pub related_person_id: Uuid,
+    pub relationship_type: RelationshipType,
+}
+
+impl NewTravelGroupRelationship {
+    pub fn new(
+        travel_group_id: Uuid,
+        person_id: Uuid,
+        related_person_id: Uuid,
+        relationship_type: RelationshipType,
+    ) -> Self {
+        NewTravelGroupRelationship {
+            travel_group_id,
+            person_id,
+            related_person_id,
+            relationship_type,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, InputObject, SimpleObject)]
+#[graphql(input_name = "RelationshipInput")]
+/// A relationship submitted with TravelData, from the submitting traveller
+/// to another traveller in the same PILQuery
+pub struct SlimRelationship {
+    /// GUARDIAN_OF, SPOUSE or HOUSEHOLD_MEMBER
+    pub relationship_type: String,
+    /// traveller_ref of the other traveller
+    pub related_traveller_ref: String,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize, DbEnum, Enum, Display, EnumString, EnumVariantNames)]
+#[PgType = "relationship_type_enum"]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+/// Stored as relationship_type_enum
+pub enum RelationshipType {
+    /// Parent or legal guardian of a minor
+    GuardianOf,
+    Spouse,
+    /// Lives at the same address
+    HouseholdMember,
+}
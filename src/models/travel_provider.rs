@@ -0,0 +1,243 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use diesel::{self, Insertable, PgConnection, Queryable, ExpressionMethods};
+use diesel::{RunQueryDsl, QueryDsl};
+use diesel_derive_enum::DbEnum;
+use rand::{Rng, thread_rng, distributions::Alphanumeric};
+use uuid::Uuid;
+use async_graphql::*;
+
+use crate::common_utils::{is_admin, RoleGuard, Role};
+use crate::errors::error_handler::CustomError;
+use crate::graphql::graphql_translate;
+use crate::models::{hash_password, verify_password};
+use crate::schema::*;
+
+/// Scope allowing a provider to submit traveller manifests to /import/travellers
+pub const MANIFEST_SUBMIT_SCOPE: &str = "manifest:submit";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize, DbEnum, Enum)]
+#[PgType = "travel_provider_type_enum"]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+/// Stored as travel_provider_type_enum
+pub enum TravelProviderType {
+    Airline,
+    Rail,
+    Cruise,
+    Bus,
+    Other,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable, AsChangeset)]
+#[table_name = "travel_providers"]
+/// Airline, rail, cruise or bus operator carrying travellers.
+/// Referenced by Trip.
+/// Providers with an API key can submit manifests without a CBSA operator account.
+pub struct TravelProvider {
+    pub id: Uuid,
+    pub provider_name: String,
+    pub provider_type: TravelProviderType,
+    pub contact_name: Option<String>,
+    pub contact_email: Option<String>,
+    pub contact_phone: Option<String>,
+    /// Public part of the API key, used to find the provider
+    pub api_key_prefix: Option<String>,
+    #[serde(skip_serializing)]
+    pub api_key_hash: Option<String>,
+    pub api_key_scopes: Vec<String>,
+    pub active: bool,
+    pub created_at: NaiveDateTime,
+}
+
+#[Object]
+impl TravelProvider {
+    pub async fn id(&self) -> FieldResult<Uuid> {
+        Ok(self.id)
+    }
+
+    pub async fn provider_name(&self) -> FieldResult<String> {
+        Ok(self.provider_name.to_owned())
+    }
+
+    pub async fn provider_type(&self) -> FieldResult<TravelProviderType> {
+        Ok(self.provider_type)
+    }
+
+    pub async fn contact_name(&self) -> FieldResult<Option<String>> {
+        Ok(self.contact_name.to_owned())
+    }
+
+    pub async fn contact_email(&self) -> FieldResult<Option<String>> {
+        Ok(self.contact_email.to_owned())
+    }
+
+    pub async fn contact_phone(&self) -> FieldResult<Option<String>> {
+        Ok(self.contact_phone.to_owned())
+    }
+
+    #[graphql(
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    pub async fn api_key_prefix(&self) -> FieldResult<Option<String>> {
+        Ok(self.api_key_prefix.to_owned())
+    }
+
+    #[graphql(
+        guard = "RoleGuard::new(Role::Admin)",
+        visible = "is_admin",
+    )]
+    pub async fn api_key_scopes(&self) -> FieldResult<Vec<String>> {
+        Ok(self.api_key_scopes.to_owned())
+    }
+
+    pub async fn active(&self) -> FieldResult<bool> {
+        Ok(self.active)
+    }
+
+    pub async fn created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+}
+
+// Non Graphql
+impl TravelProvider {
+    pub fn create(conn: &PgConnection, provider: &NewTravelProvider) -> FieldResult<TravelProvider> {
+        let res = diesel::insert_into(travel_providers::table)
+            .values(provider)
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+
+    pub fn get_by_id(conn: &PgConnection, id: Uuid) -> FieldResult<TravelProvider> {
+        let res = travel_providers::table
+            .filter(travel_providers::id.eq(id))
+            .first(conn);
+
+        graphql_translate(res)
+    }
+
+    pub fn get_all(conn: &PgConnection) -> FieldResult<Vec<TravelProvider>> {
+        let res = travel_providers::table
+            .order(travel_providers::provider_name)
+            .load::<TravelProvider>(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Registered provider with this name, if any
+    pub fn get_by_name(conn: &PgConnection, name: &str) -> FieldResult<Option<TravelProvider>> {
+        let res = travel_providers::table
+            .filter(travel_providers::provider_name.eq(name.trim()))
+            .first::<TravelProvider>(conn);
+
+        match res {
+            Ok(p) => Ok(Some(p)),
+            Err(diesel::result::Error::NotFound) => Ok(None),
+            Err(e) => graphql_translate(Err(e)),
+        }
+    }
+
+    pub fn update(&self, conn: &PgConnection) -> FieldResult<Self> {
+        let res = diesel::update(travel_providers::table)
+            .filter(travel_providers::id.eq(&self.id))
+            .set(self)
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Replaces any existing key. The key is only returned here; just its hash is stored.
+    pub fn issue_api_key(&self, conn: &PgConnection, scopes: Vec<String>) -> FieldResult<String> {
+        let prefix = random_string(8);
+        let api_key = format!("hrp_{}_{}", prefix, random_string(32));
+
+        let hash = hash_password(&api_key)
+            .map_err(|e| CustomError::Internal(format!("Unable to hash API key: {}", e)).extend())?;
+
+        let res = diesel::update(travel_providers::table)
+            .filter(travel_providers::id.eq(&self.id))
+            .set((
+                travel_providers::api_key_prefix.eq(Some(prefix)),
+                travel_providers::api_key_hash.eq(Some(hash)),
+                travel_providers::api_key_scopes.eq(scopes),
+            ))
+            .execute(conn);
+
+        graphql_translate(res)?;
+
+        Ok(api_key)
+    }
+
+    pub fn revoke_api_key(&self, conn: &PgConnection) -> FieldResult<Self> {
+        let res = diesel::update(travel_providers::table)
+            .filter(travel_providers::id.eq(&self.id))
+            .set((
+                travel_providers::api_key_prefix.eq(None::<String>),
+                travel_providers::api_key_hash.eq(None::<String>),
+                travel_providers::api_key_scopes.eq(Vec::<String>::new()),
+            ))
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Finds the active provider that was issued api_key
+    pub fn authenticate(conn: &PgConnection, api_key: &str) -> Result<TravelProvider, CustomError> {
+        let invalid = || CustomError::Unauthorized("Invalid API key".to_string());
+
+        let prefix = api_key
+            .trim()
+            .strip_prefix("hrp_")
+            .and_then(|k| k.split('_').next())
+            .ok_or_else(invalid)?;
+
+        let provider = travel_providers::table
+            .filter(travel_providers::api_key_prefix.eq(prefix))
+            .first::<TravelProvider>(conn)
+            .map_err(|_| invalid())?;
+
+        let hash = provider.api_key_hash.as_ref().ok_or_else(invalid)?;
+
+        match verify_password(hash, api_key.trim()) {
+            Ok(true) if provider.active => Ok(provider),
+            Ok(true) => Err(CustomError::Unauthorized(format!("{} is not active", provider.provider_name))),
+            _ => Err(invalid()),
+        }
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.api_key_scopes.iter().any(|s| s == scope)
+    }
+}
+
+fn random_string(length: usize) -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable, InputObject)]
+#[table_name = "travel_providers"]
+pub struct NewTravelProvider {
+    pub provider_name: String,
+    pub provider_type: TravelProviderType,
+    pub contact_name: Option<String>,
+    pub contact_email: Option<String>,
+    pub contact_phone: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, InputObject)]
+/// Input Struct to update a TravelProvider. Only accessible by Administrators.
+pub struct TravelProviderUpdate {
+    pub id: Uuid,
+    pub provider_name: Option<String>,
+    pub provider_type: Option<TravelProviderType>,
+    pub contact_name: Option<String>,
+    pub contact_email: Option<String>,
+    pub contact_phone: Option<String>,
+    pub active: Option<bool>,
+}
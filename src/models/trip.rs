@@ -1,16 +1,23 @@
 use chrono::{Duration, prelude::*};
 use serde::{Deserialize, Serialize};
-use diesel::{self, Insertable, PgConnection, Queryable, ExpressionMethods};
-use diesel::{RunQueryDsl, QueryDsl};
+use diesel::{self, Insertable, PgConnection, Queryable, BoolExpressionMethods, ExpressionMethods};
+use diesel::{Connection, RunQueryDsl, QueryDsl};
+use diesel::pg::Pg;
 use uuid::Uuid;
 use async_graphql::*;
+use chrono_tz::Tz;
+use diesel_derive_enum::DbEnum;
 
-use crate::config_variables::DATE_FORMAT;
 use crate::schema::*;
-use crate::graphql::{graphql_translate, get_connection_from_context};
-use crate::models::{Place, Person};
+use async_graphql::dataloader::DataLoader;
+
+use crate::graphql::{graphql_translate, KeysetCursor, PageArgs, SortDirection, PersonLoader, list_cost};
+use crate::database::Repository;
+use crate::models::{Place, PlaceKind, Person, TravelProvider, TripLeg, TripStateChange, NewTripStateChange};
+use crate::errors::error_handler::CustomError;
 use crate::common_utils::{is_analyst, RoleGuard, Role};
-use crate::{get_place_by_id, get_or_create_country_by_name, get_or_create_place_by_name_and_country_id};
+use crate::{get_place_by_id, get_port_of_entry_by_code, get_country_by_name,
+    get_or_create_place_by_name_and_country_id, ReferenceSource};
 
 /// Travel information for a TravelGroup
 /// CBSA responsible, but important for public health surveillance
@@ -22,18 +29,22 @@ pub struct Trip {
     // None for travel_identifier == private travel
     pub travel_identifier: Option<String>,
     pub booking_id: Option<String>,
-    pub travel_mode: String,
+    pub travel_mode: TravelMode,
     pub origin_place_id: Uuid,
     pub destination_place_id: Uuid,
-    pub travel_intent: String,
+    pub travel_intent: TravelIntent,
     pub scheduled_departure_time: Option<NaiveDateTime>,
     pub scheduled_arrival_time: Option<NaiveDateTime>,
     pub departure_time: Option<NaiveDateTime>,
     pub arrival_time: Option<NaiveDateTime>,
-    pub trip_state: String,
+    pub trip_state: TripState,
     pub travel_group_id: Uuid,
     pub person_id: Uuid,
     pub created_at: NaiveDateTime,
+    /// Registered provider matching trip_provider, if any
+    pub travel_provider_id: Option<Uuid>,
+    /// Where the traveller is processed by CBSA on entry
+    pub port_of_entry_id: Option<Uuid>,
 }
 
 #[Object]
@@ -46,8 +57,21 @@ impl Trip {
         Ok(self.trip_provider.to_owned())
     }
 
-    pub async fn travel_mode(&self) -> FieldResult<String> {
-        Ok(self.travel_mode.to_owned())
+    pub async fn travel_provider(&self, context: &Context<'_>) -> FieldResult<Option<TravelProvider>> {
+        match self.travel_provider_id {
+            Some(id) => {
+                let provider = Repository::from_context(context)?
+                    .run(move |conn| TravelProvider::get_by_id(conn, id))
+                    .await?;
+
+                Ok(Some(provider))
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub async fn travel_mode(&self) -> FieldResult<TravelMode> {
+        Ok(self.travel_mode)
     }
 
     pub async fn travel_identifier(&self) -> FieldResult<String> {
@@ -68,51 +92,39 @@ impl Trip {
         }
     }
 
-    pub async fn scheduled_departure_time(&self) -> FieldResult<String> {
-        match self.scheduled_arrival_time {
-            Some(t) => Ok(t.format(DATE_FORMAT).to_string()),
-            None => Ok("NA".to_string()),
-        } 
+    /// In the local time of the origin
+    pub async fn scheduled_departure_time(&self, context: &Context<'_>) -> FieldResult<Option<TripTime>> {
+        TripTime::at_place(context, self.scheduled_departure_time, self.origin_place_id)
     }
 
-    pub async fn scheduled_arrival_time(&self) -> FieldResult<String> {
-        match self.scheduled_departure_time {
-            Some(t) => Ok(t.format(DATE_FORMAT).to_string()),
-            None => Ok("NA".to_string()),
-        } 
+    /// In the local time of the destination
+    pub async fn scheduled_arrival_time(&self, context: &Context<'_>) -> FieldResult<Option<TripTime>> {
+        TripTime::at_place(context, self.scheduled_arrival_time, self.destination_place_id)
     }
 
-    pub async fn departure_time(&self) -> FieldResult<String> {
-        match self.arrival_time {
-            Some(t) => Ok(t.format(DATE_FORMAT).to_string()),
-            None => Ok("NA".to_string()),
-        } 
+    /// In the local time of the origin
+    pub async fn departure_time(&self, context: &Context<'_>) -> FieldResult<Option<TripTime>> {
+        TripTime::at_place(context, self.departure_time, self.origin_place_id)
     }
 
-    pub async fn arrival_time(&self) -> FieldResult<String> {
-        match self.departure_time {
-            Some(t) => Ok(t.format(DATE_FORMAT).to_string()),
-            None => Ok("NA".to_string()),
-        } 
+    /// In the local time of the destination
+    pub async fn arrival_time(&self, context: &Context<'_>) -> FieldResult<Option<TripTime>> {
+        TripTime::at_place(context, self.arrival_time, self.destination_place_id)
     }
 
-    pub async fn travel_intent(&self) -> FieldResult<String> {
-        Ok(self.travel_intent.to_owned())
+    pub async fn travel_intent(&self) -> FieldResult<TravelIntent> {
+        Ok(self.travel_intent)
     }
 
-    pub async fn trip_state(&self) -> FieldResult<String> {
-        Ok(self.trip_state.to_owned())
+    pub async fn trip_state(&self) -> FieldResult<TripState> {
+        Ok(self.trip_state)
     }
 
     pub async fn person(&self, context: &Context<'_>) -> FieldResult<Person> {
-
-        let conn = get_connection_from_context(context);
-
-        let res = persons::table.
-            filter(persons::id.eq(self.person_id))
-            .first(&conn);
-
-        graphql_translate(res)
+        context.data::<DataLoader<PersonLoader>>()?
+            .load_one(self.person_id)
+            .await?
+            .ok_or_else(|| CustomError::NotFound(format!("Person {}", self.person_id)).extend())
     }
 
     pub async fn origin(&self, context: &Context<'_>) -> FieldResult<Place> {
@@ -125,9 +137,35 @@ impl Trip {
         get_place_by_id(context, self.destination_place_id)
     }
 
+    pub async fn port_of_entry(&self, context: &Context<'_>) -> FieldResult<Option<Place>> {
+        match self.port_of_entry_id {
+            Some(id) => Ok(Some(get_place_by_id(context, id)?)),
+            None => Ok(None),
+        }
+    }
+
     pub async fn created_at(&self) -> NaiveDateTime {
         self.created_at
     }
+
+    #[graphql(complexity = "list_cost(child_complexity)")]
+    /// Itinerary in order of travel
+    pub async fn legs(&self, context: &Context<'_>) -> FieldResult<Vec<TripLeg>> {
+        let trip_id = self.id;
+
+        Repository::from_context(context)?
+            .run(move |conn| TripLeg::get_by_trip_id(conn, trip_id))
+            .await
+    }
+
+    #[graphql(complexity = "list_cost(child_complexity)")]
+    pub async fn state_history(&self, context: &Context<'_>) -> FieldResult<Vec<TripStateChange>> {
+        let trip_id = self.id;
+
+        Repository::from_context(context)?
+            .run(move |conn| TripStateChange::get_by_trip_id(conn, trip_id))
+            .await
+    }
 }
 
 // Non Graphql
@@ -139,6 +177,125 @@ impl Trip {
 
         graphql_translate(res)
     }
+
+    pub fn get_by_id(conn: &PgConnection, id: Uuid) -> FieldResult<Trip> {
+        let res = trips::table
+            .filter(trips::id.eq(id))
+            .first::<Trip>(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Locks the trip row until the end of the caller's transaction
+    fn get_for_update(conn: &PgConnection, id: Uuid) -> FieldResult<Trip> {
+        let res = trips::table
+            .filter(trips::id.eq(id))
+            .for_update()
+            .first::<Trip>(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Moves the trip to next_state if the transition is legal and records the change
+    pub fn transition(
+        conn: &PgConnection,
+        id: Uuid,
+        next_state: TripState,
+        changed_by: Uuid,
+        reason: Option<String>,
+    ) -> FieldResult<Trip> {
+
+        conn.transaction::<Trip, Error, _>(|| {
+            let trip = Trip::get_for_update(conn, id)?;
+
+            if !trip.trip_state.can_transition_to(next_state) {
+                return Err(CustomError::Conflict(format!(
+                    "Trip {} can't move from {} to {}",
+                    trip.id, trip.trip_state, next_state)).extend());
+            }
+
+            trip.set_state(conn, next_state, changed_by, reason)
+        })
+    }
+
+    /// Records the actual departure and/or arrival time. A reported departure
+    /// starts a planned trip and a reported arrival completes it.
+    pub fn report_times(
+        conn: &PgConnection,
+        id: Uuid,
+        departure_time: Option<NaiveDateTime>,
+        arrival_time: Option<NaiveDateTime>,
+        changed_by: Uuid,
+    ) -> FieldResult<Trip> {
+
+        conn.transaction::<Trip, Error, _>(|| {
+            let trip = Trip::get_for_update(conn, id)?;
+
+            if trip.trip_state == TripState::Cancelled {
+                return Err(CustomError::Conflict(format!(
+                    "Trip {} is cancelled", trip.id)).extend());
+            }
+
+            let departure_time = departure_time.or(trip.departure_time);
+            let arrival_time = arrival_time.or(trip.arrival_time);
+
+            if let (Some(departure), Some(arrival)) = (departure_time, arrival_time) {
+                if arrival <= departure {
+                    return Err(CustomError::Validation(vec![format!(
+                        "arrival_time {} must be after departure_time {}",
+                        arrival, departure)]).extend());
+                }
+            }
+
+            let res = diesel::update(trips::table.filter(trips::id.eq(id)))
+                .set((
+                    trips::departure_time.eq(departure_time),
+                    trips::arrival_time.eq(arrival_time),
+                ))
+                .get_result::<Trip>(conn);
+
+            let mut trip = graphql_translate(res)?;
+
+            // An arrival implies a departure, so a planned trip goes through both
+            if trip.trip_state == TripState::Planned && (departure_time.is_some() || arrival_time.is_some()) {
+                trip = trip.set_state(conn, TripState::InProgress, changed_by, Some("Departure reported".to_string()))?;
+            }
+
+            if trip.trip_state == TripState::InProgress && arrival_time.is_some() {
+                trip = trip.set_state(conn, TripState::Completed, changed_by, Some("Arrival reported".to_string()))?;
+            }
+
+            Ok(trip)
+        })
+    }
+
+    /// Call within a transaction so the trip and its history stay in step
+    fn set_state(
+        &self,
+        conn: &PgConnection,
+        next_state: TripState,
+        changed_by: Uuid,
+        reason: Option<String>,
+    ) -> FieldResult<Trip> {
+
+        let res = diesel::update(trips::table.filter(trips::id.eq(self.id)))
+            .set(trips::trip_state.eq(next_state))
+            .get_result::<Trip>(conn);
+
+        let trip = graphql_translate(res)?;
+
+        let change = NewTripStateChange::new(
+            self.id,
+            self.trip_state,
+            next_state,
+            changed_by,
+            reason,
+        );
+
+        TripStateChange::create(conn, &change)?;
+
+        Ok(trip)
+    }
 }
 
 #[derive(Insertable, Debug, InputObject)]
@@ -148,17 +305,19 @@ pub struct NewTrip {
     // None for travel_identifier == private travel
     pub travel_identifier: Option<String>,
     pub booking_id: Option<String>,
-    pub travel_mode: String,
+    pub travel_mode: TravelMode,
     pub origin_place_id: Uuid,
     pub destination_place_id: Uuid,
-    pub travel_intent: String,
+    pub travel_intent: TravelIntent,
     pub scheduled_departure_time: Option<NaiveDateTime>,
     pub scheduled_arrival_time: Option<NaiveDateTime>,
     pub departure_time: Option<NaiveDateTime>,
     pub arrival_time: Option<NaiveDateTime>,
-    pub trip_state: String,
+    pub trip_state: TripState,
     pub travel_group_id: Uuid,
     pub person_id: Uuid,
+    pub travel_provider_id: Option<Uuid>,
+    pub port_of_entry_id: Option<Uuid>,
 }
 
 impl<'a> NewTrip {
@@ -171,20 +330,22 @@ impl<'a> NewTrip {
             trip_provider: "Air Canada".to_string(), 
             travel_identifier: Some("ADX-Q6)Y".to_string()), 
             booking_id: Some("678326432632".to_string()), 
-            travel_mode: "AIR".to_string(), 
+            travel_mode: TravelMode::Air, 
             origin_place_id: Uuid::new_v4(),
             destination_place_id: Uuid::new_v4(), 
             
-            travel_intent: "Entry".to_string(), 
+            travel_intent: TravelIntent::Entry, 
 
             scheduled_departure_time: Some(depart + Duration::hours(4)), 
             scheduled_arrival_time: Some(arrive + Duration::hours(4)), 
             departure_time: Some(depart), 
             arrival_time: Some(arrive), 
             
-            trip_state: "planned".to_string(),
+            trip_state: TripState::Planned,
             travel_group_id: Uuid::new_v4(),
             person_id: Uuid::new_v4(),
+            travel_provider_id: None,
+            port_of_entry_id: None,
         }
     }
 
@@ -202,61 +363,73 @@ impl<'a> NewTrip {
             trip_provider: "Air Canada".to_string(), 
             travel_identifier: Some("ADX-Q6)Y".to_string()), 
             booking_id: Some("678326432632".to_string()), 
-            travel_mode: "AIR".to_string(), 
+            travel_mode: TravelMode::Air, 
             origin_place_id: origin_place_id.to_owned(), 
             
             destination_place_id: destination_place_id.to_owned(), 
             
-            travel_intent: "Entry".to_string(), 
+            travel_intent: TravelIntent::Entry, 
             scheduled_departure_time: Some(depart + Duration::hours(4)), 
             scheduled_arrival_time: Some(arrive + Duration::hours(4)), 
             departure_time: Some(depart), 
             arrival_time: Some(arrive), 
 
-            trip_state: "active".to_string(),
+            trip_state: TripState::InProgress,
             travel_group_id: travel_group_id.to_owned(),
             person_id: person_id.to_owned(),
+            travel_provider_id: None,
+            port_of_entry_id: None,
         }
     }
 
     pub fn new(
-        context: &Context<'_>,
+        context: &impl ReferenceSource,
+        conn: &PgConnection,
         trip_provider: String,
         travel_identifier: Option<String>,
         booking_id: Option<String>,
-        travel_mode: String,
+        travel_mode: TravelMode,
         origin_place_name: String,
         origin_country_name: String,
         destination_place_name: String,
         destination_country_name: String,
-        travel_intent: String,
+        port_of_entry_code: Option<String>,
+        travel_intent: TravelIntent,
         scheduled_departure_time: Option<NaiveDateTime>,
         scheduled_arrival_time: Option<NaiveDateTime>,
         departure_time: Option<NaiveDateTime>,
         arrival_time: Option<NaiveDateTime>,
-        trip_state: String,
+        trip_state: TripState,
         travel_group_id: Uuid,
         person_id: Uuid,
-    ) -> Self 
+    ) -> FieldResult<Self> 
     {        
-        let origin_country = get_or_create_country_by_name(context, origin_country_name)
-            .expect("Unable to find or create country");
+        let origin_country = get_country_by_name(context, &origin_country_name)?;
 
         let origin = get_or_create_place_by_name_and_country_id(
             context,
-            origin_place_name, origin_country.id)
-            .expect("Unable to get or create origin country");
+            conn,
+            origin_place_name, origin_country.id)?;
 
-        let destination_country = get_or_create_country_by_name(context, destination_country_name)
-            .expect("Unable to find or create country");
+        let destination_country = get_country_by_name(context, &destination_country_name)?;
 
         let destination = get_or_create_place_by_name_and_country_id(
             context,
-            destination_place_name, destination_country.id)
-            .expect("Unable to get or create origin country");
+            conn,
+            destination_place_name, destination_country.id)?;
 
+        // An unknown code is an error, otherwise a destination that is a port is assumed
+        let port_of_entry_id = match port_of_entry_code {
+            Some(code) => Some(get_port_of_entry_by_code(context, &code, travel_mode.port_kind())?.id),
+            None if destination.is_port_of_entry => Some(destination.id),
+            None => None,
+        };
 
-        NewTrip { 
+        // Providers aren't created from submissions, unregistered names are left unlinked
+        let travel_provider_id = TravelProvider::get_by_name(conn, &trip_provider)?
+            .map(|p| p.id);
+
+        Ok(NewTrip { 
             trip_provider,
             travel_identifier,
             booking_id,
@@ -271,11 +444,174 @@ impl<'a> NewTrip {
             trip_state,
             travel_group_id,
             person_id,
+            travel_provider_id,
+            port_of_entry_id,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, InputObject)]
+/// Conditions a trip must meet to be listed. Every condition given applies.
+/// Ranges include from and exclude to.
+pub struct TripFilter {
+    pub arrival_from: Option<NaiveDateTime>,
+    pub arrival_to: Option<NaiveDateTime>,
+    pub created_from: Option<NaiveDateTime>,
+    pub created_to: Option<NaiveDateTime>,
+    pub origin_country_id: Option<Uuid>,
+    pub destination_country_id: Option<Uuid>,
+    pub travel_mode: Option<TravelMode>,
+    pub trip_state: Option<TripState>,
+    pub travel_intent: Option<TravelIntent>,
+}
+
+impl TripFilter {
+    pub fn query(&self) -> trips::BoxedQuery<'static, Pg> {
+        let mut query = trips::table.into_boxed();
+
+        if let Some(from) = self.arrival_from {
+            query = query.filter(trips::arrival_time.ge(from));
+        }
+
+        if let Some(to) = self.arrival_to {
+            query = query.filter(trips::arrival_time.lt(to));
+        }
+
+        if let Some(from) = self.created_from {
+            query = query.filter(trips::created_at.ge(from));
+        }
+
+        if let Some(to) = self.created_to {
+            query = query.filter(trips::created_at.lt(to));
+        }
+
+        if let Some(country_id) = self.origin_country_id {
+            query = query.filter(trips::origin_place_id.eq_any(
+                places::table.filter(places::country_id.eq(country_id)).select(places::id)));
+        }
+
+        if let Some(country_id) = self.destination_country_id {
+            query = query.filter(trips::destination_place_id.eq_any(
+                places::table.filter(places::country_id.eq(country_id)).select(places::id)));
+        }
+
+        if let Some(travel_mode) = self.travel_mode {
+            query = query.filter(trips::travel_mode.eq(travel_mode));
+        }
+
+        if let Some(trip_state) = self.trip_state {
+            query = query.filter(trips::trip_state.eq(trip_state));
+        }
+
+        if let Some(travel_intent) = self.travel_intent {
+            query = query.filter(trips::travel_intent.eq(travel_intent));
         }
+
+        query
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TripSortField {
+    ArrivalTime,
+    DepartureTime,
+    CreatedAt,
+}
+
+#[derive(Debug, Clone, Copy, InputObject)]
+/// Trips without the time sorted on come last, or first if descending
+pub struct TripSort {
+    pub field: TripSortField,
+    #[graphql(default)]
+    pub direction: SortDirection,
+}
+
+impl Default for TripSort {
+    fn default() -> Self {
+        TripSort {
+            field: TripSortField::ArrivalTime,
+            direction: SortDirection::Asc,
+        }
+    }
+}
+
+impl TripSort {
+    /// Ties are ordered by id so pages don't overlap
+    pub fn apply(&self, query: trips::BoxedQuery<'static, Pg>) -> trips::BoxedQuery<'static, Pg> {
+        match (self.field, self.direction) {
+            (TripSortField::ArrivalTime, SortDirection::Asc) => query.order((trips::arrival_time.asc(), trips::id.asc())),
+            (TripSortField::ArrivalTime, SortDirection::Desc) => query.order((trips::arrival_time.desc(), trips::id.desc())),
+            (TripSortField::DepartureTime, SortDirection::Asc) => query.order((trips::departure_time.asc(), trips::id.asc())),
+            (TripSortField::DepartureTime, SortDirection::Desc) => query.order((trips::departure_time.desc(), trips::id.desc())),
+            (TripSortField::CreatedAt, SortDirection::Asc) => query.order((trips::created_at.asc(), trips::id.asc())),
+            (TripSortField::CreatedAt, SortDirection::Desc) => query.order((trips::created_at.desc(), trips::id.desc())),
+        }
+    }
+
+    /// The rows of a page, loaded in reverse order if paging backward
+    pub fn page(&self, mut query: trips::BoxedQuery<'static, Pg>, args: &PageArgs) -> trips::BoxedQuery<'static, Pg> {
+        for (cursor, later) in args.bounds(self.direction) {
+            query = match self.field {
+                TripSortField::ArrivalTime => crate::keyset_filter!(query, trips::arrival_time, trips::id, cursor.key, cursor.id, later),
+                TripSortField::DepartureTime => crate::keyset_filter!(query, trips::departure_time, trips::id, cursor.key, cursor.id, later),
+                TripSortField::CreatedAt => crate::keyset_filter!(query, trips::created_at, trips::id, cursor.key, cursor.id, later),
+            };
+        }
+
+        TripSort { direction: args.load_direction(self.direction), ..*self }.apply(query)
+    }
+
+    pub fn cursor(&self, trip: &Trip) -> KeysetCursor {
+        let key = match self.field {
+            TripSortField::ArrivalTime => trip.arrival_time,
+            TripSortField::DepartureTime => trip.departure_time,
+            TripSortField::CreatedAt => Some(trip.created_at),
+        };
+
+        KeysetCursor::new(key, trip.id)
+    }
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+/// A departure or arrival time. Times are stored in UTC and shown
+/// in the local time of the port as well.
+pub struct TripTime {
+    pub utc: DateTime<Utc>,
+    /// Port local time, UTC where the port's timezone is unknown
+    pub local: DateTime<FixedOffset>,
+    /// IANA timezone name of the port
+    pub timezone: String,
+}
+
+impl TripTime {
+    pub fn new(time: NaiveDateTime, timezone: Tz) -> Self {
+        let utc = DateTime::<Utc>::from_utc(time, Utc);
+        let offset = timezone.offset_from_utc_datetime(&time).fix();
+
+        TripTime {
+            utc,
+            local: utc.with_timezone(&offset),
+            timezone: timezone.name().to_string(),
+        }
+    }
+
+    /// Time in the timezone of the place with place_id
+    pub fn at_place(context: &impl ReferenceSource, time: Option<NaiveDateTime>, place_id: Uuid) -> FieldResult<Option<TripTime>> {
+        match time {
+            Some(t) => {
+                let place = get_place_by_id(context, place_id)?;
+                Ok(Some(TripTime::new(t, place.tz())))
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize, DbEnum, Enum, Display, EnumString, EnumVariantNames)]
+#[PgType = "trip_state_enum"]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+/// Stored as trip_state_enum
 pub enum TripState {
     Planned,
     InProgress,
@@ -283,27 +619,49 @@ pub enum TripState {
     Cancelled,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl TripState {
+    /// PLANNED -> IN_PROGRESS -> COMPLETED, and PLANNED or IN_PROGRESS -> CANCELLED
+    pub fn can_transition_to(&self, next_state: TripState) -> bool {
+        matches!(
+            (self, next_state),
+            (TripState::Planned, TripState::InProgress)
+            | (TripState::Planned, TripState::Cancelled)
+            | (TripState::InProgress, TripState::Completed)
+            | (TripState::InProgress, TripState::Cancelled)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize, DbEnum, Enum, Display, EnumString, EnumVariantNames)]
+#[PgType = "travel_intent_enum"]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+/// Stored as travel_intent_enum
 pub enum TravelIntent {
     Entry,
     Exit,
     Transit,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-/// Should get this info from an API
-/// Could be a struct with company info, contact info, API key, etc.
-pub struct TravelProvider {
-    id: Uuid,
-    name: String,
-    description: String,
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize, DbEnum, Enum, Display, EnumString, EnumVariantNames)]
+#[PgType = "travel_mode_enum"]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+/// Stored as travel_mode_enum
+/// The kind of carrier (rail, bus, charter...) is in trip_provider
+pub enum TravelMode {
+    Air,
+    Sea,
+    Land,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub enum TravelMode {
-    // Strings annotate the types of travel
-    // I.e., "Rail", "Bus", "Private Vehicle", "Charter"
-    Air(String),
-    Sea(String),
-    Land(String),
-}
\ No newline at end of file
+impl TravelMode {
+    /// Kind of port travellers arriving this way enter through
+    pub fn port_kind(&self) -> PlaceKind {
+        match self {
+            TravelMode::Air => PlaceKind::Airport,
+            TravelMode::Sea => PlaceKind::Seaport,
+            TravelMode::Land => PlaceKind::LandCrossing,
+        }
+    }
+}
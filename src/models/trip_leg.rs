@@ -0,0 +1,238 @@
+use std::collections::HashSet;
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use diesel::{self, Insertable, PgConnection, Queryable, ExpressionMethods};
+use diesel::{RunQueryDsl, QueryDsl};
+use uuid::Uuid;
+use async_graphql::*;
+
+use crate::schema::*;
+use crate::graphql::graphql_translate;
+use crate::models::{Country, Place, Trip, TripTime};
+use crate::{get_place_by_id, get_country_by_id, get_country_by_name,
+    get_or_create_place_by_name_and_country_id, ReferenceSource};
+
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable)]
+/// One flight, sailing or crossing of a Trip. Legs are numbered from 1
+/// and each leg's destination is the next leg's origin.
+pub struct TripLeg {
+    pub id: Uuid,
+    pub trip_id: Uuid,
+    pub leg_number: i32,
+    pub origin_place_id: Uuid,
+    pub destination_place_id: Uuid,
+    pub travel_identifier: Option<String>,
+    pub scheduled_departure_time: Option<NaiveDateTime>,
+    pub scheduled_arrival_time: Option<NaiveDateTime>,
+    pub departure_time: Option<NaiveDateTime>,
+    pub arrival_time: Option<NaiveDateTime>,
+}
+
+#[Object]
+impl TripLeg {
+    pub async fn id(&self) -> FieldResult<Uuid> {
+        Ok(self.id)
+    }
+
+    pub async fn leg_number(&self) -> FieldResult<i32> {
+        Ok(self.leg_number)
+    }
+
+    pub async fn origin(&self, context: &Context<'_>) -> FieldResult<Place> {
+        get_place_by_id(context, self.origin_place_id)
+    }
+
+    pub async fn destination(&self, context: &Context<'_>) -> FieldResult<Place> {
+        get_place_by_id(context, self.destination_place_id)
+    }
+
+    pub async fn travel_identifier(&self) -> FieldResult<Option<String>> {
+        Ok(self.travel_identifier.to_owned())
+    }
+
+    pub async fn scheduled_departure_time(&self, context: &Context<'_>) -> FieldResult<Option<TripTime>> {
+        TripTime::at_place(context, self.scheduled_departure_time, self.origin_place_id)
+    }
+
+    pub async fn scheduled_arrival_time(&self, context: &Context<'_>) -> FieldResult<Option<TripTime>> {
+        TripTime::at_place(context, self.scheduled_arrival_time, self.destination_place_id)
+    }
+
+    pub async fn departure_time(&self, context: &Context<'_>) -> FieldResult<Option<TripTime>> {
+        TripTime::at_place(context, self.departure_time, self.origin_place_id)
+    }
+
+    pub async fn arrival_time(&self, context: &Context<'_>) -> FieldResult<Option<TripTime>> {
+        TripTime::at_place(context, self.arrival_time, self.destination_place_id)
+    }
+}
+
+// Non Graphql
+impl TripLeg {
+    pub fn create_all(conn: &PgConnection, legs: &[NewTripLeg]) -> FieldResult<Vec<TripLeg>> {
+        let res = diesel::insert_into(trip_legs::table)
+            .values(legs)
+            .get_results(conn);
+
+        graphql_translate(res)
+    }
+
+    pub fn get_by_trip_id(conn: &PgConnection, trip_id: Uuid) -> FieldResult<Vec<TripLeg>> {
+        let res = trip_legs::table
+            .filter(trip_legs::trip_id.eq(trip_id))
+            .order(trip_legs::leg_number.asc())
+            .load::<TripLeg>(conn);
+
+        graphql_translate(res)
+    }
+
+    /// Latest known time for the leg, actual times preferred over scheduled
+    pub fn latest_time(&self) -> Option<NaiveDateTime> {
+        self.arrival_time
+            .or(self.scheduled_arrival_time)
+            .or(self.departure_time)
+            .or(self.scheduled_departure_time)
+    }
+
+    /// Legs of all of a person's trips that ended on or after since
+    pub fn get_by_person_since(conn: &PgConnection, person_id: Uuid, since: NaiveDateTime) -> FieldResult<Vec<TripLeg>> {
+        let trip_ids = graphql_translate(trips::table
+            .filter(trips::person_id.eq(person_id))
+            .select(trips::id)
+            .load::<Uuid>(conn))?;
+
+        let res = trip_legs::table
+            .filter(trip_legs::trip_id.eq_any(trip_ids))
+            .load::<TripLeg>(conn);
+
+        let legs = graphql_translate(res)?
+            .into_iter()
+            .filter(|l| l.latest_time().map(|t| t >= since).unwrap_or(false))
+            .collect();
+
+        Ok(legs)
+    }
+
+    /// Every country a person departed from, connected through or arrived in
+    /// on or after since, in order of first visit
+    pub fn countries_visited(
+        context: &impl ReferenceSource,
+        conn: &PgConnection,
+        person_id: Uuid,
+        since: NaiveDateTime,
+    ) -> FieldResult<Vec<Country>> {
+
+        let mut legs = TripLeg::get_by_person_since(conn, person_id, since)?;
+        legs.sort_by_key(|l| l.latest_time());
+
+        let mut seen: HashSet<Uuid> = HashSet::new();
+        let mut countries: Vec<Country> = Vec::new();
+
+        for leg in legs {
+            for place_id in [leg.origin_place_id, leg.destination_place_id] {
+                let place = get_place_by_id(context, place_id)?;
+
+                if seen.insert(place.country_id) {
+                    countries.push(get_country_by_id(context, place.country_id)?);
+                }
+            }
+        }
+
+        Ok(countries)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "trip_legs"]
+pub struct NewTripLeg {
+    pub trip_id: Uuid,
+    pub leg_number: i32,
+    pub origin_place_id: Uuid,
+    pub destination_place_id: Uuid,
+    pub travel_identifier: Option<String>,
+    pub scheduled_departure_time: Option<NaiveDateTime>,
+    pub scheduled_arrival_time: Option<NaiveDateTime>,
+    pub departure_time: Option<NaiveDateTime>,
+    pub arrival_time: Option<NaiveDateTime>,
+}
+
+impl NewTripLeg {
+    pub fn from(
+        context: &impl ReferenceSource,
+        conn: &PgConnection,
+        trip_id: Uuid,
+        leg_number: i32,
+        slim_leg: &SlimTripLeg,
+    ) -> FieldResult<Self> {
+
+        let origin_country = get_country_by_name(context, &slim_leg.origin_country_name)?;
+
+        let origin = get_or_create_place_by_name_and_country_id(
+            context,
+            conn,
+            slim_leg.origin_name.to_owned(), origin_country.id)?;
+
+        let destination_country = get_country_by_name(context, &slim_leg.destination_country_name)?;
+
+        let destination = get_or_create_place_by_name_and_country_id(
+            context,
+            conn,
+            slim_leg.destination_name.to_owned(), destination_country.id)?;
+
+        Ok(NewTripLeg {
+            trip_id,
+            leg_number,
+            origin_place_id: origin.id,
+            destination_place_id: destination.id,
+            travel_identifier: slim_leg.travel_identifier.to_owned(),
+            scheduled_departure_time: slim_leg.scheduled_departure_time,
+            scheduled_arrival_time: slim_leg.scheduled_arrival_time,
+            departure_time: slim_leg.departure_time,
+            arrival_time: slim_leg.arrival_time,
+        })
+    }
+
+    /// Single leg itinerary for a trip submitted without one
+    pub fn from_trip(trip: &Trip) -> Self {
+        NewTripLeg {
+            trip_id: trip.id,
+            leg_number: 1,
+            origin_place_id: trip.origin_place_id,
+            destination_place_id: trip.destination_place_id,
+            travel_identifier: trip.travel_identifier.to_owned(),
+            scheduled_departure_time: trip.scheduled_departure_time,
+            scheduled_arrival_time: trip.scheduled_arrival_time,
+            departure_time: trip.departure_time,
+            arrival_time: trip.arrival_time,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, InputObject, SimpleObject)]
+#[graphql(input_name = "TripLegInput")]
+/// Basic text data used to create a TripLeg
+pub struct SlimTripLeg {
+    pub origin_name: String,
+    pub origin_country_name: String,
+    pub destination_name: String,
+    pub destination_country_name: String,
+    /// Flight or sailing number of this leg
+    pub travel_identifier: Option<String>,
+    pub scheduled_departure_time: Option<NaiveDateTime>,
+    pub scheduled_arrival_time: Option<NaiveDateTime>,
+    pub departure_time: Option<NaiveDateTime>,
+    pub arrival_time: Option<NaiveDateTime>,
+}
+
+impl SlimTripLeg {
+    /// Actual departure if reported, otherwise scheduled
+    pub fn start_time(&self) -> Option<NaiveDateTime> {
+        self.departure_time.or(self.scheduled_departure_time)
+    }
+
+    /// Actual arrival if reported, otherwise scheduled
+    pub fn end_time(&self) -> Option<NaiveDateTime> {
+        self.arrival_time.or(self.scheduled_arrival_time)
+    }
+}
@@ -0,0 +1,73 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use diesel::{self, Insertable, PgConnection, Queryable, ExpressionMethods};
+use diesel::{RunQueryDsl, QueryDsl};
+use uuid::Uuid;
+use async_graphql::*;
+
+use crate::graphql::graphql_translate;
+use crate::models::TripState;
+use crate::schema::*;
+
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable, SimpleObject)]
+/// A change of TripState and the user who made it.
+/// Automatic transitions are recorded against the user who reported the times.
+pub struct TripStateChange {
+    pub id: Uuid,
+    pub trip_id: Uuid,
+    pub from_state: TripState,
+    pub to_state: TripState,
+    pub changed_by: Uuid,
+    pub reason: Option<String>,
+    pub changed_at: NaiveDateTime,
+}
+
+impl TripStateChange {
+    pub fn create(conn: &PgConnection, change: &NewTripStateChange) -> FieldResult<TripStateChange> {
+        let res = diesel::insert_into(trip_state_history::table)
+            .values(change)
+            .get_result(conn);
+
+        graphql_translate(res)
+    }
+
+    /// History of a trip, oldest first
+    pub fn get_by_trip_id(conn: &PgConnection, trip_id: Uuid) -> FieldResult<Vec<TripStateChange>> {
+        let res = trip_state_history::table
+            .filter(trip_state_history::trip_id.eq(trip_id))
+            .order(trip_state_history::changed_at.asc())
+            .load::<TripStateChange>(conn);
+
+        graphql_translate(res)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "trip_state_history"]
+pub struct NewTripStateChange {
+    pub trip_id: Uuid,
+    pub from_state: TripState,
+    pub to_state: TripState,
+    pub changed_by: Uuid,
+    pub reason: Option<String>,
+    pub changed_at: NaiveDateTime,
+}
+
+impl NewTripStateChange {
+    pub fn new(
+        trip_id: Uuid,
+        from_state: TripState,
+        to_state: TripState,
+        changed_by: Uuid,
+        reason: Option<String>,
+    ) -> Self {
+        NewTripStateChange {
+            trip_id,
+            from_state,
+            to_state,
+            changed_by,
+            reason,
+            changed_at: Utc::now().naive_utc(),
+        }
+    }
+}
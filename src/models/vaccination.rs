@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use chrono::prelude::*;
 use async_graphql::*;
 use serde::{Deserialize, Serialize};
@@ -11,8 +13,8 @@ use crate::config_variables::DATE_FORMAT;
 use crate::models::{Place, Vaccine};
 use crate::graphql::graphql_translate;
 use crate::schema::*;
-use crate::{get_or_create_country_by_name, get_vaccine_by_id, 
-    get_vaccine_by_name, get_place_by_id, get_or_create_place_by_name_and_country_id};
+use crate::{get_country_by_name, get_vaccine_by_id, 
+    get_vaccine_by_name, get_place_by_id, get_or_create_place_by_name_and_country_id, ReferenceSource};
 
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, PartialOrd, Queryable, Identifiable)]
@@ -71,16 +73,25 @@ impl Vaccination {
             .distinct()
             .first(conn);
 
-        let vaccination = match res {
-            Ok(v) => v,
-            Err(e) => {
-                // Vaccination not found
-                println!("{:?}", e);
-                let v = Vaccination::create(conn, vaccination).expect("Unable to create person");
-                v
-            }
-        };
-        Ok(vaccination)
+        match res {
+            Ok(v) => Ok(v),
+            // Vaccination not found
+            Err(diesel::result::Error::NotFound) => Vaccination::create(conn, vaccination),
+            Err(e) => graphql_translate(Err(e)),
+        }
+    }
+
+    /// Ids of the vaccinations in moved that repeat a dose in kept or earlier
+    /// in moved, i.e. the same vaccine given at the same time
+    pub fn duplicates(kept: &[Vaccination], moved: &[Vaccination]) -> Vec<Uuid> {
+        let mut doses: HashSet<(Uuid, NaiveDateTime)> = kept.iter()
+            .map(|v| (v.vaccine_id, v.provided_on))
+            .collect();
+
+        moved.iter()
+            .filter(|v| !doses.insert((v.vaccine_id, v.provided_on)))
+            .map(|v| v.id)
+            .collect()
     }
 }
 
@@ -97,7 +108,7 @@ pub struct NewVaccination {
 
 impl NewVaccination {
     pub fn new(
-        context: &Context<'_>,
+        context: &impl ReferenceSource,
         vaccine_name: String,
         dose_provider: String,
         location_provided_id: Uuid, // Place
@@ -107,8 +118,7 @@ impl NewVaccination {
 
         let vaccine = get_vaccine_by_name(
             context,
-            vaccine_name)
-            .expect("Unable to find vaccine by name");
+            vaccine_name)?;
 
         Ok(NewVaccination {
             vaccine_id: vaccine.id,
@@ -137,22 +147,21 @@ impl NewVaccination {
     }
 
     pub fn from(
-        context: &Context<'_>,
+        context: &impl ReferenceSource,
+        conn: &PgConnection,
         slim_vaccination: &SlimVaccination, 
         public_health_profile_id: Uuid
     ) -> FieldResult<Self> {
-        let provided_country = get_or_create_country_by_name(context, slim_vaccination.country_provided.to_owned())
-            .expect("Unable to find or create country");
+        let provided_country = get_country_by_name(context, &slim_vaccination.country_provided)?;
 
         let location_provided = get_or_create_place_by_name_and_country_id(
             context,
-            slim_vaccination.location_provided.to_owned(), provided_country.id)
-            .expect("Unable to get or create origin country");
+            conn,
+            slim_vaccination.location_provided.to_owned(), provided_country.id)?;
 
-            let vaccine = get_vaccine_by_name(
-                context,
-                slim_vaccination.vaccine_name.to_owned())
-            .expect("Unable to find vaccine by name");
+        let vaccine = get_vaccine_by_name(
+            context,
+            slim_vaccination.vaccine_name.to_owned())?;
 
         Ok(
             NewVaccination {
@@ -7,6 +7,7 @@ use uuid::Uuid;
 use std::collections::HashMap;
 
 use crate::graphql::graphql_translate;
+use crate::errors::error_handler::CustomError;
 use crate::schema::*;
 
 #[derive(Debug, Clone, Deserialize, Serialize, Insertable, Queryable)]
@@ -66,17 +67,16 @@ impl Vaccine {
         graphql_translate(res)
     }
 
-    pub fn load_into_hash(conn: &PgConnection) -> HashMap<Uuid, Vaccine> {
+    pub fn load_into_hash(conn: &PgConnection) -> Result<HashMap<Uuid, Vaccine>, CustomError> {
         let res = vaccines::table
-            .load::<Vaccine>(conn)
-            .expect("Unable to load countries");
+            .load::<Vaccine>(conn)?;
 
         let mut new_map: HashMap<Uuid, Vaccine> = HashMap::new();
         for v in res {
             new_map.insert(v.id, v);
         };
 
-        new_map 
+        Ok(new_map)
     }
 }
 
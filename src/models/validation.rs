@@ -0,0 +1,334 @@
+use std::str::FromStr;
+
+use chrono::prelude::*;
+use strum::VariantNames;
+
+use crate::models::{RelationshipType, ResidencyStatus, SlimTripLeg, SlimVaccination, TravelData, TravelDocumentType, TravelIntent, TravelMode, TripState,
+    normalize_country_name, normalize_place_name};
+
+/// Normalizes "in progress", "in-progress" and "In_Progress" to "IN_PROGRESS"
+pub fn normalize_enum_value(value: &str) -> String {
+    value
+        .trim()
+        .replace(|c: char| c == ' ' || c == '-', "_")
+        .to_uppercase()
+}
+
+/// Parses the free text sent for a TravelMode, TravelIntent or TripState
+pub fn parse_enum_value<T: FromStr + VariantNames>(field: &str, value: &str) -> Result<T, String> {
+    T::from_str(&normalize_enum_value(value)).map_err(|_| {
+        format!("{} \"{}\" is not one of {}", field, value, T::VARIANTS.join(", "))
+    })
+}
+
+/// Checks a travel document id against the passport number format of its issuer.
+/// Issuers without a known format accept 5 to 20 letters and digits.
+pub fn is_valid_document_id(issuer: &str, document_id: &str) -> bool {
+    let id = document_id.trim();
+
+    let letters = id.chars().take_while(|c| c.is_ascii_alphabetic()).count();
+    let digits = id.chars().skip(letters).filter(|c| c.is_ascii_digit()).count();
+    let is_letters_then_digits = letters + digits == id.len();
+
+    match normalize_country_name(issuer).as_str() {
+        // 1 or 2 letters followed by 6 digits, e.g. AB123456
+        "canada" | "ca" | "can" => is_letters_then_digits && (1..=2).contains(&letters) && digits == 6,
+        // 9 digits, or 1 letter followed by 8 digits for next generation passports
+        "united states" | "united states of america" | "usa" | "us" => {
+            is_letters_then_digits && letters + digits == 9 && letters <= 1
+        },
+        // 9 digits
+        "united kingdom" | "uk" | "gb" | "gbr" => is_letters_then_digits && letters == 0 && digits == 9,
+        _ => (5..=20).contains(&id.len()) && id.chars().all(|c| c.is_ascii_alphanumeric()),
+    }
+}
+
+/// Checks a document number against the format for its type. Passports use the
+/// format of their issuer and NEXUS numbers are 9 digits.
+pub fn is_valid_travel_document(document_type: TravelDocumentType, issuer: &str, document_number: &str) -> bool {
+    let id = document_number.trim();
+
+    match document_type {
+        TravelDocumentType::Passport => is_valid_document_id(issuer, id),
+        TravelDocumentType::Nexus => id.len() == 9 && id.chars().all(|c| c.is_ascii_digit()),
+        TravelDocumentType::PermanentResidentCard | TravelDocumentType::Other => {
+            (5..=20).contains(&id.len()) && id.chars().all(|c| c.is_ascii_alphanumeric())
+        },
+    }
+}
+
+/// Checks the references between travellers submitted together. traveller_ref
+/// must be unique and relationships and guardian consent must refer to another
+/// traveller in the group.
+pub fn validate_group(data: &[TravelData]) -> Vec<String> {
+    let mut violations: Vec<String> = Vec::new();
+
+    let refs: Vec<Option<String>> = data
+        .iter()
+        .map(|t| t.traveller_ref.as_ref().map(|r| r.trim().to_owned()))
+        .collect();
+
+    for (i, r) in refs.iter().enumerate() {
+        match r {
+            Some(r) if r.is_empty() => {
+                violations.push(format!("data[{}].traveller_ref must not be empty", i));
+            },
+            Some(r) if refs[..i].contains(&Some(r.to_owned())) => {
+                violations.push(format!("data[{}].traveller_ref \"{}\" is used by another traveller", i, r));
+            },
+            _ => {},
+        }
+    }
+
+    let is_other_traveller = |i: usize, related: &str| {
+        let related = Some(related.trim().to_owned());
+
+        refs[i] != related && refs.contains(&related)
+    };
+
+    for (i, traveller) in data.iter().enumerate() {
+        for (j, relationship) in traveller.relationships.iter().flatten().enumerate() {
+            if !is_other_traveller(i, &relationship.related_traveller_ref) {
+                violations.push(format!(
+                    "data[{}].relationships[{}].related_traveller_ref \"{}\" is not another traveller in the group",
+                    i, j, relationship.related_traveller_ref));
+            }
+        }
+
+        if let Some(guardian_ref) = traveller.guardian_consent.as_ref().and_then(|c| c.guardian_traveller_ref.as_ref()) {
+            if !is_other_traveller(i, guardian_ref) {
+                violations.push(format!(
+                    "data[{}].guardian_consent.guardian_traveller_ref \"{}\" is not another traveller in the group",
+                    i, guardian_ref));
+            }
+        }
+    }
+
+    violations
+}
+
+impl TravelData {
+    /// Checks the traveller's data before anything is written to the database.
+    /// Returns every violation found rather than stopping at the first.
+    pub fn validate(&self) -> Vec<String> {
+        let mut violations: Vec<String> = Vec::new();
+
+        // Dates
+        if self.birth_date >= Utc::now().naive_utc().date() {
+            violations.push(format!("birth_date {} must be in the past", self.birth_date));
+        }
+
+        if let (Some(departure), Some(arrival)) = (self.scheduled_departure_time, self.scheduled_arrival_time) {
+            if arrival <= departure {
+                violations.push(format!(
+                    "scheduled_arrival_time {} must be after scheduled_departure_time {}",
+                    arrival, departure));
+            }
+        }
+
+        if let (Some(departure), Some(arrival)) = (self.departure_time, self.arrival_time) {
+            if arrival <= departure {
+                violations.push(format!(
+                    "arrival_time {} must be after departure_time {}",
+                    arrival, departure));
+            }
+        }
+
+        // Vaccinations are checked against the actual arrival when known
+        let arrival = self.arrival_time.or(self.scheduled_arrival_time);
+
+        if let (Some(arrival), Some(vaccinations)) = (arrival, &self.vaccinations) {
+            for (i, v) in vaccinations.iter().enumerate() {
+                if v.provided_on >= arrival {
+                    violations.push(format!(
+                        "vaccinations[{}].provided_on {} must be before arrival {}",
+                        i, v.provided_on, arrival));
+                }
+            }
+        }
+
+        if let Some(vaccinations) = &self.vaccinations {
+            let vaccine_name = |v: &SlimVaccination| v.vaccine_name.trim().to_lowercase();
+
+            for (i, v) in vaccinations.iter().enumerate() {
+                let first = vaccinations[..i]
+                    .iter()
+                    .position(|o| o.provided_on == v.provided_on && vaccine_name(o) == vaccine_name(v));
+
+                if let Some(first) = first {
+                    violations.push(format!(
+                        "vaccinations[{}] repeats vaccinations[{}], the same vaccine given at the same time",
+                        i, first));
+                }
+            }
+        }
+
+        // Enum values
+        if let Err(e) = parse_enum_value::<TravelMode>("travel_mode", &self.travel_mode) {
+            violations.push(e);
+        }
+
+        let travel_intent = parse_enum_value::<TravelIntent>("travel_intent", &self.travel_intent);
+
+        if let Err(e) = &travel_intent {
+            violations.push(e.to_owned());
+        }
+
+        if let Err(e) = parse_enum_value::<TripState>("trip_state", &self.trip_state) {
+            violations.push(e);
+        }
+
+        if let Some(status) = &self.residency_status {
+            if let Err(e) = parse_enum_value::<ResidencyStatus>("residency_status", status) {
+                violations.push(e);
+            }
+        }
+
+        // Itinerary
+        let is_transit = travel_intent == Ok(TravelIntent::Transit);
+
+        match &self.itinerary {
+            Some(itinerary) => violations.extend(self.validate_itinerary(itinerary, is_transit)),
+            None if is_transit => {
+                violations.push("itinerary with an onward leg is required when travel_intent is TRANSIT".to_string());
+            },
+            None => {},
+        }
+
+        // Travel documents, the first is the one presented
+        for (i, document) in self.travel_documents().iter().enumerate() {
+            let (type_field, number_field) = if i == 0 {
+                ("travel_document_type".to_string(), "travel_document_id".to_string())
+            } else {
+                (format!("additional_travel_documents[{}].document_type", i - 1),
+                    format!("additional_travel_documents[{}].document_number", i - 1))
+            };
+
+            let document_type = match parse_enum_value::<TravelDocumentType>(&type_field, &document.document_type) {
+                Ok(t) => t,
+                Err(e) => {
+                    violations.push(e);
+                    continue;
+                },
+            };
+
+            if !is_valid_travel_document(document_type, &document.issuer, &document.document_number) {
+                violations.push(format!(
+                    "{} \"{}\" is not a valid format for {} issued by {}",
+                    number_field, document.document_number, document_type, document.issuer));
+            }
+        }
+
+        // Relationships, refs are checked with the group
+        for (i, relationship) in self.relationships.iter().flatten().enumerate() {
+            let field = format!("relationships[{}].relationship_type", i);
+
+            if let Err(e) = parse_enum_value::<RelationshipType>(&field, &relationship.relationship_type) {
+                violations.push(e);
+            }
+        }
+
+        if let Some(consent) = &self.guardian_consent {
+            if consent.guardian_name.trim().is_empty() {
+                violations.push("guardian_consent.guardian_name must not be empty".to_string());
+            }
+        }
+
+        // Required sub-objects
+        if self.vaccination_required && self.vaccinations.as_ref().map(|v| v.is_empty()).unwrap_or(true) {
+            violations.push("vaccinations are required when vaccination_required is true".to_string());
+        }
+
+        if self.covid_test_required && self.covid_test.is_none() {
+            violations.push("covid_test is required when covid_test_required is true".to_string());
+        }
+
+        if self.quarantine_plan_required && self.quarantine_plan.is_none() {
+            violations.push("quarantine_plan is required when quarantine_plan_required is true".to_string());
+        }
+
+        violations
+    }
+
+    /// Legs must connect from origin through to the final destination in time order.
+    /// The destination is the last stop, or for TRANSIT a connection with onward legs.
+    fn validate_itinerary(&self, itinerary: &[SlimTripLeg], is_transit: bool) -> Vec<String> {
+        let mut violations: Vec<String> = Vec::new();
+
+        if itinerary.is_empty() {
+            violations.push("itinerary must have at least one leg".to_string());
+            return violations;
+        }
+
+        let first = &itinerary[0];
+
+        if !is_same_place(&first.origin_name, &first.origin_country_name, &self.origin_name, &self.origin_country_name) {
+            violations.push(format!(
+                "itinerary[0] origin {}, {} is not the trip origin {}, {}",
+                first.origin_name, first.origin_country_name, self.origin_name, self.origin_country_name));
+        }
+
+        for (i, leg) in itinerary.iter().enumerate() {
+            if let (Some(departure), Some(arrival)) = (leg.scheduled_departure_time, leg.scheduled_arrival_time) {
+                if arrival <= departure {
+                    violations.push(format!(
+                        "itinerary[{}].scheduled_arrival_time {} must be after scheduled_departure_time {}",
+                        i, arrival, departure));
+                }
+            }
+
+            if let (Some(departure), Some(arrival)) = (leg.departure_time, leg.arrival_time) {
+                if arrival <= departure {
+                    violations.push(format!(
+                        "itinerary[{}].arrival_time {} must be after departure_time {}",
+                        i, arrival, departure));
+                }
+            }
+
+            if i == 0 {
+                continue;
+            }
+
+            let previous = &itinerary[i - 1];
+
+            if !is_same_place(&previous.destination_name, &previous.destination_country_name, &leg.origin_name, &leg.origin_country_name) {
+                violations.push(format!(
+                    "itinerary[{}] origin {}, {} does not connect with itinerary[{}] destination {}, {}",
+                    i, leg.origin_name, leg.origin_country_name,
+                    i - 1, previous.destination_name, previous.destination_country_name));
+            }
+
+            if let (Some(previous_arrival), Some(departure)) = (previous.end_time(), leg.start_time()) {
+                if departure < previous_arrival {
+                    violations.push(format!(
+                        "itinerary[{}] departs at {} before itinerary[{}] arrives at {}",
+                        i, departure, i - 1, previous_arrival));
+                }
+            }
+        }
+
+        let stop = itinerary.iter().position(|leg| {
+            is_same_place(&leg.destination_name, &leg.destination_country_name, &self.destination_name, &self.destination_country_name)
+        });
+
+        match stop {
+            None => violations.push(format!(
+                "destination {}, {} is not on the itinerary",
+                self.destination_name, self.destination_country_name)),
+            Some(i) if is_transit && i == itinerary.len() - 1 => violations.push(format!(
+                "itinerary must continue past {} when travel_intent is TRANSIT",
+                self.destination_name)),
+            Some(i) if !is_transit && i != itinerary.len() - 1 => violations.push(format!(
+                "itinerary continues past destination {}; use travel_intent TRANSIT for connections",
+                self.destination_name)),
+            Some(_) => {},
+        }
+
+        violations
+    }
+}
+
+fn is_same_place(name: &str, country_name: &str, other_name: &str, other_country_name: &str) -> bool {
+    normalize_place_name(name) == normalize_place_name(other_name)
+        && normalize_country_name(country_name) == normalize_country_name(other_country_name)
+}
@@ -13,6 +13,16 @@ table! {
         id -> Uuid,
         country_name -> Varchar,
         risk_rate -> Float8,
+        iso_alpha2 -> Nullable<Varchar>,
+        iso_alpha3 -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    country_aliases (id) {
+        id -> Uuid,
+        country_id -> Uuid,
+        alias -> Varchar,
     }
 }
 
@@ -28,6 +38,61 @@ table! {
 }
 
 table! {
+    guardian_consents (id) {
+        id -> Uuid,
+        travel_group_id -> Uuid,
+        minor_id -> Uuid,
+        guardian_name -> Varchar,
+        guardian_contact -> Nullable<Varchar>,
+        guardian_person_id -> Nullable<Uuid>,
+        recorded_by -> Uuid,
+        recorded_at -> Timestamp,
+    }
+}
+
+table! {
+    pending_countries (id) {
+        id -> Uuid,
+        country_name -> Varchar,
+        seen_count -> Int4,
+        first_seen_at -> Timestamp,
+        last_seen_at -> Timestamp,
+    }
+}
+
+table! {
+    persisted_queries (id) {
+        id -> Uuid,
+        sha256_hash -> Varchar,
+        document -> Text,
+        operation_name -> Nullable<Varchar>,
+        description -> Nullable<Varchar>,
+        created_by -> Uuid,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::models::PersonMatchStatusMapping;
+
+    person_match_reviews (id) {
+        id -> Uuid,
+        person_id -> Uuid,
+        candidate_id -> Uuid,
+        score -> Float8,
+        reasons -> Array<Varchar>,
+        status -> PersonMatchStatusMapping,
+        created_at -> Timestamp,
+        reviewed_by -> Nullable<Uuid>,
+        reviewed_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::models::ResidencyStatusMapping;
+
     persons (id) {
         id -> Uuid,
         family_name -> Varchar,
@@ -35,20 +100,51 @@ table! {
         additional_names -> Nullable<Array<Text>>,
         birth_date -> Date,
         gender -> Varchar,
-        travel_document_id -> Varchar,
-        travel_document_issuer_id -> Uuid,
         travel_group_id -> Uuid,
         approved_access_level -> Varchar,
         approved_access_granularity -> Varchar,
         created_at -> Timestamp,
+        residency_status -> Nullable<ResidencyStatusMapping>,
+        citizenship_ids -> Array<Uuid>,
     }
 }
 
 table! {
+    pil_submissions (id) {
+        id -> Uuid,
+        idempotency_key -> Varchar,
+        cbsa_id -> Uuid,
+        travel_response_ids -> Array<Uuid>,
+        created_at -> Timestamp,
+        payload_hash -> Varchar,
+        completed -> Bool,
+    }
+}
+
+table! {
+    place_aliases (id) {
+        id -> Uuid,
+        place_id -> Uuid,
+        alias -> Varchar,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::models::PlaceKindMapping;
+
     places (id) {
         id -> Uuid,
         place_name -> Varchar,
         country_id -> Uuid,
+        timezone -> Nullable<Varchar>,
+        place_kind -> PlaceKindMapping,
+        iata_code -> Nullable<Varchar>,
+        un_locode -> Nullable<Varchar>,
+        latitude -> Nullable<Float8>,
+        longitude -> Nullable<Float8>,
+        is_port_of_entry -> Bool,
+        testing_rate -> Nullable<Float8>,
     }
 }
 
@@ -86,6 +182,37 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use crate::models::{TravelDocumentTypeMapping, TravelDocumentStatusMapping};
+
+    travel_documents (id) {
+        id -> Uuid,
+        person_id -> Uuid,
+        document_type -> TravelDocumentTypeMapping,
+        document_number -> Varchar,
+        issuer_id -> Uuid,
+        expires_on -> Nullable<Date>,
+        status -> TravelDocumentStatusMapping,
+        created_at -> Timestamp,
+        last_presented_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::models::RelationshipTypeMapping;
+
+    travel_group_relationships (id) {
+        id -> Uuid,
+        travel_group_id -> Uuid,
+        person_id -> Uuid,
+        related_person_id -> Uuid,
+        relationship_type -> RelationshipTypeMapping,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     travel_groups (id) {
         id -> Uuid,
@@ -93,6 +220,25 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use crate::models::TravelProviderTypeMapping;
+
+    travel_providers (id) {
+        id -> Uuid,
+        provider_name -> Varchar,
+        provider_type -> TravelProviderTypeMapping,
+        contact_name -> Nullable<Varchar>,
+        contact_email -> Nullable<Varchar>,
+        contact_phone -> Nullable<Varchar>,
+        api_key_prefix -> Nullable<Varchar>,
+        api_key_hash -> Nullable<Varchar>,
+        api_key_scopes -> Array<Varchar>,
+        active -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     travel_responses (id) {
         id -> Uuid,
@@ -105,27 +251,63 @@ table! {
         quarantine_required -> Bool,
         date_time -> Timestamp,
         details -> Nullable<Text>,
+        person_created -> Bool,
+    }
+}
+
+table! {
+    trip_legs (id) {
+        id -> Uuid,
+        trip_id -> Uuid,
+        leg_number -> Int4,
+        origin_place_id -> Uuid,
+        destination_place_id -> Uuid,
+        travel_identifier -> Nullable<Varchar>,
+        scheduled_departure_time -> Nullable<Timestamp>,
+        scheduled_arrival_time -> Nullable<Timestamp>,
+        departure_time -> Nullable<Timestamp>,
+        arrival_time -> Nullable<Timestamp>,
     }
 }
 
 table! {
+    use diesel::sql_types::*;
+    use crate::models::TripStateMapping;
+
+    trip_state_history (id) {
+        id -> Uuid,
+        trip_id -> Uuid,
+        from_state -> TripStateMapping,
+        to_state -> TripStateMapping,
+        changed_by -> Uuid,
+        reason -> Nullable<Varchar>,
+        changed_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::models::{TravelModeMapping, TravelIntentMapping, TripStateMapping};
+
     trips (id) {
         id -> Uuid,
         trip_provider -> Varchar,
         travel_identifier -> Nullable<Varchar>,
         booking_id -> Nullable<Varchar>,
-        travel_mode -> Varchar,
+        travel_mode -> TravelModeMapping,
         origin_place_id -> Uuid,
         destination_place_id -> Uuid,
-        travel_intent -> Varchar,
+        travel_intent -> TravelIntentMapping,
         scheduled_departure_time -> Nullable<Timestamp>,
         scheduled_arrival_time -> Nullable<Timestamp>,
         departure_time -> Nullable<Timestamp>,
         arrival_time -> Nullable<Timestamp>,
-        trip_state -> Varchar,
+        trip_state -> TripStateMapping,
         travel_group_id -> Uuid,
         person_id -> Uuid,
         created_at -> Timestamp,
+        travel_provider_id -> Nullable<Uuid>,
+        port_of_entry_id -> Nullable<Uuid>,
     }
 }
 
@@ -173,19 +355,36 @@ table! {
     }
 }
 
+joinable!(country_aliases -> countries (country_id));
+joinable!(guardian_consents -> travel_groups (travel_group_id));
+joinable!(place_aliases -> places (place_id));
+joinable!(travel_documents -> persons (person_id));
+joinable!(travel_group_relationships -> travel_groups (travel_group_id));
 joinable!(users -> valid_roles (role));
 
 allow_tables_to_appear_in_same_query!(
     check_in_results,
     countries,
+    country_aliases,
     covid_tests,
+    guardian_consents,
+    pending_countries,
+    persisted_queries,
+    person_match_reviews,
     persons,
+    pil_submissions,
+    place_aliases,
     places,
     postal_addresses,
     public_health_profiles,
     quarantine_plans,
+    travel_documents,
+    travel_group_relationships,
     travel_groups,
+    travel_providers,
     travel_responses,
+    trip_legs,
+    trip_state_history,
     trips,
     users,
     vaccinations,
@@ -0,0 +1,24 @@
+//! Range of days accepted by Person::countries_visited.
+
+use health_rules_engine::config_variables::{COUNTRIES_VISITED_DAYS, MAX_COUNTRIES_VISITED_DAYS};
+use health_rules_engine::errors::error_handler::CustomError;
+use health_rules_engine::models::visited_days;
+
+#[test]
+fn days_default_to_the_configured_history() {
+    assert_eq!(visited_days(None).unwrap(), COUNTRIES_VISITED_DAYS);
+}
+
+#[test]
+fn days_within_range_are_accepted() {
+    for days in [1, 30, MAX_COUNTRIES_VISITED_DAYS] {
+        assert_eq!(visited_days(Some(days)).unwrap(), days);
+    }
+}
+
+#[test]
+fn days_out_of_range_are_rejected() {
+    for days in [0, -7, MAX_COUNTRIES_VISITED_DAYS + 1, i64::MAX, i64::MIN] {
+        assert!(matches!(visited_days(Some(days)), Err(CustomError::Validation(_))), "{} accepted", days);
+    }
+}
@@ -0,0 +1,150 @@
+//! Entry requirements by status in Canada.
+
+use chrono::{Duration, prelude::*};
+use uuid::Uuid;
+
+use health_rules_engine::config_variables::ENTRY_RULES;
+use health_rules_engine::models::{EntryAssessment, EntryDecision, ResidencyStatus, Vaccine};
+
+fn vaccine(name: &str, required_doses: i32, approved: bool) -> Vaccine {
+    Vaccine {
+        id: Uuid::new_v4(),
+        vaccine_name: name.to_string(),
+        manufacturer: name.to_string(),
+        vaccine_type: "mRNA".to_string(),
+        required_doses,
+        approved,
+        approved_on: NaiveDate::from_ymd(2020, 12, 9),
+        details: String::new(),
+    }
+}
+
+fn arrival() -> NaiveDateTime {
+    NaiveDate::from_ymd(2021, 9, 30).and_hms(19, 8, 0)
+}
+
+fn assessment(residency_status: ResidencyStatus, has_valid_document: bool, fully_vaccinated: bool) -> EntryAssessment {
+    EntryAssessment {
+        residency_status,
+        has_valid_document,
+        vaccination_required: true,
+        fully_vaccinated,
+    }
+}
+
+#[test]
+fn citizens_are_never_denied() {
+    for (has_valid_document, fully_vaccinated) in [(true, true), (false, true), (true, false), (false, false)] {
+        let outcome = ENTRY_RULES.decide(&assessment(ResidencyStatus::Citizen, has_valid_document, fully_vaccinated));
+
+        assert_ne!(outcome.decision, EntryDecision::Deny, "{:?}", outcome);
+    }
+}
+
+#[test]
+fn unvaccinated_residents_quarantine() {
+    for status in [ResidencyStatus::Citizen, ResidencyStatus::PermanentResident] {
+        let outcome = ENTRY_RULES.decide(&assessment(status, true, false));
+
+        assert_eq!(outcome.decision, EntryDecision::Quarantine, "{:?}", outcome);
+        assert_eq!(outcome.decision.response_code(), "Q");
+    }
+}
+
+#[test]
+fn foreign_nationals_need_vaccination_and_documents() {
+    let admitted = ENTRY_RULES.decide(&assessment(ResidencyStatus::ForeignNational, true, true));
+    let unvaccinated = ENTRY_RULES.decide(&assessment(ResidencyStatus::ForeignNational, true, false));
+    let undocumented = ENTRY_RULES.decide(&assessment(ResidencyStatus::ForeignNational, false, true));
+
+    assert_eq!(admitted.decision, EntryDecision::Admit);
+    assert_eq!(admitted.decision.response_code(), "I");
+    assert_eq!(unvaccinated.decision, EntryDecision::Deny);
+    assert_eq!(undocumented.decision, EntryDecision::Deny);
+}
+
+#[test]
+fn vaccination_not_required_is_admitted() {
+    let mut traveller = assessment(ResidencyStatus::ForeignNational, true, false);
+    traveller.vaccination_required = false;
+
+    assert_eq!(ENTRY_RULES.decide(&traveller).decision, EntryDecision::Admit);
+}
+
+#[test]
+fn fully_vaccinated_after_completed_series() {
+    let two_dose = vaccine("SpikeVax", 2, true);
+    let other_two_dose = vaccine("Comirnaty", 2, true);
+    let one_dose = vaccine("Jcovden", 1, true);
+    let unapproved = vaccine("Sputnik", 2, false);
+
+    let long_ago = arrival() - Duration::days(60);
+    let recently = arrival() - Duration::days(5);
+
+    // Mixed series
+    assert!(ENTRY_RULES.is_fully_vaccinated(
+        &[(two_dose.clone(), long_ago - Duration::days(28)), (other_two_dose, long_ago)], arrival()));
+
+    // Series completed too recently
+    assert!(!ENTRY_RULES.is_fully_vaccinated(
+        &[(two_dose.clone(), long_ago), (two_dose.clone(), recently)], arrival()));
+
+    // Incomplete series
+    assert!(!ENTRY_RULES.is_fully_vaccinated(&[(two_dose.clone(), long_ago)], arrival()));
+
+    // Unapproved doses don't count
+    assert!(!ENTRY_RULES.is_fully_vaccinated(
+        &[(unapproved.clone(), long_ago - Duration::days(28)), (unapproved, long_ago)], arrival()));
+
+    // A single dose vaccine completes a series
+    assert!(ENTRY_RULES.is_fully_vaccinated(&[(two_dose.clone(), recently), (one_dose, long_ago)], arrival()));
+
+    // A dose recorded twice is one dose
+    assert!(!ENTRY_RULES.is_fully_vaccinated(&[(two_dose.clone(), long_ago), (two_dose.clone(), long_ago)], arrival()));
+    assert_eq!(ENTRY_RULES.series_completed_on(
+        &[(two_dose.clone(), long_ago), (two_dose.clone(), long_ago), (two_dose, recently)]), Some(recently));
+}
+
+#[test]
+fn residency_statuses_order_by_rights() {
+    assert!(ResidencyStatus::Citizen > ResidencyStatus::PermanentResident);
+    assert!(ResidencyStatus::PermanentResident > ResidencyStatus::ForeignNational);
+    assert!(ResidencyStatus::Citizen.has_right_of_entry());
+    assert!(!ResidencyStatus::ForeignNational.has_right_of_entry());
+}
+
+#[test]
+fn minors_are_younger_than_the_age_of_majority() {
+    let birth_date = NaiveDate::from_ymd(2003, 10, 1);
+
+    assert_eq!(ENTRY_RULES.age_on(birth_date, NaiveDate::from_ymd(2021, 9, 30)), 17);
+    assert!(ENTRY_RULES.is_minor_on(birth_date, NaiveDate::from_ymd(2021, 9, 30)));
+    assert!(!ENTRY_RULES.is_minor_on(birth_date, NaiveDate::from_ymd(2021, 10, 1)));
+}
+
+#[test]
+fn households_quarantine_together() {
+    let decisions = [
+        Some(EntryDecision::Admit),
+        Some(EntryDecision::Admit),
+        Some(EntryDecision::Quarantine),
+        Some(EntryDecision::Deny),
+        Some(EntryDecision::Admit),
+        None,
+    ];
+
+    // 0 and 2 are linked through 1, 3 is denied and 4 travels alone
+    let decided = ENTRY_RULES.household_decisions(&decisions, &[(0, 1), (2, 1), (3, 2), (5, 0)]);
+
+    assert_eq!(decided, vec![
+        Some(EntryDecision::Quarantine),
+        Some(EntryDecision::Quarantine),
+        Some(EntryDecision::Quarantine),
+        Some(EntryDecision::Deny),
+        Some(EntryDecision::Admit),
+        None,
+    ]);
+
+    assert_eq!(EntryDecision::from_response_code("Q"), Some(EntryDecision::Quarantine));
+    assert_eq!(EntryDecision::from_response_code("E"), None);
+}
@@ -0,0 +1,122 @@
+//! Which export columns each role receives, and which roles may export.
+
+use actix_web::http::StatusCode;
+use actix_web::{test, web};
+use uuid::Uuid;
+
+use health_rules_engine::common_utils::Role;
+use health_rules_engine::handlers::export_dataset;
+use health_rules_engine::models::{create_token, ExportDataset, ExportFilter, ExportFormat};
+
+const DATASETS: [ExportDataset; 4] = [
+    ExportDataset::Trips,
+    ExportDataset::TravelResponses,
+    ExportDataset::Vaccinations,
+    ExportDataset::CovidTests,
+];
+
+fn headers(dataset: ExportDataset, role: Role) -> Vec<&'static str> {
+    dataset.columns()
+        .iter()
+        .filter(|c| c.visible_to(&role))
+        .map(|c| c.name)
+        .collect()
+}
+
+fn all_headers(dataset: ExportDataset) -> Vec<&'static str> {
+    dataset.columns().iter().map(|c| c.name).collect()
+}
+
+#[test]
+fn admins_receive_every_column() {
+    for dataset in DATASETS {
+        assert_eq!(headers(dataset, Role::Admin), all_headers(dataset));
+    }
+}
+
+#[test]
+fn analysts_receive_every_column() {
+    for dataset in DATASETS {
+        assert_eq!(headers(dataset, Role::Analyst), all_headers(dataset));
+    }
+}
+
+#[test]
+fn operators_receive_no_personal_columns() {
+    let trips = headers(ExportDataset::Trips, Role::Operator);
+
+    assert!(trips.contains(&"id"));
+    assert!(trips.contains(&"arrival_time"));
+
+    for name in ["family_name", "given_name", "travel_document_id", "booking_id"] {
+        assert!(!trips.contains(&name), "{} exported to Operator", name);
+    }
+
+    for dataset in DATASETS {
+        assert!(headers(dataset, Role::Operator).len() < all_headers(dataset).len());
+    }
+}
+
+#[test]
+fn users_receive_no_personal_columns() {
+    assert_eq!(
+        headers(ExportDataset::CovidTests, Role::User),
+        vec!["id", "test_name", "test_type", "date_taken", "test_result"],
+    );
+
+    for dataset in DATASETS {
+        assert!(dataset.columns()
+            .iter()
+            .filter(|c| c.pii)
+            .all(|c| !headers(dataset, Role::User).contains(&c.name)));
+    }
+}
+
+
+/// Status of an export request made with a token for role. The role check
+/// comes before the database is used, so refused requests need no database.
+async fn export_status(role: Role) -> StatusCode {
+    std::env::set_var("JWT_SECRET_KEY", "export-columns-test");
+
+    let token = create_token(Uuid::new_v4().to_string(), role);
+
+    let request = test::TestRequest::default()
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_http_request();
+
+    let filter = ExportFilter {
+        format: Some(ExportFormat::Csv),
+        from: None,
+        to: None,
+        country: None,
+        port: None,
+    };
+
+    export_dataset(request, web::Path::from(ExportDataset::Trips), web::Query(filter))
+        .await
+        .status()
+}
+
+#[actix_rt::test]
+async fn non_analysts_are_refused() {
+    for role in [Role::Operator, Role::User] {
+        assert_eq!(export_status(role).await, StatusCode::FORBIDDEN);
+    }
+}
+
+#[actix_rt::test]
+async fn requests_without_a_token_are_unauthorized() {
+    let request = test::TestRequest::default().to_http_request();
+
+    let filter = ExportFilter {
+        format: None,
+        from: None,
+        to: None,
+        country: None,
+        port: None,
+    };
+
+    let response = export_dataset(request, web::Path::from(ExportDataset::Trips), web::Query(filter)).await;
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,197 @@
+//! Payload hashes checked when an idempotency key is reused, and the
+//! transaction scope of keyed submissions. The retry test needs a database
+//! and is ignored unless run with --ignored.
+
+use std::sync::Once;
+
+use async_graphql::*;
+use chrono::prelude::*;
+use diesel::prelude::*;
+use serde_json::json;
+use uuid::Uuid;
+
+use health_rules_engine::common_utils::Role;
+use health_rules_engine::database::{self, POOL};
+use health_rules_engine::graphql::{create_schema_with_context, AppSchema};
+use health_rules_engine::models::{payload_hash, TransactionScope, TravelData};
+use health_rules_engine::schema::{persons, trips};
+
+#[test]
+fn the_same_payload_hashes_the_same() {
+    let travellers = json!([{ "familyName": "Tremblay", "givenName": "Marie" }]);
+
+    assert_eq!(payload_hash(&travellers), payload_hash(&travellers.clone()));
+    assert_eq!(payload_hash(&travellers).len(), 64);
+}
+
+#[test]
+fn a_changed_payload_hashes_differently() {
+    let original = json!([{ "familyName": "Tremblay", "givenName": "Marie" }]);
+    let changed = json!([{ "familyName": "Tremblay", "givenName": "Mario" }]);
+
+    assert_ne!(payload_hash(&original), payload_hash(&changed));
+}
+
+#[test]
+fn keyed_submissions_run_in_one_transaction() {
+    let cases = [
+        (None, true, Ok(TransactionScope::Group)),
+        (Some(TransactionScope::Group), true, Ok(TransactionScope::Group)),
+        (None, false, Ok(TransactionScope::Traveller)),
+        (Some(TransactionScope::Traveller), false, Ok(TransactionScope::Traveller)),
+        (Some(TransactionScope::Group), false, Ok(TransactionScope::Group)),
+        (Some(TransactionScope::Traveller), true, Err("VALIDATION")),
+    ];
+
+    for (requested, keyed, expected) in cases.iter() {
+        let scope = TransactionScope::for_submission(*requested, *keyed).map_err(|e| e.code());
+
+        assert_eq!(scope, *expected, "{:?} keyed: {}", requested, keyed);
+    }
+}
+
+const SUBMIT: &str = r#"
+mutation Submit($data: [TravelDataInput!]!, $key: String) {
+    PILQuery(data: $data, idempotencyKey: $key) {
+        postStatus
+    }
+}"#;
+
+static INIT: Once = Once::new();
+
+fn schema() -> AppSchema {
+    INIT.call_once(|| {
+        for (name, value) in [
+            ("ADMIN_NAME", "Idempotency Test"),
+            ("ADMIN_EMAIL", "idempotency-test@example.com"),
+            ("ADMIN_PASSWORD", "idempotency-test"),
+            ("PASSWORD_SECRET_KEY", "idempotency-test"),
+        ] {
+            if std::env::var(name).is_err() {
+                std::env::set_var(name, value);
+            }
+        }
+
+        database::init();
+    });
+
+    create_schema_with_context(POOL.clone()).expect("Unable to build schema")
+}
+
+/// London to Toronto through Pearson on 30 September 2021
+fn traveller(family_name: &str) -> TravelData {
+    TravelData {
+        family_name: family_name.to_string(),
+        given_name: "Marie".to_string(),
+        additional_names: None,
+        birth_date: NaiveDate::from_ymd(1980, 1, 1),
+        gender: "F".to_string(),
+        travel_document_id: "AB123456".to_string(),
+        travel_document_issuer: "Canada".to_string(),
+        travel_document_type: None,
+        travel_document_expires_on: None,
+        additional_travel_documents: None,
+        residency_status: None,
+        citizenships: None,
+        traveller_ref: None,
+        relationships: None,
+        guardian_consent: None,
+        approved_access_level: "Public".to_string(),
+        approved_access_granularity: "Full".to_string(),
+        trip_provider: "Air Canada".to_string(),
+        travel_identifier: Some("AC869".to_string()),
+        booking_id: None,
+        travel_mode: "AIR".to_string(),
+        origin_name: "London".to_string(),
+        origin_country_name: "United Kingdom".to_string(),
+        destination_name: "Toronto".to_string(),
+        destination_country_name: "Canada".to_string(),
+        port_of_entry_code: Some("YYZ".to_string()),
+        travel_intent: "ENTRY".to_string(),
+        scheduled_departure_time: Some(NaiveDate::from_ymd(2021, 9, 30).and_hms(10, 0, 0)),
+        scheduled_arrival_time: Some(NaiveDate::from_ymd(2021, 9, 30).and_hms(17, 0, 0)),
+        departure_time: None,
+        arrival_time: None,
+        trip_state: "IN_PROGRESS".to_string(),
+        itinerary: None,
+        smart_healthcard_pk: None,
+        vaccination_required: false,
+        vaccinations: None,
+        covid_test_required: false,
+        covid_test: None,
+        quarantine_plan_required: false,
+        quarantine_plan: None,
+        date_time: NaiveDate::from_ymd(2021, 9, 30).and_hms(18, 0, 0),
+        cbsa_officer_id: "officer".to_string(),
+    }
+}
+
+/// post_status of each traveller's response
+async fn submit(schema: &AppSchema, cbsa_id: Uuid, key: &str, data: &[TravelData]) -> Vec<String> {
+    let mut variables = Variables::default();
+    variables.insert(Name::new("data"), Value::List(data.iter().map(|t| t.to_value()).collect()));
+    variables.insert(Name::new("key"), Value::String(key.to_string()));
+
+    let request = Request::new(SUBMIT)
+        .variables(variables)
+        .data(Role::Operator)
+        .data(cbsa_id);
+
+    let response = schema.execute(request).await;
+
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    response.data.into_json().unwrap()["PILQuery"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["postStatus"].as_str().unwrap().to_string())
+        .collect()
+}
+
+fn trip_count(family_name: &str) -> i64 {
+    let conn = database::connection().unwrap();
+
+    trips::table
+        .filter(trips::person_id.eq_any(persons::table
+            .filter(persons::family_name.eq(family_name))
+            .select(persons::id)))
+        .count()
+        .get_result(&conn)
+        .unwrap()
+}
+
+#[actix_rt::test]
+#[ignore = "needs a database at DATABASE_URL"]
+async fn a_retried_submission_writes_each_traveller_once() {
+    let schema = schema();
+
+    let cbsa_id = Uuid::new_v4();
+    let key = Uuid::new_v4().to_string();
+
+    let valid = format!("Valid{}", Uuid::new_v4().to_simple());
+    let invalid = format!("Invalid{}", Uuid::new_v4().to_simple());
+
+    let mut failing = traveller(&invalid);
+    failing.birth_date = NaiveDate::from_ymd(2999, 1, 1);
+
+    // The second traveller fails, so neither is written and the key is released
+    for _ in 0..2 {
+        let statuses = submit(&schema, cbsa_id, &key, &[traveller(&valid), failing.clone()]).await;
+
+        assert!(statuses.iter().all(|s| s == "ERROR"), "{:?}", statuses);
+        assert_eq!(trip_count(&valid), 0);
+    }
+
+    let statuses = submit(&schema, cbsa_id, &key, &[traveller(&valid), traveller(&invalid)]).await;
+
+    assert!(statuses.iter().all(|s| s != "ERROR"), "{:?}", statuses);
+    assert_eq!(trip_count(&valid), 1);
+    assert_eq!(trip_count(&invalid), 1);
+
+    // A repeat of the completed submission returns its responses without writing
+    submit(&schema, cbsa_id, &key, &[traveller(&valid), traveller(&invalid)]).await;
+
+    assert_eq!(trip_count(&valid), 1);
+    assert_eq!(trip_count(&invalid), 1);
+}
@@ -0,0 +1,72 @@
+//! Query cost and per-user rate limiting.
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use health_rules_engine::config_variables::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE, NESTED_LIST_COST};
+use health_rules_engine::graphql::{list_cost, page_cost, RateLimiter};
+
+#[test]
+fn pages_cost_their_size_times_their_selection() {
+    assert_eq!(page_cost(Some(12), None, 10), 121);
+    assert_eq!(page_cost(None, Some(3), 10), 31);
+    assert_eq!(page_cost(None, None, 10), DEFAULT_PAGE_SIZE * 10 + 1);
+    // Oversized pages are rejected when run, so cost at most MAX_PAGE_SIZE
+    assert_eq!(page_cost(Some(100_000), None, 1), MAX_PAGE_SIZE + 1);
+}
+
+#[test]
+fn nested_lists_multiply_their_selection() {
+    let trips = list_cost(4);
+    let person_trips = list_cost(trips + 1);
+
+    assert_eq!(trips, NESTED_LIST_COST * 4 + 1);
+    assert!(person_trips > NESTED_LIST_COST * trips);
+}
+
+#[test]
+fn users_are_limited_separately_within_a_window() {
+    let limiter = RateLimiter::new(2, Duration::from_secs(60));
+    let start = Instant::now();
+
+    assert!(limiter.check_at("analyst", start).is_ok());
+    assert!(limiter.check_at("analyst", start).is_ok());
+    assert_eq!(limiter.check_at("analyst", start + Duration::from_secs(15)), Err(45));
+    assert!(limiter.check_at("operator", start).is_ok());
+
+    // A new window starts once the old one has passed
+    assert!(limiter.check_at("analyst", start + Duration::from_secs(60)).is_ok());
+}
+
+#[test]
+fn expired_users_are_swept_once_a_window() {
+    let limiter = RateLimiter::new(2, Duration::from_secs(60));
+    let start = Instant::now();
+
+    assert!(limiter.check_at("analyst", start).is_ok());
+    assert!(limiter.check_at("operator", start + Duration::from_secs(30)).is_ok());
+    assert_eq!(limiter.tracked(), 2);
+
+    // analyst's window has passed but the last sweep was under a window ago
+    assert!(limiter.check_at("admin", start + Duration::from_secs(50)).is_ok());
+    assert_eq!(limiter.tracked(), 3);
+
+    assert!(limiter.check_at("admin", start + Duration::from_secs(61)).is_ok());
+    assert_eq!(limiter.tracked(), 2);
+}
+
+#[test]
+fn forwarded_addresses_are_only_believed_from_trusted_proxies() {
+    let proxy: SocketAddr = "10.0.0.2:41000".parse().unwrap();
+    let client: SocketAddr = "203.0.113.7:52000".parse().unwrap();
+
+    let direct = RateLimiter::new(2, Duration::from_secs(60));
+    assert_eq!(direct.client_key(Some(client), Some("198.51.100.1")), "203.0.113.7");
+    assert_eq!(direct.client_key(Some(proxy), Some("198.51.100.1")), "10.0.0.2");
+    assert_eq!(direct.client_key(None, Some("198.51.100.1")), "unknown");
+
+    let behind_proxy = RateLimiter::new(2, Duration::from_secs(60))
+        .with_trusted_proxies(vec![proxy.ip()]);
+    assert_eq!(behind_proxy.client_key(Some(proxy), Some("198.51.100.1")), "198.51.100.1");
+    assert_eq!(behind_proxy.client_key(Some(client), Some("198.51.100.1")), "203.0.113.7");
+}
@@ -0,0 +1,53 @@
+//! SQL built by the filter and sort inputs of list queries.
+
+use chrono::prelude::*;
+use diesel::debug_query;
+use diesel::pg::Pg;
+use uuid::Uuid;
+
+use health_rules_engine::graphql::SortDirection;
+use health_rules_engine::models::{PILResponseFilter, TravelMode, TripFilter, TripSort, TripSortField};
+
+#[test]
+fn empty_filter_lists_everything() {
+    let sql = debug_query::<Pg, _>(&TripFilter::default().query()).to_string();
+
+    assert!(!sql.contains("WHERE"), "{}", sql);
+}
+
+#[test]
+fn trip_filter_combines_conditions() {
+    let filter = TripFilter {
+        arrival_from: Some(NaiveDate::from_ymd(2021, 9, 1).and_hms(0, 0, 0)),
+        origin_country_id: Some(Uuid::new_v4()),
+        travel_mode: Some(TravelMode::Air),
+        ..TripFilter::default()
+    };
+
+    let sort = TripSort {
+        field: TripSortField::CreatedAt,
+        direction: SortDirection::Desc,
+    };
+
+    let sql = debug_query::<Pg, _>(&sort.apply(filter.query())).to_string();
+
+    assert!(sql.contains("\"trips\".\"arrival_time\" >= $1"), "{}", sql);
+    assert!(sql.contains("\"trips\".\"origin_place_id\" IN (SELECT \"places\".\"id\""), "{}", sql);
+    assert!(sql.contains("\"trips\".\"travel_mode\" = $3"), "{}", sql);
+    assert!(sql.contains("ORDER BY \"trips\".\"created_at\" DESC, \"trips\".\"id\" DESC"), "{}", sql);
+}
+
+#[test]
+fn response_codes_are_matched_in_upper_case() {
+    let filter = PILResponseFilter {
+        response_code: Some(" q ".to_string()),
+        random_testing_referral: Some(true),
+        ..PILResponseFilter::default()
+    };
+
+    let sql = debug_query::<Pg, _>(&filter.query()).to_string();
+
+    assert!(sql.contains("\"travel_responses\".\"response_code\" = $1"), "{}", sql);
+    assert!(sql.contains("\"Q\""), "{}", sql);
+    assert!(sql.contains("\"travel_responses\".\"random_testing_referral\" = $2"), "{}", sql);
+}
@@ -0,0 +1,203 @@
+//! Batching of nested lookups through the crate's DataLoaders. Travellers are
+//! submitted and read back through the real schema on a pool that counts
+//! connection checkouts, one for each Repository::run. Needs a database and
+//! is ignored unless run with --ignored.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Once};
+
+use async_graphql::*;
+use chrono::prelude::*;
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use r2d2::event::CheckoutEvent;
+use r2d2::HandleEvent;
+use uuid::Uuid;
+
+use health_rules_engine::common_utils::Role;
+use health_rules_engine::database::{self, PostgresPool};
+use health_rules_engine::graphql::{create_schema_with_context, AppSchema};
+use health_rules_engine::models::{SlimVaccination, TravelData};
+use health_rules_engine::schema::{persons, trips};
+
+/// Connections checked out of the pool
+#[derive(Debug, Clone, Default)]
+struct Checkouts(Arc<AtomicUsize>);
+
+impl Checkouts {
+    fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl HandleEvent for Checkouts {
+    fn handle_checkout(&self, _event: CheckoutEvent) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+static INIT: Once = Once::new();
+
+fn counted_schema() -> (AppSchema, Checkouts) {
+    INIT.call_once(|| {
+        for (name, value) in [
+            ("ADMIN_NAME", "Loader Test"),
+            ("ADMIN_EMAIL", "loader-test@example.com"),
+            ("ADMIN_PASSWORD", "loader-test"),
+            ("PASSWORD_SECRET_KEY", "loader-test"),
+        ] {
+            if std::env::var(name).is_err() {
+                std::env::set_var(name, value);
+            }
+        }
+
+        database::init();
+    });
+
+    let checkouts = Checkouts::default();
+
+    let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let pool = PostgresPool::builder()
+        .event_handler(Box::new(checkouts.clone()))
+        .build(ConnectionManager::new(url))
+        .expect("Failed to create DB Pool");
+
+    (create_schema_with_context(pool).expect("Unable to build schema"), checkouts)
+}
+
+/// London to Toronto through Pearson on 30 September 2021 with a dose of
+/// SpikeVax. Travellers differ in name and birth date so none are matched.
+fn traveller(family_name: &str, day: u32) -> TravelData {
+    TravelData {
+        family_name: family_name.to_string(),
+        given_name: format!("Marie{}", day),
+        additional_names: None,
+        birth_date: NaiveDate::from_ymd(1980, 1, day),
+        gender: "F".to_string(),
+        travel_document_id: Uuid::new_v4().to_simple().to_string()[..9].to_uppercase(),
+        travel_document_issuer: "Canada".to_string(),
+        travel_document_type: None,
+        travel_document_expires_on: None,
+        additional_travel_documents: None,
+        residency_status: None,
+        citizenships: None,
+        traveller_ref: None,
+        relationships: None,
+        guardian_consent: None,
+        approved_access_level: "Public".to_string(),
+        approved_access_granularity: "Full".to_string(),
+        trip_provider: "Air Canada".to_string(),
+        travel_identifier: Some("AC869".to_string()),
+        booking_id: None,
+        travel_mode: "AIR".to_string(),
+        origin_name: "London".to_string(),
+        origin_country_name: "United Kingdom".to_string(),
+        destination_name: "Toronto".to_string(),
+        destination_country_name: "Canada".to_string(),
+        port_of_entry_code: Some("YYZ".to_string()),
+        travel_intent: "ENTRY".to_string(),
+        scheduled_departure_time: Some(NaiveDate::from_ymd(2021, 9, 30).and_hms(10, 0, 0)),
+        scheduled_arrival_time: Some(NaiveDate::from_ymd(2021, 9, 30).and_hms(17, 0, 0)),
+        departure_time: None,
+        arrival_time: None,
+        trip_state: "IN_PROGRESS".to_string(),
+        itinerary: None,
+        smart_healthcard_pk: None,
+        vaccination_required: false,
+        vaccinations: Some(vec![SlimVaccination {
+            vaccine_name: "SpikeVax".to_string(),
+            dose_provider: "Pharmacy".to_string(),
+            location_provided: "London".to_string(),
+            country_provided: "United Kingdom".to_string(),
+            provided_on: NaiveDate::from_ymd(2021, 6, 1).and_hms(10, 0, 0),
+        }]),
+        covid_test_required: false,
+        covid_test: None,
+        quarantine_plan_required: false,
+        quarantine_plan: None,
+        date_time: NaiveDate::from_ymd(2021, 9, 30).and_hms(18, 0, 0),
+        cbsa_officer_id: "officer".to_string(),
+    }
+}
+
+const SUBMIT: &str = r#"
+mutation Submit($data: [TravelDataInput!]!) {
+    PILQuery(data: $data) {
+        postStatus
+    }
+}"#;
+
+const GROUP_TRIPS: &str = r#"
+query GroupTrips($id: UUID!) {
+    travelGroupByID(id: $id) {
+        trips { person { publicHealthProfile { vaccinationHistory { id } } } }
+    }
+}"#;
+
+/// The group and its trips, then the person, profile and vaccination loaders once each
+const LEVELS: usize = 5;
+
+/// Id of the travel group of travellers submitted together
+async fn submit_group(schema: &AppSchema, size: usize) -> Uuid {
+    let family_names: Vec<String> = (0..size).map(|_| format!("Loader{}", Uuid::new_v4().to_simple())).collect();
+    let data: Vec<TravelData> = family_names.iter().zip(1..).map(|(name, day)| traveller(name, day)).collect();
+
+    let mut variables = Variables::default();
+    variables.insert(Name::new("data"), Value::List(data.iter().map(|t| t.to_value()).collect()));
+
+    let request = Request::new(SUBMIT)
+        .variables(variables)
+        .data(Role::Admin)
+        .data(Uuid::new_v4());
+
+    let response = schema.execute(request).await;
+
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    let data = response.data.into_json().unwrap();
+    assert!(data["PILQuery"].as_array().unwrap().iter().all(|r| r["postStatus"] != "ERROR"), "{}", data);
+
+    let conn = database::connection().unwrap();
+
+    trips::table
+        .filter(trips::person_id.eq_any(persons::table
+            .filter(persons::family_name.eq(&family_names[0]))
+            .select(persons::id)))
+        .select(trips::travel_group_id)
+        .first(&conn)
+        .unwrap()
+}
+
+#[actix_rt::test]
+#[ignore = "needs a database at DATABASE_URL"]
+async fn nested_lookups_run_once_per_level() {
+    let (schema, checkouts) = counted_schema();
+
+    for size in [1, 4] {
+        let group_id = submit_group(&schema, size).await;
+
+        let mut variables = Variables::default();
+        variables.insert(Name::new("id"), Value::String(group_id.to_string()));
+
+        let before = checkouts.count();
+
+        let response = schema.execute(Request::new(GROUP_TRIPS)
+            .variables(variables)
+            .data(Role::Admin)).await;
+
+        let runs = checkouts.count() - before;
+
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+        let data = response.data.into_json().unwrap();
+        let returned = data["travelGroupByID"]["trips"].as_array().unwrap();
+
+        assert_eq!(returned.len(), size);
+
+        for trip in returned {
+            assert_eq!(trip["person"]["publicHealthProfile"]["vaccinationHistory"].as_array().unwrap().len(), 1);
+        }
+
+        assert_eq!(runs, LEVELS, "connections used for {} trips", size);
+    }
+}
@@ -0,0 +1,176 @@
+//! Reading traveller manifests for bulk import.
+
+use chrono::prelude::*;
+
+use health_rules_engine::models::{parse_bool, parse_date, parse_date_time, parse_optional_date_time,
+    read_manifest, ManifestFormat, ManifestRow};
+
+const HEADER: &str = "family_name,given_name,additional_names,birth_date,gender,travel_document_id,\
+travel_document_issuer,citizenships,approved_access_level,approved_access_granularity,trip_provider,\
+travel_mode,origin_name,origin_country_name,destination_name,destination_country_name,travel_intent,\
+arrival_time,trip_state,vaccination_required,vaccination_1_vaccine_name,vaccination_1_dose_provider,\
+vaccination_1_location_provided,vaccination_1_country_provided,vaccination_1_provided_on,cbsa_officer_id";
+
+const VALID: &str = "Tremblay,Marie,Anne; Louise,1980-04-12,female,AB123456,Canada,Canada; France,\
+medical_records,aggregated,Air Canada,AIR,Paris,France,Montreal,Canada,VISIT,2021-11-02 14:30,\
+ARRIVED,yes,Comirnaty,Pharmacie Centrale,Paris,France,2021-06-01,officer-1";
+
+fn manifest(rows: &[&str]) -> Vec<u8> {
+    let mut lines = vec![HEADER];
+    lines.extend_from_slice(rows);
+
+    lines.join("\n").into_bytes()
+}
+
+fn read_rows(rows: &[&str]) -> Vec<(usize, Result<ManifestRow, String>)> {
+    read_manifest(&manifest(rows), ManifestFormat::Csv).unwrap()
+}
+
+#[test]
+fn formats_are_detected_from_the_zip_signature() {
+    assert_eq!(ManifestFormat::detect(b"PK\x03\x04rest of workbook"), ManifestFormat::Xlsx);
+    assert_eq!(ManifestFormat::detect(HEADER.as_bytes()), ManifestFormat::Csv);
+}
+
+#[test]
+fn rows_are_numbered_as_in_the_spreadsheet() {
+    let blank = ",".repeat(HEADER.matches(',').count());
+    let rows = read_rows(&[VALID, &blank, VALID]);
+
+    let numbers: Vec<usize> = rows.iter().map(|(n, _)| *n).collect();
+
+    // The header is row 1 and the blank row 3 is skipped
+    assert_eq!(numbers, vec![2, 4]);
+    assert!(rows.iter().all(|(_, r)| r.is_ok()));
+}
+
+#[test]
+fn unreadable_rows_are_reported_alone() {
+    let rows = read_rows(&["Tremblay,Marie", VALID]);
+
+    assert!(rows[0].1.is_err());
+    assert!(rows[1].1.is_ok());
+}
+
+#[test]
+fn manifests_need_a_header() {
+    assert_eq!(read_manifest(b"", ManifestFormat::Csv).unwrap_err(), "Manifest is empty");
+    assert!(read_manifest(HEADER.as_bytes(), ManifestFormat::Csv).unwrap().is_empty());
+    assert!(read_manifest(b"not a workbook", ManifestFormat::Xlsx).is_err());
+}
+
+#[test]
+fn rows_convert_to_travel_data() {
+    let (_, row) = read_rows(&[VALID]).remove(0);
+    let data = row.unwrap().into_travel_data().unwrap();
+
+    assert_eq!(data.family_name, "Tremblay");
+    assert_eq!(data.additional_names, Some(vec!["Anne".to_string(), "Louise".to_string()]));
+    assert_eq!(data.citizenships, Some(vec!["Canada".to_string(), "France".to_string()]));
+    assert_eq!(data.birth_date, NaiveDate::from_ymd(1980, 4, 12));
+    assert_eq!(data.arrival_time, Some(NaiveDate::from_ymd(2021, 11, 2).and_hms(14, 30, 0)));
+    assert!(data.vaccination_required);
+
+    let vaccinations = data.vaccinations.unwrap();
+    assert_eq!(vaccinations.len(), 1);
+    assert_eq!(vaccinations[0].vaccine_name, "Comirnaty");
+    assert_eq!(vaccinations[0].provided_on, NaiveDate::from_ymd(2021, 6, 1).and_hms(0, 0, 0));
+
+    assert!(data.covid_test.is_none());
+    assert!(data.quarantine_plan.is_none());
+}
+
+#[test]
+fn every_problem_with_a_row_is_reported() {
+    let invalid = VALID
+        .replace("Tremblay", " ")
+        .replace("1980-04-12", "12/04/1980")
+        .replace(",yes,", ",maybe,")
+        .replace("Pharmacie Centrale", "");
+
+    let (_, row) = read_rows(&[&invalid]).remove(0);
+    let errors = row.unwrap().into_travel_data().unwrap_err();
+
+    assert_eq!(errors, vec![
+        "family_name: value is required".to_string(),
+        "birth_date: '12/04/1980' is not a date (YYYY-MM-DD)".to_string(),
+        "vaccination_required: 'maybe' is not true or false".to_string(),
+        "vaccination_1_dose_provider: value is required".to_string(),
+    ]);
+}
+
+#[test]
+fn a_blank_birth_date_is_a_row_error() {
+    let blank = VALID.replace("1980-04-12", " ");
+
+    let (_, row) = read_rows(&[&blank]).remove(0);
+    let errors = row.unwrap().into_travel_data().unwrap_err();
+
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(errors[0].starts_with("birth_date"), "{:?}", errors);
+}
+
+#[test]
+fn booleans_accept_common_spellings() {
+    let cases = [
+        (None, Ok(false)),
+        (Some(""), Ok(false)),
+        (Some("No"), Ok(false)),
+        (Some("0"), Ok(false)),
+        (Some(" TRUE "), Ok(true)),
+        (Some("y"), Ok(true)),
+        (Some("1"), Ok(true)),
+        (Some("maybe"), Err("flag: 'maybe' is not true or false".to_string())),
+    ];
+
+    for (value, expected) in cases {
+        assert_eq!(parse_bool("flag", &value.map(|v| v.to_string())), expected, "{:?}", value);
+    }
+}
+
+#[test]
+fn dates_accept_a_date_or_date_time() {
+    let cases = [
+        ("2021-06-01", Some(NaiveDate::from_ymd(2021, 6, 1))),
+        (" 2021-06-01 ", Some(NaiveDate::from_ymd(2021, 6, 1))),
+        ("2021-06-01T08:15:00", Some(NaiveDate::from_ymd(2021, 6, 1))),
+        ("2021-06-01 08:15", Some(NaiveDate::from_ymd(2021, 6, 1))),
+        ("01/06/2021", None),
+        ("2021-02-30", None),
+    ];
+
+    for (value, expected) in cases {
+        assert_eq!(parse_date("provided_on", value).ok(), expected, "{}", value);
+    }
+}
+
+#[test]
+fn date_times_accept_each_manifest_format() {
+    let at = |h, m, s| NaiveDate::from_ymd(2021, 11, 2).and_hms(h, m, s);
+
+    let cases = [
+        ("2021-11-02T14:30:05", Some(at(14, 30, 5))),
+        ("2021-11-02T14:30:05.250", Some(NaiveDate::from_ymd(2021, 11, 2).and_hms_milli(14, 30, 5, 250))),
+        ("2021-11-02T14:30:05Z", Some(at(14, 30, 5))),
+        ("2021-11-02 14:30:05", Some(at(14, 30, 5))),
+        ("2021-11-02 14:30", Some(at(14, 30, 0))),
+        ("2021-11-02", Some(at(0, 0, 0))),
+        ("14:30 2021-11-02", None),
+    ];
+
+    for (value, expected) in cases {
+        assert_eq!(parse_date_time("arrival_time", value).ok(), expected, "{}", value);
+    }
+
+    assert_eq!(
+        parse_date_time("arrival_time", "soon").unwrap_err(),
+        "arrival_time: 'soon' is not a date time (YYYY-MM-DD HH:MM:SS)",
+    );
+}
+
+#[test]
+fn blank_optional_date_times_are_none() {
+    assert_eq!(parse_optional_date_time("departure_time", &None), Ok(None));
+    assert_eq!(parse_optional_date_time("departure_time", &Some("  ".to_string())), Ok(None));
+    assert!(parse_optional_date_time("departure_time", &Some("later".to_string())).is_err());
+}
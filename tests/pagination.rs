@@ -0,0 +1,67 @@
+//! Page arguments and keyset cursors for the cursor connections of list queries.
+
+use async_graphql::connection::CursorType;
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+use health_rules_engine::config_variables::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+use health_rules_engine::graphql::{KeysetCursor, PageArgs, SortDirection};
+
+fn cursor(day: u32) -> KeysetCursor {
+    KeysetCursor::on_date(NaiveDate::from_ymd(2021, 6, day), Uuid::new_v4())
+}
+
+#[test]
+fn first_pages_forward_from_after() {
+    let after = cursor(1);
+    let args = PageArgs::new(Some(after), None, Some(2), None).unwrap();
+
+    assert_eq!(args.limit(), 3);
+    assert_eq!(args.bounds(SortDirection::Asc), vec![(after, true)]);
+    assert_eq!(args.bounds(SortDirection::Desc), vec![(after, false)]);
+    assert_eq!(args.load_direction(SortDirection::Desc), SortDirection::Desc);
+
+    assert_eq!(args.trim(vec![1, 2, 3]), (vec![1, 2], true, true));
+    assert_eq!(args.trim(vec![1, 2]), (vec![1, 2], true, false));
+}
+
+#[test]
+fn last_pages_back_from_before() {
+    let before = cursor(10);
+    let args = PageArgs::new(None, Some(before), None, Some(2)).unwrap();
+
+    assert_eq!(args.bounds(SortDirection::Asc), vec![(before, false)]);
+    assert_eq!(args.load_direction(SortDirection::Asc), SortDirection::Desc);
+
+    // Rows come in reverse order, the extra one is before the page
+    assert_eq!(args.trim(vec![9, 8, 7]), (vec![8, 9], true, true));
+    assert_eq!(args.trim(vec![9]), (vec![9], false, true));
+
+    let args = PageArgs::new(None, None, None, Some(2)).unwrap();
+    assert_eq!(args.trim(vec![9, 8]), (vec![8, 9], false, false));
+}
+
+#[test]
+fn page_size_is_limited() {
+    let args = PageArgs::new(None, None, None, None).unwrap();
+    assert_eq!(args.size, DEFAULT_PAGE_SIZE);
+    assert!(!args.backward);
+
+    assert!(PageArgs::new(None, None, Some(MAX_PAGE_SIZE + 1), None).is_err());
+    assert!(PageArgs::new(None, None, None, Some(MAX_PAGE_SIZE + 1)).is_err());
+    assert!(PageArgs::new(None, None, Some(1), Some(1)).is_err());
+}
+
+#[test]
+fn cursors_round_trip() {
+    let id = Uuid::new_v4();
+    let time = NaiveDate::from_ymd(2021, 6, 1).and_hms_milli(13, 45, 7, 250);
+
+    for cursor in [KeysetCursor::new(Some(time), id), KeysetCursor::new(None, id), KeysetCursor::on_date(time.date(), id)] {
+        assert_eq!(KeysetCursor::decode_cursor(&cursor.encode_cursor()), Ok(cursor));
+    }
+
+    assert_eq!(KeysetCursor::on_date(time.date(), id).date(), Some(time.date()));
+    assert!(KeysetCursor::decode_cursor("3").is_err());
+    assert!(KeysetCursor::decode_cursor("2021-06-01T00:00:00|not-a-uuid").is_err());
+}
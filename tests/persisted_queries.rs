@@ -0,0 +1,66 @@
+//! Persisted query hashes and when /graphql checks the allow list.
+
+use async_graphql::{Request, Value};
+use serde_json::json;
+
+use health_rules_engine::graphql::{is_sign_in, needs_persisted_query, requested_hash, QueryMode};
+use health_rules_engine::models::query_hash;
+
+const DOCUMENT: &str = "query { allCountries { countryName } }";
+const DOCUMENT_HASH: &str = "f3a76d8d4ecc23cfce6c1f0b0c569cca34ba42afd296615118732fbc36c04211";
+
+fn by_hash(hash: &str) -> Request {
+    let mut request = Request::new("");
+
+    request.extensions.insert(
+        "persistedQuery".to_string(),
+        Value::from_json(json!({ "version": 1, "sha256Hash": hash })).unwrap(),
+    );
+
+    request
+}
+
+#[test]
+fn hashes_match_apollo_clients() {
+    assert_eq!(query_hash(DOCUMENT), DOCUMENT_HASH);
+    assert_eq!(query_hash(""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+}
+
+#[test]
+fn hash_is_read_from_extensions() {
+    assert_eq!(requested_hash(&by_hash(&DOCUMENT_HASH.to_uppercase())).as_deref(), Some(DOCUMENT_HASH));
+    assert_eq!(requested_hash(&Request::new(DOCUMENT)), None);
+}
+
+#[test]
+fn only_non_admins_are_checked_in_persisted_mode() {
+    let ad_hoc = Request::new(DOCUMENT);
+
+    assert!(!needs_persisted_query(&ad_hoc, QueryMode::Open, false));
+    assert!(needs_persisted_query(&ad_hoc, QueryMode::PersistedOnly, false));
+    assert!(!needs_persisted_query(&ad_hoc, QueryMode::PersistedOnly, true));
+
+    // Documents sent by hash are looked up in any mode
+    assert!(needs_persisted_query(&by_hash(DOCUMENT_HASH), QueryMode::Open, true));
+}
+
+#[test]
+fn sign_in_runs_without_being_registered() {
+    let sign_in = Request::new(r#"mutation { signIn(input: { email: "a@b.ca", password: "pw" }) }"#);
+
+    assert!(is_sign_in(&sign_in));
+    assert!(!needs_persisted_query(&sign_in, QueryMode::PersistedOnly, false));
+}
+
+#[test]
+fn only_documents_calling_nothing_but_sign_in_are_exempt() {
+    let with_other_fields = Request::new(
+        r#"mutation { signIn(input: { email: "a@b.ca", password: "pw" }) removePersistedQuery(id: "x") }"#);
+    let as_query = Request::new("query { signIn }");
+    let with_fragment = Request::new("mutation { ...F } fragment F on Mutation { signIn }");
+
+    for request in [with_other_fields, as_query, with_fragment, Request::new("not graphql")] {
+        assert!(!is_sign_in(&request));
+        assert!(needs_persisted_query(&request, QueryMode::PersistedOnly, false));
+    }
+}
@@ -0,0 +1,219 @@
+//! Scoring of submitted travellers against existing persons.
+
+use chrono::prelude::*;
+use uuid::Uuid;
+
+use health_rules_engine::config_variables::PERSON_MATCH_RULES;
+use health_rules_engine::models::{name_initial, name_initial_translation, name_variants, is_valid_travel_document, NewPerson, Person,
+    PresentedTravelDocument, TravelDocument, TravelDocumentStatus, TravelDocumentType};
+
+fn person(family_name: &str, given_name: &str) -> Person {
+    Person {
+        id: Uuid::new_v4(),
+        family_name: family_name.to_string(),
+        given_name: given_name.to_string(),
+        additional_names: None,
+        birth_date: NaiveDate::from_ymd(1980, 4, 12),
+        gender: "female".to_string(),
+        travel_group_id: Uuid::new_v4(),
+        approved_access_level: "medical_records".to_string(),
+        approved_access_granularity: "aggregated".to_string(),
+        created_at: Utc::now().naive_utc(),
+        residency_status: None,
+        citizenship_ids: Vec::new(),
+    }
+}
+
+fn submitted(family_name: &str, given_name: &str) -> NewPerson {
+    let p = person(family_name, given_name);
+
+    NewPerson::new(
+        p.family_name,
+        p.given_name,
+        None,
+        p.birth_date,
+        p.gender,
+        p.travel_group_id,
+        p.approved_access_level,
+        p.approved_access_granularity,
+        p.residency_status,
+        p.citizenship_ids,
+    )
+}
+
+fn passport(document_number: &str, issuer_id: Uuid) -> PresentedTravelDocument {
+    PresentedTravelDocument::new(TravelDocumentType::Passport, document_number.to_string(), issuer_id, None)
+}
+
+fn document(person: &Person, presented: &PresentedTravelDocument, status: TravelDocumentStatus) -> TravelDocument {
+    TravelDocument {
+        id: Uuid::new_v4(),
+        person_id: person.id,
+        document_type: presented.document_type,
+        document_number: presented.document_number.to_owned(),
+        issuer_id: presented.issuer_id,
+        expires_on: presented.expires_on,
+        status,
+        created_at: Utc::now().naive_utc(),
+        last_presented_at: Utc::now().naive_utc(),
+    }
+}
+
+#[test]
+fn same_document_and_names_is_a_match() {
+    let issuer = Uuid::new_v4();
+    let existing = person("Tremblay", "Marie");
+    let documents = [document(&existing, &passport("AB123456", issuer), TravelDocumentStatus::Active)];
+
+    let m = PERSON_MATCH_RULES.score(
+        &submitted("TREMBLAY", "Marie"), &[passport("ab 123456", issuer)], &existing, &documents).unwrap();
+
+    assert!(m.score >= PERSON_MATCH_RULES.auto_match_score, "{:?}", m);
+}
+
+#[test]
+fn renewed_passport_is_queued_for_review() {
+    let issuer = Uuid::new_v4();
+    let existing = person("Tremblay", "Marie");
+    let documents = [document(&existing, &passport("AB123456", issuer), TravelDocumentStatus::Active)];
+
+    let m = PERSON_MATCH_RULES.score(
+        &submitted("Tremblay", "Marie"), &[passport("CD654321", issuer)], &existing, &documents).unwrap();
+
+    assert!(m.score >= PERSON_MATCH_RULES.review_score, "{:?}", m);
+    assert!(m.score < PERSON_MATCH_RULES.auto_match_score, "{:?}", m);
+}
+
+#[test]
+fn replaced_document_is_a_match() {
+    let issuer = Uuid::new_v4();
+    let existing = person("Tremblay", "Marie");
+    let documents = [
+        document(&existing, &passport("CD654321", issuer), TravelDocumentStatus::Active),
+        document(&existing, &passport("AB123456", issuer), TravelDocumentStatus::Replaced),
+    ];
+
+    let m = PERSON_MATCH_RULES.score(
+        &submitted("Tremblay", "Marie"), &[passport("AB123456", issuer)], &existing, &documents).unwrap();
+
+    assert!(m.matched_inactive_document);
+    assert!(m.score >= PERSON_MATCH_RULES.auto_match_score, "{:?}", m);
+}
+
+#[test]
+fn any_presented_document_can_match() {
+    let canada = Uuid::new_v4();
+    let france = Uuid::new_v4();
+    let existing = person("Tremblay", "Marie");
+
+    let nexus = PresentedTravelDocument::new(TravelDocumentType::Nexus, "123456789".to_string(), canada, None);
+    let documents = [document(&existing, &nexus, TravelDocumentStatus::Active)];
+
+    let m = PERSON_MATCH_RULES.score(
+        &submitted("Tremblay", "Marie"), &[passport("12AB34567", france), nexus.clone()], &existing, &documents).unwrap();
+
+    assert!(!m.matched_inactive_document);
+    assert!(m.score >= PERSON_MATCH_RULES.auto_match_score, "{:?}", m);
+}
+
+#[test]
+fn same_number_of_another_type_is_not_a_document_match() {
+    let issuer = Uuid::new_v4();
+    let existing = person("Tremblay", "Marie");
+
+    let card = PresentedTravelDocument::new(
+        TravelDocumentType::PermanentResidentCard, "AB123456".to_string(), issuer, None);
+    let documents = [document(&existing, &card, TravelDocumentStatus::Active)];
+
+    let m = PERSON_MATCH_RULES.score(
+        &submitted("Tremblay", "Marie"), &[passport("AB123456", issuer)], &existing, &documents).unwrap();
+
+    assert!(m.score < PERSON_MATCH_RULES.auto_match_score, "{:?}", m);
+}
+
+#[test]
+fn transliterated_names_match() {
+    let issuer = Uuid::new_v4();
+    let existing = person("Müller", "Zoë");
+    let documents = [document(&existing, &passport("C01X00T47", issuer), TravelDocumentStatus::Active)];
+
+    let m = PERSON_MATCH_RULES.score(
+        &submitted("Mueller", "Zoe"), &[passport("C01X00T47", issuer)], &existing, &documents).unwrap();
+
+    assert_eq!(m.score, 1.0, "{:?}", m);
+    assert!(name_variants("Müller").contains(&"muller".to_string()));
+    assert!(name_variants("Müller").contains(&"mueller".to_string()));
+}
+
+#[test]
+fn different_birth_date_is_never_a_match() {
+    let issuer = Uuid::new_v4();
+    let existing = person("Tremblay", "Marie");
+    let documents = [document(&existing, &passport("AB123456", issuer), TravelDocumentStatus::Active)];
+
+    let mut new_person = submitted("Tremblay", "Marie");
+    new_person.birth_date = NaiveDate::from_ymd(1980, 12, 4);
+
+    assert!(PERSON_MATCH_RULES.score(&new_person, &[passport("AB123456", issuer)], &existing, &documents).is_none());
+}
+
+#[test]
+fn primary_document_is_most_recent_active() {
+    let issuer = Uuid::new_v4();
+    let existing = person("Tremblay", "Marie");
+
+    let mut lost = document(&existing, &passport("EF000001", issuer), TravelDocumentStatus::Lost);
+    lost.last_presented_at = Utc::now().naive_utc() + chrono::Duration::days(1);
+
+    let documents = [
+        document(&existing, &passport("AB123456", issuer), TravelDocumentStatus::Active),
+        lost,
+    ];
+
+    assert_eq!(TravelDocument::primary(&documents).unwrap().document_number, "AB123456");
+}
+
+#[test]
+fn documents_expire_after_their_expiry_date() {
+    let expires_on = NaiveDate::from_ymd(2026, 3, 31);
+    let presented = PresentedTravelDocument::new(
+        TravelDocumentType::Passport, "AB123456".to_string(), Uuid::new_v4(), Some(expires_on));
+
+    assert!(!presented.is_expired_on(expires_on));
+    assert!(presented.is_expired_on(expires_on.succ()));
+
+    let existing = person("Tremblay", "Marie");
+    let replaced = document(&existing, &presented, TravelDocumentStatus::Replaced);
+
+    assert!(!replaced.is_valid_on(expires_on));
+}
+
+#[test]
+fn document_formats_depend_on_type() {
+    assert!(is_valid_travel_document(TravelDocumentType::Passport, "Canada", "AB123456"));
+    assert!(!is_valid_travel_document(TravelDocumentType::Passport, "Canada", "123456789"));
+    assert!(is_valid_travel_document(TravelDocumentType::Nexus, "Canada", "123456789"));
+    assert!(!is_valid_travel_document(TravelDocumentType::Nexus, "Canada", "AB123456"));
+    assert!(is_valid_travel_document(TravelDocumentType::PermanentResidentCard, "Canada", "PA0123456"));
+}
+
+#[test]
+fn names_that_can_match_share_an_initial() {
+    for (name, other) in [("Müller", "Mueller"), ("Østergaard", "Oestergaard"), ("Żak", "zak"), ("Ærø", "Aero")] {
+        assert!(name_variants(name).iter().any(|n| name_variants(other).contains(n)), "{} / {}", name, other);
+        assert_eq!(name_initial(name), name_initial(other), "{} / {}", name, other);
+    }
+
+    assert_eq!(name_initial("  "), None);
+}
+
+#[test]
+fn initials_are_folded_in_sql_as_in_name_initial() {
+    let (from, to) = name_initial_translation();
+
+    assert_eq!(from.chars().count(), to.chars().count());
+
+    for (letter, folded) in from.chars().zip(to.chars()) {
+        assert_eq!(name_initial(&letter.to_string()), Some(folded), "{}", letter);
+    }
+}
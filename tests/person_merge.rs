@@ -0,0 +1,141 @@
+//! Health profiles, vaccinations, covid tests and match reviews kept when
+//! duplicate persons are merged.
+
+use chrono::prelude::*;
+use uuid::Uuid;
+
+use health_rules_engine::models::{CovidTest, PersonMatchReview, PersonMatchStatus, ProfileMerge,
+    PublicHealthProfile, ReviewMove, Vaccination};
+
+fn profile(person_id: Uuid, smart_healthcard_pk: Option<&str>) -> PublicHealthProfile {
+    PublicHealthProfile {
+        id: Uuid::new_v4(),
+        person_id,
+        smart_healthcard_pk: smart_healthcard_pk.map(|k| k.to_string()),
+    }
+}
+
+#[test]
+fn the_duplicate_profile_is_absorbed_by_the_survivors() {
+    let (survivor, duplicate) = (Uuid::new_v4(), Uuid::new_v4());
+    let kept = profile(survivor, Some("survivor-key"));
+    let absorbed = profile(duplicate, Some("duplicate-key"));
+
+    let plan = ProfileMerge::plan(survivor, &[absorbed.clone(), kept.clone()]).unwrap();
+
+    assert_eq!(plan, ProfileMerge {
+        target_id: kept.id,
+        absorbed_ids: vec![absorbed.id],
+        smart_healthcard_pk: Some("survivor-key".to_string()),
+    });
+}
+
+#[test]
+fn the_duplicate_profile_is_kept_when_the_survivor_has_none() {
+    let (survivor, duplicate) = (Uuid::new_v4(), Uuid::new_v4());
+    let moved = profile(duplicate, None);
+
+    let plan = ProfileMerge::plan(survivor, &[moved.clone()]).unwrap();
+
+    assert_eq!(plan.target_id, moved.id);
+    assert!(plan.absorbed_ids.is_empty());
+}
+
+#[test]
+fn the_key_of_an_absorbed_profile_fills_a_missing_one() {
+    let (survivor, duplicate) = (Uuid::new_v4(), Uuid::new_v4());
+    let kept = profile(survivor, None);
+    let absorbed = profile(duplicate, Some("duplicate-key"));
+
+    let plan = ProfileMerge::plan(survivor, &[kept.clone(), absorbed.clone()]).unwrap();
+
+    assert_eq!(plan.target_id, kept.id);
+    assert_eq!(plan.smart_healthcard_pk.as_deref(), Some("duplicate-key"));
+}
+
+#[test]
+fn nothing_is_merged_without_profiles() {
+    assert_eq!(ProfileMerge::plan(Uuid::new_v4(), &[]), None);
+}
+
+fn on(day: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd(2021, 6, day).and_hms(10, 0, 0)
+}
+
+fn vaccination(vaccine_id: Uuid, provided_on: NaiveDateTime) -> Vaccination {
+    Vaccination {
+        id: Uuid::new_v4(),
+        vaccine_id,
+        dose_provider: "Pharmacy".to_string(),
+        location_provided_id: Uuid::new_v4(),
+        provided_on,
+        public_health_profile_id: Uuid::new_v4(),
+    }
+}
+
+fn covid_test(test_name: &str, date_taken: NaiveDateTime) -> CovidTest {
+    CovidTest {
+        id: Uuid::new_v4(),
+        public_health_profile_id: Uuid::new_v4(),
+        test_name: test_name.to_string(),
+        test_type: "PCR".to_string(),
+        date_taken,
+        test_result: false,
+    }
+}
+
+#[test]
+fn doses_both_people_hold_are_kept_once() {
+    let (comirnaty, spikevax) = (Uuid::new_v4(), Uuid::new_v4());
+
+    let kept = vec![vaccination(comirnaty, on(1)), vaccination(comirnaty, on(22))];
+    let repeated = vaccination(comirnaty, on(22));
+    let twice = vaccination(spikevax, on(5));
+    let again = vaccination(spikevax, on(5));
+    let other_day = vaccination(comirnaty, on(23));
+
+    let duplicates = Vaccination::duplicates(&kept, &[repeated.clone(), twice.clone(), again.clone(), other_day]);
+
+    assert_eq!(duplicates, vec![repeated.id, again.id]);
+}
+
+#[test]
+fn tests_both_people_hold_are_kept_once() {
+    let kept = vec![covid_test("Lucira", on(1))];
+    let repeated = covid_test("Lucira", on(1));
+    let other_test = covid_test("Cue", on(1));
+
+    assert_eq!(CovidTest::duplicates(&kept, &[repeated.clone(), other_test]), vec![repeated.id]);
+}
+
+fn review(person_id: Uuid, candidate_id: Uuid, status: PersonMatchStatus) -> PersonMatchReview {
+    PersonMatchReview {
+        id: Uuid::new_v4(),
+        person_id,
+        candidate_id,
+        score: 0.5,
+        reasons: vec!["Same travel document".to_string()],
+        status,
+        created_at: on(1),
+        reviewed_by: None,
+        reviewed_at: None,
+    }
+}
+
+#[test]
+fn reviews_of_the_duplicate_follow_it_into_the_survivor() {
+    let (duplicate, survivor, other, known) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+    let pair = review(duplicate, survivor, PersonMatchStatus::Pending);
+    let with_other = review(other, duplicate, PersonMatchStatus::Pending);
+    let already_reviewed = review(known, survivor, PersonMatchStatus::Dismissed);
+    let with_known = review(duplicate, known, PersonMatchStatus::Pending);
+    let decided = review(duplicate, Uuid::new_v4(), PersonMatchStatus::Dismissed);
+
+    let plan = ReviewMove::plan(duplicate, survivor, &[
+        pair.clone(), with_other.clone(), already_reviewed, with_known.clone(), decided,
+    ]);
+
+    assert_eq!(plan.closed_ids, vec![pair.id, with_known.id]);
+    assert_eq!(plan.repointed, vec![PersonMatchReview { candidate_id: survivor, ..with_other }]);
+}
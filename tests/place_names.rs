@@ -0,0 +1,27 @@
+//! Place and country name matching used to avoid creating duplicates.
+
+use health_rules_engine::models::{normalize_country_name, normalize_place_name};
+
+#[test]
+fn place_names_ignore_case_accents_and_punctuation() {
+    let same = [
+        ("Thunder Bay", "thunder bay"),
+        ("Montréal", "Montreal"),
+        ("Saint-Jean-sur-Richelieu", "saint jean sur richelieu"),
+        ("St. John's", "St Johns"),
+        ("  Trois-Rivières ", "TROIS RIVIERES"),
+        ("Québec", "Quebec"),
+    ];
+
+    for (a, b) in same.iter() {
+        assert_eq!(normalize_place_name(a), normalize_place_name(b), "{} / {}", a, b);
+    }
+
+    assert_ne!(normalize_place_name("Paris"), normalize_place_name("Parris"));
+}
+
+#[test]
+fn country_names_ignore_case_and_periods() {
+    assert_eq!(normalize_country_name("U.S.A."), "usa");
+    assert_eq!(normalize_country_name("  United   Kingdom "), "united kingdom");
+}
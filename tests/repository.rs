@@ -0,0 +1,87 @@
+//! Database and reference cache access off the async workers.
+//! The pool points at an unreachable server so no database is needed.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_web::web;
+use async_graphql::*;
+use diesel::r2d2::ConnectionManager;
+use uuid::Uuid;
+
+use health_rules_engine::database::{PostgresPool, Repository};
+use health_rules_engine::models::{Country, Place, PlaceKind, Vaccine};
+use health_rules_engine::{get_place_by_id, ReferenceData};
+
+struct TestQuery;
+
+#[Object]
+impl TestQuery {
+    async fn place_name(&self, context: &Context<'_>, id: Uuid) -> FieldResult<String> {
+        let reference = ReferenceData::from_context(context)?;
+
+        let place = web::block(move || get_place_by_id(&reference, id))
+            .await
+            .map_err(|e| Error::new(e.to_string()))??;
+
+        Ok(place.name)
+    }
+
+    async fn ping(&self, context: &Context<'_>) -> FieldResult<bool> {
+        Repository::from_context(context)?
+            .run(|_conn| Ok(true))
+            .await
+    }
+}
+
+fn unreachable_pool() -> PostgresPool {
+    PostgresPool::builder()
+        .connection_timeout(Duration::from_millis(200))
+        .build_unchecked(ConnectionManager::new("postgres://nobody@127.0.0.1:1/none"))
+}
+
+fn schema(places: Vec<Place>) -> Schema<TestQuery, EmptyMutation, EmptySubscription> {
+    let places: HashMap<Uuid, Place> = places.into_iter().map(|p| (p.id, p)).collect();
+
+    Schema::build(TestQuery, EmptyMutation, EmptySubscription)
+        .data(Arc::new(unreachable_pool()))
+        .data(Arc::new(Mutex::new(HashMap::<Uuid, Country>::new())))
+        .data(Arc::new(Mutex::new(HashMap::<String, Uuid>::new())))
+        .data(Arc::new(Mutex::new(places)))
+        .data(Arc::new(Mutex::new(HashMap::<String, Vec<Uuid>>::new())))
+        .data(Arc::new(HashMap::<Uuid, Vaccine>::new()))
+        .finish()
+}
+
+#[actix_rt::test]
+async fn reference_caches_are_shared_with_the_blocking_pool() {
+    let place = Place {
+        id: Uuid::new_v4(),
+        name: "Montréal".to_string(),
+        country_id: Uuid::new_v4(),
+        timezone: None,
+        kind: PlaceKind::City,
+        iata_code: None,
+        un_locode: None,
+        latitude: None,
+        longitude: None,
+        is_port_of_entry: false,
+        testing_rate: None,
+    };
+
+    let response = schema(vec![place.clone()])
+        .execute(format!("{{ placeName(id: \"{}\") }}", place.id))
+        .await;
+
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data.into_json().unwrap()["placeName"], "Montréal");
+}
+
+#[actix_rt::test]
+async fn unavailable_database_is_a_field_error() {
+    let response = schema(Vec::new()).execute("{ ping }").await;
+
+    assert_eq!(response.errors.len(), 1);
+    assert!(response.errors[0].message.contains("db connection"), "{:?}", response.errors);
+}
@@ -0,0 +1,240 @@
+//! Regression tests for the Trip GraphQL resolvers.
+//! Trips are served from a fixture so no database is needed.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_graphql::*;
+use chrono::prelude::*;
+use serde_json::Value;
+use uuid::Uuid;
+
+use health_rules_engine::models::{Place, PlaceKind, TravelIntent, TravelMode, Trip, TripState};
+
+struct TestQuery;
+
+#[Object]
+impl TestQuery {
+    async fn trip(&self, context: &Context<'_>) -> Trip {
+        context.data_unchecked::<Trip>().clone()
+    }
+}
+
+fn place(name: &str, timezone: Option<&str>) -> Place {
+    Place {
+        id: Uuid::new_v4(),
+        name: name.to_string(),
+        country_id: Uuid::new_v4(),
+        timezone: timezone.map(|tz| tz.to_string()),
+        kind: PlaceKind::City,
+        iata_code: None,
+        un_locode: None,
+        latitude: None,
+        longitude: None,
+        is_port_of_entry: false,
+        testing_rate: None,
+    }
+}
+
+fn airport(name: &str, iata_code: &str, timezone: &str) -> Place {
+    Place {
+        kind: PlaceKind::Airport,
+        iata_code: Some(iata_code.to_string()),
+        is_port_of_entry: true,
+        testing_rate: Some(0.05),
+        ..place(name, Some(timezone))
+    }
+}
+
+fn utc(hour: u32, minute: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd(2021, 7, 1).and_hms(hour, minute, 0)
+}
+
+fn trip(origin: &Place, destination: &Place) -> Trip {
+    Trip {
+        id: Uuid::new_v4(),
+        trip_provider: "Air Canada".to_string(),
+        travel_identifier: Some("AC-857".to_string()),
+        booking_id: None,
+        travel_mode: TravelMode::Air,
+        origin_place_id: origin.id,
+        destination_place_id: destination.id,
+        travel_intent: TravelIntent::Entry,
+        // Every time differs so a swapped resolver can't pass
+        scheduled_departure_time: Some(utc(8, 0)),
+        scheduled_arrival_time: Some(utc(16, 0)),
+        departure_time: Some(utc(8, 30)),
+        arrival_time: Some(utc(16, 20)),
+        trip_state: TripState::InProgress,
+        travel_group_id: Uuid::new_v4(),
+        person_id: Uuid::new_v4(),
+        created_at: utc(7, 0),
+        travel_provider_id: None,
+        port_of_entry_id: None,
+    }
+}
+
+async fn query_trip(trip: Trip, places: Vec<Place>, fields: &str) -> Value {
+    let places: HashMap<Uuid, Place> = places.into_iter().map(|p| (p.id, p)).collect();
+
+    let schema = Schema::build(TestQuery, EmptyMutation, EmptySubscription)
+        .data(Arc::new(Mutex::new(places)))
+        .data(trip)
+        .finish();
+
+    let response = schema.execute(format!("{{ trip {{ {} }} }}", fields)).await;
+
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    response.data.into_json().unwrap()["trip"].clone()
+}
+
+fn time_field(value: &Value, key: &str) -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339(value[key].as_str().unwrap()).unwrap()
+}
+
+const TIME_FIELDS: &str = "
+    scheduledDepartureTime { utc local timezone }
+    scheduledArrivalTime { utc local timezone }
+    departureTime { utc local timezone }
+    arrivalTime { utc local timezone }
+";
+
+#[actix_rt::test]
+async fn time_fields_are_not_swapped() {
+    let origin = place("London", Some("Europe/London"));
+    let destination = place("Toronto", Some("America/Toronto"));
+
+    let t = query_trip(trip(&origin, &destination), vec![origin, destination], TIME_FIELDS).await;
+
+    let expected = [
+        ("scheduledDepartureTime", utc(8, 0)),
+        ("scheduledArrivalTime", utc(16, 0)),
+        ("departureTime", utc(8, 30)),
+        ("arrivalTime", utc(16, 20)),
+    ];
+
+    for (field, time) in expected.iter() {
+        assert_eq!(time_field(&t[field], "utc").naive_utc(), *time, "{}", field);
+    }
+}
+
+#[actix_rt::test]
+async fn departures_use_origin_and_arrivals_use_destination_time() {
+    let origin = place("London", Some("Europe/London"));
+    let destination = place("Toronto", Some("America/Toronto"));
+
+    let t = query_trip(trip(&origin, &destination), vec![origin, destination], TIME_FIELDS).await;
+
+    for field in ["scheduledDepartureTime", "departureTime"] {
+        assert_eq!(t[field]["timezone"], "Europe/London");
+        // British Summer Time
+        assert_eq!(time_field(&t[field], "local").offset().local_minus_utc(), 3600);
+    }
+
+    for field in ["scheduledArrivalTime", "arrivalTime"] {
+        assert_eq!(t[field]["timezone"], "America/Toronto");
+        // Eastern Daylight Time
+        assert_eq!(time_field(&t[field], "local").offset().local_minus_utc(), -4 * 3600);
+    }
+
+    let departure = time_field(&t["scheduledDepartureTime"], "local");
+    assert_eq!((departure.hour(), departure.minute()), (9, 0));
+
+    let arrival = time_field(&t["arrivalTime"], "local");
+    assert_eq!((arrival.hour(), arrival.minute()), (12, 20));
+}
+
+#[actix_rt::test]
+async fn local_and_utc_are_the_same_instant() {
+    let origin = place("Rio de Janeiro", Some("America/Sao_Paulo"));
+    let destination = place("Vancouver", Some("America/Vancouver"));
+
+    let t = query_trip(trip(&origin, &destination), vec![origin, destination], TIME_FIELDS).await;
+
+    for field in ["scheduledDepartureTime", "scheduledArrivalTime", "departureTime", "arrivalTime"] {
+        assert_eq!(time_field(&t[field], "local"), time_field(&t[field], "utc"), "{}", field);
+    }
+}
+
+#[actix_rt::test]
+async fn unknown_timezone_falls_back_to_utc() {
+    let origin = place("London", Some("Europe/London"));
+    let destination = place("Somewhere", None);
+
+    let t = query_trip(trip(&origin, &destination), vec![origin, destination], TIME_FIELDS).await;
+
+    assert_eq!(t["arrivalTime"]["timezone"], "UTC");
+    assert_eq!(time_field(&t["arrivalTime"], "local").offset().local_minus_utc(), 0);
+    assert_eq!(time_field(&t["arrivalTime"], "local").naive_utc(), utc(16, 20));
+}
+
+#[actix_rt::test]
+async fn missing_times_are_null() {
+    let origin = place("London", Some("Europe/London"));
+    let destination = place("Toronto", Some("America/Toronto"));
+
+    let mut planned = trip(&origin, &destination);
+    planned.departure_time = None;
+    planned.arrival_time = None;
+
+    let t = query_trip(planned, vec![origin, destination], TIME_FIELDS).await;
+
+    assert!(t["departureTime"].is_null());
+    assert!(t["arrivalTime"].is_null());
+    assert!(!t["scheduledDepartureTime"].is_null());
+    assert!(!t["scheduledArrivalTime"].is_null());
+}
+
+#[actix_rt::test]
+async fn enum_and_place_fields() {
+    let origin = place("London", Some("Europe/London"));
+    let destination = place("Toronto", Some("America/Toronto"));
+
+    let t = query_trip(
+        trip(&origin, &destination),
+        vec![origin, destination],
+        "travelMode travelIntent tripState tripProvider travelIdentifier origin { name } destination { name }",
+    ).await;
+
+    assert_eq!(t["travelMode"], "AIR");
+    assert_eq!(t["travelIntent"], "ENTRY");
+    assert_eq!(t["tripState"], "IN_PROGRESS");
+    assert_eq!(t["tripProvider"], "Air Canada");
+    assert_eq!(t["travelIdentifier"], "AC-857");
+    assert_eq!(t["origin"]["name"], "London");
+    assert_eq!(t["destination"]["name"], "Toronto");
+}
+
+#[actix_rt::test]
+async fn port_of_entry_fields() {
+    let origin = place("London", Some("Europe/London"));
+    let destination = airport("Toronto Pearson International Airport", "YYZ", "America/Toronto");
+
+    let mut t = trip(&origin, &destination);
+    t.port_of_entry_id = Some(destination.id);
+
+    let t = query_trip(
+        t,
+        vec![origin, destination],
+        "origin { kind isPortOfEntry } portOfEntry { name kind iataCode isPortOfEntry testingRate }",
+    ).await;
+
+    assert_eq!(t["origin"]["kind"], "CITY");
+    assert_eq!(t["origin"]["isPortOfEntry"], false);
+    assert_eq!(t["portOfEntry"]["name"], "Toronto Pearson International Airport");
+    assert_eq!(t["portOfEntry"]["kind"], "AIRPORT");
+    assert_eq!(t["portOfEntry"]["iataCode"], "YYZ");
+    assert_eq!(t["portOfEntry"]["isPortOfEntry"], true);
+    assert_eq!(t["portOfEntry"]["testingRate"], 0.05);
+}
+
+#[actix_rt::test]
+async fn missing_port_of_entry_is_null() {
+    let origin = place("London", Some("Europe/London"));
+    let destination = place("Toronto", Some("America/Toronto"));
+
+    let t = query_trip(trip(&origin, &destination), vec![origin, destination], "portOfEntry { name }").await;
+
+    assert!(t["portOfEntry"].is_null());
+}
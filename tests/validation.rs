@@ -0,0 +1,293 @@
+//! Checks on a PILQuery's travellers before anything is written.
+
+use chrono::prelude::*;
+
+use health_rules_engine::models::{is_valid_document_id, validate_group, SlimGuardianConsent,
+    SlimRelationship, SlimTravelDocument, SlimTripLeg, SlimVaccination, TravelData};
+
+fn at(day: u32, hour: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd(2021, 9, day).and_hms(hour, 0, 0)
+}
+
+fn leg(origin: (&str, &str), destination: (&str, &str), departs: NaiveDateTime, arrives: NaiveDateTime) -> SlimTripLeg {
+    SlimTripLeg {
+        origin_name: origin.0.to_string(),
+        origin_country_name: origin.1.to_string(),
+        destination_name: destination.0.to_string(),
+        destination_country_name: destination.1.to_string(),
+        travel_identifier: None,
+        scheduled_departure_time: Some(departs),
+        scheduled_arrival_time: Some(arrives),
+        departure_time: None,
+        arrival_time: None,
+    }
+}
+
+/// Paris to Montréal on 30 September 2021 with nothing wrong
+fn traveller() -> TravelData {
+    TravelData {
+        family_name: "Tremblay".to_string(),
+        given_name: "Marie".to_string(),
+        additional_names: None,
+        birth_date: NaiveDate::from_ymd(1980, 1, 1),
+        gender: "F".to_string(),
+        travel_document_id: "AB123456".to_string(),
+        travel_document_issuer: "Canada".to_string(),
+        travel_document_type: None,
+        travel_document_expires_on: None,
+        additional_travel_documents: None,
+        residency_status: None,
+        citizenships: None,
+        traveller_ref: None,
+        relationships: None,
+        guardian_consent: None,
+        approved_access_level: "Public".to_string(),
+        approved_access_granularity: "Full".to_string(),
+        trip_provider: "Air Canada".to_string(),
+        travel_identifier: Some("AC871".to_string()),
+        booking_id: None,
+        travel_mode: "AIR".to_string(),
+        origin_name: "Paris".to_string(),
+        origin_country_name: "France".to_string(),
+        destination_name: "Montréal".to_string(),
+        destination_country_name: "Canada".to_string(),
+        port_of_entry_code: None,
+        travel_intent: "ENTRY".to_string(),
+        scheduled_departure_time: Some(at(30, 13)),
+        scheduled_arrival_time: Some(at(30, 19)),
+        departure_time: None,
+        arrival_time: None,
+        trip_state: "IN_PROGRESS".to_string(),
+        itinerary: None,
+        smart_healthcard_pk: None,
+        vaccination_required: false,
+        vaccinations: None,
+        covid_test_required: false,
+        covid_test: None,
+        quarantine_plan_required: false,
+        quarantine_plan: None,
+        date_time: at(30, 20),
+        cbsa_officer_id: "officer".to_string(),
+    }
+}
+
+fn with_ref(traveller_ref: Option<&str>) -> TravelData {
+    TravelData {
+        traveller_ref: traveller_ref.map(|r| r.to_string()),
+        ..traveller()
+    }
+}
+
+fn relationship(related_traveller_ref: &str) -> SlimRelationship {
+    SlimRelationship {
+        relationship_type: "GUARDIAN_OF".to_string(),
+        related_traveller_ref: related_traveller_ref.to_string(),
+    }
+}
+
+#[test]
+fn document_ids_follow_their_issuers_format() {
+    let cases = [
+        ("Canada", "AB123456", true),
+        ("CAN", " A123456 ", true),
+        ("Canada", "ABC12345", false),
+        ("Canada", "AB12345", false),
+        ("U.S.A.", "123456789", true),
+        ("United States", "A12345678", true),
+        ("US", "AB1234567", false),
+        ("United Kingdom", "123456789", true),
+        ("GB", "A12345678", false),
+        ("France", "12AB34567", true),
+        ("France", "1234", false),
+        ("France", "12-AB-345", false),
+    ];
+
+    for (issuer, document_id, valid) in cases.iter() {
+        assert_eq!(is_valid_document_id(issuer, document_id), *valid, "{} {}", issuer, document_id);
+    }
+}
+
+#[test]
+fn travellers_report_every_violation() {
+    let cases: &[(&str, fn(&mut TravelData), &[&str])] = &[
+        ("valid", |_| {}, &[]),
+        ("future birth date", |t| t.birth_date = NaiveDate::from_ymd(2999, 1, 1), &["birth_date"]),
+        ("arrives before departing", |t| t.scheduled_arrival_time = Some(at(30, 12)), &["scheduled_arrival_time"]),
+        ("actual arrival before departure", |t| {
+            t.departure_time = Some(at(30, 14));
+            t.arrival_time = Some(at(30, 14));
+        }, &["arrival_time"]),
+        ("unknown enum values", |t| {
+            t.travel_mode = "rail".to_string();
+            t.trip_state = "lost".to_string();
+            t.residency_status = Some("tourist".to_string());
+        }, &["travel_mode", "trip_state", "residency_status"]),
+        ("enum values in any case", |t| {
+            t.travel_mode = "air".to_string();
+            t.trip_state = "in progress".to_string();
+            t.residency_status = Some("permanent-resident".to_string());
+        }, &[]),
+        ("badly formatted passport", |t| t.travel_document_id = "123".to_string(), &["travel_document_id"]),
+        ("badly formatted additional document", |t| {
+            t.additional_travel_documents = Some(vec![SlimTravelDocument {
+                document_type: "NEXUS".to_string(),
+                document_number: "12345".to_string(),
+                issuer: "Canada".to_string(),
+                expires_on: None,
+            }]);
+        }, &["additional_travel_documents[0].document_number"]),
+        ("vaccinated after arrival", |t| {
+            t.vaccinations = Some(vec![SlimVaccination {
+                vaccine_name: "SpikeVax".to_string(),
+                dose_provider: "Pharmacy".to_string(),
+                location_provided: "Paris".to_string(),
+                country_provided: "France".to_string(),
+                provided_on: at(30, 19),
+            }]);
+        }, &["vaccinations[0].provided_on"]),
+        ("dose reported twice", |t| {
+            let dose = SlimVaccination {
+                vaccine_name: "SpikeVax".to_string(),
+                dose_provider: "Pharmacy".to_string(),
+                location_provided: "Paris".to_string(),
+                country_provided: "France".to_string(),
+                provided_on: at(1, 10),
+            };
+
+            t.vaccinations = Some(vec![
+                dose.clone(),
+                SlimVaccination { provided_on: at(22, 10), ..dose.clone() },
+                SlimVaccination { vaccine_name: " spikevax".to_string(), ..dose },
+            ]);
+        }, &["vaccinations[2] repeats vaccinations[0]"]),
+        ("required sub-objects missing", |t| {
+            t.vaccination_required = true;
+            t.covid_test_required = true;
+            t.quarantine_plan_required = true;
+        }, &["vaccinations are required", "covid_test is required", "quarantine_plan is required"]),
+        ("unknown relationship type", |t| t.relationships = Some(vec![SlimRelationship {
+            relationship_type: "COUSIN".to_string(),
+            related_traveller_ref: "b".to_string(),
+        }]), &["relationships[0].relationship_type"]),
+        ("unnamed guardian", |t| t.guardian_consent = Some(SlimGuardianConsent {
+            guardian_name: " ".to_string(),
+            guardian_contact: None,
+            guardian_traveller_ref: None,
+        }), &["guardian_consent.guardian_name"]),
+        ("transit without itinerary", |t| t.travel_intent = "TRANSIT".to_string(), &["itinerary with an onward leg"]),
+    ];
+
+    for (name, change, expected) in cases.iter() {
+        let mut data = traveller();
+        change(&mut data);
+
+        let violations = data.validate();
+
+        assert_eq!(violations.len(), expected.len(), "{}: {:?}", name, violations);
+
+        for field in expected.iter() {
+            assert!(violations.iter().any(|v| v.contains(field)), "{}: no {} in {:?}", name, field, violations);
+        }
+    }
+}
+
+#[test]
+fn itineraries_connect_from_origin_to_destination() {
+    let paris = ("Paris", "France");
+    let london = ("London", "United Kingdom");
+    let montreal = ("Montréal", "Canada");
+    let toronto = ("Toronto", "Canada");
+
+    let cases: &[(&str, &str, Vec<SlimTripLeg>, &[&str])] = &[
+        ("through London", "ENTRY", vec![
+            leg(paris, london, at(30, 8), at(30, 9)),
+            leg(london, montreal, at(30, 11), at(30, 19)),
+        ], &[]),
+        ("names matched loosely", "ENTRY", vec![
+            leg(("PARIS", "france"), ("Montreal", "CANADA"), at(30, 13), at(30, 19)),
+        ], &[]),
+        ("empty", "ENTRY", vec![], &["at least one leg"]),
+        ("starts elsewhere", "ENTRY", vec![
+            leg(london, montreal, at(30, 11), at(30, 19)),
+        ], &["itinerary[0] origin"]),
+        ("leg arrives before departing", "ENTRY", vec![
+            leg(paris, montreal, at(30, 13), at(30, 12)),
+        ], &["itinerary[0].scheduled_arrival_time"]),
+        ("gap between legs", "ENTRY", vec![
+            leg(paris, london, at(30, 8), at(30, 9)),
+            leg(toronto, montreal, at(30, 11), at(30, 19)),
+        ], &["itinerary[1] origin"]),
+        ("departs before previous arrival", "ENTRY", vec![
+            leg(paris, london, at(30, 8), at(30, 10)),
+            leg(london, montreal, at(30, 9), at(30, 19)),
+        ], &["itinerary[1] departs"]),
+        ("misses destination", "ENTRY", vec![
+            leg(paris, toronto, at(30, 13), at(30, 19)),
+        ], &["is not on the itinerary"]),
+        ("continues past destination", "ENTRY", vec![
+            leg(paris, montreal, at(30, 13), at(30, 19)),
+            leg(montreal, toronto, at(30, 21), at(30, 22)),
+        ], &["continues past destination"]),
+        ("transit with onward leg", "TRANSIT", vec![
+            leg(paris, montreal, at(30, 13), at(30, 19)),
+            leg(montreal, toronto, at(30, 21), at(30, 22)),
+        ], &[]),
+        ("transit ending at destination", "TRANSIT", vec![
+            leg(paris, montreal, at(30, 13), at(30, 19)),
+        ], &["must continue past"]),
+    ];
+
+    for (name, travel_intent, itinerary, expected) in cases.iter() {
+        let data = TravelData {
+            travel_intent: travel_intent.to_string(),
+            itinerary: Some(itinerary.clone()),
+            ..traveller()
+        };
+
+        let violations = data.validate();
+
+        assert_eq!(violations.len(), expected.len(), "{}: {:?}", name, violations);
+
+        for message in expected.iter() {
+            assert!(violations.iter().any(|v| v.contains(message)), "{}: no {} in {:?}", name, message, violations);
+        }
+    }
+}
+
+#[test]
+fn groups_refer_only_to_other_travellers() {
+    let mut guardian = with_ref(Some("a"));
+    guardian.relationships = Some(vec![relationship("b")]);
+
+    let mut minor = with_ref(Some("b"));
+    minor.guardian_consent = Some(SlimGuardianConsent {
+        guardian_name: "Marie Tremblay".to_string(),
+        guardian_contact: None,
+        guardian_traveller_ref: Some("a".to_string()),
+    });
+
+    let mut to_self = with_ref(Some("a"));
+    to_self.relationships = Some(vec![relationship("a")]);
+
+    let mut to_missing = with_ref(Some("a"));
+    to_missing.relationships = Some(vec![relationship("z")]);
+
+    let cases: &[(&str, Vec<TravelData>, &[&str])] = &[
+        ("guardian and minor", vec![guardian, minor], &[]),
+        ("no refs", vec![with_ref(None), with_ref(None)], &[]),
+        ("empty ref", vec![with_ref(Some(" "))], &["data[0].traveller_ref must not be empty"]),
+        ("duplicate ref", vec![with_ref(Some("a")), with_ref(Some(" a "))], &["data[1].traveller_ref \"a\""]),
+        ("related to self", vec![to_self, with_ref(Some("b"))], &["data[0].relationships[0]"]),
+        ("related to no one", vec![to_missing], &["data[0].relationships[0]"]),
+    ];
+
+    for (name, data, expected) in cases.iter() {
+        let violations = validate_group(data);
+
+        assert_eq!(violations.len(), expected.len(), "{}: {:?}", name, violations);
+
+        for message in expected.iter() {
+            assert!(violations.iter().any(|v| v.contains(message)), "{}: no {} in {:?}", name, message, violations);
+        }
+    }
+}